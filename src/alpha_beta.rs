@@ -0,0 +1,495 @@
+//! A conventional alpha-beta searcher, as an alternative to the MCTS engine.
+//!
+//! It uses the same tuned static evaluation as the MCTS engine for its leaf nodes, and the policy network for move ordering.
+//! The search is a negamax with iterative deepening and a transposition table, and is single-threaded.
+//! This is mostly useful for benchmarking the MCTS engine against a conventional searcher, and for verifying tactics.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+use half::f16;
+
+use crate::evaluation::parameters::IncrementalPolicy;
+use crate::position::{Move, Position};
+use crate::search::cp_to_win_percentage;
+use crate::search::time::TimeBudget;
+
+/// Score of a won position. Wins in `n` plies are scored as `WIN_SCORE - n`, so that faster wins are preferred
+pub const WIN_SCORE: f32 = 100_000.0;
+
+/// Scores further from zero than this are proven wins or losses
+const MIN_WIN_SCORE: f32 = WIN_SCORE - MAX_DEPTH as f32;
+
+const MAX_DEPTH: u16 = 128;
+
+/// How often the time and the stop flag are checked
+const NODES_PER_CHECK: u64 = 1024;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct AlphaBetaSetting<const S: usize> {
+    value_params: Option<&'static [f32]>,
+    policy_params: Option<&'static [f32]>,
+    /// Number of transposition table entries
+    tt_entries: usize,
+    max_depth: u16,
+}
+
+impl<const S: usize> Default for AlphaBetaSetting<S> {
+    fn default() -> Self {
+        AlphaBetaSetting {
+            value_params: None,
+            policy_params: None,
+            tt_entries: 1 << 22,
+            max_depth: MAX_DEPTH,
+        }
+    }
+}
+
+impl<const S: usize> AlphaBetaSetting<S> {
+    /// Set the size of the transposition table, in bytes
+    pub fn mem_usage(mut self, mem_usage: usize) -> Self {
+        self.tt_entries = (mem_usage / size_of::<TtEntry<S>>()).max(1);
+        self
+    }
+
+    pub fn add_value_params(mut self, value_params: &'static [f32]) -> Self {
+        self.value_params = Some(value_params);
+        self
+    }
+
+    pub fn add_policy_params(mut self, policy_params: &'static [f32]) -> Self {
+        self.policy_params = Some(policy_params);
+        self
+    }
+
+    /// Maximum depth of the iterative deepening, in plies
+    pub fn max_depth(mut self, max_depth: u16) -> Self {
+        self.max_depth = max_depth.clamp(1, MAX_DEPTH);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// The score is at least this high
+    Lower,
+    /// The score is at most this high
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TtEntry<const S: usize> {
+    hash: u64,
+    best_move: Option<Move<S>>,
+    /// Score for the side to move. Proven results count plies from this position, not from the root
+    score: f32,
+    depth: u16,
+    bound: Bound,
+}
+
+/// The result of a completed iteration of the search
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchInfo<const S: usize> {
+    /// Depth of the iteration, in plies
+    pub depth: u16,
+    /// Score for the side to move at the root, in the static evaluation's units.
+    /// Proven wins and losses are scored as `WIN_SCORE` minus the number of plies
+    pub score: f32,
+    /// Nodes searched so far, including earlier iterations
+    pub nodes: u64,
+    /// The principal variation, starting with the best move
+    pub pv: Vec<Move<S>>,
+}
+
+impl<const S: usize> SearchInfo<S> {
+    pub fn best_move(&self) -> Move<S> {
+        self.pv[0]
+    }
+
+    /// Estimated winning probability for the side to move, in the same form as the MCTS engine's evaluation
+    pub fn win_probability(&self) -> f32 {
+        if self.score >= MIN_WIN_SCORE {
+            1.0
+        } else if self.score <= -MIN_WIN_SCORE {
+            0.0
+        } else {
+            cp_to_win_percentage(self.score)
+        }
+    }
+
+    /// Number of plies until the game is won, if the search has found a forced win for the side to move.
+    /// Negative if the side to move loses
+    pub fn proven_plies(&self) -> Option<i32> {
+        if self.score >= MIN_WIN_SCORE {
+            Some((WIN_SCORE - self.score) as i32)
+        } else if self.score <= -MIN_WIN_SCORE {
+            Some(-(WIN_SCORE + self.score) as i32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Limits for a single search. The search always completes at least one iteration
+struct SearchLimits<'a> {
+    start_time: Instant,
+    /// Abort at the hard limit, and do not start a new iteration after the soft limit
+    budget: Option<TimeBudget>,
+    max_nodes: Option<u64>,
+    should_stop: Option<&'a AtomicBool>,
+}
+
+/// An alpha-beta search engine. The transposition table is kept between searches
+pub struct AlphaBeta<const S: usize> {
+    settings: AlphaBetaSetting<S>,
+    transposition_table: Vec<Option<TtEntry<S>>>,
+    nodes: u64,
+    /// Set once the first iteration is complete. Until then, the search cannot be aborted
+    can_abort: bool,
+    aborted: bool,
+    /// Principal variations found at each ply of the current search
+    pv_table: Vec<Vec<Move<S>>>,
+    /// Move lists for each ply, re-used to avoid allocations
+    moves_per_ply: Vec<Vec<(Move<S>, f16)>>,
+    simple_moves: Vec<Move<S>>,
+    fcd_per_move: Vec<i8>,
+    policy_feature_sets: Vec<IncrementalPolicy<S>>,
+}
+
+impl<const S: usize> AlphaBeta<S> {
+    pub fn new(settings: AlphaBetaSetting<S>) -> Self {
+        AlphaBeta {
+            transposition_table: vec![None; settings.tt_entries],
+            settings,
+            nodes: 0,
+            can_abort: false,
+            aborted: false,
+            pv_table: vec![vec![]; MAX_DEPTH as usize + 1],
+            moves_per_ply: vec![vec![]; MAX_DEPTH as usize + 1],
+            simple_moves: vec![],
+            fcd_per_move: vec![],
+            policy_feature_sets: vec![],
+        }
+    }
+
+    pub fn settings(&self) -> &AlphaBetaSetting<S> {
+        &self.settings
+    }
+
+    /// Search to a fixed depth. Returns `None` if the game is already over
+    pub fn search_depth(&mut self, position: &Position<S>, depth: u16) -> Option<SearchInfo<S>> {
+        let max_depth = self.settings.max_depth;
+        self.settings.max_depth = depth.clamp(1, MAX_DEPTH);
+        let search_info = self.search(
+            position,
+            SearchLimits {
+                start_time: Instant::now(),
+                budget: None,
+                max_nodes: None,
+                should_stop: None,
+            },
+            |_| {},
+        );
+        self.settings.max_depth = max_depth;
+        search_info
+    }
+
+    /// Search until roughly `nodes` nodes have been searched
+    pub fn search_nodes(&mut self, position: &Position<S>, nodes: u64) -> Option<SearchInfo<S>> {
+        self.search(
+            position,
+            SearchLimits {
+                start_time: Instant::now(),
+                budget: None,
+                max_nodes: Some(nodes),
+                should_stop: None,
+            },
+            |_| {},
+        )
+    }
+
+    /// Search until the time budget runs out, or until `should_stop` is set.
+    /// No new iteration is started after the soft limit, which is extended if the best move changed in the last iteration.
+    /// `callback` is called after every completed iteration
+    pub fn search_for_time<F: FnMut(&SearchInfo<S>)>(
+        &mut self,
+        position: &Position<S>,
+        budget: TimeBudget,
+        should_stop: Option<&AtomicBool>,
+        callback: F,
+    ) -> Option<SearchInfo<S>> {
+        self.search(
+            position,
+            SearchLimits {
+                start_time: Instant::now(),
+                budget: Some(budget),
+                max_nodes: None,
+                should_stop,
+            },
+            callback,
+        )
+    }
+
+    fn search<F: FnMut(&SearchInfo<S>)>(
+        &mut self,
+        position: &Position<S>,
+        limits: SearchLimits,
+        mut callback: F,
+    ) -> Option<SearchInfo<S>> {
+        if position.game_result().is_some() {
+            return None;
+        }
+        let mut position = position.clone();
+        self.nodes = 0;
+        self.can_abort = false;
+        self.aborted = false;
+        let mut search_info: Option<SearchInfo<S>> = None;
+
+        for depth in 1..=self.settings.max_depth {
+            let score = self.negamax(&mut position, depth, 0, -WIN_SCORE, WIN_SCORE, &limits);
+            // Results from an aborted iteration are unreliable
+            if self.aborted {
+                break;
+            }
+            self.can_abort = true;
+            let iteration_info = SearchInfo {
+                depth,
+                score,
+                nodes: self.nodes,
+                pv: self.principal_variation(&mut position, depth),
+            };
+            callback(&iteration_info);
+            let is_proven = iteration_info.proven_plies().is_some();
+            let best_move_changed = search_info
+                .as_ref()
+                .is_some_and(|info| info.pv.first() != iteration_info.pv.first());
+            search_info = Some(iteration_info);
+
+            if is_proven
+                || limits.budget.is_some_and(|budget| {
+                    let instability = if best_move_changed { 1.0 } else { 0.0 };
+                    budget.should_stop(limits.start_time.elapsed(), instability)
+                })
+                || limits
+                    .should_stop
+                    .is_some_and(|should_stop| should_stop.load(Ordering::Relaxed))
+            {
+                break;
+            }
+        }
+        search_info
+    }
+
+    /// The principal variation from the last iteration.
+    /// Lines that were cut short by a transposition table hit are extended with the table's best moves
+    fn principal_variation(&self, position: &mut Position<S>, depth: u16) -> Vec<Move<S>> {
+        let mut pv = self.pv_table[0].clone();
+        let mut reverse_moves = vec![];
+        for mv in pv.iter() {
+            reverse_moves.push(position.do_move(*mv));
+        }
+        let mut legal_moves = vec![];
+        while pv.len() < depth as usize && position.game_result().is_none() {
+            let Some(mv) = self
+                .tt_entry(position.zobrist_hash())
+                .filter(|_| position.repetitions() == 0)
+                .and_then(|entry| entry.best_move)
+            else {
+                break;
+            };
+            // Guard against hash collisions
+            legal_moves.clear();
+            position.generate_moves(&mut legal_moves);
+            if !legal_moves.contains(&mv) {
+                break;
+            }
+            pv.push(mv);
+            reverse_moves.push(position.do_move(mv));
+        }
+        for reverse_move in reverse_moves.into_iter().rev() {
+            position.reverse_move(reverse_move);
+        }
+        pv
+    }
+
+    /// Check whether the search has run out of time or nodes
+    fn should_abort(&mut self, limits: &SearchLimits) -> bool {
+        if !self.can_abort {
+            return false;
+        }
+        if self.nodes.is_multiple_of(NODES_PER_CHECK) {
+            self.aborted |= limits
+                .budget
+                .is_some_and(|budget| limits.start_time.elapsed() >= budget.hard_limit)
+                || limits
+                    .should_stop
+                    .is_some_and(|should_stop| should_stop.load(Ordering::Relaxed));
+        }
+        self.aborted |= limits
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes >= max_nodes);
+        self.aborted
+    }
+
+    fn tt_entry(&self, hash: u64) -> Option<&TtEntry<S>> {
+        let index = (hash % self.transposition_table.len() as u64) as usize;
+        self.transposition_table[index]
+            .as_ref()
+            .filter(|entry| entry.hash == hash)
+    }
+
+    fn store_tt_entry(&mut self, entry: TtEntry<S>) {
+        let index = (entry.hash % self.transposition_table.len() as u64) as usize;
+        self.transposition_table[index] = Some(entry);
+    }
+
+    /// Returns the score for the side to move. The principal variation is written to `self.pv_table[ply]`
+    fn negamax(
+        &mut self,
+        position: &mut Position<S>,
+        depth: u16,
+        ply: usize,
+        mut alpha: f32,
+        beta: f32,
+        limits: &SearchLimits,
+    ) -> f32 {
+        self.nodes += 1;
+        self.pv_table[ply].clear();
+
+        let group_data = position.group_data();
+        if let Some(game_result) = position.game_result_with_group_data(&group_data) {
+            return match (game_result, position.side_to_move()) {
+                (GameResult::Draw, _) => 0.0,
+                (GameResult::WhiteWin, Color::White) | (GameResult::BlackWin, Color::Black) => {
+                    WIN_SCORE - ply as f32
+                }
+                (GameResult::WhiteWin, Color::Black) | (GameResult::BlackWin, Color::White) => {
+                    -WIN_SCORE + ply as f32
+                }
+            };
+        }
+
+        if depth == 0 || ply >= MAX_DEPTH as usize {
+            let static_eval = position.static_eval_with_params_and_data(
+                &group_data,
+                self.settings
+                    .value_params
+                    .unwrap_or_else(|| <Position<S>>::value_params(position.komi())),
+            );
+            return static_eval * position.side_to_move().multiplier() as f32;
+        }
+
+        // Repeated positions are never stored, because their result depends on the path to them
+        let hash = position.zobrist_hash();
+        let use_tt = position.repetitions() == 0;
+        let mut tt_move = None;
+        if let Some(entry) = self.tt_entry(hash).filter(|_| use_tt) {
+            tt_move = entry.best_move;
+            let score = score_from_tt(entry.score, ply);
+            if entry.depth >= depth && ply > 0 {
+                let is_cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if is_cutoff {
+                    if let Some(mv) = entry.best_move {
+                        self.pv_table[ply].push(mv);
+                    }
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = std::mem::take(&mut self.moves_per_ply[ply]);
+        moves.clear();
+        position.generate_moves_with_probabilities(
+            &group_data,
+            &mut self.simple_moves,
+            &mut moves,
+            &mut self.fcd_per_move,
+            self.settings
+                .policy_params
+                .unwrap_or_else(|| <Position<S>>::policy_params(position.komi())),
+            &mut self.policy_feature_sets,
+        );
+        moves.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+        if let Some(tt_index) =
+            tt_move.and_then(|tt_move| moves.iter().position(|(mv, _)| *mv == tt_move))
+        {
+            moves[..=tt_index].rotate_right(1);
+        }
+
+        let original_alpha = alpha;
+        let mut best_score = -WIN_SCORE;
+        let mut best_move = None;
+
+        for (mv, _) in moves.iter() {
+            if self.should_abort(limits) {
+                break;
+            }
+            let reverse_move = position.do_move(*mv);
+            let score = -self.negamax(position, depth - 1, ply + 1, -beta, -alpha, limits);
+            position.reverse_move(reverse_move);
+
+            if self.aborted {
+                break;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(*mv);
+                if score > alpha {
+                    alpha = score;
+                    let (pv, child_pvs) = self.pv_table.split_at_mut(ply + 1);
+                    pv[ply].clear();
+                    pv[ply].push(*mv);
+                    pv[ply].extend_from_slice(&child_pvs[0]);
+                }
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+        self.moves_per_ply[ply] = moves;
+
+        if use_tt && !self.aborted {
+            self.store_tt_entry(TtEntry {
+                hash,
+                best_move,
+                score: score_to_tt(best_score, ply),
+                depth,
+                bound: if best_score >= beta {
+                    Bound::Lower
+                } else if best_score > original_alpha {
+                    Bound::Exact
+                } else {
+                    Bound::Upper
+                },
+            });
+        }
+        best_score
+    }
+}
+
+/// Proven results in the table count plies from the stored position, instead of from the root
+fn score_to_tt(score: f32, ply: usize) -> f32 {
+    if score >= MIN_WIN_SCORE {
+        score + ply as f32
+    } else if score <= -MIN_WIN_SCORE {
+        score - ply as f32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: f32, ply: usize) -> f32 {
+    if score >= MIN_WIN_SCORE {
+        score - ply as f32
+    } else if score <= -MIN_WIN_SCORE {
+        score + ply as f32
+    } else {
+        score
+    }
+}
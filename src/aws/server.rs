@@ -1,5 +1,6 @@
 use crate::aws::{Event, Output, TimeControl};
 use crate::position::{Komi, Position};
+use crate::search::time::TimeBudget;
 use crate::search::MctsSetting;
 use crate::search::{self, MonteCarloTree};
 use board_game_traits::{GameResult, Position as EvalPosition};
@@ -13,9 +14,12 @@ type Error = Box<dyn std::error::Error + Sync + Send>;
 /// AWS serverside handler
 pub async fn handle_aws_event(event: LambdaEvent<Event>) -> Result<Output, Error> {
     match event.payload.size {
+        3 => handle_aws_event_generic::<3>(event.payload),
         4 => handle_aws_event_generic::<4>(event.payload),
         5 => handle_aws_event_generic::<5>(event.payload),
         6 => handle_aws_event_generic::<6>(event.payload),
+        7 => handle_aws_event_generic::<7>(event.payload),
+        8 => handle_aws_event_generic::<8>(event.payload),
         s => panic!("Unsupported board size {}", s),
     }
 }
@@ -76,15 +80,12 @@ pub fn handle_aws_event_generic<const S: usize>(e: Event) -> Result<Output, Erro
     let start_time = Instant::now();
 
     match e.time_control {
-        TimeControl::Time(time_left, increment) => {
-            let max_time = if position.half_moves_played() < 4 {
-                Duration::min(time_left / 80 + increment / 6, Duration::from_secs(40))
-            } else {
-                Duration::min(time_left / 40 + increment / 3, Duration::from_secs(40))
-            };
+        TimeControl::Time(..) | TimeControl::MovesToGo(..) => {
+            let budget = TimeBudget::for_clock(&position, e.time_control.clock().unwrap())
+                .limit(Duration::MAX, Duration::from_secs(40));
 
             let mut tree = MonteCarloTree::new(position, settings);
-            tree.search_for_time(max_time, |_| {});
+            tree.search_for_time(budget, None, |_| {});
 
             let score = 1.0 - tree.best_move().unwrap().1;
             let pv = tree.pv().map(|mv| mv.to_string()).collect();
@@ -4,7 +4,8 @@ use std::io::{Read, Write};
 use std::str::FromStr;
 #[cfg(feature = "constant-tuning")]
 use std::sync::atomic::{self, AtomicU64};
-use std::{fs, io, time};
+use std::sync::OnceLock;
+use std::{env, fs, io, thread, time};
 
 use board_game_traits::Position as PositionTrait;
 use board_game_traits::{Color, GameResult};
@@ -13,9 +14,11 @@ use pgn_traits::PgnPosition;
 #[cfg(feature = "constant-tuning")]
 use rayon::prelude::*;
 
-use tiltak::evaluation::parameters::{
-    self, IncrementalPolicy, PolicyIndexes, Value, ValueApplier, ValueIndexes,
-};
+use tiltak::evaluation::parameter_file::{ParameterFile, ParameterKind};
+use tiltak::evaluation::parameters::{self, PolicyIndexes, Value, ValueApplier, ValueIndexes};
+use tiltak::game_analysis;
+use tiltak::opening_book::OpeningBookBuilder;
+use tiltak::perft::{self, Perft, PerftSetting};
 #[cfg(feature = "sqlite")]
 use tiltak::policy_sqlite;
 use tiltak::position::Role;
@@ -25,21 +28,52 @@ use tiltak::position::{
 use tiltak::position::{Position, Stack};
 use tiltak::ptn::{Game, PtnMove};
 use tiltak::search::{cp_to_win_percentage, MctsSetting};
-use tiltak::{minmax, ptn};
+use tiltak::{minmax, ptn, solver};
 use tiltak::{position, search};
 
 #[cfg(test)]
 mod tests;
 
+/// Parameter files given with `--value-params` and `--policy-params`, used for analysis instead of the built-in parameters
+static VALUE_PARAMS_FILE: OnceLock<Option<ParameterFile>> = OnceLock::new();
+static POLICY_PARAMS_FILE: OnceLock<Option<ParameterFile>> = OnceLock::new();
+
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    for (flag, kind, parameter_file) in [
+        ("--value-params", ParameterKind::Value, &VALUE_PARAMS_FILE),
+        (
+            "--policy-params",
+            ParameterKind::Policy,
+            &POLICY_PARAMS_FILE,
+        ),
+    ] {
+        let loaded_file = args.iter().position(|arg| arg == flag).map(|i| {
+            let path = args
+                .get(i + 1)
+                .unwrap_or_else(|| panic!("Missing file name after {}", flag));
+            let loaded_file = ParameterFile::load(path, kind).unwrap_or_else(|err| {
+                panic!("Failed to read {} parameters {}: {}", kind, path, err)
+            });
+            println!(
+                "Using {} parameters from {} for {}s with komi {}",
+                kind, path, loaded_file.size, loaded_file.komi
+            );
+            loaded_file
+        });
+        parameter_file.set(loaded_file).unwrap();
+    }
+
     println!("play: Play against the engine through the command line");
     println!("aimatch: Watch the engine play against a very simple minmax implementation");
     println!("analyze <size>: Analyze a given position, provided from a PTN or a simple move list");
     println!("tps <size>: Analyze a given position, provided from a tps string");
-    println!("game <size>: Analyze a whole game, provided from a PTN or a simple move list");
-    println!(
-        "perft <size>: Generate perft numbers of a given position, provided from a tps string"
-    );
+    println!("tree <size> <komi> <file>: Continue analyzing a search tree saved in a file, or start a new one from a tps string. The tree is saved regularly");
+    println!("game <size> <komi> [ptn file]: Analyze a whole game, provided from a PTN or a simple move list. The annotated game is written to the ptn file");
+    println!("book <size> <komi> <ptn file> <book file>: Build an opening book from the games in a PTN file");
+    println!("explain <size> <komi>: Show which features drive the evaluation of a position, provided from a tps string");
+    println!("perft <size> [depth]: Generate perft numbers of a given position, provided from a tps string. With a depth, show the numbers for each move");
+    println!("perft suite: Check the move generator against the reference perft positions");
     #[cfg(feature = "sqlite")]
     println!("test_policy: Test how well policy scores find immediate wins in real games");
     loop {
@@ -67,6 +101,7 @@ fn main() {
                 }
             }
             "analyze" => match words.get(1) {
+                Some(&"3") => analyze_position_from_ptn::<3>(komi, &words[3..]),
                 Some(&"4") => analyze_position_from_ptn::<4>(komi, &words[3..]),
                 Some(&"5") => analyze_position_from_ptn::<5>(komi, &words[3..]),
                 Some(&"6") => analyze_position_from_ptn::<6>(komi, &words[3..]),
@@ -76,6 +111,7 @@ fn main() {
                 None => analyze_position_from_ptn::<5>(komi, &words[3..]),
             },
             "tps" => match words.get(1) {
+                Some(&"3") => analyze_position_from_tps::<3>(komi, &words[3..]),
                 Some(&"4") => analyze_position_from_tps::<4>(komi, &words[3..]),
                 Some(&"5") => analyze_position_from_tps::<5>(komi, &words[3..]),
                 Some(&"6") => analyze_position_from_tps::<6>(komi, &words[3..]),
@@ -84,16 +120,62 @@ fn main() {
                 Some(s) => println!("Unsupported size {}", s),
                 None => analyze_position_from_tps::<5>(komi, &words[3..]),
             },
-            "perft" => match words.get(1) {
-                Some(&"3") => perft_from_tps::<3>(),
-                Some(&"4") => perft_from_tps::<4>(),
-                Some(&"5") => perft_from_tps::<5>(),
-                Some(&"6") => perft_from_tps::<6>(),
-                Some(&"7") => perft_from_tps::<7>(),
-                Some(&"8") => perft_from_tps::<8>(),
+            "tree" => match (words.get(1), words.get(3)) {
+                (Some(&"3"), Some(path)) => analyze_tree_from_file::<3>(komi, path),
+                (Some(&"4"), Some(path)) => analyze_tree_from_file::<4>(komi, path),
+                (Some(&"5"), Some(path)) => analyze_tree_from_file::<5>(komi, path),
+                (Some(&"6"), Some(path)) => analyze_tree_from_file::<6>(komi, path),
+                (Some(&"7"), Some(path)) => analyze_tree_from_file::<7>(komi, path),
+                (Some(&"8"), Some(path)) => analyze_tree_from_file::<8>(komi, path),
+                (Some(s), Some(_)) => println!("Unsupported size {}", s),
+                _ => println!("Usage: tree <size> <komi> <file>"),
+            },
+            "book" => match (words.get(1), words.get(3), words.get(4)) {
+                (Some(&"3"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<3>(komi, ptn_path, book_path)
+                }
+                (Some(&"4"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<4>(komi, ptn_path, book_path)
+                }
+                (Some(&"5"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<5>(komi, ptn_path, book_path)
+                }
+                (Some(&"6"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<6>(komi, ptn_path, book_path)
+                }
+                (Some(&"7"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<7>(komi, ptn_path, book_path)
+                }
+                (Some(&"8"), Some(ptn_path), Some(book_path)) => {
+                    build_book_from_ptn::<8>(komi, ptn_path, book_path)
+                }
+                (Some(s), Some(_), Some(_)) => println!("Unsupported size {}", s),
+                _ => println!("Usage: book <size> <komi> <ptn file> <book file>"),
+            },
+            "explain" => match words.get(1) {
+                Some(&"3") => explain_position_from_tps::<3>(komi),
+                Some(&"4") => explain_position_from_tps::<4>(komi),
+                Some(&"5") => explain_position_from_tps::<5>(komi),
+                Some(&"6") => explain_position_from_tps::<6>(komi),
+                Some(&"7") => explain_position_from_tps::<7>(komi),
+                Some(&"8") => explain_position_from_tps::<8>(komi),
                 Some(s) => println!("Unsupported size {}", s),
-                None => perft_from_tps::<5>(),
+                None => explain_position_from_tps::<5>(komi),
             },
+            "perft" => {
+                let depth = words.get(2).and_then(|depth| depth.parse().ok());
+                match words.get(1) {
+                    Some(&"suite") => perft_reference_suite(),
+                    Some(&"3") => perft_from_tps::<3>(depth),
+                    Some(&"4") => perft_from_tps::<4>(depth),
+                    Some(&"5") => perft_from_tps::<5>(depth),
+                    Some(&"6") => perft_from_tps::<6>(depth),
+                    Some(&"7") => perft_from_tps::<7>(depth),
+                    Some(&"8") => perft_from_tps::<8>(depth),
+                    Some(s) => println!("Unsupported size {}", s),
+                    None => perft_from_tps::<5>(depth),
+                }
+            }
             #[cfg(feature = "constant-tuning")]
             "openings" => {
                 let depth = 4;
@@ -118,7 +200,7 @@ fn main() {
                         }
                         let result = (opening, search::mcts(position, 100_000));
                         let total = evaled.fetch_add(1, atomic::Ordering::Relaxed);
-                        if total % 1000 == 0 {
+                        if total.is_multiple_of(1000) {
                             eprintln!(
                                 "Evaluted {} openings in {}s",
                                 total,
@@ -144,42 +226,26 @@ fn main() {
                 return;
             }
             #[cfg(feature = "constant-tuning")]
-            "analyze_openings" => analyze_openings::<6>(komi, 500_000),
+            "analyze_openings" => analyze_openings::<6>(komi, 500_000, words.get(3).copied()),
             #[cfg(feature = "sqlite")]
             "test_policy" => policy_sqlite::check_all_games(),
             "game" => {
                 println!("Enter move list or a full PTN, then press enter followed by CTRL+D");
-                let mut input = String::new();
-
+                let output_path = words.get(3).copied();
                 match words.get(1) {
-                    Some(&"6") => {
-                        io::stdin().read_to_string(&mut input).unwrap();
-                        let games = tiltak::ptn::ptn_parser::parse_ptn(&input).unwrap();
-                        if games.is_empty() {
-                            continue;
-                        }
-                        println!("Analyzing 1 game: ");
-
-                        analyze_game::<6>(games[0].clone());
-                    }
-                    None | Some(&"5") => {
-                        io::stdin().read_to_string(&mut input).unwrap();
-                        let games = tiltak::ptn::ptn_parser::parse_ptn(&input).unwrap();
-                        if games.is_empty() {
-                            println!("Couldn't parse any games");
-                            continue;
-                        }
-                        println!("Analyzing 1 game: ");
-
-                        analyze_game::<5>(games[0].clone());
-                    }
+                    Some(&"3") => analyze_game_from_ptn::<3>(komi, output_path),
+                    Some(&"4") => analyze_game_from_ptn::<4>(komi, output_path),
+                    None | Some(&"5") => analyze_game_from_ptn::<5>(komi, output_path),
+                    Some(&"6") => analyze_game_from_ptn::<6>(komi, output_path),
+                    Some(&"7") => analyze_game_from_ptn::<7>(komi, output_path),
+                    Some(&"8") => analyze_game_from_ptn::<8>(komi, output_path),
                     Some(s) => println!("Game analysis at size {} not available", s),
                 }
             }
             "mem_usage" => mem_usage::<6>(),
-            "bench" => bench::<6>(),
-            "bench2" => bench2(),
-            "bench_mcts" => bench_mcts(),
+            "bench" => bench::<6>(words.get(1) == Some(&"tt")),
+            "bench2" => bench2(words.get(1) == Some(&"tt")),
+            "bench_mcts" => bench_mcts(words.get(1) == Some(&"tt")),
             "bench_old" => bench_old(),
             "selfplay" => mcts_selfplay(time::Duration::from_secs(10)),
             "process_ptn" => process_ptn::<6>("games_6s_2komi_all.ptn"),
@@ -190,9 +256,12 @@ fn main() {
                 }
                 let komi = Komi::from_str(words[2]).unwrap();
                 match words[1] {
+                    "3" => value_params::<3>(komi),
                     "4" => value_params::<4>(komi),
                     "5" => value_params::<5>(komi),
                     "6" => value_params::<6>(komi),
+                    "7" => value_params::<7>(komi),
+                    "8" => value_params::<8>(komi),
                     s => panic!("Unsupported size {}", s),
                 }
             }
@@ -203,9 +272,12 @@ fn main() {
                 }
                 let komi = Komi::from_str(words[2]).unwrap();
                 match words[1] {
+                    "3" => value_features::<3>(Position::start_position_with_komi(komi)),
                     "4" => value_features::<4>(Position::start_position_with_komi(komi)),
                     "5" => value_features::<5>(Position::start_position_with_komi(komi)),
                     "6" => value_features::<6>(Position::start_position_with_komi(komi)),
+                    "7" => value_features::<7>(Position::start_position_with_komi(komi)),
+                    "8" => value_features::<8>(Position::start_position_with_komi(komi)),
                     s => panic!("Unsupported size {}", s),
                 }
             }
@@ -216,9 +288,12 @@ fn main() {
                 }
                 let komi = Komi::from_str(words[2]).unwrap();
                 match words[1] {
+                    "3" => policy_params::<3>(komi),
                     "4" => policy_params::<4>(komi),
                     "5" => policy_params::<5>(komi),
                     "6" => policy_params::<6>(komi),
+                    "7" => policy_params::<7>(komi),
+                    "8" => policy_params::<8>(komi),
                     s => panic!("Unsupported size {}", s),
                 }
             }
@@ -227,14 +302,58 @@ fn main() {
     }
 }
 
+/// Analyze the first game of a PTN read from stdin. The game's komi tag is used if it has one, otherwise `komi`
+fn analyze_game_from_ptn<const S: usize>(komi: Komi, output_path: Option<&str>) {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).unwrap();
+    let mut games: Vec<Game<Position<S>>> = match tiltak::ptn::ptn_parser::parse_ptn(&input) {
+        Ok(games) => games,
+        Err(err) => {
+            println!("Couldn't parse game: {}", err);
+            return;
+        }
+    };
+    if games.is_empty() {
+        println!("Couldn't parse any games");
+        return;
+    }
+    println!("Analyzing 1 game: ");
+
+    let mut game = games.swap_remove(0);
+    match game
+        .tags
+        .iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case("Komi"))
+    {
+        Some((_, komi_str)) => match Komi::from_str(komi_str) {
+            Ok(game_komi) => game.start_position.set_komi(game_komi),
+            Err(err) => {
+                println!("Invalid komi tag: {}", err);
+                return;
+            }
+        },
+        None => {
+            game.start_position.set_komi(komi);
+            if komi != Komi::default() {
+                game.tags.push(("Komi".to_string(), komi.to_string()));
+            }
+        }
+    }
+
+    analyze_game::<S>(game, output_path);
+}
+
 fn value_features<const S: usize>(position: Position<S>) {
     let indexes: ValueIndexes<S> = parameters::value_indexes();
     let indexes_string = format!("{:?}", indexes);
 
     let params = match S {
+        3 => parameters::value_features_3s(position.komi()).as_slice(),
         4 => parameters::value_features_4s(position.komi()).as_slice(),
         5 => parameters::value_features_5s(position.komi()).as_slice(),
         6 => parameters::value_features_6s(position.komi()).as_slice(),
+        7 => parameters::value_features_7s(position.komi()).as_slice(),
+        8 => parameters::value_features_8s(position.komi()).as_slice(),
         _ => panic!("Unsupported size {}", S),
     };
 
@@ -309,9 +428,12 @@ fn value_params<const S: usize>(komi: Komi) {
     let indexes_string = format!("{:?}", indexes);
 
     let params = match S {
+        3 => parameters::value_features_3s(komi).as_slice(),
         4 => parameters::value_features_4s(komi).as_slice(),
         5 => parameters::value_features_5s(komi).as_slice(),
         6 => parameters::value_features_6s(komi).as_slice(),
+        7 => parameters::value_features_7s(komi).as_slice(),
+        8 => parameters::value_features_8s(komi).as_slice(),
         _ => panic!("Unsupported size {}", S),
     };
     let black_start_index = params.len() / 2;
@@ -347,9 +469,12 @@ fn policy_params<const S: usize>(komi: Komi) {
     let indexes_string = format!("{:?}", indexes);
 
     let params = match S {
+        3 => parameters::policy_features_3s(komi).as_slice(),
         4 => parameters::policy_features_4s(komi).as_slice(),
         5 => parameters::policy_features_5s(komi).as_slice(),
         6 => parameters::policy_features_6s(komi).as_slice(),
+        7 => parameters::policy_features_7s(komi).as_slice(),
+        8 => parameters::policy_features_8s(komi).as_slice(),
         _ => panic!("Unsupported size {}", S),
     };
 
@@ -479,7 +604,7 @@ fn process_ptn<const S: usize>(path: &str) {
 }
 
 #[cfg(feature = "constant-tuning")]
-fn analyze_openings<const S: usize>(komi: Komi, nodes: u32) {
+fn analyze_openings<const S: usize>(komi: Komi, nodes: u32, book_path: Option<&str>) {
     let mut input = String::new();
     io::stdin().read_to_string(&mut input).unwrap();
 
@@ -519,6 +644,9 @@ fn analyze_openings<const S: usize>(komi: Komi, nodes: u32) {
 
     eprintln!("Got {} truly unique openings", unique_openings.len());
 
+    let book_builder =
+        std::sync::Mutex::new(OpeningBookBuilder::<S>::new(komi).max_plies(usize::MAX));
+
     input
         .lines()
         .flat_map(|line| line.split(':').take(1))
@@ -544,6 +672,7 @@ fn analyze_openings<const S: usize>(komi: Komi, nodes: u32) {
                     }
                 }
             }
+            book_builder.lock().unwrap().add_tree(&tree);
             let pv: Vec<Move<S>> = tree.pv().take(4).collect();
             print!(
                 "{}: {:.4}, {:.1}s, ",
@@ -557,6 +686,14 @@ fn analyze_openings<const S: usize>(komi: Komi, nodes: u32) {
             }
             println!();
         });
+
+    if let Some(book_path) = book_path {
+        let book = book_builder.into_inner().unwrap().build();
+        fs::File::create(book_path)
+            .and_then(|file| book.write(file))
+            .unwrap();
+        eprintln!("Wrote {} book positions to {}", book.len(), book_path);
+    }
 }
 
 #[cfg(feature = "constant-tuning")]
@@ -736,92 +873,299 @@ fn analyze_position_from_tps<const S: usize>(komi: Komi, excluded_move_strings:
     analyze_position(&position, excluded_moves);
 }
 
-fn analyze_position<const S: usize>(position: &Position<S>, excluded_moves: Vec<Move<S>>) {
-    println!("TPS {}", position.to_fen());
-    println!("{:?}", position);
-    println!("Komi: {}", position.komi());
+/// Print the static evaluation of a position, and the policy of its most likely moves, broken down by feature group
+fn explain_position_from_tps<const S: usize>(komi: Komi) {
+    println!("Enter TPS");
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    let position = match <Position<S>>::from_fen_with_komi(input.trim(), komi) {
+        Ok(position) => position,
+        Err(err) => {
+            println!("Invalid TPS: {}", err);
+            return;
+        }
+    };
+    if let Some(result) = position.game_result() {
+        println!("The game is over: {:?}", result);
+        return;
+    }
+
+    let explanation =
+        position.explain_static_eval_with_params(analysis_value_params::<S>(position.komi()));
+    println!(
+        "Static eval: {:.3}, {:.1}%\n",
+        explanation.eval(),
+        100.0 * cp_to_win_percentage(explanation.eval())
+    );
+    println!(
+        "{:40} {:>8} {:>8} {:>8}",
+        "Value feature", "White", "Black", "Total"
+    );
+    for group in explanation.ranked() {
+        println!(
+            "{:40} {:8.3} {:8.3} {:8.3}",
+            group.name,
+            group.white,
+            group.black,
+            group.total()
+        );
+    }
+
+    let mut moves =
+        position.explain_policy_with_params(analysis_policy_params::<S>(position.komi()));
+    moves.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    for mv in moves.iter().take(5) {
+        println!(
+            "\n{}: policy score {:.3} (offset {:.3})",
+            position.move_to_san(&mv.mv),
+            mv.score(),
+            mv.offset
+        );
+        for (name, value) in mv.ranked() {
+            println!("    {:40} {:8.3}", name, value);
+        }
+    }
+}
+
+/// Load a search tree from `path` and continue analyzing it, or start a new analysis if the file does not exist.
+/// The tree is saved back to the file regularly.
+fn analyze_tree_from_file<const S: usize>(komi: Komi, path: &str) {
+    let tree = match fs::File::open(path) {
+        Ok(file) => match search::MonteCarloTree::load(file) {
+            Ok(tree) => {
+                println!(
+                    "Loaded search tree with {} visits from {}",
+                    tree.visits(),
+                    path
+                );
+                tree
+            }
+            Err(err) => {
+                println!("Failed to load search tree from {}: {}", path, err);
+                return;
+            }
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            println!("Enter TPS");
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).unwrap();
+            let position = <Position<S>>::from_fen_with_komi(&input, komi).unwrap();
+            search::MonteCarloTree::new(position.clone(), analysis_settings(&position, vec![]))
+        }
+        Err(err) => {
+            println!("Failed to open {}: {}", path, err);
+            return;
+        }
+    };
+    analyze_tree(tree, Some(path));
+}
 
-    // Change which sets of eval parameters to use in search
-    // Eval komi other than 0 or 2 will crash, since parameters are only trained for those two komi
-    // The raw search still uses the "real" komi to determine the game result at terminal nodes
-    let eval_komi = Komi::from_half_komi(match position.komi().half_komi() {
+/// Build an opening book from every game in a PTN file with the given size and komi
+fn build_book_from_ptn<const S: usize>(komi: Komi, ptn_path: &str, book_path: &str) {
+    let input = match fs::read_to_string(ptn_path) {
+        Ok(input) => input,
+        Err(err) => {
+            println!("Failed to read {}: {}", ptn_path, err);
+            return;
+        }
+    };
+    let (games, errors): (Vec<Game<Position<S>>>, _) =
+        tiltak::ptn::ptn_parser::parse_ptn_lenient(&input);
+    for error in errors.iter() {
+        println!("Skipped game: {}", error);
+    }
+    let mut builder = OpeningBookBuilder::<S>::new(komi);
+    let num_added = games.iter().filter(|game| builder.add_game(game)).count();
+    let book = builder.build();
+    println!(
+        "Added {} of {} games, for {} book positions",
+        num_added,
+        games.len(),
+        book.len()
+    );
+    if let Err(err) = fs::File::create(book_path).and_then(|file| book.write(file)) {
+        println!("Failed to write {}: {}", book_path, err);
+    }
+}
+
+// Change which sets of eval parameters to use in search
+// Eval komi other than 0 or 2 will crash, since parameters are only trained for those two komi
+// The raw search still uses the "real" komi to determine the game result at terminal nodes
+fn eval_komi(komi: Komi) -> Komi {
+    Komi::from_half_komi(match komi.half_komi() {
         -1 => 0,
         0 => 0,
         1 => 0,
         3 => 4,
         4 => 4,
         5 => 4,
-        _ => panic!("No static eval params for komi {}", position.komi()),
+        _ => panic!("No static eval params for komi {}", komi),
     })
-    .unwrap();
+    .unwrap()
+}
 
-    assert_eq!(position.game_result(), None, "Cannot analyze finished game");
+/// The value parameters for analysis: From `--value-params` if the file has the right size and komi, otherwise the built-in parameters
+fn analysis_value_params<const S: usize>(komi: Komi) -> &'static [f32] {
+    VALUE_PARAMS_FILE
+        .get()
+        .and_then(Option::as_ref)
+        .and_then(|parameter_file| parameter_file.params_for::<S>(komi))
+        .unwrap_or_else(|| <Position<S>>::value_params(eval_komi(komi)))
+}
 
-    let mut simple_moves = vec![];
-    let mut moves = vec![];
-    let mut fcd_per_move = vec![];
-
-    position.generate_moves_with_probabilities::<IncrementalPolicy<S>>(
-        &position.group_data(),
-        &mut simple_moves,
-        &mut moves,
-        &mut fcd_per_move,
-        <Position<S>>::policy_params(eval_komi),
-        &mut vec![],
-    );
-    moves.sort_by(|(_mv, score1), (_, score2)| score1.partial_cmp(score2).unwrap().reverse());
+/// The policy parameters for analysis: From `--policy-params` if the file has the right size and komi, otherwise the built-in parameters
+fn analysis_policy_params<const S: usize>(komi: Komi) -> &'static [f32] {
+    POLICY_PARAMS_FILE
+        .get()
+        .and_then(Option::as_ref)
+        .and_then(|parameter_file| parameter_file.params_for::<S>(komi))
+        .unwrap_or_else(|| <Position<S>>::policy_params(eval_komi(komi)))
+}
 
-    let settings: MctsSetting<S> = search::MctsSetting::default()
+fn analysis_settings<const S: usize>(
+    position: &Position<S>,
+    excluded_moves: Vec<Move<S>>,
+) -> MctsSetting<S> {
+    search::MctsSetting::default()
         .arena_size(2_u32.pow(30) * 3)
-        .add_policy_params(<Position<S>>::policy_params(eval_komi))
-        .add_value_params(<Position<S>>::value_params(eval_komi))
+        .add_policy_params(analysis_policy_params::<S>(position.komi()))
+        .add_value_params(analysis_value_params::<S>(position.komi()))
         // .add_rollout_depth(1000)
-        .exclude_moves(excluded_moves);
+        .exclude_moves(excluded_moves)
+        .all_threads()
+}
+
+fn analyze_position<const S: usize>(position: &Position<S>, excluded_moves: Vec<Move<S>>) {
+    let settings = analysis_settings(position, excluded_moves);
+    analyze_tree(
+        search::MonteCarloTree::new(position.clone(), settings),
+        None,
+    );
+}
+
+/// Search the tree forever, printing the analysis. If `tree_file` is given, the tree is also saved there regularly.
+fn analyze_tree<const S: usize>(mut tree: search::MonteCarloTree<S>, tree_file: Option<&str>) {
+    const SAVE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+    let position = tree.position().clone();
+    println!("TPS {}", position.to_fen());
+    println!("{:?}", position);
+    println!("Komi: {}", position.komi());
+
+    assert_eq!(position.game_result(), None, "Cannot analyze finished game");
+
     let start_time = time::Instant::now();
+    let mut last_save_time = start_time;
+
+    if let Some(road_win) = solver::road_win(&position, 3) {
+        println!(
+            "Road win in {} moves, starting with {}",
+            road_win.moves, road_win.mv
+        );
+    }
 
-    let mut tree = search::MonteCarloTree::new(position.clone(), settings);
-    for i in 1.. {
-        if let Err(err) = tree.select() {
+    loop {
+        if let Err(err) = tree.search_nodes(100_000, None) {
             println!("{err}");
             return;
         };
-        if i % 100_000 == 0 {
-            let static_eval = position
-                .static_eval_with_params(<Position<S>>::value_params(eval_komi))
-                * position.side_to_move().multiplier() as f32;
+        let static_eval = position
+            .static_eval_with_params(analysis_value_params::<S>(position.komi()))
+            * position.side_to_move().multiplier() as f32;
+        println!(
+            "{} visits, eval: {:.2}%, Wilem-style eval: {:+.2}, static eval: {:.4}, static winning probability: {:.2}%, {:.2}s",
+            tree.visits(),
+            tree.mean_action_value() * 100.0,
+            tree.mean_action_value() * 2.0 - 1.0,
+            static_eval,
+            search::cp_to_win_percentage(static_eval) * 100.0,
+            start_time.elapsed().as_secs_f64()
+        );
+        tree.print_info();
+        if let Some((mv, value)) = tree.best_move() {
+            println!("Best move: ({}, {})", mv, value);
+        }
+        if let Some(proven_result) = tree.proven_result() {
             println!(
-                "{} visits, eval: {:.2}%, Wilem-style eval: {:+.2}, static eval: {:.4}, static winning probability: {:.2}%, {:.2}s",
-                tree.visits(),
-                tree.mean_action_value() * 100.0,
-                tree.mean_action_value() * 2.0 - 1.0,
-                static_eval,
-                search::cp_to_win_percentage(static_eval) * 100.0,
-                start_time.elapsed().as_secs_f64()
+                "Proven {:?} in {} plies",
+                proven_result.result, proven_result.plies
             );
-            tree.print_info();
-            if let Some((mv, value)) = tree.best_move() {
-                println!("Best move: ({}, {})", mv, value);
+        }
+        for (i, line) in tree.multi_pv(5).iter().enumerate() {
+            println!(
+                "Line {}: {} visits, {:.2}% win probability,{} pv {}",
+                i + 1,
+                line.visits,
+                line.win_probability * 100.0,
+                line.proven_result
+                    .map(|proven_result| format!(
+                        " proven {:?} in {} plies,",
+                        proven_result.result, proven_result.plies
+                    ))
+                    .unwrap_or_default(),
+                line.pv
+                    .iter()
+                    .map(|mv| mv.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+        if let Some(path) = tree_file {
+            if last_save_time.elapsed() >= SAVE_INTERVAL {
+                match save_tree(&tree, path) {
+                    Ok(()) => println!("Saved search tree to {}", path),
+                    Err(err) => println!("Failed to save search tree to {}: {}", path, err),
+                }
+                last_save_time = time::Instant::now();
             }
         }
     }
 }
 
-fn perft_from_tps<const S: usize>() {
+/// Save the tree through a temporary file, so that the previous save is kept if the process is killed while saving
+fn save_tree<const S: usize>(tree: &search::MonteCarloTree<S>, path: &str) -> io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    tree.save(fs::File::create(&temp_path)?)?;
+    fs::rename(temp_path, path)
+}
+
+fn perft_from_tps<const S: usize>(depth: Option<u16>) {
     println!("Enter TPS (or leave empty for initial)");
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
-    let mut position = if input.trim().is_empty() {
+    let position = if input.trim().is_empty() {
         <Position<S>>::default()
     } else {
         <Position<S>>::from_fen(&input).unwrap()
     };
-    perft(&mut position);
+    let perft = Perft::new(PerftSetting::default().threads(available_threads()));
+    match depth {
+        Some(depth) => divide(&perft, &position, depth),
+        None => perft_all_depths(&perft, &position),
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
 }
 
-fn perft<const S: usize>(position: &mut Position<S>) {
+fn divide<const S: usize>(perft: &Perft, position: &Position<S>, depth: u16) {
+    let start_time = time::Instant::now();
+    let divide = perft.divide(position, depth);
+    for (mv, nodes) in divide.iter() {
+        println!("{}: {}", position.move_to_san(mv), nodes);
+    }
+    println!(
+        "Total: {}, {:.2}s",
+        divide.iter().map(|(_, nodes)| nodes).sum::<u64>(),
+        start_time.elapsed().as_secs_f32()
+    );
+}
+
+fn perft_all_depths<const S: usize>(perft: &Perft, position: &Position<S>) {
     for depth in 0.. {
         let start_time = time::Instant::now();
-        let result = position.bulk_perft(depth);
+        let result = perft.perft(position, depth);
         println!(
             "{}: {}, {:.2}s, {:.1} Mnps",
             depth,
@@ -832,45 +1176,62 @@ fn perft<const S: usize>(position: &mut Position<S>) {
     }
 }
 
-fn analyze_game<const S: usize>(game: Game<Position<S>>) {
-    let mut position = game.start_position.clone();
-    let mut ply_number = 2;
-    for PtnMove { mv, .. } in game.moves {
-        position.do_move(mv);
-        if let Some(game_result) = position.game_result() {
-            let result_string = match game_result {
-                GameResult::WhiteWin => "1-0",
-                GameResult::BlackWin => "0-1",
-                GameResult::Draw => "1/2-1/2",
-            };
-            if ply_number % 2 == 0 {
-                print!("{}. {} {}", ply_number / 2, mv, result_string);
-                io::stdout().flush().unwrap();
-            } else {
-                println!("{}... {} {}", ply_number / 2, mv, result_string);
-            }
+fn perft_reference_suite() {
+    let perft = Perft::new(PerftSetting::default().threads(available_threads()));
+    let start_time = time::Instant::now();
+    let mut num_failed = 0;
+    for reference in perft::reference_suite() {
+        let mismatches = reference.check(&perft, 1_000_000_000);
+        if mismatches.is_empty() {
+            println!("ok   {}s {}", reference.size, reference.tps);
         } else {
-            let (best_move, score) = search::mcts(position.clone(), 1_000_000);
-            if ply_number % 2 == 0 {
-                print!(
-                    "{}. {} {{{:.2}%, best reply {}}} ",
-                    ply_number / 2,
-                    position.move_to_san(&mv),
-                    (1.0 - score) * 100.0,
-                    best_move
+            num_failed += 1;
+            println!("FAIL {}s {}", reference.size, reference.tps);
+            for mismatch in mismatches {
+                println!(
+                    "     depth {}: expected {}, got {}",
+                    mismatch.depth, mismatch.expected, mismatch.actual
                 );
+            }
+        }
+    }
+    println!(
+        "{} positions failed, {:.1}s",
+        num_failed,
+        start_time.elapsed().as_secs_f32()
+    );
+}
+
+/// Analyze every move of the game, printing each move as soon as it has been analyzed.
+/// The annotated game is printed at the end, and written to `output_path` if given.
+fn analyze_game<const S: usize>(game: Game<Position<S>>, output_path: Option<&str>) {
+    let settings = analysis_settings(&game.start_position, vec![]).arena_size_for_nodes(1_000_000);
+    let annotated_game =
+        game_analysis::annotate_game(&game, settings, 1_000_000, |position, ptn_move| {
+            let move_string = format!(
+                "{}{} {{{}}}",
+                position.move_to_san(&ptn_move.mv),
+                ptn_move.annotations.concat(),
+                ptn_move.comment
+            );
+            let move_number = position.half_moves_played() / 2 + 1;
+            if position.side_to_move() == Color::White {
+                print!("{}. {} ", move_number, move_string);
                 io::stdout().flush().unwrap();
             } else {
-                println!(
-                    "{}... {} {{{:.2}%, best reply {}}}",
-                    ply_number / 2,
-                    position.move_to_san(&mv),
-                    (1.0 - score) * 100.0,
-                    best_move
-                );
+                println!("{}... {}", move_number, move_string);
             }
+        });
+    println!();
+
+    let mut ptn = vec![];
+    annotated_game.game_to_ptn(&mut ptn).unwrap();
+    println!("{}", String::from_utf8_lossy(&ptn));
+    if let Some(path) = output_path {
+        match fs::write(path, &ptn) {
+            Ok(()) => println!("Wrote annotated game to {}", path),
+            Err(err) => println!("Failed to write {}: {}", path, err),
         }
-        ply_number += 1;
     }
 }
 
@@ -928,7 +1289,7 @@ fn play_human(mut position: Position<5>) {
 
 // 2,2,22221C,2,x2/x,1,1,x,1,2/x,1,111212C,1212,x,2/x,112S,1,x,112S,2/11212,x,1,112S,1112,2/x2,1,1,1,1 1 40
 
-fn bench<const S: usize>() {
+fn bench<const S: usize>(transpositions: bool) {
     let mut position = <Position<S>>::start_position_with_komi(Komi::from_half_komi(4).unwrap());
 
     // Start the benchmark from opposite corners opening
@@ -937,20 +1298,20 @@ fn bench<const S: usize>() {
     position.do_move(Move::placement(Role::Flat, corner));
     position.do_move(Move::placement(Role::Flat, opposite_corner));
 
-    bench_position(position, 5_000_000);
+    bench_position(position, 5_000_000, transpositions);
 }
 
-fn bench2() {
+fn bench2(transpositions: bool) {
     // Position from game #618571 on Playtak, Tiltak vs Tones
     let position = <Position<6>>::from_fen_with_komi(
         "2,2,22221C,2,x2/x,1,1,x,1,2/x,1,111212C,1212,x,2/x,112S,1,x,112S,2/11212,x,1,112S,1112,2/x2,1,1,1,1 1 40",
         Komi::from_half_komi(4).unwrap()
     ).unwrap();
 
-    bench_position(position, 5_000_000);
+    bench_position(position, 5_000_000, transpositions);
 }
 
-fn bench_mcts() {
+fn bench_mcts(transpositions: bool) {
     // Position from Alion's puzzle #5. The engine solves it quickly, so this benchmark is for mcts select speed
     let position = <Position<6>>::from_fen_with_komi(
         "2,x4,11/x5,221/x,2,2,2,x,221/2,1,12C,1,21C,2/2,x,2,x2,2/x,2,2,2,x,121 1 25",
@@ -958,14 +1319,24 @@ fn bench_mcts() {
     )
     .unwrap();
 
-    bench_position(position, 20_000_000);
+    bench_position(position, 20_000_000, transpositions);
 }
 
-fn bench_position<const S: usize>(position: Position<S>, nodes: u32) {
-    println!("Starting benchmark");
+/// Search `position` for `nodes` nodes. With `transpositions`, the search shares nodes between transpositions
+fn bench_position<const S: usize>(position: Position<S>, nodes: u32, transpositions: bool) {
+    println!(
+        "Starting benchmark{}",
+        if transpositions {
+            " with transposition table"
+        } else {
+            ""
+        }
+    );
     let start_time = time::Instant::now();
 
-    let settings = search::MctsSetting::default().arena_size_for_nodes(nodes);
+    let settings = search::MctsSetting::default()
+        .arena_size_for_nodes(nodes)
+        .transposition_table(transpositions);
     let mut tree = search::MonteCarloTree::new(position, settings);
     let mut last_iteration_start_time = time::Instant::now();
     for n in 1..=nodes {
@@ -0,0 +1,333 @@
+//! Play a match between two engines, and report the Elo difference.
+//! Each engine is either built in (`mcts` or `alphabeta`), or an external TEI engine given as `tei:<command>`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+
+use tiltak::alpha_beta::AlphaBetaSetting;
+use tiltak::position::Komi;
+use tiltak::search::{MctsSetting, TimeControl};
+use tiltak::tune::match_runner::{self, MatchEngine, MatchEngineKind, MatchSettings};
+use tiltak::tune::openings::openings_from_file;
+use tiltak::tune::play_match::Player;
+use tiltak::tune::sprt::{MatchScore, Sprt, SprtResult};
+
+fn main() {
+    let matches = Command::new("Tiltak match runner")
+        .about("Play pairs of games between two engines with colors swapped, and report the Elo difference. Engines are either `mcts`, `alphabeta` or `tei:<command>` for an external TEI engine.")
+        .arg(
+            Arg::new("size")
+                .short('s')
+                .long("size")
+                .help("Board size")
+                .default_value("6")
+                .value_parser(clap::value_parser!(u64).range(3..=8)),
+        )
+        .arg(
+            Arg::new("komi")
+                .long("komi")
+                .default_value("0")
+                .allow_hyphen_values(true)
+                .value_parser(|input: &str| input.parse::<Komi>()),
+        )
+        .arg(Arg::new("engine1").long("engine1").required(true))
+        .arg(Arg::new("engine2").long("engine2").required(true))
+        .arg(
+            Arg::new("option1")
+                .long("option1")
+                .help("Set a TEI option for engine 1. Can be repeated. Example: `--option1 Hash=1024`")
+                .value_name("name=value")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("option2")
+                .long("option2")
+                .help("Set a TEI option for engine 2. Can be repeated")
+                .value_name("name=value")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("pairs")
+                .long("pairs")
+                .help("Number of game pairs to play, unless the SPRT finishes first")
+                .default_value("100")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("tc")
+                .long("tc")
+                .help("Time control in seconds, with optional increment, or a number of moves per time period. Examples: `--tc 60+0.6`, `--tc 40/60`"),
+        )
+        .arg(
+            Arg::new("nodes")
+                .long("nodes")
+                .help("Search a fixed number of nodes per move. Only for built-in engines")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .group(ArgGroup::new("time-control").args(["tc", "nodes"]).required(true))
+        .arg(
+            Arg::new("book")
+                .long("book")
+                .help("Openings file, with one opening per line. Without a book, each pair starts with two random placements")
+                .value_name("book.txt"),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .help("Number of games to play in parallel")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("sprt")
+                .long("sprt")
+                .help("Stop when the Elo difference is likely to be either elo0 or elo1. Example: `--sprt 0,5`")
+                .value_name("elo0,elo1"),
+        )
+        .arg(
+            Arg::new("alpha")
+                .long("alpha")
+                .help("SPRT false positive rate")
+                .default_value("0.05")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("beta")
+                .long("beta")
+                .help("SPRT false negative rate")
+                .default_value("0.05")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("Write the games to this PTN file")
+                .value_name("games.ptn"),
+        )
+        .get_matches();
+
+    let size = *matches.get_one::<u64>("size").unwrap() as usize;
+
+    let sprt = matches.get_one::<String>("sprt").map(|input| {
+        let Some((elo0, elo1)) = input
+            .split_once(',')
+            .and_then(|(elo0, elo1)| Some((elo0.trim().parse().ok()?, elo1.trim().parse().ok()?)))
+        else {
+            eprintln!("Error: --sprt must be two numbers, like \"0,5\"");
+            exit(1)
+        };
+        Sprt {
+            elo0,
+            elo1,
+            alpha: *matches.get_one::<f64>("alpha").unwrap(),
+            beta: *matches.get_one::<f64>("beta").unwrap(),
+        }
+    });
+
+    let time_control = match matches.get_one::<u64>("nodes") {
+        Some(nodes) => TimeControl::FixedNodes(*nodes),
+        None => {
+            let tc = matches.get_one::<String>("tc").unwrap();
+            let Some(time_control) = parse_tc(tc) else {
+                eprintln!("Error: Invalid time control \"{}\"", tc);
+                exit(1)
+            };
+            time_control
+        }
+    };
+
+    let options = MatchOptions {
+        komi: *matches.get_one::<Komi>("komi").unwrap(),
+        engine1: matches.get_one::<String>("engine1").unwrap(),
+        engine2: matches.get_one::<String>("engine2").unwrap(),
+        options1: tei_options(&matches, "option1"),
+        options2: tei_options(&matches, "option2"),
+        pairs: *matches.get_one::<u64>("pairs").unwrap() as usize,
+        time_control,
+        book: matches.get_one::<String>("book").map(String::as_str),
+        concurrency: *matches.get_one::<u64>("concurrency").unwrap() as usize,
+        sprt,
+        output: matches.get_one::<String>("output").map(Path::new),
+    };
+
+    let result = match size {
+        3 => run::<3>(options),
+        4 => run::<4>(options),
+        5 => run::<5>(options),
+        6 => run::<6>(options),
+        7 => run::<7>(options),
+        8 => run::<8>(options),
+        _ => unreachable!(),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        exit(1)
+    }
+}
+
+struct MatchOptions<'a> {
+    komi: Komi,
+    engine1: &'a str,
+    engine2: &'a str,
+    options1: Vec<(String, String)>,
+    options2: Vec<(String, String)>,
+    pairs: usize,
+    time_control: TimeControl,
+    book: Option<&'a str>,
+    concurrency: usize,
+    sprt: Option<Sprt>,
+    output: Option<&'a Path>,
+}
+
+fn tei_options(matches: &ArgMatches, id: &str) -> Vec<(String, String)> {
+    matches
+        .get_many::<String>(id)
+        .unwrap_or_default()
+        .map(|option| match option.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                eprintln!("Error: TEI option \"{}\" must be name=value", option);
+                exit(1)
+            }
+        })
+        .collect()
+}
+
+/// Parse a time control like `60+0.6`, or `40/60` for 60 seconds per 40 moves
+fn parse_tc(input: &str) -> Option<TimeControl> {
+    if let Some((moves, time)) = input.split_once('/') {
+        let moves = moves.parse().ok().filter(|moves| *moves > 0)?;
+        return Some(TimeControl::MovesToGo(
+            Duration::try_from_secs_f64(time.parse().ok()?).ok()?,
+            moves,
+        ));
+    }
+    let (time, increment) = input.split_once('+').unwrap_or((input, "0"));
+    Some(TimeControl::Time(
+        Duration::try_from_secs_f64(time.parse().ok()?).ok()?,
+        Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+    ))
+}
+
+fn parse_engine<const S: usize>(
+    input: &str,
+    options: Vec<(String, String)>,
+    time_control: &TimeControl,
+) -> io::Result<MatchEngine<S>> {
+    let kind = match input {
+        "mcts" => {
+            // Give enough memory for the longest possible search, at roughly 200K nps
+            let max_nodes = match time_control {
+                TimeControl::FixedNodes(nodes) => *nodes,
+                TimeControl::Time(time, increment) => {
+                    ((*time / 2 + *increment).as_secs_f64() * 200_000.0) as u64
+                }
+                TimeControl::MovesToGo(time, _) => (time.as_secs_f64() * 200_000.0) as u64,
+            };
+            let settings = MctsSetting::default()
+                .arena_size_for_nodes(max_nodes.min(2_u64.pow(31) / (S * S * 3) as u64) as u32);
+            MatchEngineKind::InProcess(Player::Mcts(settings))
+        }
+        "alphabeta" => MatchEngineKind::InProcess(Player::AlphaBeta(AlphaBetaSetting::default())),
+        _ => {
+            let mut words = input
+                .strip_prefix("tei:")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(ToString::to_string);
+            let Some(program) = words.next() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Unknown engine \"{}\", expected mcts, alphabeta or tei:<command>",
+                        input
+                    ),
+                ));
+            };
+            MatchEngineKind::Tei {
+                program,
+                args: words.collect(),
+                options,
+            }
+        }
+    };
+    Ok(MatchEngine {
+        name: input.strip_prefix("tei:").unwrap_or(input).to_string(),
+        kind,
+    })
+}
+
+fn print_score(score: &MatchScore, sprt: Option<&Sprt>) {
+    print!(
+        "{} games, {}, Elo {:.1} +/- {:.1}, LOS {:.1}%",
+        score.games(),
+        score,
+        score.elo(),
+        score.elo_error(),
+        score.los() * 100.0
+    );
+    if let Some(sprt) = sprt {
+        let (lower_bound, upper_bound) = sprt.bounds();
+        print!(
+            ", LLR {:.2} ({:.2}, {:.2})",
+            sprt.llr(score),
+            lower_bound,
+            upper_bound
+        );
+    }
+    println!();
+}
+
+fn run<const S: usize>(options: MatchOptions) -> io::Result<()> {
+    let engine1 = parse_engine::<S>(options.engine1, options.options1, &options.time_control)?;
+    let engine2 = parse_engine::<S>(options.engine2, options.options2, &options.time_control)?;
+    let openings = match options.book {
+        Some(path) => openings_from_file::<S>(path, options.komi)?,
+        None => vec![],
+    };
+    let settings = MatchSettings {
+        komi: options.komi,
+        time_control: options.time_control,
+        openings,
+        pairs: options.pairs,
+        concurrency: options.concurrency,
+        sprt: options.sprt,
+    };
+    let mut output = options.output.map(fs::File::create).transpose()?;
+
+    println!(
+        "{} vs {}, {} pairs, {}s, {} komi",
+        engine1.name, engine2.name, settings.pairs, S, settings.komi
+    );
+    let mut write_result = Ok(());
+    let result = match_runner::run_match(&engine1, &engine2, &settings, |pair_result, score| {
+        print!("Pair {}: ", pair_result.pair_number + 1);
+        print_score(score, settings.sprt.as_ref());
+        if let Some(output) = output.as_mut() {
+            for game in pair_result.games.iter() {
+                if write_result.is_ok() {
+                    write_result = game
+                        .game_to_ptn(output)
+                        .and_then(|()| writeln!(output))
+                        .and_then(|()| output.flush());
+                }
+            }
+        }
+    })?;
+    write_result?;
+
+    print!("Final result: ");
+    print_score(&result.score, settings.sprt.as_ref());
+    match result.sprt_result {
+        Some(SprtResult::AcceptH0) => println!("SPRT: H0 accepted"),
+        Some(SprtResult::AcceptH1) => println!("SPRT: H1 accepted"),
+        Some(SprtResult::Continue) => println!("SPRT: inconclusive"),
+        None => (),
+    }
+    Ok(())
+}
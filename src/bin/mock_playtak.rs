@@ -0,0 +1,92 @@
+//! A local stand-in for the playtak.com server. Clients that seek a game get a game against a random mover.
+//! Run the `playtak` bot against it with `--playtak-base-url 127.0.0.1 --playtak-port <port>`.
+
+use std::io;
+
+use clap::{Arg, Command};
+use tiltak::mock_playtak::{ClientMessage, GameStart, MockClient, MockPlaytakServer};
+
+const OPPONENT_NAME: &str = "RandomBot";
+
+pub fn main() -> io::Result<()> {
+    let matches = Command::new("Mock Playtak server")
+        .about("Stand-in for the playtak.com server, for testing the playtak bot locally")
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .help("Network port to listen on")
+                .default_value("10000")
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("games")
+                .long("games")
+                .help("Exit after this many games. Play forever if not set")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .get_matches();
+
+    let port = *matches.get_one::<u16>("port").unwrap();
+    let max_games = matches.get_one::<u64>("games").copied();
+
+    let mut server = MockPlaytakServer::bind(("127.0.0.1", port))?;
+    println!("Listening on {}", server.local_addr()?);
+
+    let mut games_played = 0;
+    while max_games.is_none_or(|max_games| games_played < max_games) {
+        let mut client = server.accept()?;
+        println!("{} logged in", client.name());
+        match serve_client(&mut client, &mut games_played, max_games) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                println!("{} disconnected", client.name())
+            }
+            Err(err) => println!("Error from {}: {}", client.name(), err),
+        }
+    }
+    Ok(())
+}
+
+/// Play games against the client whenever it seeks, until it disconnects or enough games have been played
+fn serve_client(
+    client: &mut MockClient,
+    games_played: &mut u64,
+    max_games: Option<u64>,
+) -> io::Result<()> {
+    let mut rng = rand::thread_rng();
+    client.send_online(2)?;
+    while max_games.is_none_or(|max_games| *games_played < max_games) {
+        match client.read_message()? {
+            ClientMessage::Seek(seek) => {
+                let game =
+                    GameStart::from_seek(*games_played + 1, &seek, client.name(), OPPONENT_NAME);
+                println!(
+                    "Game #{}: {} vs {}, {}s, {} komi",
+                    game.game_no, game.white_player, game.black_player, seek.size, seek.komi
+                );
+                let result = match seek.size {
+                    3 => client.play_random_game::<3, _>(&game, &mut rng)?,
+                    4 => client.play_random_game::<4, _>(&game, &mut rng)?,
+                    5 => client.play_random_game::<5, _>(&game, &mut rng)?,
+                    6 => client.play_random_game::<6, _>(&game, &mut rng)?,
+                    7 => client.play_random_game::<7, _>(&game, &mut rng)?,
+                    8 => client.play_random_game::<8, _>(&game, &mut rng)?,
+                    size => {
+                        client.send_line("NOK")?;
+                        println!("Unsupported size {}", size);
+                        continue;
+                    }
+                };
+                println!("Game #{} finished: {}", game.game_no, result);
+                *games_played += 1;
+            }
+            ClientMessage::Tell { receiver, message } => {
+                println!("{} tells {}: {}", client.name(), receiver, message)
+            }
+            ClientMessage::Shout(message) => println!("{} shouts: {}", client.name(), message),
+            ClientMessage::Accept(_) | ClientMessage::Move { .. } => client.send_line("NOK")?,
+            ClientMessage::Other(_) => (),
+        }
+    }
+    Ok(())
+}
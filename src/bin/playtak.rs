@@ -1,9 +1,13 @@
 use std::convert::Infallible;
 use std::io::{BufRead, Result, Write};
 use std::net::TcpStream;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
-use std::{io, net, thread};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::{fs, io, net, thread};
 
 use board_game_traits::{Color, GameResult, Position as PositionTrait};
 use bufstream::BufStream;
@@ -15,11 +19,14 @@ use pgn_traits::PgnPosition;
 
 use rand::seq::SliceRandom;
 use rand::Rng;
+use tiltak::evaluation::parameter_file::{ParameterFile, ParameterKind};
+use tiltak::opening_book::OpeningBook;
 use tiltak::position;
 use tiltak::position::{squares_iterator, Move, Role, Square};
 use tiltak::position::{Komi, Position};
 use tiltak::ptn::{Game, PtnMove};
 use tiltak::search;
+use tiltak::search::time::{Clock, TimeBudget};
 use tiltak::search::MctsSetting;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -40,13 +47,36 @@ pub struct PlaytakSettings {
     komi: Komi,
     opening_value_variance: Option<f32>,
     middlegame_value_variance: Option<f32>,
+    threads: usize,
+    ponder: bool,
+    /// Loaded once at startup, and kept for the rest of the program
+    opening_book: Option<&'static OpeningBook>,
+    /// Directory where finished games are written as PTN files
+    archive_dir: Option<&'static Path>,
+    /// Loaded once at startup. Only used in games with the file's size and komi
+    value_params: Option<&'static ParameterFile>,
+    policy_params: Option<&'static ParameterFile>,
 }
 
 impl PlaytakSettings {
-    pub fn to_mcts_setting<const S: usize>(&self, ply_number: usize) -> MctsSetting<S> {
+    pub fn to_mcts_setting<const S: usize>(&self, ply_number: usize, komi: Komi) -> MctsSetting<S> {
         let mut settings = MctsSetting::default()
             .add_rollout_depth(self.rollout_depth)
-            .add_rollout_temperature(self.rollout_temperature);
+            .add_rollout_temperature(self.rollout_temperature)
+            .threads(self.threads);
+
+        if let Some(params) = self
+            .value_params
+            .and_then(|file| file.params_for::<S>(komi))
+        {
+            settings = settings.add_value_params(params);
+        }
+        if let Some(params) = self
+            .policy_params
+            .and_then(|file| file.params_for::<S>(komi))
+        {
+            settings = settings.add_policy_params(params);
+        }
 
         if let Some(dirichlet) = self.dirichlet_noise {
             settings = settings.add_dirichlet(dirichlet);
@@ -95,7 +125,7 @@ pub fn main() -> Result<()> {
                 .help("Board size")
                 .num_args(1)
                 .default_value("5")
-                .value_parser(clap::value_parser!(u64).range(4..=8)),
+                .value_parser(clap::value_parser!(u64).range(3..=8)),
         )
         .arg(
             Arg::new("logfile")
@@ -223,7 +253,39 @@ pub fn main() -> Result<()> {
             .help("Network port to connect to")
             .num_args(1)
             .default_value("10000")
-            .value_parser(clap::value_parser!(u16)));
+            .value_parser(clap::value_parser!(u16)))
+        .arg(Arg::new("threads")
+            .long("threads")
+            .env("THREADS")
+            .help("Number of search threads. Defaults to the number of logical cores")
+            .num_args(1)
+            .value_parser(clap::value_parser!(u64).range(1..)))
+        .arg(Arg::new("ponder")
+            .long("ponder")
+            .env("PONDER")
+            .help("Keep searching while waiting for the opponent's move. If the opponent plays a move that was searched, the search tree is kept. Has no effect with --fixed-nodes")
+            .action(ArgAction::SetTrue)
+            .num_args(0))
+        .arg(Arg::new("book")
+            .long("book")
+            .env("BOOK")
+            .help("Opening book file. Book moves are played instantly, in games with the book's size and komi")
+            .num_args(1))
+        .arg(Arg::new("archiveDir")
+            .long("archive-dir")
+            .env("ARCHIVE_DIR")
+            .help("Write every finished game as a PTN file in this directory, with clock times and the engine's evaluations")
+            .num_args(1))
+        .arg(Arg::new("valueParams")
+            .long("value-params")
+            .env("VALUE_PARAMS")
+            .help("Value parameter file, used instead of the compiled-in parameters in games with the file's size and komi")
+            .num_args(1))
+        .arg(Arg::new("policyParams")
+            .long("policy-params")
+            .env("POLICY_PARAMS")
+            .help("Policy parameter file, used instead of the compiled-in parameters in games with the file's size and komi")
+            .num_args(1));
 
     let matches = app.get_matches();
 
@@ -328,6 +390,38 @@ pub fn main() -> Result<()> {
 
     let seek_unrated = matches.get_flag("seekUnrated");
 
+    let threads = matches
+        .get_one::<u64>("threads")
+        .map(|threads| *threads as usize)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+
+    let opening_book: Option<&'static OpeningBook> =
+        matches.get_one::<String>("book").map(|path| {
+            let book = fs::File::open(path)
+                .and_then(OpeningBook::read)
+                .unwrap_or_else(|err| panic!("Failed to read opening book {}: {}", path, err));
+            &*Box::leak(Box::new(book))
+        });
+
+    let archive_dir: Option<&'static Path> = matches.get_one::<String>("archiveDir").map(|path| {
+        fs::create_dir_all(path)
+            .unwrap_or_else(|err| panic!("Failed to create archive dir {}: {}", path, err));
+        &*Box::leak(PathBuf::from(path).into_boxed_path())
+    });
+
+    let [value_params, policy_params]: [Option<&'static ParameterFile>; 2] = [
+        ("valueParams", ParameterKind::Value),
+        ("policyParams", ParameterKind::Policy),
+    ]
+    .map(|(arg, kind)| {
+        matches.get_one::<String>(arg).map(|path| {
+            let parameter_file = ParameterFile::load(path, kind).unwrap_or_else(|err| {
+                panic!("Failed to read {} parameters {}: {}", kind, path, err)
+            });
+            &*Box::leak(Box::new(parameter_file))
+        })
+    });
+
     let playtak_base_url = matches.get_one::<String>("playtakBaseUrl").unwrap();
     let playtak_port = *matches.get_one::<u16>("playtakPort").unwrap();
 
@@ -350,6 +444,12 @@ pub fn main() -> Result<()> {
         komi,
         opening_value_variance: opening_value_noise,
         middlegame_value_variance: middlegame_value_noise,
+        threads,
+        ponder: matches.get_flag("ponder"),
+        opening_book,
+        archive_dir,
+        value_params,
+        policy_params,
     };
 
     loop {
@@ -379,9 +479,12 @@ pub fn main() -> Result<()> {
         let error = match matches.get_one::<String>("playBot") {
             Some(bot_name) => {
                 match match size {
+                    3 => session.accept_seek::<3>(playtak_settings, bot_name),
                     4 => session.accept_seek::<4>(playtak_settings, bot_name),
                     5 => session.accept_seek::<5>(playtak_settings, bot_name),
                     6 => session.accept_seek::<6>(playtak_settings, bot_name),
+                    7 => session.accept_seek::<7>(playtak_settings, bot_name),
+                    8 => session.accept_seek::<8>(playtak_settings, bot_name),
                     s => panic!("Unsupported size {}", s),
                 } {
                     Ok(_game) => return Ok(()),
@@ -389,7 +492,7 @@ pub fn main() -> Result<()> {
                 }
             }
             None => match size {
-                4..=6 => session.seek_playtak_games(playtak_settings),
+                3..=8 => session.seek_playtak_games(playtak_settings),
                 s => panic!("Unsupported size {}", s),
             }
             .unwrap_err(),
@@ -488,14 +591,17 @@ impl<'a> ChatCommand<'a> {
 
     pub fn process_size_command(&self, session: &mut PlaytakSession) -> Result<Option<usize>> {
         let next_game_size = match self.argument {
+            Some("3") => 3,
             Some("4") => 4,
             Some("5") => 5,
             Some("6") => 6,
+            Some("7") => 7,
+            Some("8") => 8,
             s => {
                 self.respond(
                     session,
                     &format!(
-                        "Unsupported size {}. Must be 4, 5 or 6",
+                        "Unsupported size {}. Must be between 3 and 8",
                         s.unwrap_or_default()
                     ),
                 )?;
@@ -698,6 +804,11 @@ impl PlaytakSession {
                         playtak_settings.seek_increment,
                     );
                     let (updated_seek_size, updated_seek_color) = match playtak_game.size {
+                        3 => self.play_game::<3>(
+                            playtak_game,
+                            playtak_settings,
+                            restoring_previous_session,
+                        )?,
                         4 => self.play_game::<4>(
                             playtak_game,
                             playtak_settings,
@@ -713,6 +824,16 @@ impl PlaytakSession {
                             playtak_settings,
                             restoring_previous_session,
                         )?,
+                        7 => self.play_game::<7>(
+                            playtak_game,
+                            playtak_settings,
+                            restoring_previous_session,
+                        )?,
+                        8 => self.play_game::<8>(
+                            playtak_game,
+                            playtak_settings,
+                            restoring_previous_session,
+                        )?,
                         s => panic!("Unsupported size {}", s),
                     };
                     restoring_previous_session = false;
@@ -822,40 +943,66 @@ impl PlaytakSession {
             game.increment.as_secs_f32(),
             game.komi
         );
+        for parameter_file in [
+            playtak_settings.value_params,
+            playtak_settings.policy_params,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if parameter_file.size != S || parameter_file.komi != game.komi {
+                warn!(
+                    "{} parameters are for {}s with komi {}, using the built-in parameters for this game",
+                    parameter_file.kind, parameter_file.size, parameter_file.komi
+                );
+            }
+        }
         let mut next_seek_size = playtak_settings.default_seek_size;
         let mut next_seek_color = playtak_settings.default_seek_color;
         let mut position = <Position<S>>::start_position_with_komi(game.komi);
         let mut moves = vec![];
+        // White's and black's time left, with the number of moves played when the server sent them.
+        // The server sends them after every move, but they may not be read until after our next move
+        let mut clock_times: Vec<(usize, Duration, Duration)> = vec![];
+        // The opponent's last move, if the server has not yet sent the clocks after it
+        let mut unclocked_move_ply: Option<usize> = None;
+        let mut server_result: Option<&'static str> = None;
         let mut our_time_left = game.time_left;
+        // Search tree from our last move, which is re-used if the opponent plays a move we have searched
+        let mut previous_tree: Option<search::MonteCarloTree<S>> = None;
         'gameloop: loop {
+            let mut game_over = false;
             if position.game_result().is_some() {
                 // Double check that the game is still over, if we remove information about move repetitions
                 // Playtak does not have this rule, so we want to play on, even if the position is a repetition
                 let position_without_history = <Position<S>>::from_fen(&position.to_fen()).unwrap();
                 if position_without_history.game_result().is_some() {
-                    break;
+                    // Keep reading until the server ends the game, to get the clocks after the last move
+                    game_over = true;
                 } else {
                     position = position_without_history;
                 }
             }
-            if position.side_to_move() == game.our_color && !restoring_previous_session {
-                let (best_move, score) =
+            if position.side_to_move() == game.our_color
+                && !restoring_previous_session
+                && !game_over
+            {
+                let (best_move, comment) =
                     // On the very first move, always place instantly in a random corner
                     if squares_iterator::<S>().all(|square| position.stack_heights()[square] == 0) {
                         let mut rng = rand::thread_rng();
                         let corner_placements: Vec<Move<S>> = Square::corners().into_iter().map(|square| Move::placement(Role::Flat, square)).collect();
 
-                        (*corner_placements.choose(&mut rng).unwrap(), 0.0)
+                        (*corner_placements.choose(&mut rng).unwrap(), String::new())
+                    } else if let Some(book_move) = playtak_settings.opening_book.and_then(|book| book.probe(&position, &mut rand::thread_rng())) {
+                        (book_move, "book".to_string())
                     } else if let Some(fixed_nodes) = playtak_settings.fixed_nodes {
                         let settings =
-                            playtak_settings.to_mcts_setting(position.half_moves_played())
+                            playtak_settings.to_mcts_setting(position.half_moves_played(), position.komi())
                             .arena_size_for_nodes(fixed_nodes as u32);
                         let mut tree = search::MonteCarloTree::new(position.clone(), settings);
-                        for _ in 0..fixed_nodes {
-                            if let Err(err) = tree.select() {
-                                eprintln!("Warning: {err}");
-                                break;
-                            }
+                        if let Err(err) = tree.search_nodes(fixed_nodes, None) {
+                            eprintln!("Warning: {err}");
                         }
 
                         // Wait for a bit
@@ -863,25 +1010,28 @@ impl PlaytakSession {
                         let sleep_duration = Duration::from_millis(rng.gen_range(1000..2500));
                         thread::sleep(sleep_duration);
 
-                        tree.best_move().unwrap()
+                        let (best_move, score) = tree.best_move().unwrap();
+                        (best_move, search_comment(&tree, score))
                     } else {
                         {
-                            let maximum_time = if let Some(target_move_time) =  playtak_settings.target_move_time {
-                                if let Some((trigger_move, _)) = playtak_settings.extra_time {
-                                    if position.half_moves_played() / 2 > trigger_move as usize {
-                                        (our_time_left / 6 + game.increment / 2).min(6 * target_move_time)
-                                    } else {
-                                        (our_time_left / 6 + game.increment / 2).min(2 * target_move_time)
-                                    }
-                                } else {
-                                (our_time_left / 6 + game.increment / 2).min(2 * target_move_time)
-                                }
-                            } else {
-                                our_time_left / 6 + game.increment / 2
+                            let clock = Clock {
+                                time_left: our_time_left,
+                                increment: game.increment,
+                                moves_to_go: None,
                             };
+                            let mut budget = TimeBudget::for_clock(&position, clock);
+                            if let Some(target_move_time) = playtak_settings.target_move_time {
+                                let max_time_factor = match playtak_settings.extra_time {
+                                    Some((trigger_move, _)) if position.half_moves_played() / 2 > trigger_move as usize => 6,
+                                    _ => 2,
+                                };
+                                budget = budget.limit(target_move_time, max_time_factor * target_move_time);
+                            }
 
-                            // Give enough memory for a CPU calculating at roughly 200K nps.
-                            let max_nodes = (maximum_time.as_secs() as u32).saturating_mul(200_000);
+                            // Give enough memory for a CPU calculating at roughly 200K nps per thread.
+                            let max_nodes = (budget.hard_limit.as_secs() as u32)
+                                .saturating_mul(200_000)
+                                .saturating_mul(playtak_settings.threads as u32);
 
                             // For 6s, the toughest position I've found required 40 elements/node searched
                             // This formula gives 72, which is hopefully plenty
@@ -893,10 +1043,17 @@ impl PlaytakSession {
                             };
 
                             let settings =
-                                playtak_settings.to_mcts_setting(position.half_moves_played())
+                                playtak_settings.to_mcts_setting(position.half_moves_played(), position.komi())
                                 .arena_size(max_arena_size.min(2_u32.pow(31)));
 
-                            search::play_move_time(position.clone(), maximum_time, settings)
+                            // Re-using the previous tree takes time, which counts against the time budget
+                            let setup_start_time = Instant::now();
+                            let mut tree = search::MonteCarloTree::from_previous(previous_tree.take(), position.clone(), settings);
+                            tree.search_for_time(budget.saturating_sub(setup_start_time.elapsed()), None, |_| {});
+                            let (best_move, score) = tree.best_move().unwrap();
+                            let comment = search_comment(&tree, score);
+                            previous_tree = Some(tree);
+                            (best_move, comment)
                         }
                     };
 
@@ -904,7 +1061,8 @@ impl PlaytakSession {
                 moves.push(PtnMove {
                     mv: best_move,
                     annotations: vec![],
-                    comment: score.to_string(),
+                    comment,
+                    variations: vec![],
                 });
 
                 let output_string =
@@ -932,6 +1090,18 @@ impl PlaytakSession {
                     }
                 }
             } else {
+                // Think on the opponent's time, by searching the position until the opponent has moved
+                let ponder_search =
+                    if playtak_settings.ponder && !restoring_previous_session && !game_over {
+                        previous_tree.take().and_then(|mut tree| {
+                            let settings = tree.settings().clone();
+                            tree.reroot(&position, settings)
+                                .then(|| PonderSearch::start(tree))
+                        })
+                    } else {
+                        None
+                    };
+
                 // Wait for the opponent's move. The server may send other messages in the meantime
                 loop {
                     let line = self.read_line()?;
@@ -978,8 +1148,10 @@ impl PlaytakSession {
                                 moves.push(PtnMove {
                                     mv: move_played,
                                     annotations: vec![],
-                                    comment: "0.0".to_string(),
+                                    comment: String::new(),
+                                    variations: vec![],
                                 });
+                                unclocked_move_ply = Some(moves.len());
                                 break;
                             }
                             "Time" => {
@@ -991,14 +1163,32 @@ impl PlaytakSession {
                                     Color::White => white_time_left,
                                     Color::Black => black_time_left,
                                 };
+                                // The clocks after the opponent's move may arrive after we have replied to it
+                                let ply = unclocked_move_ply.take().unwrap_or(moves.len());
+                                clock_times.push((ply, white_time_left, black_time_left));
                             }
-                            "Abandoned" | "Abandoned." | "Over" => break 'gameloop,
+                            "Over" => {
+                                // Also covers results that can't be seen from the position, like time losses
+                                server_result = words.get(2).and_then(|result| {
+                                    <Position<S>>::POSSIBLE_GAME_RESULTS
+                                        .iter()
+                                        .find(|(result_str, _)| result_str == result)
+                                        .map(|(result_str, _)| *result_str)
+                                });
+                                break 'gameloop;
+                            }
+                            "Abandoned" | "Abandoned." => break 'gameloop,
                             _ => debug!("Ignoring server message \"{}\"", line),
                         }
                     } else if words[0] == "NOK" {
                         warn!("Received NOK from server, ignoring.");
                     }
                 }
+                if let Some(ponder_search) = ponder_search {
+                    let tree = ponder_search.stop();
+                    debug!("Pondered to {} visits", tree.visits());
+                    previous_tree = Some(tree);
+                }
             }
         }
 
@@ -1017,18 +1207,71 @@ impl PlaytakSession {
                 format!("{}.{:0>2}.{:0>2}", date.year(), date.month(), date.day()),
             ),
             ("Komi".to_string(), position.komi().to_string()),
+            (
+                "Clock".to_string(),
+                format!(
+                    "{}:{:0>2} +{}",
+                    game.time_left.as_secs() / 60,
+                    game.time_left.as_secs() % 60,
+                    game.increment.as_secs_f32()
+                ),
+            ),
         ];
 
-        let game = Game {
+        // If the server sent the clocks more than once for a move, the last ones are used
+        let mut move_clocks: Vec<Option<Duration>> = vec![None; moves.len()];
+        for (ply, white_time_left, black_time_left) in clock_times {
+            // Clocks sent before the first move are not attached to any move
+            if let Some(move_clock) = ply.checked_sub(1).and_then(|i| move_clocks.get_mut(i)) {
+                *move_clock = Some(if ply % 2 == 1 {
+                    white_time_left
+                } else {
+                    black_time_left
+                });
+            }
+        }
+
+        let mut ptn_moves = moves.clone();
+        for (ptn_move, time_left) in ptn_moves
+            .iter_mut()
+            .zip(move_clocks)
+            .filter_map(|(ptn_move, time_left)| Some((ptn_move, time_left?)))
+        {
+            let clock_comment = format!("[%clk {}]", format_clock_time(time_left));
+            if ptn_move.comment.is_empty() {
+                ptn_move.comment = clock_comment;
+            } else {
+                ptn_move.comment = format!("{} {}", clock_comment, ptn_move.comment);
+            }
+        }
+
+        let ptn_game = Game {
             start_position: <Position<S>>::start_position(),
-            moves: moves.clone(),
-            game_result_str: position.pgn_game_result(),
+            moves: ptn_moves,
+            game_result_str: position.pgn_game_result().or(server_result),
             tags,
+            comment: String::new(),
         };
 
         let mut ptn = Vec::new();
 
-        game.game_to_ptn(&mut ptn)?;
+        ptn_game.game_to_ptn(&mut ptn)?;
+
+        if let Some(archive_dir) = playtak_settings.archive_dir {
+            let file_name = format!(
+                "{}_game{}_{}_vs_{}.ptn",
+                date.format("%Y-%m-%d_%H-%M-%S"),
+                game.game_no,
+                game.white_player,
+                game.black_player
+            );
+            let path = archive_dir.join(file_name);
+            // Failing to archive a game is not a reason to stop playing
+            match fs::write(&path, &ptn) {
+                Ok(()) => info!("Wrote game to {}", path.display()),
+                Err(err) => warn!("Failed to write game to {}: {}", path.display(), err),
+            }
+        }
 
         info!("{}", String::from_utf8(ptn).unwrap());
 
@@ -1042,6 +1285,57 @@ impl PlaytakSession {
     }
 }
 
+/// Comment for a searched move, with the win probability and the principal variation
+fn search_comment<const S: usize>(tree: &search::MonteCarloTree<S>, score: f32) -> String {
+    let pv: Vec<String> = tree.pv().take(8).map(|mv| mv.to_string()).collect();
+    format!("{:.1}%, pv {}", score * 100.0, pv.join(" "))
+}
+
+/// Format a clock time as `h:mm:ss`
+fn format_clock_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:0>2}:{:0>2}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// A search running in the background, while waiting for the opponent's move
+struct PonderSearch<const S: usize> {
+    should_stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<search::MonteCarloTree<S>>>,
+}
+
+impl<const S: usize> PonderSearch<S> {
+    fn start(mut tree: search::MonteCarloTree<S>) -> Self {
+        let should_stop = Arc::new(AtomicBool::new(false));
+        let should_stop_clone = should_stop.clone();
+        let handle = thread::spawn(move || {
+            if let Err(err) = tree.search_nodes(u64::MAX, Some(&should_stop_clone)) {
+                warn!("Stopped pondering: {err}");
+            }
+            tree
+        });
+        PonderSearch {
+            should_stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop the search, and return the search tree
+    fn stop(mut self) -> search::MonteCarloTree<S> {
+        self.should_stop.store(true, Ordering::Relaxed);
+        self.handle.take().unwrap().join().unwrap()
+    }
+}
+
+impl<const S: usize> Drop for PonderSearch<S> {
+    fn drop(&mut self) {
+        // Make sure the search does not keep running if the game ends while pondering
+        self.should_stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
 fn connect(playtak_url: &str) -> Result<BufStream<TcpStream>> {
     let connection = dial(playtak_url)?;
     Ok(connection)
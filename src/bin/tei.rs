@@ -1,308 +1,1064 @@
-use board_game_traits::{Color, Position as PositionTrait};
+use board_game_traits::Position as PositionTrait;
 use pgn_traits::PgnPosition;
 use std::io::{BufRead, BufReader};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
-use std::{env, io};
+use std::{env, fs, io};
 use tiltak::position::{Komi, Position};
+use tiltak::tei::{GoCommand, GoTimeControl, PositionCommand, TeiCommand, TeiError};
 
 use std::any::Any;
-use tiltak::search::{self, MctsSetting, MonteCarloTree};
+use tiltak::alpha_beta::{AlphaBeta, AlphaBetaSetting, SearchInfo};
+use tiltak::evaluation::network::{NetworkFile, NetworkKind};
+use tiltak::evaluation::parameter_file::{ParameterFile, ParameterKind};
+use tiltak::opening_book::OpeningBook;
+use tiltak::perft::{Perft, PerftSetting};
+use tiltak::search::time::TimeBudget;
+use tiltak::search::{GameResultForUs, MctsSetting, MonteCarloTree, ProvenResult, PvLine};
+
+/// The alpha-beta engine's transposition table is allocated up front, unlike the MCTS arena, so its size is capped lower
+const MAX_ALPHA_BETA_HASH: usize = 1024;
+
+/// The search algorithm, selected with the `Engine` option
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SearchEngine {
+    #[default]
+    Mcts,
+    /// A conventional alpha-beta search. Only the `Hash` and `HalfKomi` options apply to it
+    AlphaBeta,
+}
+
+impl SearchEngine {
+    const ALL: [SearchEngine; 2] = [SearchEngine::Mcts, SearchEngine::AlphaBeta];
+
+    fn name(self) -> &'static str {
+        match self {
+            SearchEngine::Mcts => "mcts",
+            SearchEngine::AlphaBeta => "alphabeta",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|engine| engine.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// A named set of search settings, which can be selected with the `Preset` option
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Preset {
+    #[default]
+    Default,
+    /// Plays with random rollouts
+    Slatebot,
+    /// Like slatebot, but with additional randomness at the root
+    Cobblebot,
+}
+
+impl Preset {
+    const ALL: [Preset; 3] = [Preset::Default, Preset::Slatebot, Preset::Cobblebot];
+
+    fn name(self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Slatebot => "slatebot",
+            Preset::Cobblebot => "cobblebot",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Overwrite the options that are controlled by the preset
+    fn apply(self, options: &mut EngineOptions) {
+        options.preset = self;
+        let (rollout_depth, rollout_temperature, dirichlet) = match self {
+            Preset::Default => (0, 0.0, 0.0),
+            Preset::Slatebot => (200, 0.2, 0.0),
+            Preset::Cobblebot => (200, 0.2, 0.25),
+        };
+        options.rollout_depth = rollout_depth;
+        options.rollout_temperature = rollout_temperature;
+        options.dirichlet = dirichlet;
+    }
+}
+
+/// Engine settings that are shared between all searches, set with `setoption`
+#[derive(Clone, Copy, Debug)]
+struct EngineOptions {
+    komi: Komi,
+    search_engine: SearchEngine,
+    /// If set, the expected reply is sent with `bestmove`, so that the GUI can ask the engine to ponder on it
+    ponder: bool,
+    /// Play moves from the opening book set with `BookFile`, instead of searching
+    own_book: bool,
+    /// Maximum size of the search tree, in megabytes
+    hash: usize,
+    threads: usize,
+    /// Share search nodes between transpositions
+    transpositions: bool,
+    multi_pv: usize,
+    preset: Preset,
+    /// Zero disables rollouts
+    rollout_depth: u16,
+    /// Zero means rollouts always pick the highest-scoring move
+    rollout_temperature: f64,
+    /// Alpha of the dirichlet noise applied at the root. Zero disables noise
+    dirichlet: f32,
+    /// Zero disables randomness in the static evaluation
+    static_eval_variance: f32,
+    c_puct_init: f32,
+    c_puct_base: f32,
+    /// Networks loaded with `ValueNetwork` and `PolicyNetwork`, used instead of the linear evaluation
+    value_network: Option<&'static NetworkFile>,
+    policy_network: Option<&'static NetworkFile>,
+    /// Parameter files loaded with `ValueParams` and `PolicyParams`, used instead of the compiled-in parameters
+    value_params: Option<&'static ParameterFile>,
+    policy_params: Option<&'static ParameterFile>,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        let mcts_settings = MctsSetting::<6>::default();
+        EngineOptions {
+            komi: Komi::default(),
+            search_engine: SearchEngine::Mcts,
+            ponder: false,
+            own_book: false,
+            hash: 48 * 1024,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            transpositions: false,
+            multi_pv: 1,
+            preset: Preset::Default,
+            rollout_depth: 0,
+            rollout_temperature: 0.0,
+            dirichlet: 0.0,
+            static_eval_variance: 0.0,
+            c_puct_init: mcts_settings.c_puct_init(),
+            c_puct_base: mcts_settings.c_puct_base(),
+            value_network: None,
+            policy_network: None,
+            value_params: None,
+            policy_params: None,
+        }
+    }
+}
+
+impl EngineOptions {
+    /// Print the `option` lines that are sent before `teiok`, with the current values as defaults
+    fn print_options(&self) {
+        println!(
+            "option name HalfKomi type spin default {} min -10 max 10",
+            self.komi.half_komi()
+        );
+        println!(
+            "option name Engine type combo default {} {}",
+            self.search_engine.name(),
+            SearchEngine::ALL
+                .iter()
+                .map(|engine| format!("var {}", engine.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        println!("option name Ponder type check default {}", self.ponder);
+        println!("option name OwnBook type check default {}", self.own_book);
+        println!(
+            "option name Hash type spin default {} min 1 max 65535",
+            self.hash
+        );
+        println!(
+            "option name Threads type spin default {} min 1 max 1024",
+            self.threads
+        );
+        println!(
+            "option name Transpositions type check default {}",
+            self.transpositions
+        );
+        println!(
+            "option name MultiPV type spin default {} min 1 max 256",
+            self.multi_pv
+        );
+        println!(
+            "option name Preset type combo default {} {}",
+            self.preset.name(),
+            Preset::ALL
+                .iter()
+                .map(|preset| format!("var {}", preset.name()))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        println!(
+            "option name RolloutDepth type spin default {} min 0 max 1000",
+            self.rollout_depth
+        );
+        println!(
+            "option name RolloutTemperature type string default {}",
+            self.rollout_temperature
+        );
+        println!(
+            "option name DirichletNoise type string default {}",
+            self.dirichlet
+        );
+        println!(
+            "option name StaticEvalVariance type string default {}",
+            self.static_eval_variance
+        );
+        println!(
+            "option name CPuctInit type string default {}",
+            self.c_puct_init
+        );
+        println!(
+            "option name CPuctBase type string default {}",
+            self.c_puct_base
+        );
+        // Not real options: setting them saves the last search tree to a file, or loads one
+        println!("option name SaveTree type string default <empty>");
+        println!("option name LoadTree type string default <empty>");
+        println!("option name BookFile type string default <empty>");
+        println!("option name ValueNetwork type string default <empty>");
+        println!("option name PolicyNetwork type string default <empty>");
+        println!("option name ValueParams type string default <empty>");
+        println!("option name PolicyParams type string default <empty>");
+    }
+
+    /// Set an option from a `setoption` command. Option names are case-insensitive
+    fn set_option(&mut self, name: &str, value: &str) -> Result<(), TeiError> {
+        fn parse<T: FromStr + PartialOrd>(value: &str, range: RangeInclusive<T>) -> Option<T> {
+            value.parse().ok().filter(|value| range.contains(value))
+        }
+
+        let parsed = match name.to_ascii_lowercase().as_str() {
+            "halfkomi" => parse(value, -10..=10)
+                .and_then(Komi::from_half_komi)
+                .map(|komi| self.komi = komi),
+            "engine" => SearchEngine::from_name(value)
+                .map(|search_engine| self.search_engine = search_engine),
+            "ponder" => parse(value, false..=true).map(|ponder| self.ponder = ponder),
+            "ownbook" => parse(value, false..=true).map(|own_book| self.own_book = own_book),
+            "hash" => parse(value, 1..=65535).map(|hash| self.hash = hash),
+            "threads" => parse(value, 1..=1024).map(|threads| self.threads = threads),
+            "transpositions" => parse(value, false..=true)
+                .map(|transpositions| self.transpositions = transpositions),
+            "multipv" => parse(value, 1..=256).map(|multi_pv| self.multi_pv = multi_pv),
+            "preset" => Preset::from_name(value).map(|preset| preset.apply(self)),
+            "rolloutdepth" => {
+                parse(value, 0..=1000).map(|rollout_depth| self.rollout_depth = rollout_depth)
+            }
+            "rollouttemperature" => parse(value, 0.0..=f64::MAX)
+                .map(|rollout_temperature| self.rollout_temperature = rollout_temperature),
+            "dirichletnoise" => {
+                parse(value, 0.0..=f32::MAX).map(|dirichlet| self.dirichlet = dirichlet)
+            }
+            "staticevalvariance" => parse(value, 0.0..=f32::MAX)
+                .map(|static_eval_variance| self.static_eval_variance = static_eval_variance),
+            "cpuctinit" => {
+                parse(value, 0.0..=f32::MAX).map(|c_puct_init| self.c_puct_init = c_puct_init)
+            }
+            "cpuctbase" => parse(value, f32::MIN_POSITIVE..=f32::MAX)
+                .map(|c_puct_base| self.c_puct_base = c_puct_base),
+            _ => return Err(TeiError::UnknownOption(name.to_string())),
+        };
+        parsed.ok_or_else(|| TeiError::InvalidOptionValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    fn mcts_settings<const S: usize>(&self) -> MctsSetting<S> {
+        let default_settings = MctsSetting::<S>::default();
+        let mut settings = default_settings
+            .clone()
+            .mem_usage(self.hash * 1024 * 1024)
+            .threads(self.threads)
+            .transposition_table(self.transpositions)
+            .add_search_params(
+                vec![
+                    self.c_puct_init,
+                    self.c_puct_base,
+                    default_settings.initial_mean_action_value(),
+                ]
+                .into_boxed_slice(),
+            )
+            .add_rollout_depth(self.rollout_depth);
+        if self.rollout_temperature > 0.0 {
+            settings = settings.add_rollout_temperature(self.rollout_temperature);
+        }
+        if self.dirichlet > 0.0 {
+            settings = settings.add_dirichlet(self.dirichlet);
+        }
+        if self.static_eval_variance > 0.0 {
+            settings = settings.add_static_eval_variance(self.static_eval_variance);
+        }
+        if let Some(params) = self
+            .value_params
+            .and_then(|file| file.params_for::<S>(self.komi))
+        {
+            settings = settings.add_value_params(params);
+        }
+        if let Some(params) = self
+            .policy_params
+            .and_then(|file| file.params_for::<S>(self.komi))
+        {
+            settings = settings.add_policy_params(params);
+        }
+        if let Some(network) = self.value_network.filter(|network| network.size == S) {
+            settings = settings.add_value_network(&network.params);
+        }
+        if let Some(network) = self.policy_network.filter(|network| network.size == S) {
+            settings = settings.add_policy_network(&network.params);
+        }
+        settings
+    }
+
+    /// Check that the networks, if any, are for size `S`
+    fn check_network_sizes<const S: usize>(&self) -> Result<(), TeiError> {
+        for network in [self.value_network, self.policy_network]
+            .into_iter()
+            .flatten()
+        {
+            if network.size != S {
+                return Err(TeiError::WrongNetworkSize {
+                    network_size: network.size,
+                    size: S,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the parameter files, if any, are for size `S` and the current komi
+    fn check_parameter_files<const S: usize>(&self) -> Result<(), TeiError> {
+        for parameter_file in [self.value_params, self.policy_params]
+            .into_iter()
+            .flatten()
+        {
+            if parameter_file.params_for::<S>(self.komi).is_none() {
+                return Err(TeiError::WrongParameterFile {
+                    file_size: parameter_file.size,
+                    file_komi: parameter_file.komi,
+                    size: S,
+                    komi: self.komi,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn alpha_beta_settings<const S: usize>(&self) -> AlphaBetaSetting<S> {
+        let mut settings =
+            AlphaBetaSetting::default().mem_usage(self.hash.min(MAX_ALPHA_BETA_HASH) * 1024 * 1024);
+        if let Some(params) = self
+            .value_params
+            .and_then(|file| file.params_for::<S>(self.komi))
+        {
+            settings = settings.add_value_params(params);
+        }
+        if let Some(params) = self
+            .policy_params
+            .and_then(|file| file.params_for::<S>(self.komi))
+        {
+            settings = settings.add_policy_params(params);
+        }
+        settings
+    }
+}
 
 pub fn main() {
-    let is_slatebot = env::args().any(|arg| arg == "--slatebot");
-    let is_cobblebot = env::args().any(|arg| arg == "--cobblebot");
+    let mut options = EngineOptions::default();
+    if env::args().any(|arg| arg == "--slatebot") {
+        Preset::Slatebot.apply(&mut options);
+    } else if env::args().any(|arg| arg == "--cobblebot") {
+        Preset::Cobblebot.apply(&mut options);
+    }
+    // Parameter files can also be set with `setoption`, but giving them on the command line works with any GUI
+    let args: Vec<String> = env::args().collect();
+    for (flag, kind) in [
+        ("--value-params", ParameterKind::Value),
+        ("--policy-params", ParameterKind::Policy),
+    ] {
+        if let Some(path) = args
+            .iter()
+            .position(|arg| arg == flag)
+            .and_then(|i| args.get(i + 1))
+        {
+            let parameter_file = match load_parameter_file(kind, path) {
+                Ok(parameter_file) => parameter_file,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            };
+            match kind {
+                ParameterKind::Value => options.value_params = parameter_file,
+                ParameterKind::Policy => options.policy_params = parameter_file,
+            }
+        }
+    }
 
     loop {
         let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        if io::stdin().read_line(&mut input).unwrap() == 0 {
+            return;
+        }
         if input.trim() == "tei" {
             break;
         }
     }
 
-    println!("id name Tiltak");
-    println!("id author Morten Lohne");
-    println!("option name HalfKomi type spin default 0 min -10 max 10");
-    println!("teiok");
+    print_id(&options);
 
-    // Position stored in a `dyn Any` variable, because it can be any size
-    let mut position: Option<Box<dyn Any>> = None;
-    let mut size: Option<usize> = None;
-    let mut komi = Komi::default();
-    let mut calculating_handle: Option<JoinHandle<()>> = None;
-    let should_stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let mut engine = Engine::new(options);
 
     for line in BufReader::new(io::stdin()).lines().map(Result::unwrap) {
-        let mut words = line.split_whitespace();
-        match words.next().unwrap() {
-            "quit" => {
-                should_stop.store(true, atomic::Ordering::Relaxed);
-                if let Some(handle) = calculating_handle.take() {
-                    handle.join().unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Errors are reported to the GUI, but never stop the engine
+        match TeiCommand::parse(&line) {
+            Ok(TeiCommand::Quit) => {
+                if let Err(err) = engine.stop() {
+                    println!("info string error {}", err);
                 }
                 break;
             }
-            "stop" => {
-                should_stop.store(true, atomic::Ordering::Relaxed);
-                if let Some(handle) = calculating_handle.take() {
-                    handle.join().unwrap();
+            Ok(command) => {
+                if let Err(err) = engine.handle_command(command) {
+                    println!("info string error {}", err);
                 }
-                should_stop.store(false, atomic::Ordering::Relaxed);
             }
-            "isready" => println!("readyok"),
-            "setoption" => {
-                if [
-                    words.next().unwrap_or_default(),
-                    words.next().unwrap_or_default(),
-                    words.next().unwrap_or_default(),
-                ]
-                .join(" ")
-                    == "name HalfKomi value"
-                {
-                    if let Some(k) = words
-                        .next()
-                        .and_then(|komi_string| komi_string.parse::<i8>().ok())
-                        .and_then(Komi::from_half_komi)
-                    {
-                        komi = k;
-                    } else {
-                        panic!("Invalid komi setting \"{}\"", line);
-                    }
-                } else {
-                    panic!("Invalid setoption string \"{}\"", line);
-                }
+            Err(err) => println!("info string error {}", err),
+        }
+    }
+}
+
+fn print_id(options: &EngineOptions) {
+    println!("id name Tiltak");
+    println!("id author Morten Lohne");
+    options.print_options();
+    println!("teiok");
+}
+
+/// The engine's state between commands
+struct Engine {
+    options: EngineOptions,
+    // Position stored in a `dyn Any` variable, because it can be any size
+    position: Option<Box<dyn Any>>,
+    size: Option<usize>,
+    calculating_handle: Option<JoinHandle<Box<dyn Any + Send>>>,
+    // The search tree from the last `go` command, which can be re-used for the next one
+    last_tree: Option<Box<dyn Any + Send>>,
+    book: Option<OpeningBook>,
+    should_stop: Arc<AtomicBool>,
+    // Set on `ponderhit` or `stop`, to end a `go ponder` search
+    stop_pondering: Arc<AtomicBool>,
+}
+
+impl Engine {
+    fn new(options: EngineOptions) -> Self {
+        Engine {
+            options,
+            position: None,
+            size: None,
+            calculating_handle: None,
+            last_tree: None,
+            book: None,
+            should_stop: Arc::new(AtomicBool::new(false)),
+            stop_pondering: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn handle_command(&mut self, command: TeiCommand) -> Result<(), TeiError> {
+        match command {
+            TeiCommand::Tei => print_id(&self.options),
+            TeiCommand::IsReady => println!("readyok"),
+            TeiCommand::Quit | TeiCommand::Stop => self.stop()?,
+            TeiCommand::PonderHit => self.stop_pondering.store(true, atomic::Ordering::Relaxed),
+            // Saving and loading search trees are actions, not settings
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("savetree") => self
+                .with_size(|engine, size| match size {
+                    3 => engine.save_tree::<3>(&value),
+                    4 => engine.save_tree::<4>(&value),
+                    5 => engine.save_tree::<5>(&value),
+                    6 => engine.save_tree::<6>(&value),
+                    7 => engine.save_tree::<7>(&value),
+                    8 => engine.save_tree::<8>(&value),
+                    s => Err(TeiError::UnsupportedSize(s)),
+                })?,
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("loadtree") => self
+                .with_size(|engine, size| match size {
+                    3 => engine.load_tree::<3>(&value),
+                    4 => engine.load_tree::<4>(&value),
+                    5 => engine.load_tree::<5>(&value),
+                    6 => engine.load_tree::<6>(&value),
+                    7 => engine.load_tree::<7>(&value),
+                    8 => engine.load_tree::<8>(&value),
+                    s => Err(TeiError::UnsupportedSize(s)),
+                })?,
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("bookfile") => {
+                self.load_book(&value)?
             }
-            "teinewgame" => {
-                let size_string = words.next();
-                size = size_string.and_then(|s| usize::from_str(s).ok());
-                position = None;
-
-                match size {
-                    Some(4) | Some(5) | Some(6) => (),
-                    _ => panic!("Error: Unsupported size {}", size.unwrap_or_default()),
-                }
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("valuenetwork") => {
+                self.options.value_network = load_network(NetworkKind::Value, &value)?
+            }
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("policynetwork") => {
+                self.options.policy_network = load_network(NetworkKind::Policy, &value)?
             }
-            "position" => {
-                position = match size {
-                    None => panic!("Received position without receiving teinewgame string"),
-                    Some(4) => Some(Box::new(parse_position_string::<4>(&line, komi))),
-                    Some(5) => Some(Box::new(parse_position_string::<5>(&line, komi))),
-                    Some(6) => Some(Box::new(parse_position_string::<6>(&line, komi))),
-                    Some(s) => panic!("Unsupported size {}", s),
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("valueparams") => {
+                self.options.value_params = load_parameter_file(ParameterKind::Value, &value)?
+            }
+            TeiCommand::SetOption { name, value } if name.eq_ignore_ascii_case("policyparams") => {
+                self.options.policy_params = load_parameter_file(ParameterKind::Policy, &value)?
+            }
+            TeiCommand::SetOption { name, value } => self.options.set_option(&name, &value)?,
+            TeiCommand::TeiNewGame { size } => {
+                if !matches!(size, 3..=8) {
+                    return Err(TeiError::UnsupportedSize(size));
                 }
+                self.size = Some(size);
+                self.position = None;
+                self.last_tree = None;
             }
-            "go" => {
-                let should_stop_clone = should_stop.clone();
-                calculating_handle = match size {
-                    Some(4) => {
-                        let position = position
-                            .as_ref()
-                            .and_then(|p| p.downcast_ref::<Position<4>>())
-                            .unwrap()
-                            .clone();
-                        Some(thread::spawn(move || {
-                            parse_go_string::<4>(
-                                &line,
-                                position,
-                                should_stop_clone,
-                                is_slatebot,
-                                is_cobblebot,
-                            )
-                        }))
-                    }
-                    Some(5) => {
-                        let position = position
-                            .as_ref()
-                            .and_then(|p| p.downcast_ref::<Position<5>>())
-                            .unwrap()
-                            .clone();
-                        Some(thread::spawn(move || {
-                            parse_go_string::<5>(
-                                &line,
-                                position,
-                                should_stop_clone,
-                                is_slatebot,
-                                is_cobblebot,
-                            )
-                        }))
-                    }
-                    Some(6) => {
-                        let position = position
-                            .as_ref()
-                            .and_then(|p| p.downcast_ref::<Position<6>>())
-                            .unwrap()
-                            .clone();
-                        Some(thread::spawn(move || {
-                            parse_go_string::<6>(
-                                &line,
-                                position,
-                                should_stop_clone,
-                                is_slatebot,
-                                is_cobblebot,
-                            )
-                        }))
-                    }
-                    Some(s) => panic!("Error: Unsupported size {}", s),
-                    None => panic!("Error: Received go without receiving teinewgame string"),
-                };
+            TeiCommand::Position(position_command) => {
+                // Clear the old position first, so that an invalid position is never searched
+                self.position = None;
+                self.position = Some(match self.size {
+                    Some(3) => self.parse_position::<3>(&position_command)?,
+                    Some(4) => self.parse_position::<4>(&position_command)?,
+                    Some(5) => self.parse_position::<5>(&position_command)?,
+                    Some(6) => self.parse_position::<6>(&position_command)?,
+                    Some(7) => self.parse_position::<7>(&position_command)?,
+                    Some(8) => self.parse_position::<8>(&position_command)?,
+                    Some(s) => return Err(TeiError::UnsupportedSize(s)),
+                    None => return Err(TeiError::NoGame),
+                });
             }
-            s => panic!("Unknown command \"{}\"", s),
+            TeiCommand::Go(go_command) => match self.size {
+                Some(3) => self.go::<3>(go_command)?,
+                Some(4) => self.go::<4>(go_command)?,
+                Some(5) => self.go::<5>(go_command)?,
+                Some(6) => self.go::<6>(go_command)?,
+                Some(7) => self.go::<7>(go_command)?,
+                Some(8) => self.go::<8>(go_command)?,
+                Some(s) => return Err(TeiError::UnsupportedSize(s)),
+                None => return Err(TeiError::NoGame),
+            },
+            TeiCommand::Perft { depth } => self.with_size(|engine, size| match size {
+                3 => engine.perft::<3>(depth),
+                4 => engine.perft::<4>(depth),
+                5 => engine.perft::<5>(depth),
+                6 => engine.perft::<6>(depth),
+                7 => engine.perft::<7>(depth),
+                8 => engine.perft::<8>(depth),
+                s => Err(TeiError::UnsupportedSize(s)),
+            })?,
         }
+        Ok(())
+    }
+
+    fn parse_position<const S: usize>(
+        &self,
+        position_command: &PositionCommand,
+    ) -> Result<Box<dyn Any>, TeiError> {
+        Ok(Box::new(
+            position_command.to_position::<S>(self.options.komi)?,
+        ))
+    }
+
+    /// Run `f` with the current board size, once the last search has finished
+    fn with_size(
+        &mut self,
+        f: impl FnOnce(&mut Self, usize) -> Result<(), TeiError>,
+    ) -> Result<(), TeiError> {
+        let size = self.size.ok_or(TeiError::NoGame)?;
+        if let Some(handle) = self.calculating_handle.take() {
+            if !handle.is_finished() {
+                self.calculating_handle = Some(handle);
+                return Err(TeiError::Searching);
+            }
+            self.last_tree = Some(handle.join().map_err(|_| TeiError::SearchFailed)?);
+        }
+        f(self, size)
     }
-}
 
-fn parse_position_string<const S: usize>(line: &str, komi: Komi) -> Position<S> {
-    let mut words_iter = line.split_whitespace();
-    words_iter.next(); // position
-    let mut position = match words_iter.next() {
-        Some("startpos") => Position::start_position_with_komi(komi),
-        Some("tps") => {
-            let tps: String = (&mut words_iter).take(3).collect::<Vec<_>>().join(" ");
-            <Position<S>>::from_fen_with_komi(&tps, komi).unwrap()
+    /// Print the perft count below each legal move, followed by the total
+    fn perft<const S: usize>(&self, depth: u16) -> Result<(), TeiError> {
+        let position = self
+            .position
+            .as_ref()
+            .and_then(|p| p.downcast_ref::<Position<S>>())
+            .ok_or(TeiError::NoPosition)?;
+        let start_time = Instant::now();
+        let perft = Perft::new(PerftSetting::default().threads(self.options.threads));
+        let divide = perft.divide(position, depth);
+        for (mv, nodes) in divide.iter() {
+            println!("{}: {}", position.move_to_san(mv), nodes);
+        }
+        let nodes = if depth == 0 {
+            1
+        } else {
+            divide.iter().map(|(_, nodes)| nodes).sum()
+        };
+        println!();
+        println!("Nodes searched: {}", nodes);
+        println!(
+            "info string perft {:.2}s",
+            start_time.elapsed().as_secs_f32()
+        );
+        Ok(())
+    }
+
+    /// Save the search tree from the last `go` command to a file
+    fn save_tree<const S: usize>(&self, path: &str) -> Result<(), TeiError> {
+        let tree = self
+            .last_tree
+            .as_ref()
+            .and_then(|tree| tree.downcast_ref::<MonteCarloTree<S>>())
+            .ok_or(TeiError::NoSearchTree)?;
+        fs::File::create(path)
+            .and_then(|file| tree.save(file))
+            .map_err(|err| TeiError::SearchTreeFile {
+                path: path.to_string(),
+                error: err.to_string(),
+            })?;
+        println!(
+            "info string saved search tree with {} visits",
+            tree.visits()
+        );
+        Ok(())
+    }
+
+    /// Load a search tree from a file, into an arena sized by the `Hash` option. It is re-used by the next `go` command if the position matches
+    fn load_tree<const S: usize>(&mut self, path: &str) -> Result<(), TeiError> {
+        let tree = fs::File::open(path)
+            .and_then(|file| {
+                <MonteCarloTree<S>>::load_with_mem_usage(file, self.options.hash * 1024 * 1024)
+            })
+            .map_err(|err| TeiError::SearchTreeFile {
+                path: path.to_string(),
+                error: err.to_string(),
+            })?;
+        println!(
+            "info string loaded search tree with {} visits for tps {}",
+            tree.visits(),
+            tree.position().to_fen()
+        );
+        self.last_tree = Some(Box::new(tree));
+        Ok(())
+    }
+
+    fn load_book(&mut self, path: &str) -> Result<(), TeiError> {
+        let book = fs::File::open(path)
+            .and_then(OpeningBook::read)
+            .map_err(|err| TeiError::BookFile {
+                path: path.to_string(),
+                error: err.to_string(),
+            })?;
+        println!(
+            "info string loaded opening book with {} positions for {}s, komi {}",
+            book.len(),
+            book.size(),
+            book.komi()
+        );
+        self.book = Some(book);
+        Ok(())
+    }
+
+    /// Stop the current search, if any, and keep its tree for the next search
+    fn stop(&mut self) -> Result<(), TeiError> {
+        self.should_stop.store(true, atomic::Ordering::Relaxed);
+        self.stop_pondering.store(true, atomic::Ordering::Relaxed);
+        let result = self.join_search();
+        self.should_stop.store(false, atomic::Ordering::Relaxed);
+        result
+    }
+
+    /// Wait for the current search, if any, and keep its tree for the next search
+    fn join_search(&mut self) -> Result<(), TeiError> {
+        if let Some(handle) = self.calculating_handle.take() {
+            self.last_tree = Some(handle.join().map_err(|_| TeiError::SearchFailed)?);
+        }
+        Ok(())
+    }
+
+    fn go<const S: usize>(&mut self, go_command: GoCommand) -> Result<(), TeiError> {
+        let position = self
+            .position
+            .as_ref()
+            .and_then(|p| p.downcast_ref::<Position<S>>())
+            .ok_or(TeiError::NoPosition)?
+            .clone();
+        if position.game_result().is_some() {
+            return Err(TeiError::GameOver);
         }
-        _ => panic!("Expected \"startpos\" or \"tps\" to specify position."),
-    };
 
-    match words_iter.next() {
-        Some("moves") => {
-            for move_string in words_iter {
-                position.do_move(position.move_from_san(move_string).unwrap());
+        if self.options.search_engine == SearchEngine::Mcts {
+            self.options.check_network_sizes::<S>()?;
+        }
+        self.options.check_parameter_files::<S>()?;
+
+        // A `go infinite` or `go ponder` search never ends by itself, and the `stop` that would end it cannot be read while we wait for it
+        self.stop()?;
+        self.stop_pondering.store(false, atomic::Ordering::Relaxed);
+
+        // Analysis and pondering always search
+        if self.options.own_book
+            && !go_command.ponder
+            && go_command.time_control != GoTimeControl::Infinite
+        {
+            if let Some(mv) = self
+                .book
+                .as_ref()
+                .and_then(|book| book.probe(&position, &mut rand::thread_rng()))
+            {
+                println!("info string book move");
+                println!("bestmove {}", position.move_to_san(&mv));
+                return Ok(());
             }
         }
-        Some(s) => panic!("Expected \"moves\" in \"{}\", got \"{}\".", line, s),
-        None => (),
+
+        let previous_tree = self.last_tree.take();
+        let should_stop = self.should_stop.clone();
+        let stop_pondering = self.stop_pondering.clone();
+        let options = self.options;
+        self.calculating_handle = Some(thread::spawn(move || {
+            search::<S>(
+                go_command,
+                position,
+                previous_tree,
+                should_stop,
+                stop_pondering,
+                options,
+            )
+        }));
+        Ok(())
+    }
+}
+
+/// Load a value or policy network. An empty path unloads the network
+fn load_network(kind: NetworkKind, path: &str) -> Result<Option<&'static NetworkFile>, TeiError> {
+    if path.is_empty() || path == "<empty>" {
+        return Ok(None);
     }
-    position
+    let network_file = fs::File::open(path)
+        .and_then(|file| NetworkFile::read(BufReader::new(file)))
+        .and_then(|network_file| {
+            if network_file.kind == kind {
+                Ok(network_file)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Expected a {} network, got a {} network",
+                        kind, network_file.kind
+                    ),
+                ))
+            }
+        })
+        .map_err(|err| TeiError::NetworkFile {
+            path: path.to_string(),
+            error: err.to_string(),
+        })?;
+    println!(
+        "info string loaded {} network for {}s, with {} hidden neurons",
+        kind,
+        network_file.size,
+        network_file.network().num_hidden()
+    );
+    // The search settings require the parameters to live forever
+    Ok(Some(Box::leak(Box::new(network_file))))
 }
 
-fn parse_go_string<const S: usize>(
-    line: &str,
+/// Run the search, and return the search tree so that it can be re-used for the next search
+fn search<const S: usize>(
+    go_command: GoCommand,
     position: Position<S>,
+    previous_tree: Option<Box<dyn Any + Send>>,
     should_stop: Arc<AtomicBool>,
-    is_slatebot: bool,
-    is_cobblebot: bool,
-) {
-    let mut words = line.split_whitespace();
-    words.next(); // go
-
-    let mcts_settings = if is_slatebot {
-        MctsSetting::default()
-            .add_rollout_depth(200)
-            .add_rollout_temperature(0.2)
-    } else if is_cobblebot {
-        MctsSetting::default()
-            .add_rollout_depth(200)
-            .add_rollout_temperature(0.2)
-            .add_dirichlet(0.25)
-    } else {
-        MctsSetting::default()
-    };
+    stop_pondering: Arc<AtomicBool>,
+    options: EngineOptions,
+) -> Box<dyn Any + Send> {
+    if options.search_engine == SearchEngine::AlphaBeta {
+        return alpha_beta_search(
+            go_command,
+            position,
+            previous_tree,
+            should_stop,
+            stop_pondering,
+            options,
+        );
+    }
+    let mcts_settings = options.mcts_settings();
+    let setup_start_time = Instant::now();
 
-    match words.next() {
-        Some(word @ "movetime") | Some(word @ "infinite") => {
-            let movetime = if word == "movetime" {
-                Duration::from_millis(u64::from_str(words.next().unwrap()).unwrap())
-            } else {
-                Duration::MAX // 'go infinite' is just movetime with a very long duration
-            };
-            let start_time = Instant::now();
-            let mut tree = search::MonteCarloTree::new(position.clone(), mcts_settings);
-
-            for i in 0.. {
-                let nodes_to_search = (200.0 * f64::powf(1.26, i as f64)) as u64;
-                let mut oom = false;
-                for _ in 0..nodes_to_search {
-                    if should_stop.load(atomic::Ordering::Relaxed) {
-                        break;
-                    }
-                    if let Err(err) = tree.select() {
-                        eprintln!("Warning: {err}");
-                        oom = true;
-                        break;
-                    }
-                }
-                let (best_move, best_score) = tree.best_move().unwrap();
-                let pv: Vec<_> = tree.pv().collect();
-                println!(
-                    "info depth {} seldepth {} nodes {} score cp {} time {} nps {:.0} pv {}",
-                    ((tree.visits() as f64 / 10.0).log2()) as u64,
-                    pv.len(),
-                    tree.visits(),
-                    (best_score * 200.0 - 100.0) as i64,
-                    start_time.elapsed().as_millis(),
-                    tree.visits() as f32 / start_time.elapsed().as_secs_f32(),
-                    pv.iter()
-                        .map(|mv| position.move_to_san(mv))
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                );
-                if oom
-                    || should_stop.load(atomic::Ordering::Relaxed)
-                    || start_time.elapsed().as_secs_f64() > movetime.as_secs_f64() * 0.7
-                {
-                    println!("bestmove {}", position.move_to_san(&best_move));
-                    break;
+    let previous_tree =
+        previous_tree.and_then(|tree| tree.downcast::<MonteCarloTree<S>>().ok().map(|tree| *tree));
+
+    let mut tree = MonteCarloTree::from_previous(previous_tree, position.clone(), mcts_settings);
+    // Re-using the previous tree takes time, which counts against the time budget
+    let setup_time = setup_start_time.elapsed();
+
+    // `go ponder` searches until the opponent plays the expected move (`ponderhit`), or until `stop`
+    // After a `ponderhit`, the search continues as a regular search, re-using the tree
+    if go_command.ponder {
+        let start_time = Instant::now();
+        let start_visits = tree.visits();
+        for i in 0.. {
+            let nodes_to_search = (200.0 * f64::powf(1.26, i as f64)) as u64;
+            if let Err(err) = tree.search_nodes(nodes_to_search, Some(&stop_pondering)) {
+                eprintln!("Warning: {err}");
+                // We may not send `bestmove` until the ponder search is over
+                while !stop_pondering.load(atomic::Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1));
                 }
             }
-        }
-        Some("wtime") | Some("btime") | Some("winc") | Some("binc") => {
-            let parse_time = |s: Option<&str>| {
-                Duration::from_millis(
-                    s.and_then(|w| w.parse().ok())
-                        .unwrap_or_else(|| panic!("Incorrect go command {}", line)),
-                )
-            };
-            let mut words = line.split_whitespace().skip(1).peekable();
-            let mut white_time = Duration::default();
-            let mut white_inc = Duration::default();
-            let mut black_time = Duration::default();
-            let mut black_inc = Duration::default();
-
-            while let Some(word) = words.next() {
-                match word {
-                    "wtime" => white_time = parse_time(words.next()),
-                    "winc" => white_inc = parse_time(words.next()),
-                    "btime" => black_time = parse_time(words.next()),
-                    "binc" => black_inc = parse_time(words.next()),
-                    _ => (),
-                }
+            print_info(&tree, &position, start_time, start_visits, options);
+            if stop_pondering.load(atomic::Ordering::Relaxed) {
+                break;
             }
+        }
+        if should_stop.load(atomic::Ordering::Relaxed) {
+            print_best_move(&tree, &position, options);
+            return Box::new(tree);
+        }
+    }
 
-            let max_time = match position.side_to_move() {
-                Color::White => white_time / 5 + white_inc / 2,
-                Color::Black => black_time / 5 + black_inc / 2,
-            };
+    let budget = match go_command.time_control {
+        GoTimeControl::MoveTime(movetime) => Some(TimeBudget::fixed(movetime)),
+        GoTimeControl::Clock { .. } => Some(TimeBudget::for_clock(
+            &position,
+            go_command.clock(position.side_to_move()).unwrap(),
+        )),
+        GoTimeControl::Infinite => None,
+    };
+
+    let start_time = Instant::now();
+    let start_visits = tree.visits();
+
+    if let Some(budget) = budget {
+        let budget = budget.saturating_sub(setup_time);
+        tree.search_for_time(budget, Some(&should_stop), |tree| {
+            print_info(tree, &position, start_time, start_visits, options);
+        });
+        print_best_move(&tree, &position, options);
+        return Box::new(tree);
+    }
 
-            let start_time = Instant::now();
-
-            let mut tree = MonteCarloTree::new(position.clone(), mcts_settings);
-            tree.search_for_time(max_time, |tree| {
-                let best_score = tree.best_move().unwrap().1;
-                let pv: Vec<_> = tree.pv().collect();
-                println!(
-                    "info depth {} seldepth {} nodes {} score cp {} time {} nps {:.0} pv {}",
-                    ((tree.visits() as f64 / 10.0).log2()) as u64,
-                    pv.len(),
-                    tree.visits(),
-                    (best_score * 200.0 - 100.0) as i64,
-                    start_time.elapsed().as_millis(),
-                    tree.visits() as f32 / start_time.elapsed().as_secs_f32(),
-                    pv.iter()
-                        .map(|mv| position.move_to_san(mv))
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                );
-            });
-            let best_move = tree.best_move().unwrap().0;
-
-            println!("bestmove {}", position.move_to_san(&best_move));
-        }
-        Some(_) | None => {
-            panic!("Invalid go command \"{}\"", line);
+    for i in 0.. {
+        let nodes_to_search = (200.0 * f64::powf(1.26, i as f64)) as u64;
+        let result = tree.search_nodes(nodes_to_search, Some(&should_stop));
+        print_info(&tree, &position, start_time, start_visits, options);
+        if let Err(err) = result {
+            eprintln!("Warning: {err}");
+            break;
+        }
+        // Searching a proven root gains nothing
+        if should_stop.load(atomic::Ordering::Relaxed) || tree.proven_result().is_some() {
+            break;
         }
     }
+    // `go infinite` must not return before `stop`, even if the search cannot continue
+    while !should_stop.load(atomic::Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(1));
+    }
+    print_best_move(&tree, &position, options);
+    Box::new(tree)
+}
+
+fn print_info<const S: usize>(
+    tree: &MonteCarloTree<S>,
+    position: &Position<S>,
+    start_time: Instant,
+    start_visits: u32,
+    options: EngineOptions,
+) {
+    for (i, line) in tree.multi_pv(options.multi_pv).iter().enumerate() {
+        println!(
+            "info depth {} seldepth {} multipv {} nodes {} score {} time {} nps {:.0} pv {}",
+            ((tree.visits() as f64 / 10.0).log2()) as u64,
+            line.pv.len(),
+            i + 1,
+            tree.visits(),
+            score_string(line),
+            start_time.elapsed().as_millis(),
+            (tree.visits() - start_visits) as f32 / start_time.elapsed().as_secs_f32(),
+            line.pv
+                .iter()
+                .map(|mv| position.move_to_san(mv))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+    }
+}
+
+/// The score of a line, as sent in `info`. Proven wins and losses are sent as `mate N`, where N is the number of moves by the side to move
+fn score_string<const S: usize>(line: &PvLine<S>) -> String {
+    match line.proven_result {
+        Some(ProvenResult {
+            result: GameResultForUs::Win,
+            plies,
+        }) => format!("mate {}", plies.div_ceil(2)),
+        Some(ProvenResult {
+            result: GameResultForUs::Loss,
+            plies,
+        }) => format!("mate -{}", plies.div_ceil(2)),
+        Some(ProvenResult {
+            result: GameResultForUs::Draw,
+            ..
+        }) => "cp 0".to_string(),
+        None => format!("cp {}", (line.win_probability * 200.0 - 100.0) as i64),
+    }
+}
+
+fn print_best_move<const S: usize>(
+    tree: &MonteCarloTree<S>,
+    position: &Position<S>,
+    options: EngineOptions,
+) {
+    let best_move = tree.best_move().unwrap().0;
+    match tree.pv().nth(1) {
+        Some(ponder_move) if options.ponder => println!(
+            "bestmove {} ponder {}",
+            position.move_to_san(&best_move),
+            position.move_to_san(&ponder_move)
+        ),
+        _ => println!("bestmove {}", position.move_to_san(&best_move)),
+    }
+}
+
+/// Run the alpha-beta search, and return the engine so that its transposition table can be re-used for the next search
+fn alpha_beta_search<const S: usize>(
+    go_command: GoCommand,
+    position: Position<S>,
+    previous_engine: Option<Box<dyn Any + Send>>,
+    should_stop: Arc<AtomicBool>,
+    stop_pondering: Arc<AtomicBool>,
+    options: EngineOptions,
+) -> Box<dyn Any + Send> {
+    let settings = options.alpha_beta_settings();
+    let mut engine = previous_engine
+        .and_then(|engine| engine.downcast::<AlphaBeta<S>>().ok())
+        .filter(|engine| *engine.settings() == settings)
+        .unwrap_or_else(|| Box::new(AlphaBeta::new(settings)));
+
+    if go_command.ponder {
+        let start_time = Instant::now();
+        let search_info = engine.search_for_time(
+            &position,
+            TimeBudget::fixed(Duration::MAX),
+            Some(&stop_pondering),
+            |info| print_alpha_beta_info(info, &position, start_time),
+        );
+        // We may not send `bestmove` until the ponder search is over
+        while !stop_pondering.load(atomic::Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        if should_stop.load(atomic::Ordering::Relaxed) {
+            print_alpha_beta_best_move(search_info.as_ref(), &position, options);
+            return engine;
+        }
+    }
+
+    let budget = match go_command.time_control {
+        // Don't start an iteration that is unlikely to finish in time
+        GoTimeControl::MoveTime(movetime) => {
+            TimeBudget::fixed(movetime).limit(movetime / 2, movetime)
+        }
+        GoTimeControl::Infinite => TimeBudget::fixed(Duration::MAX),
+        GoTimeControl::Clock { .. } => TimeBudget::for_clock(
+            &position,
+            go_command.clock(position.side_to_move()).unwrap(),
+        ),
+    };
+    let start_time = Instant::now();
+    let search_info = engine.search_for_time(&position, budget, Some(&should_stop), |info| {
+        print_alpha_beta_info(info, &position, start_time)
+    });
+    if go_command.time_control == GoTimeControl::Infinite {
+        // `go infinite` must not return before `stop`, even if the search is finished
+        while !should_stop.load(atomic::Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    print_alpha_beta_best_move(search_info.as_ref(), &position, options);
+    engine
+}
+
+fn print_alpha_beta_info<const S: usize>(
+    search_info: &SearchInfo<S>,
+    position: &Position<S>,
+    start_time: Instant,
+) {
+    let score = match search_info.proven_plies() {
+        Some(plies) if plies > 0 => format!("mate {}", (plies + 1) / 2),
+        Some(plies) => format!("mate -{}", (-plies + 1) / 2),
+        None => format!(
+            "cp {}",
+            (search_info.win_probability() * 200.0 - 100.0) as i64
+        ),
+    };
+    println!(
+        "info depth {} seldepth {} nodes {} score {} time {} nps {:.0} pv {}",
+        search_info.depth,
+        search_info.pv.len(),
+        search_info.nodes,
+        score,
+        start_time.elapsed().as_millis(),
+        search_info.nodes as f32 / start_time.elapsed().as_secs_f32(),
+        search_info
+            .pv
+            .iter()
+            .map(|mv| position.move_to_san(mv))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+}
+
+fn print_alpha_beta_best_move<const S: usize>(
+    search_info: Option<&SearchInfo<S>>,
+    position: &Position<S>,
+    options: EngineOptions,
+) {
+    let Some(search_info) = search_info else {
+        println!("info string error the game is already over");
+        return;
+    };
+    match search_info.pv.get(1) {
+        Some(ponder_move) if options.ponder => println!(
+            "bestmove {} ponder {}",
+            position.move_to_san(&search_info.best_move()),
+            position.move_to_san(ponder_move)
+        ),
+        _ => println!(
+            "bestmove {}",
+            position.move_to_san(&search_info.best_move())
+        ),
+    }
+}
+
+fn load_parameter_file(
+    kind: ParameterKind,
+    path: &str,
+) -> Result<Option<&'static ParameterFile>, TeiError> {
+    if path.is_empty() || path == "<empty>" {
+        return Ok(None);
+    }
+    let parameter_file =
+        ParameterFile::load(path, kind).map_err(|err| TeiError::ParameterFile {
+            path: path.to_string(),
+            error: err.to_string(),
+        })?;
+    println!(
+        "info string loaded {} parameters for {}s with komi {}",
+        kind, parameter_file.size, parameter_file.komi
+    );
+    // The search settings require the parameters to live forever
+    Ok(Some(Box::leak(Box::new(parameter_file))))
 }
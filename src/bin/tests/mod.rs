@@ -1 +1,2 @@
 mod playtak_parse_tests;
+mod tei_parse_tests;
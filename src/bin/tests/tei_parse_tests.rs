@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use board_game_traits::{Color, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+use tiltak::position::{Komi, Position};
+use tiltak::search::time::Clock;
+use tiltak::tei::{GoCommand, GoTimeControl, PositionCommand, TeiCommand, TeiError};
+
+#[test]
+fn parse_simple_commands_test() {
+    assert_eq!(TeiCommand::parse("isready"), Ok(TeiCommand::IsReady));
+    assert_eq!(TeiCommand::parse("  stop  "), Ok(TeiCommand::Stop));
+    assert_eq!(
+        TeiCommand::parse("teinewgame 6"),
+        Ok(TeiCommand::TeiNewGame { size: 6 })
+    );
+    assert_eq!(TeiCommand::parse(""), Err(TeiError::EmptyCommand));
+    assert_eq!(
+        TeiCommand::parse("teinewgame six"),
+        Err(TeiError::InvalidSize("six".to_string()))
+    );
+    assert_eq!(
+        TeiCommand::parse("teinewgame"),
+        Err(TeiError::InvalidSize("".to_string()))
+    );
+    assert_eq!(
+        TeiCommand::parse("gogo infinite"),
+        Err(TeiError::UnknownCommand("gogo".to_string()))
+    );
+}
+
+#[test]
+fn parse_setoption_test() {
+    assert_eq!(
+        TeiCommand::parse("setoption name HalfKomi value 4"),
+        Ok(TeiCommand::SetOption {
+            name: "HalfKomi".to_string(),
+            value: "4".to_string()
+        })
+    );
+    assert_eq!(
+        TeiCommand::parse("setoption name Some Option value a b"),
+        Ok(TeiCommand::SetOption {
+            name: "Some Option".to_string(),
+            value: "a b".to_string()
+        })
+    );
+    assert!(matches!(
+        TeiCommand::parse("setoption HalfKomi 4"),
+        Err(TeiError::InvalidSetOptionCommand(_))
+    ));
+    assert!(matches!(
+        TeiCommand::parse("setoption name value 4"),
+        Err(TeiError::InvalidSetOptionCommand(_))
+    ));
+}
+
+#[test]
+fn parse_startpos_test() {
+    let command = PositionCommand::parse("position startpos moves a1 e5 c3").unwrap();
+    assert_eq!(command.tps, None);
+    assert_eq!(command.moves, vec!["a1", "e5", "c3"]);
+
+    let position = command.to_position::<5>(Komi::default()).unwrap();
+    assert_eq!(position.half_moves_played(), 3);
+    assert_eq!(position.side_to_move(), Color::Black);
+
+    let command = PositionCommand::parse("position startpos").unwrap();
+    assert_eq!(
+        command.to_position::<5>(Komi::default()).unwrap(),
+        <Position<5>>::start_position()
+    );
+
+    let command = PositionCommand::parse("position startpos moves").unwrap();
+    assert!(command.moves.is_empty());
+}
+
+#[test]
+fn parse_tps_test() {
+    let tps = "2,x4/x5/x5/x5/x4,1 1 2";
+    let command = PositionCommand::parse(&format!("position tps {} moves c3", tps)).unwrap();
+    assert_eq!(command.tps.as_deref(), Some(tps));
+    assert_eq!(command.moves, vec!["c3"]);
+
+    let position = command.to_position::<5>(Komi::default()).unwrap();
+    let mut expected = <Position<5>>::from_fen(tps).unwrap();
+    expected.do_move(expected.move_from_san("c3").unwrap());
+    assert_eq!(position, expected);
+
+    let command = PositionCommand::parse(&format!("position tps {}", tps)).unwrap();
+    assert!(command.moves.is_empty());
+    assert!(command.to_position::<5>(Komi::default()).is_ok());
+}
+
+#[test]
+fn parse_invalid_tps_test() {
+    assert!(matches!(
+        PositionCommand::parse("position tps moves a1"),
+        Err(TeiError::InvalidPositionCommand(_))
+    ));
+    assert!(matches!(
+        PositionCommand::parse("position moves a1"),
+        Err(TeiError::InvalidPositionCommand(_))
+    ));
+    assert!(matches!(
+        PositionCommand::parse("position startpos a1"),
+        Err(TeiError::InvalidPositionCommand(_))
+    ));
+
+    // A 5s tps, used in a 6s game
+    let command = PositionCommand::parse("position tps x5/x5/x5/x5/x5 1 1").unwrap();
+    assert!(matches!(
+        command.to_position::<6>(Komi::default()),
+        Err(TeiError::InvalidTps { .. })
+    ));
+
+    let command = PositionCommand::parse("position tps x5/x5/x5/x5/x5 1").unwrap();
+    assert!(matches!(
+        command.to_position::<5>(Komi::default()),
+        Err(TeiError::InvalidTps { .. })
+    ));
+}
+
+#[test]
+fn parse_illegal_moves_test() {
+    let illegal_move_lines = [
+        "position startpos moves a1 a1",     // Placing on an occupied square
+        "position startpos moves Ca1",       // Capstone on the first move
+        "position startpos moves a1 e5 a1+", // Moving the opponent's stone
+        "position startpos moves f6",        // Off the board
+        "position startpos moves a1 xyz",    // Not a move
+    ];
+    for line in illegal_move_lines {
+        let command = PositionCommand::parse(line).unwrap();
+        assert!(
+            matches!(
+                command.to_position::<5>(Komi::default()),
+                Err(TeiError::IllegalMove { .. })
+            ),
+            "{} was parsed successfully",
+            line
+        );
+    }
+
+    // Moving after a road win
+    let command =
+        PositionCommand::parse("position tps x4,2/x4,2/x4,2/x4,2/x4,2 2 6 moves a1").unwrap();
+    assert!(matches!(
+        command.to_position::<5>(Komi::default()),
+        Err(TeiError::IllegalMove { .. })
+    ));
+}
+
+#[test]
+fn parse_go_test() {
+    assert_eq!(
+        GoCommand::parse("go movetime 1000"),
+        Ok(GoCommand {
+            ponder: false,
+            time_control: GoTimeControl::MoveTime(Duration::from_secs(1))
+        })
+    );
+    assert_eq!(
+        GoCommand::parse("go ponder infinite"),
+        Ok(GoCommand {
+            ponder: true,
+            time_control: GoTimeControl::Infinite
+        })
+    );
+
+    let command = GoCommand::parse("go wtime 60000 btime 30000 winc 1000").unwrap();
+    assert!(!command.ponder);
+    assert_eq!(
+        command.clock(Color::White),
+        Some(Clock {
+            time_left: Duration::from_secs(60),
+            increment: Duration::from_secs(1),
+            moves_to_go: None,
+        })
+    );
+    assert_eq!(
+        command.clock(Color::Black),
+        Some(Clock {
+            time_left: Duration::from_secs(30),
+            increment: Duration::ZERO,
+            moves_to_go: None,
+        })
+    );
+
+    let command = GoCommand::parse("go wtime 60000 btime 30000 movestogo 12").unwrap();
+    assert_eq!(
+        command.clock(Color::Black),
+        Some(Clock {
+            time_left: Duration::from_secs(30),
+            increment: Duration::ZERO,
+            moves_to_go: Some(12),
+        })
+    );
+}
+
+#[test]
+fn parse_invalid_go_test() {
+    let invalid_lines = [
+        "go",
+        "go ponder",
+        "go movetime",
+        "go movetime -5",
+        "go wtime 1000 btime",
+        "go movetime 1000 infinite",
+        "go movetime 1000 wtime 1000",
+        "go movetime 1000 movestogo 10",
+        "go wtime 1000 movestogo 0",
+        "go wtime 1000 movestogo",
+        "go depth 5",
+    ];
+    for line in invalid_lines {
+        assert_eq!(
+            GoCommand::parse(line),
+            Err(TeiError::InvalidGoCommand(line.to_string())),
+        );
+    }
+}
+
+#[test]
+fn parse_perft_test() {
+    assert_eq!(
+        TeiCommand::parse("perft 4"),
+        Ok(TeiCommand::Perft { depth: 4 })
+    );
+    for line in ["perft", "perft four", "perft 4 5", "perft -1"] {
+        assert_eq!(
+            TeiCommand::parse(line),
+            Err(TeiError::InvalidPerftCommand(line.to_string()))
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use std::path::Path;
 use std::process::exit;
+use std::{fs, io};
 
 use clap::{Arg, Command};
 
+use tiltak::evaluation::parameter_file::{ParameterFile, ParameterKind};
 use tiltak::evaluation::parameters::{
     self, NUM_POLICY_FEATURES_4S, NUM_POLICY_FEATURES_5S, NUM_POLICY_FEATURES_6S,
     NUM_VALUE_FEATURES_4S, NUM_VALUE_FEATURES_5S, NUM_VALUE_FEATURES_6S,
@@ -104,6 +106,60 @@ fn main() {
                     .required(true)
                     .value_name("move_scores.txt"))
         )
+        .subcommand(
+            Command::new("both-from-samples")
+                .about("Tune value and policy constants from randomly initialized values, using the given binary samples file")
+                .arg(Arg::new("file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("samples.bin"))
+                .arg(Arg::new("value-output")
+                    .long("value-output")
+                    .help("Also write the value parameters to a parameter file, which can be loaded with --value-params")
+                    .num_args(1))
+                .arg(Arg::new("policy-output")
+                    .long("policy-output")
+                    .help("Also write the policy parameters to a parameter file, which can be loaded with --policy-params")
+                    .num_args(1))
+        )
+        .subcommand(
+            Command::new("networks-from-samples")
+                .about("Train value and policy networks from randomly initialized weights, using the given binary samples file")
+                .arg(Arg::new("file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("samples.bin"))
+                .arg(Arg::new("hidden")
+                    .long("hidden")
+                    .help("Number of neurons in the networks' hidden layer.")
+                    .default_value("32")
+                    .num_args(1)
+                    .value_parser(clap::value_parser!(u64).range(1..)))
+                .arg(Arg::new("value-output")
+                    .long("value-output")
+                    .default_value("value_network.txt")
+                    .num_args(1))
+                .arg(Arg::new("policy-output")
+                    .long("policy-output")
+                    .default_value("policy_network.txt")
+                    .num_args(1))
+        )
+        .subcommand(
+            Command::new("convert-to-samples")
+                .about("Convert a ptn file and its move scores text file into a binary samples file")
+                .arg(Arg::new("value-file-name")
+                    .index(1)
+                    .required(true)
+                    .value_name("games.ptn"))
+                .arg(Arg::new("policy-file-name")
+                    .index(2)
+                    .required(true)
+                    .value_name("move_scores.txt"))
+                .arg(Arg::new("output-file-name")
+                    .index(3)
+                    .required(true)
+                    .value_name("samples.bin"))
+        )
         .subcommand(Command::new("spsa")
             .about("Tune exploration parameters using SPSA. Starting values are hard-coded.")
             .arg(Arg::new("book")
@@ -333,6 +389,133 @@ fn main() {
                 _ => panic!("Size {} not supported.", size),
             }
         }
+        Some(("both-from-samples", arg)) => {
+            let file_name = arg.get_one::<String>("file-name").unwrap();
+            let result = match size {
+                4 => training::tune_value_and_policy_from_samples_file::<
+                    4,
+                    NUM_VALUE_FEATURES_4S,
+                    NUM_POLICY_FEATURES_4S,
+                >(file_name)
+                .map(|(value_params, policy_params)| {
+                    (value_params.to_vec(), policy_params.to_vec())
+                }),
+                5 => training::tune_value_and_policy_from_samples_file::<
+                    5,
+                    NUM_VALUE_FEATURES_5S,
+                    NUM_POLICY_FEATURES_5S,
+                >(file_name)
+                .map(|(value_params, policy_params)| {
+                    (value_params.to_vec(), policy_params.to_vec())
+                }),
+                6 => training::tune_value_and_policy_from_samples_file::<
+                    6,
+                    NUM_VALUE_FEATURES_6S,
+                    NUM_POLICY_FEATURES_6S,
+                >(file_name)
+                .map(|(value_params, policy_params)| {
+                    (value_params.to_vec(), policy_params.to_vec())
+                }),
+                _ => panic!("Size {} not supported.", size),
+            };
+            match result {
+                Ok((value_params, policy_params)) => {
+                    println!("Value: {:?}", value_params);
+                    println!("Policy: {:?}", policy_params);
+                    for (kind, params, output) in [
+                        (
+                            ParameterKind::Value,
+                            value_params,
+                            arg.get_one::<String>("value-output"),
+                        ),
+                        (
+                            ParameterKind::Policy,
+                            policy_params,
+                            arg.get_one::<String>("policy-output"),
+                        ),
+                    ] {
+                        let Some(output) = output else {
+                            continue;
+                        };
+                        let parameter_file = ParameterFile {
+                            kind,
+                            size: *size as usize,
+                            komi: *komi,
+                            params,
+                        };
+                        if let Err(err) = fs::File::create(output)
+                            .and_then(|file| parameter_file.write(io::BufWriter::new(file)))
+                        {
+                            eprintln!("Error: Failed to write {}: {}", output, err);
+                            exit(1)
+                        }
+                        println!("Wrote {} parameters to {}", kind, output);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    exit(1)
+                }
+            }
+        }
+        Some(("networks-from-samples", arg)) => {
+            let file_name = arg.get_one::<String>("file-name").unwrap();
+            let num_hidden = *arg.get_one::<u64>("hidden").unwrap() as usize;
+            let value_output = arg.get_one::<String>("value-output").unwrap();
+            let policy_output = arg.get_one::<String>("policy-output").unwrap();
+            let result = match size {
+                4 => training::train_networks_from_samples_file::<
+                    4,
+                    NUM_VALUE_FEATURES_4S,
+                    NUM_POLICY_FEATURES_4S,
+                >(file_name, num_hidden, value_output, policy_output),
+                5 => training::train_networks_from_samples_file::<
+                    5,
+                    NUM_VALUE_FEATURES_5S,
+                    NUM_POLICY_FEATURES_5S,
+                >(file_name, num_hidden, value_output, policy_output),
+                6 => training::train_networks_from_samples_file::<
+                    6,
+                    NUM_VALUE_FEATURES_6S,
+                    NUM_POLICY_FEATURES_6S,
+                >(file_name, num_hidden, value_output, policy_output),
+                _ => panic!("Size {} not supported.", size),
+            };
+            if let Err(err) = result {
+                eprintln!("Error: {}", err);
+                exit(1)
+            }
+        }
+        Some(("convert-to-samples", arg)) => {
+            let value_file_name = arg.get_one::<String>("value-file-name").unwrap();
+            let policy_file_name = arg.get_one::<String>("policy-file-name").unwrap();
+            let output_file_name = arg.get_one::<String>("output-file-name").unwrap();
+            let result = match size {
+                4 => training::convert_to_samples_file::<4>(
+                    value_file_name,
+                    policy_file_name,
+                    output_file_name,
+                    *komi,
+                ),
+                5 => training::convert_to_samples_file::<5>(
+                    value_file_name,
+                    policy_file_name,
+                    output_file_name,
+                    *komi,
+                ),
+                6 => training::convert_to_samples_file::<6>(
+                    value_file_name,
+                    policy_file_name,
+                    output_file_name,
+                    *komi,
+                ),
+                _ => panic!("Size {} not supported.", size),
+            };
+            if let Err(err) = result {
+                eprintln!("Error: {}", err);
+                exit(1)
+            }
+        }
         Some(("spsa", arg)) => {
             let mut variables = vec![
                 spsa::Variable {
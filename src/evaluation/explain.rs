@@ -0,0 +1,244 @@
+//! Breakdown of the linear evaluation into the contribution of each feature group, for debugging strange evaluations.
+//!
+//! A feature group is one `IndexPair` in `ValueIndexes` or `PolicyIndexes`, and is named as its field there.
+//! Its contribution is the sum of its features, each multiplied by its parameter.
+
+use std::cmp::Ordering;
+
+use board_game_traits::Position as PositionTrait;
+use half::f16;
+
+use super::parameters::{
+    num_policy_features, num_value_features, policy_indexes, value_indexes, IndexPair,
+    PolicyApplier, ValueApplier,
+};
+use super::policy_eval::{policy_offset, sigmoid};
+use crate::position::{Move, Position};
+
+/// A value applier that records the contribution of every feature.
+/// Unlike the other appliers, `finish` does not reset the features, so that the contributions can be read afterwards
+#[derive(Debug, Clone)]
+pub struct ExplainValue<const S: usize> {
+    contributions: Vec<f32>,
+    parameters: &'static [f32],
+}
+
+impl<const S: usize> ValueApplier for ExplainValue<S> {
+    fn new(parameters: &'static [f32]) -> Self {
+        ExplainValue {
+            contributions: vec![0.0; num_value_features::<S>() / 2],
+            parameters,
+        }
+    }
+
+    fn eval(&mut self, index_pair: IndexPair, index: usize, val: f16) {
+        index_pair.as_mut_slice(&mut self.contributions)[index] +=
+            index_pair.as_slice(self.parameters)[index] * val.to_f32()
+    }
+
+    fn finish(&mut self) -> f32 {
+        self.contributions.iter().sum()
+    }
+}
+
+impl<const S: usize> ExplainValue<S> {
+    /// The total contribution of each feature group, in the order of `ValueIndexes`
+    pub fn group_contributions(&self) -> Vec<(&'static str, f32)> {
+        value_indexes::<S>()
+            .named_groups()
+            .iter()
+            .map(|(name, index_pair)| {
+                (*name, index_pair.as_slice(&self.contributions).iter().sum())
+            })
+            .collect()
+    }
+}
+
+/// A policy applier that records the contribution of every feature.
+/// Unlike the other appliers, `finish` does not reset the features, so that the contributions can be read afterwards
+#[derive(Debug, Clone)]
+pub struct ExplainPolicy<const S: usize> {
+    contributions: Vec<f32>,
+    parameters: &'static [f32],
+    has_immediate_win: bool,
+    offset: f32,
+}
+
+impl<const S: usize> PolicyApplier for ExplainPolicy<S> {
+    fn new(parameters: &'static [f32]) -> Self {
+        ExplainPolicy {
+            contributions: vec![0.0; num_policy_features::<S>()],
+            parameters,
+            has_immediate_win: false,
+            offset: 0.0,
+        }
+    }
+
+    fn eval(&mut self, index_pair: IndexPair, index: usize, val: f16) {
+        self.eval_f32(index_pair, index, val.to_f32())
+    }
+
+    fn eval_i8(&mut self, index_pair: IndexPair, index: usize, val: i8) {
+        self.eval_f32(index_pair, index, val as f32)
+    }
+
+    fn eval_f32(&mut self, index_pair: IndexPair, index: usize, val: f32) {
+        index_pair.as_mut_slice(&mut self.contributions)[index] +=
+            index_pair.as_slice(self.parameters)[index] * val
+    }
+
+    fn set_immediate_win(&mut self) {
+        self.has_immediate_win = true
+    }
+
+    fn has_immediate_win(&self) -> bool {
+        self.has_immediate_win
+    }
+
+    fn finish(&mut self, num_moves: usize) -> f16 {
+        self.offset = policy_offset(num_moves);
+        f16::from_f32(sigmoid(
+            self.contributions.iter().sum::<f32>() + self.offset,
+        ))
+    }
+}
+
+impl<const S: usize> ExplainPolicy<S> {
+    /// The total contribution of each feature group, in the order of `PolicyIndexes`
+    pub fn group_contributions(&self) -> Vec<(&'static str, f32)> {
+        policy_indexes::<S>()
+            .named_groups()
+            .iter()
+            .map(|(name, index_pair)| {
+                (*name, index_pair.as_slice(&self.contributions).iter().sum())
+            })
+            .collect()
+    }
+}
+
+/// A value feature group's contribution, from each side's half of the parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueGroupContribution {
+    pub name: &'static str,
+    pub white: f32,
+    pub black: f32,
+}
+
+impl ValueGroupContribution {
+    pub fn total(&self) -> f32 {
+        self.white + self.black
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueExplanation {
+    /// Every feature group, in the order of `ValueIndexes`
+    pub groups: Vec<ValueGroupContribution>,
+}
+
+impl ValueExplanation {
+    /// The static evaluation that is explained, equal to `static_eval()` up to rounding
+    pub fn eval(&self) -> f32 {
+        self.groups.iter().map(ValueGroupContribution::total).sum()
+    }
+
+    /// The groups that contributed anything, with the largest absolute contribution first
+    pub fn ranked(&self) -> Vec<&ValueGroupContribution> {
+        let mut groups: Vec<&ValueGroupContribution> = self
+            .groups
+            .iter()
+            .filter(|group| group.white != 0.0 || group.black != 0.0)
+            .collect();
+        groups.sort_by(|a, b| cmp_abs_descending(a.total(), b.total()));
+        groups
+    }
+}
+
+/// The policy evaluation of a single move
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveExplanation<const S: usize> {
+    pub mv: Move<S>,
+    /// Every feature group, in the order of `PolicyIndexes`
+    pub groups: Vec<(&'static str, f32)>,
+    /// The offset for the number of legal moves, see `policy_offset`
+    pub offset: f32,
+}
+
+impl<const S: usize> MoveExplanation<S> {
+    /// The move's evaluation before the sigmoid
+    pub fn raw_eval(&self) -> f32 {
+        self.groups.iter().map(|(_, value)| value).sum::<f32>() + self.offset
+    }
+
+    /// The move's policy score, before it is normalized against the other moves
+    pub fn score(&self) -> f32 {
+        sigmoid(self.raw_eval())
+    }
+
+    /// The groups that contributed anything, with the largest absolute contribution first
+    pub fn ranked(&self) -> Vec<(&'static str, f32)> {
+        let mut groups: Vec<(&'static str, f32)> = self
+            .groups
+            .iter()
+            .filter(|(_, value)| *value != 0.0)
+            .copied()
+            .collect();
+        groups.sort_by(|(_, a), (_, b)| cmp_abs_descending(*a, *b));
+        groups
+    }
+}
+
+fn cmp_abs_descending(a: f32, b: f32) -> Ordering {
+    b.abs().partial_cmp(&a.abs()).unwrap_or(Ordering::Equal)
+}
+
+impl<const S: usize> Position<S> {
+    /// Breakdown of `static_eval()` by feature group
+    pub fn explain_static_eval(&self) -> ValueExplanation {
+        self.explain_static_eval_with_params(Self::value_params(self.komi()))
+    }
+
+    pub fn explain_static_eval_with_params(&self, params: &'static [f32]) -> ValueExplanation {
+        let (white_params, black_params) = params.split_at(params.len() / 2);
+        let mut white_value: ExplainValue<S> = ExplainValue::new(white_params);
+        let mut black_value: ExplainValue<S> = ExplainValue::new(black_params);
+
+        self.static_eval_features(&mut white_value, &mut black_value);
+
+        let groups = white_value
+            .group_contributions()
+            .into_iter()
+            .zip(black_value.group_contributions())
+            .map(|((name, white), (_, black))| ValueGroupContribution { name, white, black })
+            .collect();
+        ValueExplanation { groups }
+    }
+
+    /// Breakdown of the policy evaluation of every legal move, by feature group
+    pub fn explain_policy(&self) -> Vec<MoveExplanation<S>> {
+        self.explain_policy_with_params(Self::policy_params(self.komi()))
+    }
+
+    pub fn explain_policy_with_params(&self, params: &'static [f32]) -> Vec<MoveExplanation<S>> {
+        let mut moves = vec![];
+        self.generate_moves(&mut moves);
+        let mut policies: Vec<ExplainPolicy<S>> =
+            moves.iter().map(|_| ExplainPolicy::new(params)).collect();
+
+        self.features_for_moves(&mut policies, &moves, &mut vec![], &self.group_data());
+
+        let num_moves = moves.len();
+        moves
+            .into_iter()
+            .zip(policies)
+            .map(|(mv, mut policy)| {
+                policy.finish(num_moves);
+                MoveExplanation {
+                    mv,
+                    groups: policy.group_contributions(),
+                    offset: policy.offset,
+                }
+            })
+            .collect()
+    }
+}
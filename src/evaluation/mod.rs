@@ -1,3 +1,6 @@
+pub mod explain;
+pub mod network;
+pub mod parameter_file;
 pub mod parameters;
 pub mod policy_eval;
 pub mod value_eval;
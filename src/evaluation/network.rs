@@ -0,0 +1,328 @@
+//! Small neural network evaluators, as a non-linear alternative to the linear value and policy parameters.
+//!
+//! A network is a multi-layer perceptron with one hidden ReLU layer, over the same feature vectors as the linear evaluation.
+//! Like the linear parameters, a network is stored as a flat `&'static [f32]`: First the hidden layer's weights, one row of `num_inputs` weights per hidden neuron,
+//! then the hidden layer's biases, the output weights, and finally the output bias. The number of hidden neurons is derived from the length.
+//!
+//! The value network's output is on the same scale as the linear static evaluation, and the policy network's output gets the same offset and sigmoid as the linear policy.
+
+use std::io::{self, BufRead, Write};
+use std::{array, fmt};
+
+use half::f16;
+use rand::Rng;
+
+use super::parameter_file::{invalid_data, read_params};
+use super::parameters::{
+    num_features_for_size, num_policy_features, num_value_features, IndexPair, PolicyApplier,
+    Value, ValueApplier,
+};
+use super::policy_eval::{policy_offset, sigmoid};
+
+const MAGIC: &str = "tiltak-network";
+
+pub const fn num_network_params(num_inputs: usize, num_hidden: usize) -> usize {
+    num_hidden * (num_inputs + 2) + 1
+}
+
+/// A view of a network's parameters
+#[derive(Clone, Copy, Debug)]
+pub struct Network<'a> {
+    num_inputs: usize,
+    num_hidden: usize,
+    params: &'a [f32],
+}
+
+impl<'a> Network<'a> {
+    /// Returns `None` if the number of parameters does not fit any network with `num_inputs` inputs
+    pub fn new(params: &'a [f32], num_inputs: usize) -> Option<Self> {
+        if params.is_empty() || !(params.len() - 1).is_multiple_of(num_inputs + 2) {
+            return None;
+        }
+        Some(Network {
+            num_inputs,
+            num_hidden: (params.len() - 1) / (num_inputs + 2),
+            params,
+        })
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_hidden(&self) -> usize {
+        self.num_hidden
+    }
+
+    pub fn hidden_weights(&self) -> &'a [f32] {
+        &self.params[..self.num_hidden * self.num_inputs]
+    }
+
+    pub fn hidden_biases(&self) -> &'a [f32] {
+        let start = self.num_hidden * self.num_inputs;
+        &self.params[start..start + self.num_hidden]
+    }
+
+    pub fn output_weights(&self) -> &'a [f32] {
+        let start = self.num_hidden * (self.num_inputs + 1);
+        &self.params[start..start + self.num_hidden]
+    }
+
+    pub fn output_bias(&self) -> f32 {
+        self.params[self.params.len() - 1]
+    }
+
+    /// The network's output, before any sigmoid
+    pub fn forward(&self, inputs: &[f32]) -> f32 {
+        self.forward_with_hidden(inputs, |_, _| ())
+    }
+
+    /// Like `forward`, but also calls `on_hidden` with each hidden neuron's index and activation, for training
+    pub fn forward_with_hidden<F: FnMut(usize, f32)>(
+        &self,
+        inputs: &[f32],
+        mut on_hidden: F,
+    ) -> f32 {
+        assert_eq!(inputs.len(), self.num_inputs);
+        let mut output = self.output_bias();
+        for (i, ((weights, bias), output_weight)) in self
+            .hidden_weights()
+            .chunks_exact(self.num_inputs)
+            .zip(self.hidden_biases())
+            .zip(self.output_weights())
+            .enumerate()
+        {
+            let activation = f32::max(0.0, dot(weights, inputs) + bias);
+            on_hidden(i, activation);
+            output += activation * output_weight;
+        }
+        output
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    const SIMD_WIDTH: usize = 8;
+    let partial_sums: [f32; SIMD_WIDTH] = a
+        .chunks_exact(SIMD_WIDTH)
+        .zip(b.chunks_exact(SIMD_WIDTH))
+        .fold([0.0; SIMD_WIDTH], |acc, (c, p)| {
+            array::from_fn(|i| acc[i] + c[i] * p[i])
+        });
+    let remainder = a.len() - a.len() % SIMD_WIDTH;
+    partial_sums.iter().sum::<f32>()
+        + a[remainder..]
+            .iter()
+            .zip(&b[remainder..])
+            .map(|(c, p)| c * p)
+            .sum::<f32>()
+}
+
+/// Randomly initialized parameters for a network. The output weights start small, so that a new network's evaluations are close to neutral
+pub fn random_network_params<R: Rng>(
+    num_inputs: usize,
+    num_hidden: usize,
+    rng: &mut R,
+) -> Vec<f32> {
+    let hidden_range = 1.0 / (num_inputs as f32).sqrt();
+    let output_range = 0.1 / (num_hidden as f32).sqrt();
+    let mut params = Vec::with_capacity(num_network_params(num_inputs, num_hidden));
+    params.extend((0..num_hidden * num_inputs).map(|_| rng.gen_range(-hidden_range..hidden_range)));
+    params.extend((0..num_hidden).map(|_| 0.0));
+    params.extend((0..num_hidden).map(|_| rng.gen_range(-output_range..output_range)));
+    params.push(0.0);
+    params
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkKind {
+    Value,
+    Policy,
+}
+
+impl NetworkKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            NetworkKind::Value => "value",
+            NetworkKind::Policy => "policy",
+        }
+    }
+
+    /// The length of the network's feature vector for the given size, or `None` if the size is not supported
+    pub fn num_inputs(self, size: usize) -> Option<usize> {
+        let (num_value_features, num_policy_features) = num_features_for_size(size)?;
+        match self {
+            NetworkKind::Value => Some(num_value_features),
+            NetworkKind::Policy => Some(num_policy_features),
+        }
+    }
+}
+
+impl fmt::Display for NetworkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A network stored in a text file. The first line is `tiltak-network <kind> <size> <num_hidden>`, followed by the parameters, one per line
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkFile {
+    pub kind: NetworkKind,
+    pub size: usize,
+    pub params: Vec<f32>,
+}
+
+impl NetworkFile {
+    pub fn network(&self) -> Network<'_> {
+        Network::new(&self.params, self.kind.num_inputs(self.size).unwrap()).unwrap()
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{} {} {} {}",
+            MAGIC,
+            self.kind,
+            self.size,
+            self.network().num_hidden()
+        )?;
+        for param in self.params.iter() {
+            writeln!(writer, "{}", param)?;
+        }
+        writer.flush()
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid_data("Empty network file".to_string()))??;
+        let words: Vec<&str> = header.split_whitespace().collect();
+        let (kind, size, num_hidden) = match words[..] {
+            [MAGIC, kind, size, num_hidden] => (kind, size, num_hidden),
+            _ => {
+                return Err(invalid_data(format!(
+                    "Invalid network header \"{}\"",
+                    header
+                )))
+            }
+        };
+        let kind = match kind {
+            "value" => NetworkKind::Value,
+            "policy" => NetworkKind::Policy,
+            _ => return Err(invalid_data(format!("Invalid network kind \"{}\"", kind))),
+        };
+        let (num_inputs, size) = size
+            .parse()
+            .ok()
+            .and_then(|size| Some((kind.num_inputs(size)?, size)))
+            .ok_or_else(|| invalid_data(format!("Unsupported network size \"{}\"", size)))?;
+        let num_hidden: usize = num_hidden
+            .parse()
+            .map_err(|_| invalid_data(format!("Invalid hidden layer size \"{}\"", num_hidden)))?;
+
+        let params = read_params(lines)?;
+
+        if params.len() != num_network_params(num_inputs, num_hidden) {
+            return Err(invalid_data(format!(
+                "Expected {} parameters for a {}s {} network with {} hidden neurons, got {}",
+                num_network_params(num_inputs, num_hidden),
+                size,
+                kind,
+                num_hidden,
+                params.len()
+            )));
+        }
+        Ok(NetworkFile { kind, size, params })
+    }
+}
+
+/// Evaluates a move with a policy network. Used in place of `IncrementalPolicy` when the search has a policy network
+#[derive(Debug, Clone)]
+pub struct NetworkPolicy<const S: usize> {
+    features: Vec<f32>,
+    parameters: &'static [f32],
+    has_immediate_win: bool,
+}
+
+impl<const S: usize> PolicyApplier for NetworkPolicy<S> {
+    fn new(parameters: &'static [f32]) -> Self {
+        NetworkPolicy {
+            features: vec![0.0; num_policy_features::<S>()],
+            parameters,
+            has_immediate_win: false,
+        }
+    }
+
+    fn eval(&mut self, index_pair: IndexPair, index: usize, val: f16) {
+        index_pair.as_mut_slice(&mut self.features)[index] += val.to_f32()
+    }
+
+    fn eval_i8(&mut self, index_pair: IndexPair, index: usize, val: i8) {
+        index_pair.as_mut_slice(&mut self.features)[index] += val as f32
+    }
+
+    fn eval_f32(&mut self, index_pair: IndexPair, index: usize, val: f32) {
+        index_pair.as_mut_slice(&mut self.features)[index] += val
+    }
+
+    fn set_immediate_win(&mut self) {
+        self.has_immediate_win = true
+    }
+
+    fn has_immediate_win(&self) -> bool {
+        self.has_immediate_win
+    }
+
+    fn finish(&mut self, num_moves: usize) -> f16 {
+        if num_moves < 2 {
+            eprintln!("Warning: Got {} legal moves", num_moves,);
+        }
+        let offset = policy_offset(num_moves);
+        let network = Network::new(self.parameters, num_policy_features::<S>())
+            .expect("Policy network has the wrong number of parameters");
+        let total_value = network.forward(&self.features) + offset;
+
+        self.features.fill(0.0);
+        self.has_immediate_win = false;
+
+        f16::from_f32(sigmoid(total_value))
+    }
+}
+
+/// Feature vectors for evaluating a position with a value network, re-used between evaluations to avoid allocations
+#[derive(Debug, Clone)]
+pub struct NetworkValue<const S: usize> {
+    pub(crate) white_value: Value<S>,
+    pub(crate) black_value: Value<S>,
+    inputs: Vec<f32>,
+}
+
+impl<const S: usize> Default for NetworkValue<S> {
+    fn default() -> Self {
+        NetworkValue {
+            white_value: Value::new(&[]),
+            black_value: Value::new(&[]),
+            inputs: Vec::with_capacity(num_value_features::<S>()),
+        }
+    }
+}
+
+impl<const S: usize> NetworkValue<S> {
+    /// Evaluate the features collected in `white_value` and `black_value`, and reset them for the next position
+    pub(crate) fn finish(&mut self, network: &'static [f32]) -> f32 {
+        self.inputs.clear();
+        self.inputs.extend(
+            self.white_value
+                .features
+                .iter()
+                .chain(self.black_value.features.iter())
+                .map(|feature| feature.to_f32()),
+        );
+        self.white_value.features.fill(f16::ZERO);
+        self.black_value.features.fill(f16::ZERO);
+
+        Network::new(network, num_value_features::<S>())
+            .expect("Value network has the wrong number of parameters")
+            .forward(&self.inputs)
+    }
+}
@@ -0,0 +1,176 @@
+//! Value and policy parameters stored in text files, so that newly tuned parameters can be used without recompiling.
+//!
+//! The first line is `tiltak-params <version> <kind> <size> <komi> <num_features>`, followed by the parameters, one per line.
+//! Like the compiled-in parameters, a file is only valid for one board size and komi.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::{fmt, fs};
+
+use super::parameters::{num_features_for_size, num_policy_features, num_value_features};
+use crate::position::Komi;
+
+const MAGIC: &str = "tiltak-params";
+
+/// Increment when the meaning of the parameters changes, for example when features are added
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterKind {
+    Value,
+    Policy,
+}
+
+impl ParameterKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ParameterKind::Value => "value",
+            ParameterKind::Policy => "policy",
+        }
+    }
+
+    /// The number of parameters for the given size, or `None` if the size is not supported
+    pub fn num_features(self, size: usize) -> Option<usize> {
+        let (num_value_features, num_policy_features) = num_features_for_size(size)?;
+        match self {
+            ParameterKind::Value => Some(num_value_features),
+            ParameterKind::Policy => Some(num_policy_features),
+        }
+    }
+}
+
+impl fmt::Display for ParameterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterFile {
+    pub kind: ParameterKind,
+    pub size: usize,
+    pub komi: Komi,
+    pub params: Vec<f32>,
+}
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the parameters after a file's header, one per line. Empty lines are skipped
+pub(crate) fn read_params<R: BufRead>(lines: io::Lines<R>) -> io::Result<Vec<f32>> {
+    lines
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            line.trim()
+                .parse()
+                .map_err(|_| invalid_data(format!("Invalid parameter \"{}\"", line)))
+        })
+        .collect()
+}
+
+impl ParameterFile {
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{} {} {} {} {} {}",
+            MAGIC,
+            VERSION,
+            self.kind,
+            self.size,
+            self.komi,
+            self.params.len()
+        )?;
+        for param in self.params.iter() {
+            writeln!(writer, "{}", param)?;
+        }
+        writer.flush()
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid_data("Empty parameter file".to_string()))??;
+        let words: Vec<&str> = header.split_whitespace().collect();
+        let (version, kind, size, komi, num_features) = match words[..] {
+            [MAGIC, version, kind, size, komi, num_features] => {
+                (version, kind, size, komi, num_features)
+            }
+            _ => {
+                return Err(invalid_data(format!(
+                    "Invalid parameter file header \"{}\"",
+                    header
+                )))
+            }
+        };
+        if version != VERSION.to_string() {
+            return Err(invalid_data(format!(
+                "Unsupported parameter file version {}, expected {}",
+                version, VERSION
+            )));
+        }
+        let kind = match kind {
+            "value" => ParameterKind::Value,
+            "policy" => ParameterKind::Policy,
+            _ => return Err(invalid_data(format!("Invalid parameter kind \"{}\"", kind))),
+        };
+        let (expected_num_features, size) = size
+            .parse()
+            .ok()
+            .and_then(|size| Some((kind.num_features(size)?, size)))
+            .ok_or_else(|| invalid_data(format!("Unsupported size \"{}\"", size)))?;
+        let komi: Komi = komi.parse().map_err(invalid_data)?;
+        if num_features != expected_num_features.to_string() {
+            return Err(invalid_data(format!(
+                "{}s {} parameters have {} features, but the file has {}",
+                size, kind, expected_num_features, num_features
+            )));
+        }
+
+        let params = read_params(lines)?;
+
+        if params.len() != expected_num_features {
+            return Err(invalid_data(format!(
+                "Expected {} parameters for {}s {}, got {}",
+                expected_num_features,
+                size,
+                kind,
+                params.len()
+            )));
+        }
+        Ok(ParameterFile {
+            kind,
+            size,
+            komi,
+            params,
+        })
+    }
+
+    /// Read a parameter file from disk, and check that it has the expected kind
+    pub fn load<P: AsRef<Path>>(path: P, kind: ParameterKind) -> io::Result<Self> {
+        let parameter_file = Self::read(BufReader::new(fs::File::open(path)?))?;
+        if parameter_file.kind != kind {
+            return Err(invalid_data(format!(
+                "Expected {} parameters, got {} parameters",
+                kind, parameter_file.kind
+            )));
+        }
+        Ok(parameter_file)
+    }
+
+    /// The parameters, if the file is for size `S` and the given komi
+    pub fn params_for<const S: usize>(&self, komi: Komi) -> Option<&[f32]> {
+        let num_features = match self.kind {
+            ParameterKind::Value => num_value_features::<S>(),
+            ParameterKind::Policy => num_policy_features::<S>(),
+        };
+        if self.size == S && self.komi == komi {
+            assert_eq!(self.params.len(), num_features);
+            Some(&self.params)
+        } else {
+            None
+        }
+    }
+}
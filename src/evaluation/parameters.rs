@@ -1,14 +1,21 @@
+use std::sync::OnceLock;
 use std::{array, mem};
 
 use half::f16;
 
 use crate::{
     evaluation::policy_eval::sigmoid,
-    position::{num_line_symmetries, num_square_symmetries, Komi},
+    position::{
+        line_symmetries, lookup_square_symmetries, num_line_symmetries, num_square_symmetries,
+        squares_iterator, Komi, Square,
+    },
 };
 
 use super::policy_eval::policy_offset;
 
+pub const NUM_VALUE_FEATURES_3S: usize = 384;
+pub const NUM_POLICY_FEATURES_3S: usize = 176;
+
 pub const NUM_VALUE_FEATURES_4S: usize = 400;
 pub const NUM_POLICY_FEATURES_4S: usize = 184;
 
@@ -18,20 +25,32 @@ pub const NUM_POLICY_FEATURES_5S: usize = 216;
 pub const NUM_VALUE_FEATURES_6S: usize = 560;
 pub const NUM_POLICY_FEATURES_6S: usize = 224;
 
+pub const NUM_VALUE_FEATURES_7S: usize = 736;
+pub const NUM_POLICY_FEATURES_7S: usize = 256;
+
+pub const NUM_VALUE_FEATURES_8S: usize = 768;
+pub const NUM_POLICY_FEATURES_8S: usize = 264;
+
 const fn value_padding<const S: usize>() -> usize {
     match S {
+        3 => 12,
         4 => 14,
         5 => 10,
         6 => 14,
+        7 => 0,
+        8 => 6,
         _ => unimplemented!(),
     }
 }
 
 const fn policy_padding<const S: usize>() -> usize {
     match S {
+        3 => 5,
         4 => 3,
         5 => 7,
         6 => 5,
+        7 => 3,
+        8 => 1,
         _ => unimplemented!(),
     }
 }
@@ -54,6 +73,14 @@ impl IndexPair {
     pub fn as_mut_slice<'a, T>(&self, slice: &'a mut [T]) -> &'a mut [T] {
         &mut slice[self.start..self.start + self.length]
     }
+
+    /// The range from the start of `first` to the end of `last`
+    fn between(first: IndexPair, last: IndexPair) -> IndexPair {
+        IndexPair {
+            start: first.start,
+            length: last.start + last.length - first.start,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -236,11 +263,135 @@ impl<const S: usize> ValueIndexes<S> {
     }
 }
 
+pub const VALUE_INDEXES_3S: ValueIndexes<3> = ValueIndexes::new();
 pub const VALUE_INDEXES_4S: ValueIndexes<4> = ValueIndexes::new();
 pub const VALUE_INDEXES_5S: ValueIndexes<5> = ValueIndexes::new();
 pub const VALUE_INDEXES_6S: ValueIndexes<6> = ValueIndexes::new();
+pub const VALUE_INDEXES_7S: ValueIndexes<7> = ValueIndexes::new();
+pub const VALUE_INDEXES_8S: ValueIndexes<8> = ValueIndexes::new();
 
 impl<const S: usize> ValueIndexes<S> {
+    /// All the features that are indexed by square symmetry
+    fn psqts(&self) -> [IndexPair; 16] {
+        [
+            self.second_ply,
+            self.flat_psqt_opening,
+            self.flat_psqt_middlegame,
+            self.flat_psqt_endgame,
+            self.wall_psqt_opening,
+            self.wall_psqt_middlegame,
+            self.wall_psqt_endgame,
+            self.cap_psqt_opening,
+            self.cap_psqt_middlegame,
+            self.cap_psqt_endgame,
+            self.supports_psqt_opening,
+            self.supports_psqt_middlegame,
+            self.supports_psqt_endgame,
+            self.captives_psqt_opening,
+            self.captives_psqt_middlegame,
+            self.captives_psqt_endgame,
+        ]
+    }
+
+    /// Every feature group, named as its field
+    pub fn named_groups(&self) -> [(&'static str, IndexPair); 54] {
+        [
+            ("first_ply", self.first_ply),
+            ("second_ply", self.second_ply),
+            ("flat_psqt_opening", self.flat_psqt_opening),
+            ("flat_psqt_middlegame", self.flat_psqt_middlegame),
+            ("flat_psqt_endgame", self.flat_psqt_endgame),
+            ("wall_psqt_opening", self.wall_psqt_opening),
+            ("wall_psqt_middlegame", self.wall_psqt_middlegame),
+            ("wall_psqt_endgame", self.wall_psqt_endgame),
+            ("cap_psqt_opening", self.cap_psqt_opening),
+            ("cap_psqt_middlegame", self.cap_psqt_middlegame),
+            ("cap_psqt_endgame", self.cap_psqt_endgame),
+            ("supports_psqt_opening", self.supports_psqt_opening),
+            ("supports_psqt_middlegame", self.supports_psqt_middlegame),
+            ("supports_psqt_endgame", self.supports_psqt_endgame),
+            ("captives_psqt_opening", self.captives_psqt_opening),
+            ("captives_psqt_middlegame", self.captives_psqt_middlegame),
+            ("captives_psqt_endgame", self.captives_psqt_endgame),
+            ("flat_win_this_ply", self.flat_win_this_ply),
+            ("flat_win_next_ply", self.flat_win_next_ply),
+            ("flat_win_two_ply", self.flat_win_two_ply),
+            ("flat_win_three_ply", self.flat_win_three_ply),
+            ("one_reserve_left_us", self.one_reserve_left_us),
+            ("one_reserve_left_them", self.one_reserve_left_them),
+            (
+                "shallow_supports_per_piece",
+                self.shallow_supports_per_piece,
+            ),
+            (
+                "shallow_supports_per_piece_mobility",
+                self.shallow_supports_per_piece_mobility,
+            ),
+            (
+                "shallow_supports_per_piece_mob_scaled",
+                self.shallow_supports_per_piece_mob_scaled,
+            ),
+            ("deep_supports_per_piece", self.deep_supports_per_piece),
+            (
+                "shallow_captives_per_piece",
+                self.shallow_captives_per_piece,
+            ),
+            (
+                "shallow_captives_per_piece_mobility",
+                self.shallow_captives_per_piece_mobility,
+            ),
+            (
+                "shallow_captives_per_piece_mob_scaled",
+                self.shallow_captives_per_piece_mob_scaled,
+            ),
+            ("deep_captives_per_piece", self.deep_captives_per_piece),
+            (
+                "to_move_opening_flatstone_lead",
+                self.to_move_opening_flatstone_lead,
+            ),
+            (
+                "to_move_middlegame_flatstone_lead",
+                self.to_move_middlegame_flatstone_lead,
+            ),
+            (
+                "to_move_endgame_flatstone_lead",
+                self.to_move_endgame_flatstone_lead,
+            ),
+            ("i_number_of_groups", self.i_number_of_groups),
+            ("critical_squares", self.critical_squares),
+            (
+                "critical_square_cap_attack",
+                self.critical_square_cap_attack,
+            ),
+            ("winning_spread_to_move", self.winning_spread_to_move),
+            (
+                "winning_flat_spread_not_to_move",
+                self.winning_flat_spread_not_to_move,
+            ),
+            (
+                "winning_cap_spread_not_to_move",
+                self.winning_cap_spread_not_to_move,
+            ),
+            ("flat_next_to_our_stack", self.flat_next_to_our_stack),
+            ("wall_next_to_our_stack", self.wall_next_to_our_stack),
+            ("cap_next_to_our_stack", self.cap_next_to_our_stack),
+            ("num_lines_occupied", self.num_lines_occupied),
+            ("line_control_empty", self.line_control_empty),
+            (
+                "line_control_their_blocking_piece",
+                self.line_control_their_blocking_piece,
+            ),
+            ("line_control_other", self.line_control_other),
+            ("line_control_guarded_flat", self.line_control_guarded_flat),
+            ("line_control_guarded_wall", self.line_control_guarded_wall),
+            ("line_control_guarded_cap", self.line_control_guarded_cap),
+            ("sidelined_cap", self.sidelined_cap),
+            ("fully_isolated_cap", self.fully_isolated_cap),
+            ("semi_isolated_cap", self.semi_isolated_cap),
+            ("padding", self.padding),
+        ]
+    }
+
     pub const fn downcast_size<const N: usize>(self) -> ValueIndexes<N> {
         if S == N {
             unsafe { mem::transmute::<ValueIndexes<S>, ValueIndexes<N>>(self) }
@@ -252,9 +403,12 @@ impl<const S: usize> ValueIndexes<S> {
 
 pub const fn value_indexes<const S: usize>() -> ValueIndexes<S> {
     match S {
+        3 => VALUE_INDEXES_3S.downcast_size(),
         4 => VALUE_INDEXES_4S.downcast_size(),
         5 => VALUE_INDEXES_5S.downcast_size(),
         6 => VALUE_INDEXES_6S.downcast_size(),
+        7 => VALUE_INDEXES_7S.downcast_size(),
+        8 => VALUE_INDEXES_8S.downcast_size(),
         _ => panic!(),
     }
 }
@@ -324,11 +478,147 @@ pub struct PolicyIndexes<const S: usize> {
     pub padding: IndexPair,
 }
 
+pub const POLICY_INDEXES_3S: PolicyIndexes<3> = PolicyIndexes::new();
 pub const POLICY_INDEXES_4S: PolicyIndexes<4> = PolicyIndexes::new();
 pub const POLICY_INDEXES_5S: PolicyIndexes<5> = PolicyIndexes::new();
 pub const POLICY_INDEXES_6S: PolicyIndexes<6> = PolicyIndexes::new();
+pub const POLICY_INDEXES_7S: PolicyIndexes<7> = PolicyIndexes::new();
+pub const POLICY_INDEXES_8S: PolicyIndexes<8> = PolicyIndexes::new();
 
 impl<const S: usize> PolicyIndexes<S> {
+    /// All the features that are indexed by square symmetry
+    fn psqts(&self) -> [IndexPair; 6] {
+        [
+            self.flat_psqt_white,
+            self.flat_psqt_black,
+            self.wall_psqt_white,
+            self.wall_psqt_black,
+            self.cap_psqt_white,
+            self.cap_psqt_black,
+        ]
+    }
+
+    /// Every feature group, named as its field
+    pub fn named_groups(&self) -> [(&'static str, IndexPair); 61] {
+        [
+            ("flat_psqt_white", self.flat_psqt_white),
+            ("flat_psqt_black", self.flat_psqt_black),
+            ("wall_psqt_white", self.wall_psqt_white),
+            ("wall_psqt_black", self.wall_psqt_black),
+            ("cap_psqt_white", self.cap_psqt_white),
+            ("cap_psqt_black", self.cap_psqt_black),
+            ("move_role_bonus_white", self.move_role_bonus_white),
+            ("move_role_bonus_black", self.move_role_bonus_black),
+            ("decline_win", self.decline_win),
+            ("place_to_win", self.place_to_win),
+            ("place_to_draw", self.place_to_draw),
+            ("place_to_loss", self.place_to_loss),
+            (
+                "place_to_allow_opponent_to_end",
+                self.place_to_allow_opponent_to_end,
+            ),
+            ("two_flats_left", self.two_flats_left),
+            ("three_flats_left", self.three_flats_left),
+            ("our_road_stones_in_line", self.our_road_stones_in_line),
+            ("their_road_stones_in_line", self.their_road_stones_in_line),
+            ("extend_single_group_base", self.extend_single_group_base),
+            (
+                "extend_single_group_linear",
+                self.extend_single_group_linear,
+            ),
+            (
+                "extend_single_group_to_new_line_base",
+                self.extend_single_group_to_new_line_base,
+            ),
+            (
+                "extend_single_group_to_new_line_linear",
+                self.extend_single_group_to_new_line_linear,
+            ),
+            ("merge_two_groups_base", self.merge_two_groups_base),
+            ("merge_two_groups_linear", self.merge_two_groups_linear),
+            ("block_merger_base", self.block_merger_base),
+            ("block_merger_linear", self.block_merger_linear),
+            ("anchor_group_base", self.anchor_group_base),
+            ("anchor_group_linear", self.anchor_group_linear),
+            (
+                "block_anchoring_group_base",
+                self.block_anchoring_group_base,
+            ),
+            (
+                "block_anchoring_group_linear",
+                self.block_anchoring_group_linear,
+            ),
+            ("place_our_critical_square", self.place_our_critical_square),
+            (
+                "place_their_critical_square",
+                self.place_their_critical_square,
+            ),
+            (
+                "ignore_their_critical_square",
+                self.ignore_their_critical_square,
+            ),
+            ("next_to_our_last_stone", self.next_to_our_last_stone),
+            ("next_to_their_last_stone", self.next_to_their_last_stone),
+            (
+                "diagonal_to_our_last_stone",
+                self.diagonal_to_our_last_stone,
+            ),
+            (
+                "diagonal_to_their_last_stone",
+                self.diagonal_to_their_last_stone,
+            ),
+            ("attack_strong_flats", self.attack_strong_flats),
+            (
+                "blocking_stone_blocks_extensions_of_two_flats",
+                self.blocking_stone_blocks_extensions_of_two_flats,
+            ),
+            (
+                "attack_strong_stack_with_wall",
+                self.attack_strong_stack_with_wall,
+            ),
+            (
+                "attack_strong_stack_with_cap",
+                self.attack_strong_stack_with_cap,
+            ),
+            ("attack_last_movement", self.attack_last_movement),
+            ("place_last_movement", self.place_last_movement),
+            ("simple_movement", self.simple_movement),
+            ("simple_capture", self.simple_capture),
+            ("simple_self_capture", self.simple_self_capture),
+            ("pure_spread", self.pure_spread),
+            ("fcd_highest_board", self.fcd_highest_board),
+            ("fcd_highest_stack", self.fcd_highest_stack),
+            ("fcd_other", self.fcd_other),
+            (
+                "stack_captured_by_movement",
+                self.stack_captured_by_movement,
+            ),
+            (
+                "stack_capture_in_strong_line",
+                self.stack_capture_in_strong_line,
+            ),
+            (
+                "stack_capture_in_strong_line_cap",
+                self.stack_capture_in_strong_line_cap,
+            ),
+            ("move_cap_onto_strong_line", self.move_cap_onto_strong_line),
+            (
+                "move_cap_onto_strong_line_with_critical_square",
+                self.move_cap_onto_strong_line_with_critical_square,
+            ),
+            ("recapture_stack_pure", self.recapture_stack_pure),
+            ("recapture_stack_impure", self.recapture_stack_impure),
+            ("move_last_placement", self.move_last_placement),
+            ("continue_spread", self.continue_spread),
+            ("move_onto_critical_square", self.move_onto_critical_square),
+            (
+                "spread_that_connects_groups_to_win",
+                self.spread_that_connects_groups_to_win,
+            ),
+            ("padding", self.padding),
+        ]
+    }
+
     pub const fn downcast_size<const N: usize>(self) -> PolicyIndexes<N> {
         if S == N {
             unsafe { mem::transmute::<PolicyIndexes<S>, PolicyIndexes<N>>(self) }
@@ -340,9 +630,12 @@ impl<const S: usize> PolicyIndexes<S> {
 
 pub const fn policy_indexes<const S: usize>() -> PolicyIndexes<S> {
     match S {
+        3 => POLICY_INDEXES_3S.downcast_size(),
         4 => POLICY_INDEXES_4S.downcast_size(),
         5 => POLICY_INDEXES_5S.downcast_size(),
         6 => POLICY_INDEXES_6S.downcast_size(),
+        7 => POLICY_INDEXES_7S.downcast_size(),
+        8 => POLICY_INDEXES_8S.downcast_size(),
         _ => panic!(),
     }
 }
@@ -673,22 +966,41 @@ impl<const S: usize> PolicyApplier for IncrementalPolicy<S> {
 
 pub const fn num_value_features<const S: usize>() -> usize {
     match S {
+        3 => NUM_VALUE_FEATURES_3S,
         4 => NUM_VALUE_FEATURES_4S,
         5 => NUM_VALUE_FEATURES_5S,
         6 => NUM_VALUE_FEATURES_6S,
+        7 => NUM_VALUE_FEATURES_7S,
+        8 => NUM_VALUE_FEATURES_8S,
         _ => unimplemented!(),
     }
 }
 
 pub const fn num_policy_features<const S: usize>() -> usize {
     match S {
+        3 => NUM_POLICY_FEATURES_3S,
         4 => NUM_POLICY_FEATURES_4S,
         5 => NUM_POLICY_FEATURES_5S,
         6 => NUM_POLICY_FEATURES_6S,
+        7 => NUM_POLICY_FEATURES_7S,
+        8 => NUM_POLICY_FEATURES_8S,
         _ => unimplemented!(),
     }
 }
 
+/// The number of value and policy features for a size only known at runtime, or `None` if the size is not supported
+pub fn num_features_for_size(size: usize) -> Option<(usize, usize)> {
+    match size {
+        3 => Some((NUM_VALUE_FEATURES_3S, NUM_POLICY_FEATURES_3S)),
+        4 => Some((NUM_VALUE_FEATURES_4S, NUM_POLICY_FEATURES_4S)),
+        5 => Some((NUM_VALUE_FEATURES_5S, NUM_POLICY_FEATURES_5S)),
+        6 => Some((NUM_VALUE_FEATURES_6S, NUM_POLICY_FEATURES_6S)),
+        7 => Some((NUM_VALUE_FEATURES_7S, NUM_POLICY_FEATURES_7S)),
+        8 => Some((NUM_VALUE_FEATURES_8S, NUM_POLICY_FEATURES_8S)),
+        _ => None,
+    }
+}
+
 pub fn value_features_4s(komi: Komi) -> &'static [f32; NUM_VALUE_FEATURES_4S] {
     match komi.half_komi() {
         0 => &VALUE_PARAMS_4S_0KOMI,
@@ -737,6 +1049,281 @@ pub fn policy_features_6s(komi: Komi) -> &'static [f32; NUM_POLICY_FEATURES_6S]
     }
 }
 
+// There are no tuned parameters for 3s, 7s and 8s yet.
+// Instead, they are generated on first use from the closest size that has them
+
+static VALUE_PARAMS_3S: [OnceLock<[f32; NUM_VALUE_FEATURES_3S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+static POLICY_PARAMS_3S: [OnceLock<[f32; NUM_POLICY_FEATURES_3S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+static VALUE_PARAMS_7S: [OnceLock<[f32; NUM_VALUE_FEATURES_7S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+static POLICY_PARAMS_7S: [OnceLock<[f32; NUM_POLICY_FEATURES_7S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+static VALUE_PARAMS_8S: [OnceLock<[f32; NUM_VALUE_FEATURES_8S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+static POLICY_PARAMS_8S: [OnceLock<[f32; NUM_POLICY_FEATURES_8S]>; 2] =
+    [OnceLock::new(), OnceLock::new()];
+
+/// Index into the generated parameter tables, which exist for 0 and 2 komi
+fn generated_komi_index(komi: Komi, size: usize) -> usize {
+    match komi.half_komi() {
+        0 => 0,
+        4 => 1,
+        _ => unimplemented!("{}s is not supported for {} komi.", size, komi),
+    }
+}
+
+pub fn value_features_3s(komi: Komi) -> &'static [f32; NUM_VALUE_FEATURES_3S] {
+    VALUE_PARAMS_3S[generated_komi_index(komi, 3)].get_or_init(|| {
+        generate_value_params::<4, 3, NUM_VALUE_FEATURES_3S>(value_features_4s(komi))
+    })
+}
+
+pub fn policy_features_3s(komi: Komi) -> &'static [f32; NUM_POLICY_FEATURES_3S] {
+    POLICY_PARAMS_3S[generated_komi_index(komi, 3)].get_or_init(|| {
+        generate_policy_params::<4, 3, NUM_POLICY_FEATURES_3S>(policy_features_4s(komi))
+    })
+}
+
+pub fn value_features_7s(komi: Komi) -> &'static [f32; NUM_VALUE_FEATURES_7S] {
+    VALUE_PARAMS_7S[generated_komi_index(komi, 7)].get_or_init(|| {
+        generate_value_params::<6, 7, NUM_VALUE_FEATURES_7S>(value_features_6s(komi))
+    })
+}
+
+pub fn policy_features_7s(komi: Komi) -> &'static [f32; NUM_POLICY_FEATURES_7S] {
+    POLICY_PARAMS_7S[generated_komi_index(komi, 7)].get_or_init(|| {
+        generate_policy_params::<6, 7, NUM_POLICY_FEATURES_7S>(policy_features_6s(komi))
+    })
+}
+
+pub fn value_features_8s(komi: Komi) -> &'static [f32; NUM_VALUE_FEATURES_8S] {
+    VALUE_PARAMS_8S[generated_komi_index(komi, 8)].get_or_init(|| {
+        generate_value_params::<6, 8, NUM_VALUE_FEATURES_8S>(value_features_6s(komi))
+    })
+}
+
+pub fn policy_features_8s(komi: Komi) -> &'static [f32; NUM_POLICY_FEATURES_8S] {
+    POLICY_PARAMS_8S[generated_komi_index(komi, 8)].get_or_init(|| {
+        generate_policy_params::<6, 8, NUM_POLICY_FEATURES_8S>(policy_features_6s(komi))
+    })
+}
+
+/// Generate value parameters for size `S`, from the parameters of size `B`.
+/// Size-independent features are copied directly. Square tables are scaled to the new board size,
+/// and features that count road stones in a line are matched by how many stones are missing for a road.
+pub fn generate_value_params<const B: usize, const S: usize, const N: usize>(
+    base_params: &[f32],
+) -> [f32; N] {
+    assert_eq!(base_params.len(), num_value_features::<B>());
+    assert_eq!(N, num_value_features::<S>());
+
+    let base_indexes = value_indexes::<B>();
+    let indexes = value_indexes::<S>();
+    let mut params = [0.0; N];
+
+    let (base_white, base_black) = base_params.split_at(base_params.len() / 2);
+    let (white, black) = params.split_at_mut(N / 2);
+
+    for (base_params, params) in [(base_white, white), (base_black, black)] {
+        copy_range(
+            base_params,
+            params,
+            base_indexes.first_ply,
+            indexes.first_ply,
+        );
+
+        for (base_psqt, psqt) in base_indexes.psqts().into_iter().zip(indexes.psqts()) {
+            scale_psqt::<B, S>(base_params, params, base_psqt, psqt);
+        }
+
+        copy_range(
+            base_params,
+            params,
+            IndexPair::between(
+                base_indexes.flat_win_this_ply,
+                base_indexes.cap_next_to_our_stack,
+            ),
+            IndexPair::between(indexes.flat_win_this_ply, indexes.cap_next_to_our_stack),
+        );
+
+        for i in 0..=S {
+            let base_i = (i + B).saturating_sub(S).min(B);
+            indexes.num_lines_occupied.as_mut_slice(params)[i] =
+                base_indexes.num_lines_occupied.as_slice(base_params)[base_i];
+        }
+
+        for (base_line_control, line_control) in [
+            (base_indexes.line_control_empty, indexes.line_control_empty),
+            (
+                base_indexes.line_control_their_blocking_piece,
+                indexes.line_control_their_blocking_piece,
+            ),
+            (base_indexes.line_control_other, indexes.line_control_other),
+        ] {
+            for symmetry in 0..num_line_symmetries::<S>() {
+                let base_symmetry = scale_line_symmetry::<B, S>(symmetry);
+                for road_stones in 0..S {
+                    let base_road_stones = scale_road_stones::<B, S>(road_stones);
+                    line_control.as_mut_slice(params)[road_stones + symmetry * S] =
+                        base_line_control.as_slice(base_params)
+                            [base_road_stones + base_symmetry * B];
+                }
+            }
+        }
+
+        copy_range(
+            base_params,
+            params,
+            IndexPair::between(
+                base_indexes.line_control_guarded_flat,
+                base_indexes.semi_isolated_cap,
+            ),
+            IndexPair::between(indexes.line_control_guarded_flat, indexes.semi_isolated_cap),
+        );
+    }
+    params
+}
+
+/// Generate policy parameters for size `S`, from the parameters of size `B`.
+/// See `generate_value_params` for details.
+pub fn generate_policy_params<const B: usize, const S: usize, const N: usize>(
+    base_params: &[f32],
+) -> [f32; N] {
+    assert_eq!(base_params.len(), num_policy_features::<B>());
+    assert_eq!(N, num_policy_features::<S>());
+
+    let base_indexes = policy_indexes::<B>();
+    let indexes = policy_indexes::<S>();
+    let mut params = [0.0; N];
+
+    for (base_psqt, psqt) in base_indexes.psqts().into_iter().zip(indexes.psqts()) {
+        scale_psqt::<B, S>(base_params, &mut params, base_psqt, psqt);
+    }
+
+    copy_range(
+        base_params,
+        &mut params,
+        IndexPair::between(
+            base_indexes.move_role_bonus_white,
+            base_indexes.three_flats_left,
+        ),
+        IndexPair::between(indexes.move_role_bonus_white, indexes.three_flats_left),
+    );
+
+    for (base_road_stones_in_line, road_stones_in_line) in [
+        (
+            base_indexes.our_road_stones_in_line,
+            indexes.our_road_stones_in_line,
+        ),
+        (
+            base_indexes.their_road_stones_in_line,
+            indexes.their_road_stones_in_line,
+        ),
+    ] {
+        for role_id in 0..3 {
+            for road_stones in 0..S {
+                let base_road_stones = scale_road_stones::<B, S>(road_stones);
+                road_stones_in_line.as_mut_slice(&mut params)[S * role_id + road_stones] =
+                    base_road_stones_in_line.as_slice(base_params)[B * role_id + base_road_stones];
+            }
+        }
+    }
+
+    copy_range(
+        base_params,
+        &mut params,
+        IndexPair::between(
+            base_indexes.extend_single_group_to_new_line_base,
+            base_indexes.stack_captured_by_movement,
+        ),
+        IndexPair::between(
+            indexes.extend_single_group_to_new_line_base,
+            indexes.stack_captured_by_movement,
+        ),
+    );
+
+    for (base_strong_line, strong_line) in [
+        (
+            base_indexes.stack_capture_in_strong_line,
+            indexes.stack_capture_in_strong_line,
+        ),
+        (
+            base_indexes.stack_capture_in_strong_line_cap,
+            indexes.stack_capture_in_strong_line_cap,
+        ),
+        (
+            base_indexes.move_cap_onto_strong_line,
+            indexes.move_cap_onto_strong_line,
+        ),
+        (
+            base_indexes.move_cap_onto_strong_line_with_critical_square,
+            indexes.move_cap_onto_strong_line_with_critical_square,
+        ),
+    ] {
+        // These features are indexed by the number of road stones in the line, minus 3
+        for i in 0..S.saturating_sub(3) {
+            let base_i = (scale_road_stones::<B, S>(i + 3).max(3) - 3).min(B - 4);
+            strong_line.as_mut_slice(&mut params)[i] =
+                base_strong_line.as_slice(base_params)[base_i];
+        }
+    }
+
+    copy_range(
+        base_params,
+        &mut params,
+        IndexPair::between(
+            base_indexes.recapture_stack_pure,
+            base_indexes.spread_that_connects_groups_to_win,
+        ),
+        IndexPair::between(
+            indexes.recapture_stack_pure,
+            indexes.spread_that_connects_groups_to_win,
+        ),
+    );
+
+    params
+}
+
+fn copy_range(base_params: &[f32], params: &mut [f32], base_range: IndexPair, range: IndexPair) {
+    assert_eq!(base_range.length, range.length);
+    range
+        .as_mut_slice(params)
+        .copy_from_slice(base_range.as_slice(base_params));
+}
+
+/// Copy a square table from size `B` to size `S`, by picking the square at the same relative position on the other board
+fn scale_psqt<const B: usize, const S: usize>(
+    base_params: &[f32],
+    params: &mut [f32],
+    base_psqt: IndexPair,
+    psqt: IndexPair,
+) {
+    for square in squares_iterator::<S>() {
+        let scale = |i: u8| ((i as usize * (B - 1) + (S - 1) / 2) / (S - 1)) as u8;
+        let base_square = Square::<B>::from_rank_file(scale(square.rank()), scale(square.file()));
+        psqt.as_mut_slice(params)[lookup_square_symmetries(square)] =
+            base_psqt.as_slice(base_params)[lookup_square_symmetries(base_square)];
+    }
+}
+
+/// The line symmetry on size `B` that is closest to the given line symmetry on size `S`
+fn scale_line_symmetry<const B: usize, const S: usize>(symmetry: usize) -> usize {
+    let line = line_symmetries::<S>()
+        .iter()
+        .position(|sym| *sym == symmetry)
+        .unwrap();
+    let base_line = (line * (B - 1) + (S - 1) / 2) / (S - 1);
+    line_symmetries::<B>()[base_line]
+}
+
+/// Map a number of road stones in a line on size `S` to size `B`, such that the same number of stones are missing for a road
+/// Never returns a full line, which would be a road
+fn scale_road_stones<const B: usize, const S: usize>(road_stones: usize) -> usize {
+    (road_stones + B).saturating_sub(S).min(B - 1)
+}
+
 #[allow(clippy::unreadable_literal)]
 pub const VALUE_PARAMS_4S_0KOMI: [f32; NUM_VALUE_FEATURES_4S] = [
     2.4297364,
@@ -0,0 +1,151 @@
+//! Whole-game analysis, producing games annotated with the engine's evaluations.
+//!
+//! Each position in the game is searched once. A move's quality is the difference between the mover's win probability before the move,
+//! and their win probability after it.
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+
+use crate::position::{Move, Position};
+use crate::ptn::{Game, PtnMove};
+use crate::search::{MctsSetting, MonteCarloTree, PvLine};
+
+/// Moves that lose at least this much win probability are annotated with `?`
+pub const MISTAKE_THRESHOLD: f32 = 0.1;
+/// Moves that lose at least this much win probability are annotated with `??`
+pub const BLUNDER_THRESHOLD: f32 = 0.25;
+/// The best move is annotated with `!` if the second best move is worse by at least this much
+pub const ONLY_MOVE_THRESHOLD: f32 = 0.2;
+/// The engine's line is added as a variation to moves that lose at least this much win probability
+pub const VARIATION_THRESHOLD: f32 = 0.05;
+
+const MAX_LINE_LENGTH: usize = 8;
+
+struct PositionAnalysis<const S: usize> {
+    /// Win probability for the side to move
+    win_probability: f32,
+    /// The two best lines, or none if the game is over
+    lines: Vec<PvLine<S>>,
+}
+
+fn analyze_position<const S: usize>(
+    position: &Position<S>,
+    settings: &MctsSetting<S>,
+    nodes: u64,
+) -> PositionAnalysis<S> {
+    match position.game_result() {
+        Some(game_result) => PositionAnalysis {
+            win_probability: match (game_result, position.side_to_move()) {
+                (GameResult::WhiteWin, Color::White) | (GameResult::BlackWin, Color::Black) => 1.0,
+                (GameResult::WhiteWin, Color::Black) | (GameResult::BlackWin, Color::White) => 0.0,
+                (GameResult::Draw, _) => 0.5,
+            },
+            lines: vec![],
+        },
+        None => {
+            let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+            // If the arena fills up, use the search so far
+            let _ = tree.search_nodes(nodes, None);
+            let lines = tree.multi_pv(2);
+            PositionAnalysis {
+                win_probability: lines.first().map_or(0.5, |line| line.win_probability),
+                lines,
+            }
+        }
+    }
+}
+
+fn line_to_string<const S: usize>(line: &[Move<S>]) -> String {
+    line.iter()
+        .take(MAX_LINE_LENGTH)
+        .map(|mv| mv.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Search every position of the game for `nodes` nodes, and return the game annotated with the results.
+///
+/// Each move's comment is replaced with the mover's win probability after the move, and the engine's best line from there.
+/// Moves that lose win probability are annotated with `?` or `??`, and get the engine's line as a variation.
+/// `on_move` is called with the position before each move, and the annotated move, as soon as it has been analyzed.
+pub fn annotate_game<const S: usize>(
+    game: &Game<Position<S>>,
+    settings: MctsSetting<S>,
+    nodes: u64,
+    mut on_move: impl FnMut(&Position<S>, &PtnMove<Move<S>>),
+) -> Game<Position<S>> {
+    let mut position = game.start_position.clone();
+    let mut analysis = analyze_position(&position, &settings, nodes);
+    let mut annotated_moves = Vec::with_capacity(game.moves.len());
+
+    for ptn_move in game.moves.iter() {
+        let reverse_move = position.do_move(ptn_move.mv);
+        let next_analysis = analyze_position(&position, &settings, nodes);
+        position.reverse_move(reverse_move);
+
+        let win_probability = 1.0 - next_analysis.win_probability;
+        let loss = (analysis.win_probability - win_probability).max(0.0);
+
+        let mut annotations: Vec<&'static str> = ptn_move
+            .annotations
+            .iter()
+            .copied()
+            .filter(|annotation| *annotation != "?" && *annotation != "!")
+            .collect();
+        if loss >= BLUNDER_THRESHOLD {
+            annotations.extend(["?", "?"]);
+        } else if loss >= MISTAKE_THRESHOLD {
+            annotations.push("?");
+        } else if analysis.lines.len() > 1
+            && analysis.lines[0].mv == ptn_move.mv
+            && analysis.lines[0].win_probability - analysis.lines[1].win_probability
+                >= ONLY_MOVE_THRESHOLD
+        {
+            annotations.push("!");
+        }
+
+        let mut comment = format!("{:.1}%", win_probability * 100.0);
+        if let Some(best_line) = next_analysis.lines.first() {
+            comment.push_str(", best line ");
+            comment.push_str(&line_to_string(&best_line.pv));
+        }
+
+        let mut variations = ptn_move.variations.clone();
+        if let Some(best_line) = analysis.lines.first() {
+            if best_line.mv != ptn_move.mv && loss >= VARIATION_THRESHOLD {
+                let mut variation: Vec<PtnMove<Move<S>>> = best_line
+                    .pv
+                    .iter()
+                    .take(MAX_LINE_LENGTH)
+                    .map(|mv| PtnMove {
+                        mv: *mv,
+                        annotations: vec![],
+                        comment: String::new(),
+                        variations: vec![],
+                    })
+                    .collect();
+                variation[0].comment = format!("{:.1}%", best_line.win_probability * 100.0);
+                variations.insert(0, variation);
+            }
+        }
+
+        let annotated_move = PtnMove {
+            mv: ptn_move.mv,
+            annotations,
+            comment,
+            variations,
+        };
+        on_move(&position, &annotated_move);
+        annotated_moves.push(annotated_move);
+
+        position.do_move(ptn_move.mv);
+        analysis = next_analysis;
+    }
+
+    Game {
+        start_position: game.start_position.clone(),
+        moves: annotated_moves,
+        game_result_str: game.game_result_str,
+        tags: game.tags.clone(),
+        comment: game.comment.clone(),
+    }
+}
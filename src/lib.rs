@@ -32,14 +32,21 @@ extern crate pgn_traits;
 
 pub use search::mcts;
 
+pub mod alpha_beta;
 #[cfg(feature = "aws-lambda-runtime")]
 pub mod aws;
+pub mod game_analysis;
 pub mod minmax;
+pub mod mock_playtak;
 pub mod move_gen;
+pub mod opening_book;
+pub mod perft;
 #[cfg(feature = "sqlite")]
 pub mod policy_sqlite;
 pub mod position;
 pub mod search;
+pub mod solver;
+pub mod tei;
 #[cfg(test)]
 mod tests;
 #[cfg(feature = "constant-tuning")]
@@ -0,0 +1,462 @@
+//! A local stand-in for the playtak.com server, for testing the `playtak` bot.
+//!
+//! The server speaks the parts of the Playtak text protocol that the bot uses: logging in, seeks, game starts, moves,
+//! clock updates, game ends, and chat. Each connected client is a [`MockClient`], which is driven one message at a time.
+//! This lets tests act as both the server and the opponent. [`MockClient::play_random_game`] plays a whole game against the client instead.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use board_game_traits::{Color, Position as PositionTrait};
+use log::{debug, info};
+use pgn_traits::PgnPosition;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::position::{Komi, Move, Position};
+
+pub struct MockPlaytakServer {
+    listener: TcpListener,
+    guests_logged_in: u64,
+}
+
+impl MockPlaytakServer {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        Ok(MockPlaytakServer {
+            listener: TcpListener::bind(address)?,
+            guests_logged_in: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for a client to connect and log in. Any username and password is accepted.
+    ///
+    /// The client's first message after logging in is sent by the caller.
+    /// The `playtak` bot seeks after any message, except a game start, which resumes an unfinished game.
+    pub fn accept(&mut self) -> io::Result<MockClient> {
+        let (stream, address) = self.listener.accept()?;
+        info!("Connection from {}", address);
+        let mut client = MockClient {
+            name: String::new(),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        client.send_line("Welcome!")?;
+        client.send_line("Login or Register")?;
+        loop {
+            let line = client.read_line()?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["Login", "Guest"] => {
+                    self.guests_logged_in += 1;
+                    client.name = format!("Guest{}", self.guests_logged_in);
+                    break;
+                }
+                ["Login", name, _password] => {
+                    client.name = name.to_string();
+                    break;
+                }
+                ["client", ..] => (),
+                _ => client.send_line("NOK")?,
+            }
+        }
+        client.send_line(&format!("Welcome {}!", client.name))?;
+        Ok(client)
+    }
+}
+
+/// A seek, either sent by the client or sent to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Seek {
+    pub size: usize,
+    pub time: Duration,
+    pub increment: Duration,
+    /// The seeker's color, or `None` for either
+    pub color: Option<Color>,
+    pub komi: Komi,
+    pub flat_stones: u8,
+    pub capstones: u8,
+    pub unrated: bool,
+    pub tournament: bool,
+    /// Extra time given after the trigger move
+    pub extra_time: Option<(u32, Duration)>,
+}
+
+impl Seek {
+    /// Parse the client's `Seek` command
+    fn from_words(words: &[&str]) -> Option<Self> {
+        let number = |i: usize| words.get(i).and_then(|word| u64::from_str(word).ok());
+        Some(Seek {
+            size: number(1)? as usize,
+            time: Duration::from_secs(number(2)?),
+            increment: Duration::from_secs(number(3)?),
+            color: match *words.get(4)? {
+                "W" => Some(Color::White),
+                "B" => Some(Color::Black),
+                "A" => None,
+                _ => return None,
+            },
+            komi: Komi::from_half_komi(i8::from_str(words.get(5)?).ok()?)?,
+            flat_stones: number(6)? as u8,
+            capstones: number(7)? as u8,
+            unrated: number(8)? != 0,
+            tournament: number(9)? != 0,
+            extra_time: match (number(10), number(11)) {
+                (Some(trigger), Some(amount)) if trigger > 0 => {
+                    Some((trigger as u32, Duration::from_secs(amount)))
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+/// The start of a new game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameStart {
+    pub game_no: u64,
+    pub size: usize,
+    pub white_player: String,
+    pub black_player: String,
+    pub client_color: Color,
+    pub time: Duration,
+    /// Not part of the `Game Start` message, but used for the clocks in [`MockClient::play_random_game`]
+    pub increment: Duration,
+    pub komi: Komi,
+}
+
+impl GameStart {
+    /// Start a game for a seek, against `opponent`
+    pub fn from_seek(game_no: u64, seek: &Seek, seeker: &str, opponent: &str) -> Self {
+        let seeker_color = seek.color.unwrap_or_else(|| {
+            if rand::thread_rng().gen() {
+                Color::White
+            } else {
+                Color::Black
+            }
+        });
+        let (white_player, black_player) = match seeker_color {
+            Color::White => (seeker, opponent),
+            Color::Black => (opponent, seeker),
+        };
+        GameStart {
+            game_no,
+            size: seek.size,
+            white_player: white_player.to_string(),
+            black_player: black_player.to_string(),
+            client_color: seeker_color,
+            time: seek.time,
+            increment: seek.increment,
+            komi: seek.komi,
+        }
+    }
+}
+
+/// A message from the client
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientMessage {
+    Seek(Seek),
+    Accept(u64),
+    /// A move in Playtak notation, like `P A1` or `M A1 C1 1 2`
+    Move {
+        game_no: u64,
+        mv: String,
+    },
+    Tell {
+        receiver: String,
+        message: String,
+    },
+    Shout(String),
+    Other(String),
+}
+
+impl ClientMessage {
+    fn parse(line: &str) -> Self {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let rest_of_line = |n: usize| words.get(n..).unwrap_or_default().join(" ");
+        match words.as_slice() {
+            ["Seek", ..] => match Seek::from_words(&words) {
+                Some(seek) => ClientMessage::Seek(seek),
+                None => ClientMessage::Other(line.to_string()),
+            },
+            ["Accept", number] if u64::from_str(number).is_ok() => {
+                ClientMessage::Accept(u64::from_str(number).unwrap())
+            }
+            ["Tell", receiver, ..] => ClientMessage::Tell {
+                receiver: receiver.to_string(),
+                message: rest_of_line(2),
+            },
+            ["Shout", ..] => ClientMessage::Shout(rest_of_line(1)),
+            [game, "P" | "M", ..] => match game
+                .strip_prefix("Game#")
+                .and_then(|game_no| u64::from_str(game_no).ok())
+            {
+                Some(game_no) => ClientMessage::Move {
+                    game_no,
+                    mv: rest_of_line(1),
+                },
+                None => ClientMessage::Other(line.to_string()),
+            },
+            _ => ClientMessage::Other(line.to_string()),
+        }
+    }
+}
+
+/// A logged in client
+pub struct MockClient {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl MockClient {
+    /// The name the client logged in with
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        debug!("< {}", line);
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Client disconnected",
+                ));
+            }
+            debug!("> {}", line.trim());
+            if line.trim() == "PING" {
+                self.send_line("OK")?;
+            } else if !line.trim().is_empty() {
+                return Ok(line.trim().to_string());
+            }
+        }
+    }
+
+    /// Read the next message from the client, answering pings
+    pub fn read_message(&mut self) -> io::Result<ClientMessage> {
+        self.read_line().map(|line| ClientMessage::parse(&line))
+    }
+
+    fn unexpected_message(expected: &str, message: ClientMessage) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Expected {}, got {:?}", expected, message),
+        )
+    }
+
+    /// Read the next message, which must be a seek
+    pub fn expect_seek(&mut self) -> io::Result<Seek> {
+        match self.read_message()? {
+            ClientMessage::Seek(seek) => Ok(seek),
+            message => Err(Self::unexpected_message("a seek", message)),
+        }
+    }
+
+    /// Read the next message, which must be a tell. Returns the receiver and the message
+    pub fn expect_tell(&mut self) -> io::Result<(String, String)> {
+        match self.read_message()? {
+            ClientMessage::Tell { receiver, message } => Ok((receiver, message)),
+            message => Err(Self::unexpected_message("a tell", message)),
+        }
+    }
+
+    /// Read the next message, which must be a legal move in the game
+    pub fn expect_move<const S: usize>(
+        &mut self,
+        game_no: u64,
+        position: &Position<S>,
+    ) -> io::Result<Move<S>> {
+        match self.read_message()? {
+            ClientMessage::Move {
+                game_no: move_game_no,
+                mv,
+            } if move_game_no == game_no => legal_move_from_playtak(position, &mv),
+            message => Err(Self::unexpected_message(
+                &format!("a move in game #{}", game_no),
+                message,
+            )),
+        }
+    }
+
+    /// Announce another player's seek
+    pub fn send_seek(&mut self, seek_no: u64, seeker: &str, seek: &Seek) -> io::Result<()> {
+        let (trigger, extra_time) = seek.extra_time.unwrap_or_default();
+        self.send_line(&format!(
+            "Seek new {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            seek_no,
+            seeker,
+            seek.size,
+            seek.time.as_secs(),
+            seek.increment.as_secs(),
+            match seek.color {
+                Some(Color::White) => "W",
+                Some(Color::Black) => "B",
+                None => "A",
+            },
+            seek.komi.half_komi(),
+            seek.flat_stones,
+            seek.capstones,
+            seek.unrated as u8,
+            seek.tournament as u8,
+            trigger,
+            extra_time.as_secs(),
+        ))
+    }
+
+    pub fn start_game(&mut self, game: &GameStart) -> io::Result<()> {
+        self.send_line(&format!(
+            "Game Start {} {} {} vs {} {} {} {} {} {} 0 0",
+            game.game_no,
+            game.size,
+            game.white_player,
+            game.black_player,
+            match game.client_color {
+                Color::White => "white",
+                Color::Black => "black",
+            },
+            game.time.as_secs(),
+            game.komi.half_komi(),
+            crate::position::starting_stones(game.size),
+            crate::position::starting_capstones(game.size),
+        ))
+    }
+
+    pub fn send_move<const S: usize>(&mut self, game_no: u64, mv: Move<S>) -> io::Result<()> {
+        self.send_line(&format!("Game#{} {}", game_no, mv.to_string_playtak()))
+    }
+
+    pub fn send_time(
+        &mut self,
+        game_no: u64,
+        white_time: Duration,
+        black_time: Duration,
+    ) -> io::Result<()> {
+        self.send_line(&format!(
+            "Game#{} Time {} {}",
+            game_no,
+            white_time.as_secs(),
+            black_time.as_secs()
+        ))
+    }
+
+    /// End the game with a result like `R-0` or `0-F`
+    pub fn send_game_over(&mut self, game_no: u64, result: &str) -> io::Result<()> {
+        self.send_line(&format!("Game#{} Over {}", game_no, result))
+    }
+
+    pub fn send_abandoned(&mut self, game_no: u64, quitter: &str) -> io::Result<()> {
+        self.send_line(&format!("Game#{} Abandoned. {} quit", game_no, quitter))
+    }
+
+    /// Send the number of players online, like the server does after logging in
+    pub fn send_online(&mut self, players: usize) -> io::Result<()> {
+        self.send_line(&format!("Online {}", players))
+    }
+
+    pub fn send_message(&mut self, message: &str) -> io::Result<()> {
+        self.send_line(&format!("Message {}", message))
+    }
+
+    /// Send a private message from `sender` to the client
+    pub fn tell(&mut self, sender: &str, message: &str) -> io::Result<()> {
+        self.send_line(&format!("Tell <{}> {}", sender, message))
+    }
+
+    pub fn shout(&mut self, sender: &str, message: &str) -> io::Result<()> {
+        self.send_line(&format!("Shout <{}> {}", sender, message))
+    }
+
+    /// Play a game against the client, with random moves for the other side.
+    /// The client's clock is kept like on Playtak, and the client loses if it runs out of time.
+    /// Other messages from the client are ignored until the game is over. Returns the result string.
+    pub fn play_random_game<const S: usize, R: Rng>(
+        &mut self,
+        game: &GameStart,
+        rng: &mut R,
+    ) -> io::Result<&'static str> {
+        assert_eq!(game.size, S);
+        self.start_game(game)?;
+        let mut position = <Position<S>>::start_position_with_komi(game.komi);
+        let mut white_time = game.time;
+        let mut black_time = game.time;
+
+        while position.game_result().is_none() {
+            let mv = if position.side_to_move() == game.client_color {
+                let start_time = Instant::now();
+                let mv = loop {
+                    match self.read_message()? {
+                        ClientMessage::Move { game_no, mv } if game_no == game.game_no => {
+                            break legal_move_from_playtak(&position, &mv)?;
+                        }
+                        ClientMessage::Move { .. } => self.send_line("NOK")?,
+                        message => debug!("Ignoring {:?} during game", message),
+                    }
+                };
+                let time_left = match game.client_color {
+                    Color::White => &mut white_time,
+                    Color::Black => &mut black_time,
+                };
+                let elapsed = start_time.elapsed();
+                if elapsed >= *time_left {
+                    let result = match game.client_color {
+                        Color::White => "0-1",
+                        Color::Black => "1-0",
+                    };
+                    self.send_game_over(game.game_no, result)?;
+                    return Ok(result);
+                }
+                *time_left = *time_left - elapsed + game.increment;
+                mv
+            } else {
+                let mut legal_moves = vec![];
+                position.generate_moves(&mut legal_moves);
+                let mv = *legal_moves.choose(rng).unwrap();
+                self.send_move(game.game_no, mv)?;
+                mv
+            };
+            position.do_move(mv);
+            self.send_time(game.game_no, white_time, black_time)?;
+        }
+        let result = position.pgn_game_result().unwrap();
+        self.send_game_over(game.game_no, result)?;
+        Ok(result)
+    }
+}
+
+/// Find the legal move with the given Playtak notation
+fn legal_move_from_playtak<const S: usize>(
+    position: &Position<S>,
+    move_string: &str,
+) -> io::Result<Move<S>> {
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    legal_moves
+        .into_iter()
+        .find(|legal_move| legal_move.to_string_playtak() == move_string)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Illegal move {} in position {}",
+                    move_string,
+                    position.to_fen()
+                ),
+            )
+        })
+}
@@ -0,0 +1,310 @@
+//! Opening books, giving weighted moves for known opening positions.
+//!
+//! A book stores positions by the Zobrist hash of their canonical orientation, the smallest hash of their 8 symmetries.
+//! Each entry is the position reached *after* a book move, weighted by how often the move should be played.
+//! Probing a position looks up the result of every legal move, which also finds moves that transpose into the book.
+//!
+//! Book files depend on the Zobrist keys, and must be rebuilt if those change.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::str::FromStr;
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+use rand::Rng;
+
+use crate::position::{Komi, Move, Position};
+use crate::ptn::Game;
+use crate::search::MonteCarloTree;
+
+const MAGIC: &[u8; 8] = b"TILTAKBK";
+const VERSION: u32 = 1;
+
+/// The Zobrist hash of the position's canonical orientation, which is the same for all its symmetries
+pub fn canonical_hash<const S: usize>(position: &Position<S>) -> u64 {
+    position
+        .symmetries()
+        .iter()
+        .map(Position::zobrist_hash_from_scratch)
+        .min()
+        .unwrap()
+}
+
+/// A move from the book, with its weight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookMove<const S: usize> {
+    pub mv: Move<S>,
+    pub weight: u32,
+}
+
+/// An opening book for one board size and komi
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpeningBook {
+    size: usize,
+    komi: Komi,
+    weights: HashMap<u64, u32>,
+}
+
+impl OpeningBook {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn komi(&self) -> Komi {
+        self.komi
+    }
+
+    /// Number of positions in the book
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    /// All book moves in the position, with the highest weights first.
+    /// Moves that lead to symmetrical positions are only included once.
+    ///
+    /// Returns no moves if the position is not in the book, or if the book is for a different size or komi.
+    pub fn moves<const S: usize>(&self, position: &Position<S>) -> Vec<BookMove<S>> {
+        if S != self.size || position.komi() != self.komi || position.game_result().is_some() {
+            return vec![];
+        }
+        let mut position = position.clone();
+        let mut legal_moves = vec![];
+        position.generate_moves(&mut legal_moves);
+
+        let mut book_moves: Vec<(u64, BookMove<S>)> = vec![];
+        for mv in legal_moves {
+            let reverse_move = position.do_move(mv);
+            let hash = canonical_hash(&position);
+            position.reverse_move(reverse_move);
+            if let Some(weight) = self.weights.get(&hash) {
+                if book_moves.iter().all(|(other_hash, _)| *other_hash != hash) {
+                    book_moves.push((
+                        hash,
+                        BookMove {
+                            mv,
+                            weight: *weight,
+                        },
+                    ));
+                }
+            }
+        }
+        book_moves.sort_by_key(|(_, book_move)| u32::MAX - book_move.weight);
+        book_moves
+            .into_iter()
+            .map(|(_, book_move)| book_move)
+            .collect()
+    }
+
+    /// Pick a book move at random, with probabilities proportional to the weights
+    pub fn probe<const S: usize, R: Rng>(
+        &self,
+        position: &Position<S>,
+        rng: &mut R,
+    ) -> Option<Move<S>> {
+        let book_moves = self.moves(position);
+        let total_weight: u64 = book_moves
+            .iter()
+            .map(|book_move| book_move.weight as u64)
+            .sum();
+        if total_weight == 0 {
+            return None;
+        }
+        let mut choice = rng.gen_range(0..total_weight);
+        book_moves
+            .into_iter()
+            .find(|book_move| {
+                if choice < book_move.weight as u64 {
+                    true
+                } else {
+                    choice -= book_move.weight as u64;
+                    false
+                }
+            })
+            .map(|book_move| book_move.mv)
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[self.size as u8, self.komi.half_komi() as u8])?;
+        writer.write_all(&(self.weights.len() as u64).to_le_bytes())?;
+        // Sort the entries, so that the same book always gives the same file
+        let mut entries: Vec<(u64, u32)> = self
+            .weights
+            .iter()
+            .map(|(hash, weight)| (*hash, *weight))
+            .collect();
+        entries.sort_unstable();
+        for (hash, weight) in entries {
+            writer.write_all(&hash.to_le_bytes())?;
+            writer.write_all(&weight.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let invalid_data = |error: String| io::Error::new(io::ErrorKind::InvalidData, error);
+
+        let mut header = [0; 22];
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(invalid_data("Not an opening book file".to_string()));
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported opening book version {}",
+                version
+            )));
+        }
+        let size = header[12] as usize;
+        if !(3..=8).contains(&size) {
+            return Err(invalid_data(format!("Unsupported size {}", size)));
+        }
+        let komi = Komi::from_half_komi(header[13] as i8)
+            .ok_or_else(|| invalid_data(format!("Invalid half komi {}", header[13] as i8)))?;
+        let num_entries = u64::from_le_bytes(header[14..22].try_into().unwrap());
+
+        let mut weights = HashMap::new();
+        for _ in 0..num_entries {
+            let mut entry = [0; 12];
+            reader.read_exact(&mut entry)?;
+            weights.insert(
+                u64::from_le_bytes(entry[0..8].try_into().unwrap()),
+                u32::from_le_bytes(entry[8..12].try_into().unwrap()),
+            );
+        }
+        Ok(OpeningBook {
+            size,
+            komi,
+            weights,
+        })
+    }
+}
+
+/// Creates opening books from game collections and searches
+#[derive(Clone, Debug)]
+pub struct OpeningBookBuilder<const S: usize> {
+    komi: Komi,
+    max_plies: usize,
+    min_weight: u32,
+    weights: HashMap<u64, u32>,
+}
+
+impl<const S: usize> OpeningBookBuilder<S> {
+    pub fn new(komi: Komi) -> Self {
+        OpeningBookBuilder {
+            komi,
+            max_plies: 12,
+            min_weight: 1,
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Only add moves from the first `max_plies` plies of each game or line. Defaults to 12
+    pub fn max_plies(mut self, max_plies: usize) -> Self {
+        self.max_plies = max_plies;
+        self
+    }
+
+    /// Leave out moves with a total weight below `min_weight`. Defaults to 1
+    pub fn min_weight(mut self, min_weight: u32) -> Self {
+        self.min_weight = min_weight;
+        self
+    }
+
+    fn add_weight(&mut self, position: &Position<S>, weight: u32) {
+        match self.weights.entry(canonical_hash(position)) {
+            Entry::Occupied(mut entry) => {
+                *entry.get_mut() = entry.get().saturating_add(weight);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(weight);
+            }
+        }
+    }
+
+    /// Add each move of the line, played from `position`, with the same weight
+    pub fn add_line(&mut self, position: &Position<S>, moves: &[Move<S>], weight: u32) {
+        let mut position = position.clone();
+        for mv in moves {
+            if position.half_moves_played() >= self.max_plies || position.game_result().is_some() {
+                break;
+            }
+            position.do_move(*mv);
+            self.add_weight(&position, weight);
+        }
+    }
+
+    /// Add the moves of a game. Moves by the winner get weight 2, and moves in drawn or unfinished games get weight 1.
+    /// The loser's moves are not added.
+    ///
+    /// Returns false if the game was not added, because it has a different komi or does not start from the start position.
+    pub fn add_game(&mut self, game: &Game<Position<S>>) -> bool {
+        let komi_tag = game
+            .tags
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("komi"))
+            .map(|(_, value)| Komi::from_str(value));
+        let has_same_komi = match komi_tag {
+            Some(Ok(komi)) => komi == self.komi,
+            Some(Err(_)) => false,
+            None => self.komi == Komi::default(),
+        };
+        if !has_same_komi || game.start_position != Position::start_position() {
+            return false;
+        }
+
+        let mut position = Position::start_position_with_komi(self.komi);
+        for ptn_move in game.moves.iter() {
+            if position.half_moves_played() >= self.max_plies || position.game_result().is_some() {
+                break;
+            }
+            let weight = match (game.game_result(), position.side_to_move()) {
+                (Some(GameResult::WhiteWin), Color::White) => 2,
+                (Some(GameResult::BlackWin), Color::Black) => 2,
+                (Some(GameResult::WhiteWin), Color::Black) => 0,
+                (Some(GameResult::BlackWin), Color::White) => 0,
+                (Some(GameResult::Draw), _) | (None, _) => 1,
+            };
+            position.do_move(ptn_move.mv);
+            if weight > 0 {
+                self.add_weight(&position, weight);
+            }
+        }
+        true
+    }
+
+    /// Add the moves from the root of a search tree, weighted by their number of visits
+    pub fn add_tree(&mut self, tree: &MonteCarloTree<S>) {
+        let mut position = tree.position().clone();
+        if position.komi() != self.komi || position.half_moves_played() >= self.max_plies {
+            return;
+        }
+        for line in tree.multi_pv(usize::MAX) {
+            let reverse_move = position.do_move(line.mv);
+            self.add_weight(&position, line.visits);
+            position.reverse_move(reverse_move);
+        }
+    }
+
+    pub fn build(self) -> OpeningBook {
+        let min_weight = self.min_weight;
+        OpeningBook {
+            size: S,
+            komi: self.komi,
+            weights: self
+                .weights
+                .into_iter()
+                .filter(|(_, weight)| *weight >= min_weight)
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Perft counts the leaf nodes of the game tree to a fixed depth, for testing and benchmarking the move generator.
+//!
+//! The root moves are split between several threads, and a hash table shared between the threads caches the counts of transpositions.
+//! Repetition draws make the count depend on the moves that led to a position, so only positions right after an irreversible move are cached.
+//!
+//! `reference_suite()` has known results for positions of every size, which any change to the move generator must reproduce.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::{Move, Position};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PerftSetting {
+    threads: usize,
+    /// Number of hash table entries. Zero disables the hash table
+    hash_entries: usize,
+}
+
+impl Default for PerftSetting {
+    fn default() -> Self {
+        PerftSetting {
+            threads: 1,
+            hash_entries: 1 << 20,
+        }
+    }
+}
+
+impl PerftSetting {
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Set the size of the hash table, in bytes. Zero disables the hash table
+    pub fn mem_usage(mut self, mem_usage: usize) -> Self {
+        self.hash_entries = mem_usage / size_of::<PerftEntry>();
+        self
+    }
+}
+
+/// A hash table entry. The key is stored xor-ed with the data, so that an entry that was torn by two threads writing at once is never read as valid
+#[derive(Debug, Default)]
+struct PerftEntry {
+    key: AtomicU64,
+    /// The node count in the upper 56 bits, and the depth in the lower 8 bits
+    data: AtomicU64,
+}
+
+/// Runs perft with the given settings. The hash table is kept between runs
+#[derive(Debug)]
+pub struct Perft {
+    threads: usize,
+    hash_table: Box<[PerftEntry]>,
+}
+
+impl Perft {
+    pub fn new(settings: PerftSetting) -> Self {
+        // Round down to a power of two
+        let num_entries = if settings.hash_entries == 0 {
+            0
+        } else {
+            1 << settings.hash_entries.ilog2()
+        };
+        Perft {
+            threads: settings.threads,
+            hash_table: (0..num_entries).map(|_| PerftEntry::default()).collect(),
+        }
+    }
+
+    /// Number of leaf nodes at exactly `depth` plies. Game-ending moves are counted if they are at the last ply, but are not searched further
+    pub fn perft<const S: usize>(&self, position: &Position<S>, depth: u16) -> u64 {
+        if depth == 0 {
+            1
+        } else {
+            self.divide(position, depth)
+                .iter()
+                .map(|(_, nodes)| nodes)
+                .sum()
+        }
+    }
+
+    /// The perft count below each legal move, in move generation order. Empty if the game is over or `depth` is zero
+    pub fn divide<const S: usize>(
+        &self,
+        position: &Position<S>,
+        depth: u16,
+    ) -> Vec<(Move<S>, u64)> {
+        if depth == 0 || position.game_result().is_some() {
+            return vec![];
+        }
+        let mut moves = vec![];
+        position.generate_moves(&mut moves);
+
+        let next_move = AtomicUsize::new(0);
+        let nodes_per_move: Vec<AtomicU64> = moves.iter().map(|_| AtomicU64::new(0)).collect();
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(moves.len()) {
+                scope.spawn(|| {
+                    let mut position = position.clone();
+                    loop {
+                        let i = next_move.fetch_add(1, Ordering::Relaxed);
+                        let Some(mv) = moves.get(i) else {
+                            break;
+                        };
+                        let reverse_move = position.do_move(*mv);
+                        let nodes = self.count(&mut position, depth - 1);
+                        position.reverse_move(reverse_move);
+                        nodes_per_move[i].store(nodes, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+
+        moves
+            .into_iter()
+            .zip(nodes_per_move)
+            .map(|(mv, nodes)| (mv, nodes.into_inner()))
+            .collect()
+    }
+
+    fn count<const S: usize>(&self, position: &mut Position<S>, depth: u16) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        // Depth 1 is counted directly from the move list, which is cheaper than a table lookup
+        let use_hash_table = depth > 1 && position.after_irreversible_move();
+        if use_hash_table {
+            if let Some(nodes) = self.get(position.zobrist_hash(), depth) {
+                return nodes;
+            }
+        }
+        if position.game_result().is_some() {
+            return 0;
+        }
+        let mut moves = Vec::with_capacity(S * S * 4);
+        position.generate_moves(&mut moves);
+
+        let nodes = if depth == 1 {
+            moves.len() as u64
+        } else {
+            moves
+                .into_iter()
+                .map(|mv| {
+                    let reverse_move = position.do_move(mv);
+                    let nodes = self.count(position, depth - 1);
+                    position.reverse_move(reverse_move);
+                    nodes
+                })
+                .sum()
+        };
+        if use_hash_table {
+            self.insert(position.zobrist_hash(), depth, nodes);
+        }
+        nodes
+    }
+
+    fn entry(&self, hash: u64) -> Option<&PerftEntry> {
+        if self.hash_table.is_empty() {
+            None
+        } else {
+            Some(&self.hash_table[hash as usize & (self.hash_table.len() - 1)])
+        }
+    }
+
+    fn get(&self, hash: u64, depth: u16) -> Option<u64> {
+        let entry = self.entry(hash)?;
+        let data = entry.data.load(Ordering::Relaxed);
+        let key = entry.key.load(Ordering::Relaxed);
+        if key ^ data == hash && data & 0xff == depth as u64 {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    /// Insert a result, replacing any previous entry
+    fn insert(&self, hash: u64, depth: u16, nodes: u64) {
+        // Depths that don't fit in 8 bits would take far too long to search anyway
+        if let (Some(entry), Ok(depth)) = (self.entry(hash), u8::try_from(depth)) {
+            let data = nodes << 8 | depth as u64;
+            entry.key.store(hash ^ data, Ordering::Relaxed);
+            entry.data.store(data, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A position from the reference suite, with its known perft results
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PerftReference {
+    pub size: usize,
+    pub tps: &'static str,
+    /// The perft result at depth `i + 1`
+    pub results: Vec<u64>,
+}
+
+/// A perft result that does not match the reference suite
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PerftMismatch {
+    pub depth: u16,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl PerftReference {
+    /// Run perft for every depth with a known result of at most `max_nodes`, and return the wrong results
+    pub fn check(&self, perft: &Perft, max_nodes: u64) -> Vec<PerftMismatch> {
+        match self.size {
+            3 => self.check_sized::<3>(perft, max_nodes),
+            4 => self.check_sized::<4>(perft, max_nodes),
+            5 => self.check_sized::<5>(perft, max_nodes),
+            6 => self.check_sized::<6>(perft, max_nodes),
+            7 => self.check_sized::<7>(perft, max_nodes),
+            8 => self.check_sized::<8>(perft, max_nodes),
+            _ => unreachable!(),
+        }
+    }
+
+    fn check_sized<const S: usize>(&self, perft: &Perft, max_nodes: u64) -> Vec<PerftMismatch> {
+        let position = <Position<S>>::from_fen(self.tps).unwrap();
+        (1..)
+            .zip(self.results.iter())
+            .take_while(|(_, expected)| **expected <= max_nodes)
+            .map(|(depth, expected)| PerftMismatch {
+                depth,
+                expected: *expected,
+                actual: perft.perft(&position, depth),
+            })
+            .filter(|mismatch| mismatch.actual != mismatch.expected)
+            .collect()
+    }
+}
+
+/// Parse the checked-in suite of reference positions
+pub fn reference_suite() -> Vec<PerftReference> {
+    include_str!("reference_positions.txt")
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split(';').map(str::trim);
+            let tps = fields.next().unwrap();
+            let size = tps.split('/').count();
+            assert!(matches!(size, 3..=8), "Unsupported size in \"{}\"", line);
+            let results = (1..)
+                .zip(fields)
+                .map(|(depth, field)| {
+                    let nodes = field.strip_prefix(&format!("D{} ", depth));
+                    nodes
+                        .and_then(|nodes| nodes.parse().ok())
+                        .unwrap_or_else(|| panic!("Invalid perft result \"{}\"", field))
+                })
+                .collect();
+            PerftReference { size, tps, results }
+        })
+        .collect()
+}
@@ -150,6 +150,48 @@ impl BitBoard {
         BitBoard::from_u64(self.board & (mask << i as u64))
     }
 
+    /// All squares on a board of size `S`
+    #[inline]
+    pub fn board<const S: usize>() -> Self {
+        Self::lower_n_bits((S * S) as u8)
+    }
+
+    /// The squares on the north, south, west and east edges, in that order
+    #[inline]
+    pub fn edges<const S: usize>() -> [Self; 4] {
+        [
+            Self::full().rank::<S>(0),
+            Self::full().rank::<S>(S as u8 - 1),
+            Self::full().file::<S>(0),
+            Self::full().file::<S>(S as u8 - 1),
+        ]
+    }
+
+    /// The squares in the bitboard, plus all their neighbors
+    #[inline]
+    pub fn dilate<const S: usize>(self) -> Self {
+        let [north_edge, south_edge, _, _] = Self::edges::<S>();
+        let board = self.board
+            | (self.board >> 1 & !south_edge.board)
+            | (self.board << 1 & !north_edge.board)
+            | self.board >> S
+            | self.board << S;
+        BitBoard::from_u64(board) & Self::board::<S>()
+    }
+
+    /// The group of squares in `self` that is connected to `square`
+    #[inline]
+    pub fn group_of_square<const S: usize>(self, square: Square<S>) -> Self {
+        let mut group = BitBoard::empty().set_square(square);
+        loop {
+            let next = group.dilate::<S>() & self;
+            if next == group {
+                return group;
+            }
+            group = next;
+        }
+    }
+
     #[inline]
     pub fn is_empty(self) -> bool {
         self.board == 0
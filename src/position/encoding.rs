@@ -0,0 +1,371 @@
+//! A compact binary encoding of positions, with a fixed size for each board size.
+//!
+//! The encoding consists of, in order:
+//! * A 5-byte header: the board size, the side to move (0 for white, 1 for black), the komi in half-points as an `i8`, and the number of half-moves played as a little-endian `u16`
+//! * The height of each stack, one byte per square
+//! * Bitboards of the squares with a wall and a capstone on top, with one bit per square
+//! * The color of every piece on the board, one bit per piece, set for black.
+//!   The pieces are listed square by square, from the bottom of each stack to the top, and unused bits are zero
+//!
+//! The reserves are not stored, because they are always the starting reserves minus the pieces on the board.
+//!
+//! The encoding with history is the encoding of the position before the first move,
+//! followed by the number of moves as a little-endian `u16`, and each move's `to_u16()` representation, also little-endian.
+
+use arrayvec::ArrayVec;
+use board_game_traits::{Color, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::fmt;
+
+use super::{
+    squares_iterator, starting_capstones, starting_stones, AbstractBoard, BitBoard, ExpMove, Komi,
+    Move, Piece, Position, ReverseMove, Role, Stack,
+};
+
+const HEADER_SIZE: usize = 5;
+
+/// The highest stack `Stack` can hold
+const MAX_STACK_HEIGHT: u8 = 64;
+
+const fn bitboard_bytes(size: usize) -> usize {
+    (size * size).div_ceil(8)
+}
+
+/// Every piece of both players, if they are all on the board
+const fn max_pieces(size: usize) -> usize {
+    2 * (starting_stones(size) as usize + starting_capstones(size) as usize)
+}
+
+fn decode_error(message: String) -> pgn_traits::Error {
+    pgn_traits::Error::new_parse_error(message)
+}
+
+impl<const S: usize> Position<S> {
+    /// The length of `encode()`'s output, in bytes
+    pub const ENCODED_SIZE: usize =
+        HEADER_SIZE + S * S + 2 * bitboard_bytes(S) + max_pieces(S).div_ceil(8);
+
+    /// Encode the position in exactly `ENCODED_SIZE` bytes, without its move history
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::ENCODED_SIZE);
+        bytes.push(S as u8);
+        bytes.push((self.side_to_move() == Color::Black) as u8);
+        bytes.push(self.komi.half_komi() as u8);
+        bytes.extend_from_slice(&(self.half_moves_played as u16).to_le_bytes());
+
+        for square in squares_iterator::<S>() {
+            bytes.push(self.stack_heights[square]);
+        }
+
+        let walls = self.top_stone_bitboards[Piece::WhiteWall as usize]
+            | self.top_stone_bitboards[Piece::BlackWall as usize];
+        let caps = self.top_stone_bitboards[Piece::WhiteCap as usize]
+            | self.top_stone_bitboards[Piece::BlackCap as usize];
+        for bitboard in [walls, caps] {
+            bytes.extend_from_slice(&bitboard.board.to_le_bytes()[..bitboard_bytes(S)]);
+        }
+
+        let mut colors = vec![0; max_pieces(S).div_ceil(8)];
+        let mut i = 0;
+        for square in squares_iterator::<S>() {
+            for piece in self.get_stack(square).into_iter() {
+                if piece.color() == Color::Black {
+                    colors[i / 8] |= 1 << (i % 8);
+                }
+                i += 1;
+            }
+        }
+        bytes.extend_from_slice(&colors);
+
+        debug_assert_eq!(bytes.len(), Self::ENCODED_SIZE);
+        bytes
+    }
+
+    /// Decode a position from `encode()`. Returns an error if the data is not a valid position of size `S`
+    pub fn decode(bytes: &[u8]) -> Result<Self, pgn_traits::Error> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(decode_error(format!(
+                "Expected {} bytes for a {}s position, got {}",
+                Self::ENCODED_SIZE,
+                S,
+                bytes.len()
+            )));
+        }
+        let (header, bytes) = bytes.split_at(HEADER_SIZE);
+        let (heights, bytes) = bytes.split_at(S * S);
+        let (walls, bytes) = bytes.split_at(bitboard_bytes(S));
+        let (caps, colors) = bytes.split_at(bitboard_bytes(S));
+
+        if header[0] as usize != S {
+            return Err(decode_error(format!(
+                "Expected a {}s position, got size {}",
+                S, header[0]
+            )));
+        }
+        let side_to_move = match header[1] {
+            0 => Color::White,
+            1 => Color::Black,
+            n => return Err(decode_error(format!("Invalid side to move {}", n))),
+        };
+        let komi = Komi::from_half_komi(header[2] as i8)
+            .ok_or_else(|| decode_error(format!("Invalid half komi {}", header[2] as i8)))?;
+        let half_moves_played = u16::from_le_bytes([header[3], header[4]]) as usize;
+
+        if let Some(height) = heights.iter().find(|height| **height > MAX_STACK_HEIGHT) {
+            return Err(decode_error(format!("Invalid stack height {}", height)));
+        }
+        let num_pieces: usize = heights.iter().map(|height| *height as usize).sum();
+        if num_pieces > max_pieces(S) {
+            return Err(decode_error(format!(
+                "{} pieces on the board, but only {} in the game",
+                num_pieces,
+                max_pieces(S)
+            )));
+        }
+
+        let occupied = squares_iterator::<S>()
+            .filter(|square| heights[square.into_inner() as usize] > 0)
+            .fold(BitBoard::empty(), |board, square| board.set_square(square));
+        let read_bitboard = |bytes: &[u8]| {
+            let mut board = [0; 8];
+            board[..bytes.len()].copy_from_slice(bytes);
+            BitBoard::from_u64(u64::from_le_bytes(board))
+        };
+        let walls = read_bitboard(walls);
+        let caps = read_bitboard(caps);
+        if !(walls & caps).is_empty() || !(walls & !occupied).is_empty() {
+            return Err(decode_error("Invalid wall squares".to_string()));
+        }
+        if !(caps & !occupied).is_empty() {
+            return Err(decode_error("Invalid capstone squares".to_string()));
+        }
+
+        let color_bit = |i: usize| colors[i / 8] & (1 << (i % 8)) != 0;
+        if (num_pieces..colors.len() * 8).any(color_bit) {
+            return Err(decode_error("Unused color bits must be zero".to_string()));
+        }
+
+        let mut stacks: AbstractBoard<Stack, S> = Default::default();
+        let mut i = 0;
+        for square in squares_iterator::<S>() {
+            let height = heights[square.into_inner() as usize];
+            for j in 0..height {
+                let color = if color_bit(i) {
+                    Color::Black
+                } else {
+                    Color::White
+                };
+                let role = if j < height - 1 {
+                    Role::Flat
+                } else if walls.get_square(square) {
+                    Role::Wall
+                } else if caps.get_square(square) {
+                    Role::Cap
+                } else {
+                    Role::Flat
+                };
+                stacks[square].push(Piece::from_role_color(role, color));
+                i += 1;
+            }
+        }
+
+        Self::check_reserves(&stacks).map_err(decode_error)?;
+
+        Ok(Position::from_stacks(
+            &stacks,
+            side_to_move,
+            half_moves_played,
+            komi,
+        ))
+    }
+
+    /// Encode the position along with its move history.
+    /// Unlike `encode()`, the length depends on the number of moves
+    pub fn encode_with_history(&self) -> Vec<u8> {
+        let mut bytes = self.position_before_moves().encode();
+        bytes.reserve(2 + 2 * self.moves.len());
+        bytes.extend_from_slice(&(self.moves.len() as u16).to_le_bytes());
+        for mv in self.moves.iter() {
+            bytes.extend_from_slice(&mv.to_u16().to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode a position from `encode_with_history()`, replaying its moves. Returns an error if any of the moves are illegal
+    pub fn decode_with_history(bytes: &[u8]) -> Result<Self, pgn_traits::Error> {
+        if bytes.len() < Self::ENCODED_SIZE + 2 {
+            return Err(decode_error(format!(
+                "Expected at least {} bytes for a {}s position with history, got {}",
+                Self::ENCODED_SIZE + 2,
+                S,
+                bytes.len()
+            )));
+        }
+        let (position_bytes, bytes) = bytes.split_at(Self::ENCODED_SIZE);
+        let (num_moves, move_bytes) = bytes.split_at(2);
+        let num_moves = u16::from_le_bytes([num_moves[0], num_moves[1]]) as usize;
+        if move_bytes.len() != 2 * num_moves {
+            return Err(decode_error(format!(
+                "Expected {} bytes for {} moves, got {}",
+                2 * num_moves,
+                num_moves,
+                move_bytes.len()
+            )));
+        }
+        let moves = move_bytes
+            .chunks_exact(2)
+            .map(|mv| {
+                let data = u16::from_le_bytes([mv[0], mv[1]]);
+                Move::from_u16(data).ok_or_else(|| decode_error(format!("Invalid move {}", data)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut position = Self::decode(position_bytes)?;
+        position.do_legal_moves(moves)?;
+        Ok(position)
+    }
+
+    fn do_legal_moves(
+        &mut self,
+        moves: impl IntoIterator<Item = Move<S>>,
+    ) -> Result<(), pgn_traits::Error> {
+        for mv in moves {
+            if self.game_result().is_some() || !self.move_is_legal(mv) {
+                return Err(decode_error(format!(
+                    "Illegal move {} in position {}",
+                    mv,
+                    self.to_fen()
+                )));
+            }
+            self.do_move(mv);
+        }
+        Ok(())
+    }
+
+    /// The position before the first move in `moves()`, without any history
+    fn position_before_moves(&self) -> Position<S> {
+        let mut position = self.clone();
+        while !position.moves.is_empty() {
+            position.undo_last_move();
+        }
+        position
+    }
+
+    /// Undo the last move, without its `ReverseMove`
+    fn undo_last_move(&mut self) {
+        let mv = *self.moves.last().unwrap();
+        let previous_hash = *self.hash_history.last().unwrap();
+        let reverse_move = match mv.expand() {
+            ExpMove::Place(_, square) => ReverseMove::Place(square),
+            ExpMove::Move(square, direction, stack_movement) => {
+                let pieces_held: ArrayVec<u8, 8> = stack_movement
+                    .into_iter()
+                    .map(|movement| movement.pieces_to_take)
+                    .collect();
+                let pieces_left_behind: ArrayVec<u8, 8> = pieces_held
+                    .iter()
+                    .zip(pieces_held.iter().skip(1).chain([0].iter()))
+                    .map(|(held, taken)| held - taken)
+                    .collect();
+
+                // A lone capstone may have flattened a wall at the destination square,
+                // which only the hash of the previous position can tell
+                let destination = mv.destination_square();
+                let may_flatten_wall = pieces_left_behind.last() == Some(&1)
+                    && self.stack_heights[destination] > 1
+                    && self.top_stones[destination].map(Piece::role) == Some(Role::Cap);
+                let flattens_wall = may_flatten_wall && {
+                    let mut position = self.clone();
+                    position.reverse_move(ReverseMove::Move(
+                        square,
+                        direction,
+                        stack_movement,
+                        pieces_left_behind.clone(),
+                        false,
+                    ));
+                    position.hash != previous_hash
+                };
+                ReverseMove::Move(
+                    square,
+                    direction,
+                    stack_movement,
+                    pieces_left_behind,
+                    flattens_wall,
+                )
+            }
+        };
+        self.reverse_move(reverse_move);
+        debug_assert_eq!(self.hash, previous_hash);
+    }
+}
+
+/// The human-readable serde representation of a position, as a TPS string of the position before the first move, followed by the moves
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct PositionWithHistory<const S: usize> {
+    tps: String,
+    komi: Komi,
+    moves: Vec<Move<S>>,
+}
+
+/// Serialized as a TPS string with a list of moves in human-readable formats, and with `encode_with_history()` in binary formats
+#[cfg(feature = "serde")]
+impl<const S: usize> Serialize for Position<S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        if serializer.is_human_readable() {
+            PositionWithHistory {
+                tps: self.position_before_moves().to_fen(),
+                komi: self.komi,
+                moves: self.moves.clone(),
+            }
+            .serialize(serializer)
+        } else {
+            serializer.serialize_bytes(&self.encode_with_history())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const S: usize> Deserialize<'de> for Position<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let data = PositionWithHistory::<S>::deserialize(deserializer)?;
+            let mut position =
+                Position::from_fen_with_komi(&data.tps, data.komi).map_err(de::Error::custom)?;
+            position
+                .do_legal_moves(data.moves)
+                .map_err(de::Error::custom)?;
+            Ok(position)
+        } else {
+            deserializer.deserialize_bytes(EncodedPositionVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+struct EncodedPositionVisitor<const S: usize>;
+
+#[cfg(feature = "serde")]
+impl<'de, const S: usize> de::Visitor<'de> for EncodedPositionVisitor<S> {
+    type Value = Position<S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an encoded {}s position", S)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Position::decode_with_history(bytes).map_err(E::custom)
+    }
+
+    // Formats without a separate bytes type store them as a sequence
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
@@ -26,12 +26,14 @@ pub use utils::AbstractBoard;
 
 pub use mv::{ExpMove, Move, ReverseMove};
 
+use crate::evaluation::network::NetworkValue;
 use crate::evaluation::parameters::{self, IncrementalValue, PolicyApplier, ValueApplier};
 use crate::evaluation::value_eval;
 use crate::position::color_trait::ColorTr;
 
 pub(crate) mod bitboard;
 pub(crate) mod color_trait;
+mod encoding;
 mod mv;
 mod square;
 mod utils;
@@ -71,9 +73,12 @@ pub const fn starting_capstones(size: usize) -> u8 {
 
 pub(crate) const fn num_square_symmetries<const S: usize>() -> usize {
     match S {
+        3 => 3,
         4 => 3,
         5 => 6,
         6 => 6,
+        7 => 10,
+        8 => 10,
         _ => 0,
     }
 }
@@ -101,33 +106,45 @@ const fn generate_square_symmetries_table<const S: usize>() -> AbstractBoard<usi
     table
 }
 
+pub(crate) const SQUARE_SYMMETRIES_3S: AbstractBoard<usize, 3> = generate_square_symmetries_table();
 pub(crate) const SQUARE_SYMMETRIES_4S: AbstractBoard<usize, 4> = generate_square_symmetries_table();
 pub(crate) const SQUARE_SYMMETRIES_5S: AbstractBoard<usize, 5> = generate_square_symmetries_table();
 pub(crate) const SQUARE_SYMMETRIES_6S: AbstractBoard<usize, 6> = generate_square_symmetries_table();
+pub(crate) const SQUARE_SYMMETRIES_7S: AbstractBoard<usize, 7> = generate_square_symmetries_table();
+pub(crate) const SQUARE_SYMMETRIES_8S: AbstractBoard<usize, 8> = generate_square_symmetries_table();
 
 pub(crate) fn lookup_square_symmetries<const S: usize>(square: Square<S>) -> usize {
     match S {
+        3 => SQUARE_SYMMETRIES_3S[square.downcast_size()],
         4 => SQUARE_SYMMETRIES_4S[square.downcast_size()],
         5 => SQUARE_SYMMETRIES_5S[square.downcast_size()],
         6 => SQUARE_SYMMETRIES_6S[square.downcast_size()],
+        7 => SQUARE_SYMMETRIES_7S[square.downcast_size()],
+        8 => SQUARE_SYMMETRIES_8S[square.downcast_size()],
         _ => unimplemented!("Unsupported size {}", S),
     }
 }
 
 pub(crate) const fn num_line_symmetries<const S: usize>() -> usize {
     match S {
+        3 => 2,
         4 => 2,
         5 => 3,
         6 => 3,
+        7 => 4,
+        8 => 4,
         _ => 0,
     }
 }
 
 pub(crate) const fn line_symmetries<const S: usize>() -> &'static [usize] {
     match S {
+        3 => &[0, 1, 0],
         4 => &[0, 1, 1, 0],
         5 => &[0, 1, 2, 1, 0],
         6 => &[0, 1, 2, 2, 1, 0],
+        7 => &[0, 1, 2, 3, 2, 1, 0],
+        8 => &[0, 1, 2, 3, 3, 2, 1, 0],
         _ => &[],
     }
 }
@@ -236,7 +253,7 @@ impl ops::BitAnd for GroupEdgeConnection {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct MovementSynopsis<const S: usize> {
     pub origin: Square<S>,
     pub destination: Square<S>,
@@ -266,7 +283,7 @@ fn get_movement_in_history<const S: usize>(
         })
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct GroupData<const S: usize> {
     pub(crate) groups: AbstractBoard<u8, S>,
     pub(crate) amount_in_group: ArrayVec<(u8, GroupEdgeConnection), 65>, // Size is max_size^2 + 1
@@ -533,6 +550,7 @@ pub struct Position<const S: usize> {
     stacks: AbstractBoard<BitBoard, S>,
     stack_heights: AbstractBoard<u8, S>,
     top_stones: AbstractBoard<Option<Piece>, S>,
+    top_stone_bitboards: [BitBoard; 6], // The squares of each kind of top stone, indexed by `Piece`
     to_move: Color,
     white_stones_left: u8,
     black_stones_left: u8,
@@ -542,7 +560,7 @@ pub struct Position<const S: usize> {
     moves: Vec<Move<S>>,
     komi: Komi,
     hash: u64,              // Zobrist hash of current position
-    hash_history: Vec<u64>, // Zobrist hashes of previous board states, up to the last irreversible move. Does not include the corrent position
+    hash_history: Vec<u64>, // Zobrist hashes of previous board states, one for each move in `moves`. Does not include the current position
 }
 
 impl<const S: usize> Clone for Position<S> {
@@ -551,6 +569,7 @@ impl<const S: usize> Clone for Position<S> {
             stacks: self.stacks.clone(),
             stack_heights: self.stack_heights.clone(),
             top_stones: self.top_stones.clone(),
+            top_stone_bitboards: self.top_stone_bitboards,
             to_move: self.to_move,
             white_stones_left: self.white_stones_left,
             black_stones_left: self.black_stones_left,
@@ -567,6 +586,7 @@ impl<const S: usize> Clone for Position<S> {
         self.stacks = source.stacks.clone();
         self.stack_heights = source.stack_heights.clone();
         self.top_stones = source.top_stones.clone();
+        self.top_stone_bitboards = source.top_stone_bitboards;
         self.to_move = source.to_move;
         self.white_stones_left = source.white_stones_left;
         self.black_stones_left = source.black_stones_left;
@@ -671,6 +691,7 @@ impl<const S: usize> Position<S> {
             stacks: Default::default(),
             stack_heights: Default::default(),
             top_stones: Default::default(),
+            top_stone_bitboards: Default::default(),
             to_move: Color::White,
             white_stones_left: starting_stones(S),
             black_stones_left: starting_stones(S),
@@ -690,6 +711,53 @@ impl<const S: usize> Position<S> {
         Ok(position)
     }
 
+    /// Check that the stacks have no more stones or capstones of either color than the starting reserves
+    pub fn check_reserves(stacks: &AbstractBoard<Stack, S>) -> Result<(), String> {
+        for color in [Color::White, Color::Black] {
+            let pieces = || {
+                square::squares_iterator::<S>()
+                    .flat_map(|square| stacks[square].into_iter())
+                    .filter(move |piece| piece.color() == color)
+            };
+            let caps = pieces().filter(|piece| piece.role() == Cap).count();
+            let stones = pieces().count() - caps;
+            if stones > starting_stones(S) as usize || caps > starting_capstones(S) as usize {
+                return Err(format!(
+                    "{} has {} stones and {} capstones on the board, more than the starting reserves",
+                    color, stones, caps
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set up a position from its stacks, without any move history. The reserves are counted from the stacks,
+    /// which must pass `check_reserves`
+    pub fn from_stacks(
+        stacks: &AbstractBoard<Stack, S>,
+        side_to_move: Color,
+        half_moves_played: usize,
+        komi: Komi,
+    ) -> Self {
+        let mut position = Self::start_position_with_komi(komi);
+        for square in square::squares_iterator::<S>() {
+            let stack = stacks[square];
+            for piece in stack.into_iter() {
+                match piece {
+                    WhiteFlat | WhiteWall => position.white_stones_left -= 1,
+                    WhiteCap => position.white_caps_left -= 1,
+                    BlackFlat | BlackWall => position.black_stones_left -= 1,
+                    BlackCap => position.black_caps_left -= 1,
+                }
+            }
+            position.set_stack(square, stack);
+        }
+        position.to_move = side_to_move;
+        position.half_moves_played = half_moves_played;
+        position.hash = position.zobrist_hash_from_scratch();
+        position
+    }
+
     pub fn get_stack(&self, square: Square<S>) -> Stack {
         let bitboard = self.stacks[square];
         let top_stone = self.top_stones[square];
@@ -704,7 +772,21 @@ impl<const S: usize> Position<S> {
     pub fn set_stack(&mut self, square: Square<S>, stack: Stack) {
         self.stacks[square] = stack.bitboard;
         self.stack_heights[square] = stack.height;
-        self.top_stones[square] = stack.top_stone;
+        self.set_top_stone(square, stack.top_stone);
+    }
+
+    /// Set the top stone of a square, keeping `top_stone_bitboards` up to date.
+    /// All changes to `top_stones` must go through this function
+    fn set_top_stone(&mut self, square: Square<S>, top_stone: Option<Piece>) {
+        if let Some(piece) = self.top_stones[square] {
+            self.top_stone_bitboards[piece as usize] =
+                self.top_stone_bitboards[piece as usize].clear_square(square);
+        }
+        if let Some(piece) = top_stone {
+            self.top_stone_bitboards[piece as usize] =
+                self.top_stone_bitboards[piece as usize].set_square(square);
+        }
+        self.top_stones[square] = top_stone;
     }
 
     pub fn top_stones(&self) -> &AbstractBoard<Option<Piece>, S> {
@@ -731,11 +813,28 @@ impl<const S: usize> Position<S> {
         self.black_caps_left
     }
 
-    #[cfg(test)]
     pub fn zobrist_hash(&self) -> u64 {
         self.hash
     }
 
+    /// Number of times the current position has occurred before, since the last irreversible move
+    pub fn repetitions(&self) -> usize {
+        // Placements are irreversible, so positions from before the last placement are never repeated
+        self.hash_history
+            .iter()
+            .zip(self.moves.iter())
+            .rev()
+            .take_while(|(_, mv)| !mv.is_placement())
+            .filter(|(hash, _)| **hash == self.hash)
+            .count()
+    }
+
+    /// Whether the last move was irreversible, or there are no moves. If so, no earlier position can be repeated,
+    /// and the game tree from here does not depend on the moves that led to the position
+    pub fn after_irreversible_move(&self) -> bool {
+        self.moves.last().is_none_or(|mv| mv.is_placement())
+    }
+
     pub fn komi(&self) -> Komi {
         self.komi
     }
@@ -812,8 +911,10 @@ impl<const S: usize> Position<S> {
                     self.stacks[Square::from_rank_file(S as u8 - rank - 1, file)];
                 new_board.stack_heights[Square::from_rank_file(rank, file)] =
                     self.stack_heights[Square::from_rank_file(S as u8 - rank - 1, file)];
-                new_board.top_stones[Square::from_rank_file(rank, file)] =
-                    self.top_stones[Square::from_rank_file(S as u8 - rank - 1, file)];
+                new_board.set_top_stone(
+                    Square::from_rank_file(rank, file),
+                    self.top_stones[Square::from_rank_file(S as u8 - rank - 1, file)],
+                );
             }
         }
         new_board
@@ -827,8 +928,10 @@ impl<const S: usize> Position<S> {
                     self.stacks[Square::from_rank_file(rank, S as u8 - file - 1)];
                 new_board.stack_heights[Square::from_rank_file(rank, file)] =
                     self.stack_heights[Square::from_rank_file(rank, S as u8 - file - 1)];
-                new_board.top_stones[Square::from_rank_file(rank, file)] =
-                    self.top_stones[Square::from_rank_file(rank, S as u8 - file - 1)];
+                new_board.set_top_stone(
+                    Square::from_rank_file(rank, file),
+                    self.top_stones[Square::from_rank_file(rank, S as u8 - file - 1)],
+                );
             }
         }
         new_board
@@ -844,8 +947,10 @@ impl<const S: usize> Position<S> {
                     self.stacks[Square::from_rank_file(new_rank, new_file)];
                 new_board.stack_heights[Square::from_rank_file(rank, file)] =
                     self.stack_heights[Square::from_rank_file(new_rank, new_file)];
-                new_board.top_stones[Square::from_rank_file(rank, file)] =
-                    self.top_stones[Square::from_rank_file(new_rank, new_file)];
+                new_board.set_top_stone(
+                    Square::from_rank_file(rank, file),
+                    self.top_stones[Square::from_rank_file(new_rank, new_file)],
+                );
             }
         }
         new_board
@@ -860,7 +965,7 @@ impl<const S: usize> Position<S> {
             }
             new_board.stacks[square] = new_stack.bitboard;
             new_board.stack_heights[square] = new_stack.height;
-            new_board.top_stones[square] = new_stack.top_stone;
+            new_board.set_top_stone(square, new_stack.top_stone);
         }
         mem::swap(
             &mut new_board.white_stones_left,
@@ -902,8 +1007,40 @@ impl<const S: usize> Position<S> {
             .sum()
     }
 
+    /// Connectivity, critical squares and bitboards of the top stones.
+    /// The bitboards are kept up to date through `do_move` and `reverse_move`, so only the groups are computed here
     #[inline(never)]
     pub fn group_data(&self) -> GroupData<S> {
+        let mut group_data = GroupData {
+            last_movement: their_last_movement(self),
+            second_to_last_movement: our_last_movement(self),
+            white_flat_stones: self.top_stone_bitboards[WhiteFlat as usize],
+            black_flat_stones: self.top_stone_bitboards[BlackFlat as usize],
+            white_walls: self.top_stone_bitboards[WhiteWall as usize],
+            black_walls: self.top_stone_bitboards[BlackWall as usize],
+            white_caps: self.top_stone_bitboards[WhiteCap as usize],
+            black_caps: self.top_stone_bitboards[BlackCap as usize],
+            ..Default::default()
+        };
+
+        let mut highest_component_id = 1;
+
+        group_data.white_critical_squares = connect_groups(
+            group_data.white_road_pieces(),
+            &mut group_data,
+            &mut highest_component_id,
+        );
+        group_data.black_critical_squares = connect_groups(
+            group_data.black_road_pieces(),
+            &mut group_data,
+            &mut highest_component_id,
+        );
+        group_data
+    }
+
+    /// Slow reference implementation of `group_data`, which only looks at `top_stones`.
+    /// For checking the incrementally updated data in tests
+    pub fn group_data_from_scratch(&self) -> GroupData<S> {
         let mut group_data = GroupData {
             last_movement: their_last_movement(self),
             second_to_last_movement: our_last_movement(self),
@@ -1046,13 +1183,7 @@ impl<const S: usize> Position<S> {
     }
 
     fn detailed_game_result(&self, group_data: &GroupData<S>) -> Option<DetailedGameResult> {
-        let repetitions = self
-            .hash_history
-            .iter()
-            .filter(|hash| **hash == self.hash)
-            .count();
-
-        if repetitions >= 2 {
+        if self.repetitions() >= 2 {
             return Some(DetailedGameResult::Draw);
         }
 
@@ -1152,6 +1283,21 @@ impl<const S: usize> Position<S> {
         white_value_features.finish() + black_value_features.finish()
     }
 
+    pub(crate) fn static_eval_with_network_and_data(
+        &self,
+        group_data: &GroupData<S>,
+        network: &'static [f32],
+        network_value: &mut NetworkValue<S>,
+    ) -> f32 {
+        value_eval::static_eval_game_phase(
+            self,
+            group_data,
+            &mut network_value.white_value,
+            &mut network_value.black_value,
+        );
+        network_value.finish(network)
+    }
+
     pub fn value_params(komi: Komi) -> &'static [f32] {
         match komi.half_komi() {
             0 => Self::value_params_0komi(),
@@ -1170,36 +1316,48 @@ impl<const S: usize> Position<S> {
 
     pub fn value_params_0komi() -> &'static [f32] {
         match S {
+            3 => parameters::value_features_3s(Komi::default()),
             4 => &parameters::VALUE_PARAMS_4S_0KOMI,
             5 => &parameters::VALUE_PARAMS_5S_0KOMI,
             6 => &parameters::VALUE_PARAMS_6S_0KOMI,
+            7 => parameters::value_features_7s(Komi::default()),
+            8 => parameters::value_features_8s(Komi::default()),
             _ => unimplemented!("{}s is not supported for 0 komi.", S),
         }
     }
 
     pub fn value_params_2komi() -> &'static [f32] {
         match S {
+            3 => parameters::value_features_3s(Komi::from_half_komi(4).unwrap()),
             4 => &parameters::VALUE_PARAMS_4S_2KOMI,
             5 => &parameters::VALUE_PARAMS_5S_2KOMI,
             6 => &parameters::VALUE_PARAMS_6S_2KOMI,
+            7 => parameters::value_features_7s(Komi::from_half_komi(4).unwrap()),
+            8 => parameters::value_features_8s(Komi::from_half_komi(4).unwrap()),
             _ => unimplemented!("{}s is not supported for 2 komi.", S),
         }
     }
 
     pub fn policy_params_0komi() -> &'static [f32] {
         match S {
+            3 => parameters::policy_features_3s(Komi::default()),
             4 => &parameters::POLICY_PARAMS_4S_0KOMI,
             5 => &parameters::POLICY_PARAMS_5S_0KOMI,
             6 => &parameters::POLICY_PARAMS_6S_0KOMI,
+            7 => parameters::policy_features_7s(Komi::default()),
+            8 => parameters::policy_features_8s(Komi::default()),
             _ => unimplemented!("{}s is not supported for 0 komi.", S),
         }
     }
 
     pub fn policy_params_2komi() -> &'static [f32] {
         match S {
+            3 => parameters::policy_features_3s(Komi::from_half_komi(4).unwrap()),
             4 => &parameters::POLICY_PARAMS_4S_2KOMI,
             5 => &parameters::POLICY_PARAMS_5S_2KOMI,
             6 => &parameters::POLICY_PARAMS_6S_2KOMI,
+            7 => parameters::policy_features_7s(Komi::from_half_komi(4).unwrap()),
+            8 => parameters::policy_features_8s(Komi::from_half_komi(4).unwrap()),
             _ => unimplemented!("{}s is not supported for 2 komi.", S),
         }
     }
@@ -1332,6 +1490,16 @@ impl<const S: usize> Position<S> {
 
         white_value.finish() + black_value.finish()
     }
+
+    /// Static evaluation with a value network, instead of the linear value parameters
+    pub fn static_eval_with_network(&self, network: &'static [f32]) -> f32 {
+        debug_assert!(self.game_result().is_none());
+        self.static_eval_with_network_and_data(
+            &self.group_data(),
+            network,
+            &mut NetworkValue::default(),
+        )
+    }
 }
 
 impl<const S: usize> PositionTrait for Position<S> {
@@ -1415,7 +1583,7 @@ impl<const S: usize> PositionTrait for Position<S> {
                 };
                 let piece = Piece::from_role_color(role, color_to_place);
 
-                self.top_stones[to] = Some(piece);
+                self.set_top_stone(to, Some(piece));
                 self.stack_heights[to] = 1;
 
                 match piece {
@@ -1427,7 +1595,6 @@ impl<const S: usize> PositionTrait for Position<S> {
 
                 self.hash ^= zobrist_top_stones::<S>(to, piece);
                 self.hash ^= zobrist_stack_heights(to, 1);
-                self.hash_history.clear(); // This move is irreversible, so previous position are never repeated from here
 
                 ReverseMove::Place(to)
             }
@@ -1604,8 +1771,8 @@ impl<const S: usize> PositionTrait for Position<S> {
                 if flattens_wall {
                     debug_assert_eq!(self.top_stones[to].map(Piece::role), Some(Flat));
                     match self.top_stones[to].unwrap().color() {
-                        Color::White => self.top_stones[to].replace(WhiteWall),
-                        Color::Black => self.top_stones[to].replace(BlackWall),
+                        Color::White => self.set_top_stone(to, Some(WhiteWall)),
+                        Color::Black => self.set_top_stone(to, Some(BlackWall)),
                     };
                 };
 
@@ -1755,36 +1922,27 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
                     e,
                 )
             })?;
-        let mut position = Position::start_position_with_settings(settings);
+        let mut stacks: AbstractBoard<Stack, S> = Default::default();
         for square in square::squares_iterator::<S>() {
             let (file, rank) = (square.file(), square.rank());
-            let stack = rows[rank as usize][file as usize];
-            for piece in stack.into_iter() {
-                match piece {
-                    WhiteFlat | WhiteWall => position.white_stones_left -= 1,
-                    WhiteCap => position.white_caps_left -= 1,
-                    BlackFlat | BlackWall => position.black_stones_left -= 1,
-                    BlackCap => position.black_caps_left -= 1,
-                }
-            }
-            position.set_stack(square, stack);
+            stacks[square] = rows[rank as usize][file as usize];
         }
 
-        match fen_words[1] {
-            "1" => position.to_move = Color::White,
-            "2" => position.to_move = Color::Black,
+        let side_to_move = match fen_words[1] {
+            "1" => Color::White,
+            "2" => Color::Black,
             s => {
                 return Err(pgn_traits::Error::new_parse_error(format!(
                     "Error parsing TPS \"{}\": Got bad side to move \"{}\"",
                     fen, s
                 )))
             }
-        }
+        };
 
-        match fen_words[2].parse::<usize>() {
-            Ok(n) => match position.side_to_move() {
-                Color::White => position.half_moves_played = 2 * n - 2,
-                Color::Black => position.half_moves_played = 2 * n - 1,
+        let half_moves_played = match fen_words[2].parse::<usize>() {
+            Ok(n) => match side_to_move {
+                Color::White => 2 * n - 2,
+                Color::Black => 2 * n - 1,
             },
             Err(e) => {
                 return Err(pgn_traits::Error::new_caused_by(
@@ -1796,11 +1954,14 @@ impl<const S: usize> pgn_traits::PgnPosition for Position<S> {
                     e,
                 ))
             }
-        }
-
-        position.hash = position.zobrist_hash_from_scratch();
+        };
 
-        return Ok(position);
+        return Ok(Position::from_stacks(
+            &stacks,
+            side_to_move,
+            half_moves_played,
+            settings.komi,
+        ));
 
         fn parse_row<const S: usize>(row_str: &str) -> Result<[Stack; S], pgn_traits::Error> {
             let mut column_id = 0;
@@ -1934,6 +2095,48 @@ pub(crate) fn connected_components_graph<const S: usize>(
     }
 }
 
+/// Label each group of `road_pieces` in `group_data`, using the same ids as `connected_components_graph`.
+/// Returns the critical squares for `road_pieces`, the squares that would complete a road if one was placed there
+fn connect_groups<const S: usize>(
+    road_pieces: BitBoard,
+    group_data: &mut GroupData<S>,
+    id: &mut u8,
+) -> BitBoard {
+    let edges = BitBoard::edges::<S>();
+    // For each edge, the squares that are on the edge, or next to a group connected to it
+    let mut touches_edges = edges;
+
+    let mut unlabeled = road_pieces;
+    while let Some(square) = unlabeled.occupied_square::<S>() {
+        let group = road_pieces.group_of_square(square);
+        unlabeled &= !group;
+
+        for square in group.into_iter::<S>() {
+            group_data.groups[square] = *id;
+        }
+
+        let neighborhood = group.dilate::<S>();
+        let mut edge_connection = GroupEdgeConnection::empty();
+        for ((edge, touches_edge), connect) in
+            edges.into_iter().zip(touches_edges.iter_mut()).zip([
+                GroupEdgeConnection::connect_north,
+                GroupEdgeConnection::connect_south,
+                GroupEdgeConnection::connect_west,
+                GroupEdgeConnection::connect_east,
+            ])
+        {
+            if !(group & edge).is_empty() {
+                edge_connection = connect(edge_connection);
+                *touches_edge |= neighborhood;
+            }
+        }
+        group_data.amount_in_group[*id as usize] = (group.count(), edge_connection);
+        *id += 1;
+    }
+    let [touches_north, touches_south, touches_west, touches_east] = touches_edges;
+    (touches_north & touches_south) | (touches_west & touches_east)
+}
+
 fn connect_component<const S: usize>(
     road_pieces: &mut BitBoard,
     components: &mut AbstractBoard<u8, S>,
@@ -4,7 +4,7 @@ use std::str::FromStr;
 
 use arrayvec::ArrayVec;
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::position::utils::Direction::{East, North, South, West};
 use crate::position::utils::Role::{Cap, Flat, Wall};
@@ -22,8 +22,9 @@ pub enum ExpMove<const S: usize> {
 }
 
 /// A legal move for a position.
+///
+/// Serialized in PTN notation in human-readable formats, and as `to_u16()` in binary formats
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Move<const S: usize> {
     inner: u16,
 }
@@ -56,6 +57,33 @@ impl<const S: usize> Move<S> {
         }
     }
 
+    /// The move's compressed representation, which can be converted back with `from_u16`
+    pub fn to_u16(self) -> u16 {
+        self.inner
+    }
+
+    /// Read a move from its compressed representation.
+    /// Returns `None` if the data does not describe a move on the board. The move may still be illegal in a given position.
+    pub fn from_u16(data: u16) -> Option<Self> {
+        let square = data as u8 & 63;
+        if square as usize >= S * S {
+            return None;
+        }
+        let stack_movement = data >> 8;
+        if stack_movement == 0 {
+            // Placements have no direction bits, only a role
+            return (data >> 6 < 3).then_some(Move { inner: data });
+        }
+        if stack_movement >> S != 0 {
+            return None;
+        }
+        let direction = Direction::from_disc((data as u8 >> 6) & 3);
+        let distance = StackMovement::<S>::from_u8(stack_movement as u8).len();
+        Square::<S>::from_u8(square)
+            .jump_direction(direction, distance as u8)
+            .map(|_| Move { inner: data })
+    }
+
     pub fn expand(self) -> ExpMove<S> {
         if self.inner >> 8 == 0 {
             unsafe {
@@ -248,6 +276,45 @@ impl<const S: usize> fmt::Display for Move<S> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<const S: usize> Serialize for Move<S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u16(self.inner)
+        }
+    }
+}
+
+/// A move in human-readable formats. Older versions wrote moves as `{"inner": <u16>}`, which is still accepted
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HumanReadableMove {
+    Ptn(String),
+    Legacy { inner: u16 },
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const S: usize> Deserialize<'de> for Move<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = if deserializer.is_human_readable() {
+            match HumanReadableMove::deserialize(deserializer)? {
+                HumanReadableMove::Ptn(input) => Move::<S>::from_string(&input)
+                    .map_err(de::Error::custom)?
+                    .to_u16(),
+                HumanReadableMove::Legacy { inner } => inner,
+            }
+        } else {
+            u16::deserialize(deserializer)?
+        };
+        // Also rejects spreads that go off the board, which the PTN parser does not check
+        Move::from_u16(data)
+            .ok_or_else(|| de::Error::custom(format!("Invalid {}s move {}", S, data)))
+    }
+}
+
 impl<const S: usize> ExpMove<S> {
     pub fn origin_square(&self) -> Square<S> {
         Move::compress(self.clone()).origin_square()
@@ -2,13 +2,14 @@ use std::fmt;
 use std::mem;
 
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use super::Direction::{self, *};
 
 /// A location on the board. Can be used to index a `Board`.
+///
+/// Serialized as its name, e.g. `c3`, in human-readable formats, and as its index in binary formats
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Square<const S: usize> {
     inner: u8,
 }
@@ -173,6 +174,50 @@ impl<const S: usize> fmt::Display for Square<S> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<const S: usize> Serialize for Square<S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u8(self.inner)
+        }
+    }
+}
+
+/// A square in human-readable formats. Older versions wrote squares as `{"inner": <u8>}`, which is still accepted
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HumanReadableSquare {
+    Name(String),
+    Legacy { inner: u8 },
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const S: usize> Deserialize<'de> for Square<S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = if deserializer.is_human_readable() {
+            match HumanReadableSquare::deserialize(deserializer)? {
+                HumanReadableSquare::Name(input) => {
+                    return Square::parse_square(&input).map_err(de::Error::custom)
+                }
+                HumanReadableSquare::Legacy { inner } => inner,
+            }
+        } else {
+            u8::deserialize(deserializer)?
+        };
+        if (inner as usize) < S * S {
+            Ok(Square { inner })
+        } else {
+            Err(de::Error::custom(format!(
+                "Invalid square {} at size {}",
+                inner, S
+            )))
+        }
+    }
+}
+
 /// Iterates over all board squares.
 pub fn squares_iterator<const S: usize>() -> impl Iterator<Item = Square<S>> {
     // Safety: `i` must be smaller than `S * S`, which is trivially true here
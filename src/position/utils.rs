@@ -16,7 +16,13 @@ use crate::position::Role::{Cap, Flat, Wall};
 
 use super::{GroupEdgeConnection, Square, SquareCacheEntry};
 
+/// Serialized as its value in points, e.g. `2.5`
 #[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(try_from = "f64", into = "f64")
+)]
 pub struct Komi {
     half_komi: i8,
 }
@@ -1,11 +1,40 @@
 use board_game_traits::{GameResult, Position};
 use pgn_traits::PgnPosition;
-use std::error;
+use std::{error, fmt};
 
 pub mod ptn_parser;
 pub mod ptn_writer;
 
-type ParseError = Box<dyn error::Error + Send + Sync>;
+pub type ParseError = Box<dyn error::Error + Send + Sync>;
+
+/// An error in a PTN file, with the line and column where it occurred. Both are 1-indexed
+#[derive(Debug)]
+pub struct PtnError {
+    /// Index of the game in the file, counting from 0
+    pub game: usize,
+    pub line: usize,
+    pub column: usize,
+    pub error: ParseError,
+}
+
+impl fmt::Display for PtnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Game {}, line {}, column {}: {}",
+            self.game + 1,
+            self.line,
+            self.column,
+            self.error
+        )
+    }
+}
+
+impl error::Error for PtnError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Game<B: Position> {
@@ -13,6 +42,8 @@ pub struct Game<B: Position> {
     pub moves: Vec<PtnMove<B::Move>>,
     pub game_result_str: Option<&'static str>,
     pub tags: Vec<(String, String)>,
+    /// Comment before the first move
+    pub comment: String,
 }
 
 impl<B: PgnPosition> Game<B> {
@@ -32,4 +63,6 @@ pub struct PtnMove<Move> {
     pub mv: Move,
     pub annotations: Vec<&'static str>,
     pub comment: String,
+    /// Alternatives to this move, each starting from the position before it
+    pub variations: Vec<Vec<PtnMove<Move>>>,
 }
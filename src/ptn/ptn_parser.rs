@@ -1,187 +1,272 @@
-use crate::ptn::{Game, ParseError, PtnMove};
+use crate::ptn::{Game, ParseError, PtnError, PtnMove};
 use pgn_traits::PgnPosition;
 use std::str::FromStr;
 
-pub fn parse_ptn<B: PgnPosition>(input: &str) -> Result<Vec<Game<B>>, ParseError> {
-    let mut parser = ParserData { input };
+/// Parse all games in the input. Fails on the first game that cannot be parsed
+pub fn parse_ptn<B: PgnPosition>(input: &str) -> Result<Vec<Game<B>>, PtnError> {
+    let mut parser = ParserData::new(input);
     let mut games = vec![];
-    loop {
-        if parser.input.chars().any(|ch| !ch.is_whitespace()) {
-            match parse_game(&mut parser) {
-                Ok(game) => games.push(game),
-                Err(err) => {
-                    eprintln!("Couldn't parse game: {}", err);
-                    if games.is_empty() {
-                        return Err(err);
-                    } else {
-                        return Ok(games);
-                    }
-                }
+    while parser.input.chars().any(|ch| !ch.is_whitespace()) {
+        games.push(parse_game(&mut parser)?);
+        parser.game += 1;
+    }
+    Ok(games)
+}
+
+/// Parse all games in the input, skipping games that cannot be parsed.
+/// Returns the parsed games, and an error for each skipped game
+pub fn parse_ptn_lenient<B: PgnPosition>(input: &str) -> (Vec<Game<B>>, Vec<PtnError>) {
+    let mut parser = ParserData::new(input);
+    let mut games = vec![];
+    let mut errors = vec![];
+    while parser.input.chars().any(|ch| !ch.is_whitespace()) {
+        match parse_game(&mut parser) {
+            Ok(game) => games.push(game),
+            Err(err) => {
+                errors.push(err);
+                parser.skip_rest_of_game();
             }
-        } else {
-            return Ok(games);
         }
+        parser.game += 1;
     }
+    (games, errors)
 }
 
-fn parse_game<B: PgnPosition>(input: &mut ParserData) -> Result<Game<B>, ParseError> {
+fn parse_game<B: PgnPosition>(input: &mut ParserData) -> Result<Game<B>, PtnError> {
     let mut tags = vec![];
+    let mut start_position_tag_offset = None;
+    input.in_moves = false;
     input.skip_whitespaces();
     while input.peek() == Some('[') {
+        let tag_offset = input.offset();
         let (tag, value) = parse_tag(input)?;
         input.skip_whitespaces();
+        if B::START_POSITION_TAG_NAME.is_some_and(|fen_tag| tag.eq_ignore_ascii_case(fen_tag)) {
+            start_position_tag_offset = Some(tag_offset);
+        }
         tags.push((tag.to_string(), value));
     }
+    input.in_moves = true;
 
     // Thunk to get the game's start position
     // It can't be a regular variable, because there is no `B: Clone` bound
-    let start_position = || {
+    let start_position = |input: &ParserData| {
         if let Some(fen_tag) = B::START_POSITION_TAG_NAME {
             if let Some((_, tps)) = tags
                 .iter()
                 .find(|(name, _)| name.eq_ignore_ascii_case(fen_tag))
             {
-                B::from_fen(tps)
-            } else {
-                Ok(B::start_position())
+                return B::from_fen(tps)
+                    .map_err(|err| input.error_at(start_position_tag_offset.unwrap(), err));
             }
-        } else {
-            Ok(B::start_position())
         }
+        Ok(B::start_position())
     };
 
-    let (moves, game_result_str) = parse_moves(input, start_position()?)?;
+    let mut position = start_position(input)?;
+    let (moves, game_result_str, comment) = parse_moves(input, &mut position, None)?;
 
     Ok(Game {
-        start_position: start_position()?,
+        start_position: start_position(input)?,
         moves,
         game_result_str,
         tags,
+        comment,
     })
 }
 
-fn parse_tag<'a>(input: &mut ParserData<'a>) -> Result<(&'a str, String), pgn_traits::Error> {
+fn parse_tag<'a>(input: &mut ParserData<'a>) -> Result<(&'a str, String), PtnError> {
+    let tag_offset = input.offset();
     assert_eq!(input.take(), Some('['));
     let tag: &'a str = input.take_word();
 
     input.skip_whitespaces();
+    let value_offset = input.offset();
     if input.take() != Some('"') {
-        return Err(pgn_traits::Error::new_parse_error(format!(
-            "Tag value for {} didn't start with \"",
-            tag
-        )));
+        return Err(input.parse_error_at(
+            value_offset,
+            format!("Tag value for {} didn't start with \"", tag),
+        ));
     }
 
     let mut value = String::new();
     loop {
         match input.take() {
             Some('"') => break,
-            Some('\\') => match input.take().unwrap() {
-                '"' => value.push('"'),
-                '\\' => value.push('\\'),
-                ch => {
+            Some('\\') => match input.take() {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some(ch) => {
                     value.push('\\');
                     value.push(ch);
                 }
+                None => break,
             },
             Some(ch) => value.push(ch),
             None => {
-                return Err(pgn_traits::Error::new_parse_error(format!(
-                    "Unexpected EOF parsing tag value for {}",
-                    tag
-                )))
+                return Err(input.parse_error_at(
+                    tag_offset,
+                    format!("Unexpected EOF parsing tag value for {}", tag),
+                ))
             }
         }
     }
     input.skip_whitespaces();
+    let end_offset = input.offset();
     if input.take() == Some(']') {
         Ok((tag, value))
     } else {
-        Err(pgn_traits::Error::new_parse_error(format!(
-            "Unexpected EOF parsing tag value for {}",
-            tag
-        )))
+        Err(input.parse_error_at(end_offset, format!("Expected ] after tag {}", tag)))
     }
 }
 
+/// Parse moves until the end of the game, or the end of the variation if `variation_offset` is set.
+/// Variations leave `position` as it was, while the main line leaves it at the end of the game.
+/// Also returns any comment before the first move
 #[allow(clippy::type_complexity)]
 fn parse_moves<B: PgnPosition>(
     input: &mut ParserData,
-    mut position: B,
-) -> Result<(Vec<PtnMove<B::Move>>, Option<&'static str>), ParseError> {
+    position: &mut B,
+    variation_offset: Option<usize>,
+) -> Result<(Vec<PtnMove<B::Move>>, Option<&'static str>, String), PtnError> {
     let mut moves: Vec<PtnMove<B::Move>> = vec![];
+    let mut reverse_moves = vec![];
+    let mut first_comment = String::new();
     let mut _ply_counter = 0; // Last ply seen
     loop {
         input.skip_whitespaces();
-        if input.peek().is_none() || input.peek() == Some('[') {
-            // Games without a result aren't allowed by the spec,
-            // but try to accept it anyway and return a `None` result
-            if !moves.is_empty() {
-                return Ok((moves, None));
+        let token_offset = input.offset();
+        match (input.peek(), variation_offset) {
+            (None, None) | (Some('['), None) => {
+                // Games without a result aren't allowed by the spec,
+                // but try to accept it anyway and return a `None` result
+                if !moves.is_empty() {
+                    return Ok((moves, None, first_comment));
+                }
+                // Return an error if we've read tags, but no moves
+                return Err(input.parse_error_at(
+                    token_offset,
+                    "Unexpected EOF, expected a move or a game result.".to_string(),
+                ));
             }
-            // Return an error if we've read tags, but no moves
-            return Err(Box::new(pgn_traits::Error::new_parse_error(
-                "Unexpected EOF, expected a move or a game result.".to_string(),
-            )));
-        }
-        let word = input.take_word();
-
-        assert!(!word.is_empty());
-
-        if let Some(num_string) = word.strip_suffix("...") {
-            let _num = u64::from_str(num_string)?;
-            _ply_counter = _num * 2 - 1;
-        } else if let Some(num_string) = word.strip_suffix('.') {
-            let _num = u64::from_str(num_string)?;
-            _ply_counter = _num * 2 - 2;
-        } else if let Some((result_str, _)) = B::POSSIBLE_GAME_RESULTS
-            .iter()
-            .find(|(s, _result)| *s == word)
-        {
-            return Ok((moves, Some(*result_str)));
-        } else {
-            let mut move_string = word;
-            let mut annotations = vec![];
-            while let Some(annotation) = B::POSSIBLE_MOVE_ANNOTATIONS
-                .iter()
-                .find(|annotation| move_string.strip_suffix(*annotation).is_some())
-            {
-                move_string = move_string.strip_suffix(*annotation).unwrap();
-                annotations.insert(0, *annotation);
+            (None, Some(variation_offset)) | (Some('['), Some(variation_offset)) => {
+                return Err(input.parse_error_at(
+                    variation_offset,
+                    "Variation was not closed with )".to_string(),
+                ));
+            }
+            (Some('{'), _) => {
+                input.take();
+                let comment = input.take_while(|ch| ch != '}');
+                if input.take().is_none() {
+                    return Err(input.parse_error_at(
+                        token_offset,
+                        "Comment was not closed with }".to_string(),
+                    ));
+                }
+                let previous_comment = match moves.last_mut() {
+                    Some(last_move) => &mut last_move.comment,
+                    None => &mut first_comment,
+                };
+                if !previous_comment.is_empty() {
+                    previous_comment.push(' ');
+                }
+                previous_comment.push_str(comment);
+            }
+            (Some('('), _) => {
+                input.take();
+                let (Some(last_move), Some(reverse_move)) = (moves.last_mut(), reverse_moves.pop())
+                else {
+                    return Err(input.parse_error_at(
+                        token_offset,
+                        "Variation before the first move".to_string(),
+                    ));
+                };
+                // The variation is an alternative to the last move
+                position.reverse_move(reverse_move);
+                let (mut variation, _, comment) = parse_moves(input, position, Some(token_offset))?;
+                reverse_moves.push(position.do_move(last_move.mv.clone()));
+                if variation.is_empty() {
+                    return Err(input.parse_error_at(token_offset, "Empty variation".to_string()));
+                }
+                if !comment.is_empty() {
+                    if !variation[0].comment.is_empty() {
+                        variation[0].comment.insert(0, ' ');
+                    }
+                    variation[0].comment.insert_str(0, &comment);
+                }
+                last_move.variations.push(variation);
+            }
+            (Some(')'), None) => {
+                return Err(input.parse_error_at(
+                    token_offset,
+                    "Unexpected ) outside of a variation".to_string(),
+                ));
             }
+            (Some(')'), Some(_)) => {
+                input.take();
+                for reverse_move in reverse_moves.into_iter().rev() {
+                    position.reverse_move(reverse_move);
+                }
+                return Ok((moves, None, first_comment));
+            }
+            (Some(_), _) => {
+                let word = input.take_word();
+                assert!(!word.is_empty());
 
-            match position.move_from_san(move_string) {
-                Ok(mv) => {
-                    if !position.move_is_legal(mv.clone()) {
-                        return Err(Box::new(pgn_traits::Error::new(
-                            pgn_traits::ErrorKind::IllegalMove,
-                            word,
-                        )));
+                if let Some(num_string) = word.strip_suffix("...") {
+                    let _num = u64::from_str(num_string)
+                        .map_err(|err| input.error_at(token_offset, err))?;
+                    _ply_counter = _num * 2 - 1;
+                } else if let Some(num_string) = word.strip_suffix('.') {
+                    let _num = u64::from_str(num_string)
+                        .map_err(|err| input.error_at(token_offset, err))?;
+                    _ply_counter = _num * 2 - 2;
+                } else if let Some((result_str, _)) = B::POSSIBLE_GAME_RESULTS
+                    .iter()
+                    .find(|(s, _result)| *s == word)
+                {
+                    // Results at the end of variations are accepted, but not kept
+                    if variation_offset.is_none() {
+                        return Ok((moves, Some(*result_str), first_comment));
                     }
-                    position.do_move(mv.clone());
-                    input.skip_whitespaces();
-                    if input.peek() == Some('{') {
-                        input.take();
-                        let comment = input.take_while(|ch| ch != '}');
-                        input.take();
-                        moves.push(PtnMove {
-                            mv,
-                            annotations,
-                            comment: comment.to_string(),
-                        })
-                    } else {
-                        moves.push(PtnMove {
-                            mv,
-                            annotations,
-                            comment: String::new(),
-                        });
+                } else {
+                    let mut move_string = word;
+                    let mut annotations = vec![];
+                    while let Some(annotation) = B::POSSIBLE_MOVE_ANNOTATIONS
+                        .iter()
+                        .find(|annotation| move_string.strip_suffix(*annotation).is_some())
+                    {
+                        move_string = move_string.strip_suffix(*annotation).unwrap();
+                        annotations.insert(0, *annotation);
+                    }
+
+                    match position.move_from_san(move_string) {
+                        Ok(mv) => {
+                            if !position.move_is_legal(mv.clone()) {
+                                return Err(input.error_at(
+                                    token_offset,
+                                    pgn_traits::Error::new(
+                                        pgn_traits::ErrorKind::IllegalMove,
+                                        word,
+                                    ),
+                                ));
+                            }
+                            reverse_moves.push(position.do_move(mv.clone()));
+                            moves.push(PtnMove {
+                                mv,
+                                annotations,
+                                comment: String::new(),
+                                variations: vec![],
+                            });
+                        }
+                        Err(err) => {
+                            return Err(input.parse_error_at(
+                                token_offset,
+                                format!("Couldn't parse move {}: {}", word, err),
+                            ));
+                        }
                     }
-                }
-                Err(err) => {
-                    return Err(Box::new(pgn_traits::Error::new_parse_error(format!(
-                        "Couldn't parse move {}: {}",
-                        word, err
-                    ))));
                 }
             }
         }
@@ -189,17 +274,70 @@ fn parse_moves<B: PgnPosition>(
 }
 
 struct ParserData<'a> {
+    full_input: &'a str,
     input: &'a str,
+    /// Index of the game being parsed
+    game: usize,
+    /// Whether the parser has read past the game's tags
+    in_moves: bool,
 }
 
 impl<'a> ParserData<'a> {
+    fn new(input: &'a str) -> Self {
+        ParserData {
+            full_input: input,
+            input,
+            game: 0,
+            in_moves: false,
+        }
+    }
+
+    /// Byte offset of the current position in the full input
+    fn offset(&self) -> usize {
+        self.full_input.len() - self.input.len()
+    }
+
+    fn error_at<E: Into<ParseError>>(&self, offset: usize, error: E) -> PtnError {
+        let preceding = &self.full_input[..offset];
+        let line_start = preceding.rfind('\n').map_or(0, |i| i + 1);
+        PtnError {
+            game: self.game,
+            line: preceding.matches('\n').count() + 1,
+            column: preceding[line_start..].chars().count() + 1,
+            error: error.into(),
+        }
+    }
+
+    fn parse_error_at(&self, offset: usize, message: String) -> PtnError {
+        self.error_at(offset, pgn_traits::Error::new_parse_error(message))
+    }
+
+    /// Skip to the next line that starts a new game, after a parse error
+    fn skip_rest_of_game(&mut self) {
+        if !self.in_moves {
+            // Skip the game's remaining tags
+            self.skip_line();
+            while self.input.trim_start().starts_with('[') {
+                self.skip_line();
+            }
+        }
+        while !self.input.is_empty() && !self.input.trim_start().starts_with('[') {
+            self.skip_line();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.take_while(|ch| ch != '\n');
+        self.take();
+    }
+
     fn skip_whitespaces(&mut self) {
         self.input = self.input.trim_start_matches(char::is_whitespace);
     }
 
     fn take_word(&mut self) -> &'a str {
         self.skip_whitespaces();
-        self.take_while(|ch| !ch.is_whitespace())
+        self.take_while(|ch| !ch.is_whitespace() && !['{', '(', ')'].contains(&ch))
     }
 
     fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
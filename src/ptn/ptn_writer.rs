@@ -55,78 +55,102 @@ impl<B: PgnPosition + Clone> Game<B> {
 
         writeln!(f)?;
 
-        let mut position = self.start_position.clone();
-        let mut column_position = 0;
-        let mut buffer = String::new();
-
-        let start_move_number = position.full_move_number().unwrap_or(1) as usize;
-
-        for (
-            i,
-            PtnMove {
-                mv,
-                comment,
-                annotations,
-            },
-        ) in self.moves.iter().enumerate()
-        {
-            let move_string = position.move_to_san(mv);
-            if i == 0 && position.side_to_move() == Color::Black {
-                write!(buffer, "{}... {}", start_move_number, move_string).unwrap();
-            } else if position.side_to_move() == Color::White {
-                write!(
-                    buffer,
-                    "{}. {}",
-                    i.div_ceil(2) + start_move_number,
-                    move_string
-                )
-                .unwrap();
-            } else {
-                buffer.push_str(&move_string);
-            }
-
-            for annotation in annotations {
-                buffer.push_str(annotation);
-            }
+        let start_move_number = self.start_position.full_move_number().unwrap_or(1) as usize;
 
-            if !comment.is_empty() {
-                buffer.push_str(" {");
-                buffer.push_str(comment);
-                buffer.push('}');
-            }
+        let mut units = vec![];
+        if !self.comment.is_empty() {
+            units.push(format!("{{{}}}", self.comment));
+        }
+        write_line(
+            &mut self.start_position.clone(),
+            &self.moves,
+            start_move_number,
+            &mut units,
+        );
 
-            if i == self.moves.len() - 1 {
-                buffer.push(' ');
-                buffer.push_str(self.game_result_str.unwrap_or("*"));
+        // Write the result together with the last move
+        let result = self.game_result_str.unwrap_or("*");
+        match units.last_mut() {
+            Some(last_unit) => {
+                last_unit.push(' ');
+                last_unit.push_str(result);
             }
+            None => units.push(result.to_string()),
+        }
 
-            if position.side_to_move() == Color::Black || i == self.moves.len() - 1 {
-                if column_position == 0 {
-                    write!(f, "{}", buffer)?;
-                    column_position = buffer.len();
-                } else if column_position + buffer.len() < LINE_WIDTH {
-                    write!(f, " {}", buffer)?;
-                    column_position += buffer.len() + 1;
-                } else {
-                    write!(f, "\n{}", buffer)?;
-                    column_position = buffer.len();
-                }
-                buffer.clear();
+        let mut column_position = 0;
+        for unit in units {
+            if column_position == 0 {
+                write!(f, "{}", unit)?;
+                column_position = unit.len();
+            } else if column_position + unit.len() < LINE_WIDTH {
+                write!(f, " {}", unit)?;
+                column_position += unit.len() + 1;
             } else {
-                buffer.push(' ');
+                write!(f, "\n{}", unit)?;
+                column_position = unit.len();
             }
-
-            position.do_move(mv.clone());
         }
 
-        assert!(
-            buffer.is_empty(),
-            "\"{}\" was not written to the ptn",
-            buffer
-        );
-
         writeln!(f)?;
         writeln!(f)?;
         Ok(())
     }
 }
+
+/// Split a line of moves into units that are never split across lines.
+/// A unit is usually a full move, but each variation is a unit, and the move before it ends a unit.
+fn write_line<B: PgnPosition + Clone>(
+    position: &mut B,
+    moves: &[PtnMove<B::Move>],
+    mut move_number: usize,
+    units: &mut Vec<String>,
+) {
+    let mut buffer = String::new();
+    for PtnMove {
+        mv,
+        annotations,
+        comment,
+        variations,
+    } in moves.iter()
+    {
+        let move_string = position.move_to_san(mv);
+        if position.side_to_move() == Color::White {
+            write!(buffer, "{}. {}", move_number, move_string).unwrap();
+        } else if buffer.is_empty() {
+            write!(buffer, "{}... {}", move_number, move_string).unwrap();
+        } else {
+            buffer.push(' ');
+            buffer.push_str(&move_string);
+        }
+
+        for annotation in annotations {
+            buffer.push_str(annotation);
+        }
+
+        if !comment.is_empty() {
+            buffer.push_str(" {");
+            buffer.push_str(comment);
+            buffer.push('}');
+        }
+
+        if position.side_to_move() == Color::Black || !variations.is_empty() {
+            units.push(std::mem::take(&mut buffer));
+        }
+
+        for variation in variations {
+            let first_unit = units.len();
+            write_line(&mut position.clone(), variation, move_number, units);
+            units[first_unit].insert(0, '(');
+            units.last_mut().unwrap().push(')');
+        }
+
+        if position.side_to_move() == Color::Black {
+            move_number += 1;
+        }
+        position.do_move(mv.clone());
+    }
+    if !buffer.is_empty() {
+        units.push(buffer);
+    }
+}
@@ -19,6 +19,11 @@ pub struct Arena<const S: usize = 16> {
     pub stats: ArenaStats,
 }
 
+// Safety: The arena only hands out memory through the atomic bump pointer, so allocating from several threads is safe.
+// Synchronizing access to the elements themselves is the responsibility of the caller.
+unsafe impl<const S: usize> Send for Arena<S> {}
+unsafe impl<const S: usize> Sync for Arena<S> {}
+
 #[derive(Debug, Default)]
 pub struct ArenaStats {
     pub bytes_allocated: AtomicUsize,
@@ -50,6 +55,76 @@ impl<T> Index<T> {
             phantom: PhantomData,
         }
     }
+
+    /// The index as a plain integer, for storing it outside the arena
+    pub fn to_raw(&self) -> NonZeroU32 {
+        self.data
+    }
+
+    /// Re-create an index from `to_raw`. The index must be from the same arena
+    pub fn from_raw(data: NonZeroU32) -> Self {
+        Self::new(data)
+    }
+}
+
+/// Raw value used by `AtomicIndex` to signal that another thread is currently writing the index
+const LOCKED_INDEX: u32 = u32::MAX;
+
+/// An optional index that can be written once, while being shared between threads.
+///
+/// A thread must call `try_lock` before creating the element, and then either `set` or `unlock`.
+/// Other threads may observe the index as locked in the meantime.
+#[derive(Debug)]
+pub struct AtomicIndex<T> {
+    data: AtomicU32,
+    phantom: PhantomData<T>,
+}
+
+impl<T> AtomicIndex<T> {
+    pub const fn empty() -> Self {
+        Self {
+            data: AtomicU32::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn new(index: Index<T>) -> Self {
+        Self {
+            data: AtomicU32::new(index.data.get()),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the index, if it has been set
+    pub fn get(&self) -> Option<Index<T>> {
+        match self.data.load(Ordering::Acquire) {
+            LOCKED_INDEX => None,
+            raw_index => NonZeroU32::new(raw_index).map(Index::new),
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.data.load(Ordering::Acquire) == LOCKED_INDEX
+    }
+
+    /// Try to get exclusive rights to set the index. Fails if the index is already set, or locked by another thread
+    pub fn try_lock(&self) -> bool {
+        self.data
+            .compare_exchange(0, LOCKED_INDEX, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Set the index, releasing the lock
+    pub fn set(&self, index: Index<T>) {
+        debug_assert!(self.is_locked());
+        self.data.store(index.data.get(), Ordering::Release);
+    }
+
+    /// Release the lock without setting the index, letting other threads try again
+    pub fn unlock(&self) {
+        debug_assert!(self.is_locked());
+        self.data.store(0, Ordering::Release);
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -81,7 +156,7 @@ impl<T> Default for SliceIndex<T> {
 
 const fn raw_alignment(mut alignment: usize) -> usize {
     let mut raw_alignment = 1;
-    while alignment % 2 == 0 {
+    while alignment.is_multiple_of(2) {
         raw_alignment *= 2;
         alignment /= 2;
     }
@@ -141,7 +216,7 @@ impl<const S: usize> Arena<S> {
             }
 
             // Make sure the pointer is correctly aligned
-            if (ptr as usize) % S == 0 {
+            if (ptr as usize).is_multiple_of(S) {
                 (ptr, ptr)
             } else {
                 (ptr.add(S - (ptr as usize) % S), ptr)
@@ -161,7 +236,7 @@ impl<const S: usize> Arena<S> {
     /// Get a reference to an element in the arena
     /// # Safety
     /// This function is actually unsafe, if the index is from a different arena
-    pub fn get<'a, T>(&'a self, index: &'a Index<T>) -> &'a T {
+    pub fn get<'a, T>(&'a self, index: &Index<T>) -> &'a T {
         unsafe {
             let ptr = self.ptr_to_index(index.data.get()) as *const T;
             &*ptr
@@ -181,7 +256,7 @@ impl<const S: usize> Arena<S> {
     /// Get a slice from the arena
     /// # Safety
     /// This function is actually unsafe, if the index is from a different arena
-    pub fn get_slice<'a, T>(&'a self, index: &'a SliceIndex<T>) -> &'a [T] {
+    pub fn get_slice<'a, T>(&'a self, index: &SliceIndex<T>) -> &'a [T] {
         if index.length == 0 {
             Default::default()
         } else {
@@ -298,7 +373,7 @@ impl<const S: usize> Arena<S> {
     }
 
     pub const fn supports_type<T>(&self) -> bool {
-        S % mem::align_of::<T>() == 0
+        S.is_multiple_of(mem::align_of::<T>())
     }
 
     pub fn slots_used(&self) -> u32 {
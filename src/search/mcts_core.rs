@@ -1,5 +1,9 @@
+use std::collections::HashMap;
 use std::f32;
+use std::hint;
+use std::num::NonZeroU32;
 use std::ops;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 
 use board_game_traits::{Color, GameResult, Position as PositionTrait};
 use half::f16;
@@ -7,34 +11,168 @@ use half::slice::HalfFloatSliceExt;
 use rand::Rng;
 use rand_distr::Distribution;
 
+use crate::evaluation::network::{NetworkPolicy, NetworkValue};
 use crate::evaluation::parameters::IncrementalPolicy;
 use crate::position::Move;
 /// This module contains the core of the MCTS search algorithm
 use crate::position::Position;
 use crate::search::{cp_to_win_percentage, MctsSetting};
 
+use super::arena::{AtomicIndex, Index};
+use super::transposition_table::TranspositionTable;
 use super::{arena, Arena, Error};
 
 /// A Monte Carlo Search Tree, containing every node that has been seen in search.
-#[derive(PartialEq, Debug)]
+///
+/// The tree may be searched by several threads at once, so every field that changes during search is atomic.
+/// Nodes and their children are created by whichever thread manages to lock the corresponding `AtomicIndex`.
+///
+/// With a transposition table, a node may be shared by several parents, so its number of visits is counted separately from the visits of the edges leading to it.
+///
+/// Terminal nodes have a proven result from the start. Other nodes become proven once their children's results are proven,
+/// after which they are never searched further.
+#[derive(Debug)]
 pub struct Tree<const S: usize> {
-    pub total_action_value: f64,
-    pub game_result: Option<GameResultForUs>,
-    pub children: Option<arena::Index<TreeBridge<S>>>,
+    pub total_action_value: AtomicF64,
+    pub visits: AtomicU32,
+    pub proven_result: AtomicProvenResult,
+    pub children: AtomicIndex<TreeBridge<S>>,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Debug)]
 pub struct TreeBridge<const S: usize> {
     pub children: arena::SliceIndex<TreeEdge<S>>,
     pub moves: arena::SliceIndex<Option<Move<S>>>,
-    pub mean_action_values: arena::SliceIndex<f32>,
-    pub visitss: arena::SliceIndex<u32>,
+    pub mean_action_values: arena::SliceIndex<AtomicF32>,
+    pub visitss: arena::SliceIndex<AtomicU32>,
     pub heuristic_scores: arena::SliceIndex<f16>,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Debug)]
 pub struct TreeEdge<const S: usize> {
-    pub child: Option<arena::Index<Tree<S>>>,
+    pub child: AtomicIndex<Tree<S>>,
+}
+
+impl<const S: usize> TreeEdge<S> {
+    pub const fn empty() -> Self {
+        TreeEdge {
+            child: AtomicIndex::empty(),
+        }
+    }
+
+    pub fn new(child: arena::Index<Tree<S>>) -> Self {
+        TreeEdge {
+            child: AtomicIndex::new(child),
+        }
+    }
+}
+
+/// Score added to a child node while another thread is searching it, as if the visit was a loss for the parent.
+/// This discourages other threads from selecting the same line.
+const VIRTUAL_LOSS: f64 = 1.0;
+
+/// An `f32` that can be shared between search threads
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        AtomicF32(AtomicU32::new(value.to_bits()))
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline(always)]
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// An `f64` that can be shared between search threads
+#[derive(Debug, Default)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub fn new(value: f64) -> Self {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline(always)]
+    pub fn fetch_add(&self, value: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+}
+
+/// A game result that is known with best play from both sides
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProvenResult {
+    /// The result for the side to move
+    pub result: GameResultForUs,
+    /// Number of plies until the game ends
+    pub plies: u16,
+}
+
+impl ProvenResult {
+    /// The result one ply earlier, for the other side
+    pub fn parent_result(self) -> ProvenResult {
+        ProvenResult {
+            result: !self.result,
+            plies: self.plies.saturating_add(1),
+        }
+    }
+}
+
+/// An optional `ProvenResult` that can be shared between search threads
+#[derive(Debug, Default)]
+pub struct AtomicProvenResult(AtomicU32);
+
+impl AtomicProvenResult {
+    pub fn new(proven_result: Option<ProvenResult>) -> Self {
+        let atomic_result = AtomicProvenResult::default();
+        if let Some(proven_result) = proven_result {
+            atomic_result.store(proven_result);
+        }
+        atomic_result
+    }
+
+    #[inline(always)]
+    pub fn load(&self) -> Option<ProvenResult> {
+        let value = self.0.load(Ordering::Relaxed);
+        let result = match value & 0b11 {
+            0 => return None,
+            1 => GameResultForUs::Win,
+            2 => GameResultForUs::Loss,
+            _ => GameResultForUs::Draw,
+        };
+        Some(ProvenResult {
+            result,
+            plies: (value >> 2) as u16,
+        })
+    }
+
+    pub fn store(&self, proven_result: ProvenResult) {
+        let result = match proven_result.result {
+            GameResultForUs::Win => 1,
+            GameResultForUs::Loss => 2,
+            GameResultForUs::Draw => 3,
+        };
+        self.0.store(
+            result | (proven_result.plies as u32) << 2,
+            Ordering::Relaxed,
+        );
+    }
 }
 
 /// Temporary vectors that are continually re-used during search to avoid unnecessary allocations
@@ -44,6 +182,8 @@ pub struct TempVectors<const S: usize> {
     moves: Vec<(Move<S>, f16)>,
     fcd_per_move: Vec<i8>,
     policy_feature_sets: Vec<IncrementalPolicy<S>>,
+    network_policy_feature_sets: Vec<NetworkPolicy<S>>,
+    network_value: NetworkValue<S>,
     unpacked_heuristic_scores: Vec<f32>,
 }
 
@@ -54,6 +194,8 @@ impl<const S: usize> Default for TempVectors<S> {
             moves: vec![],
             fcd_per_move: vec![],
             policy_feature_sets: vec![],
+            network_policy_feature_sets: vec![],
+            network_value: NetworkValue::default(),
             unpacked_heuristic_scores: vec![0.; 65536],
         }
     }
@@ -80,7 +222,7 @@ fn fast_ln(f: f32) -> f32 {
 impl<const S: usize> TreeBridge<S> {
     #[inline(always)]
     pub fn best_child(
-        &mut self,
+        &self,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors<S>,
         arena: &Arena,
@@ -105,13 +247,13 @@ impl<const S: usize> TreeBridge<S> {
 
         for i in 0..heuristic_scores.len() {
             let heuristic_score = &mut heuristic_scores[i];
-            let mean_action_value = &mean_action_values[i];
-            let child_visits = &visitss[i];
+            let mean_action_value = mean_action_values[i].load();
+            let child_visits = visitss[i].load(Ordering::Relaxed);
 
             *heuristic_score = exploration_value(
-                *mean_action_value,
+                mean_action_value,
                 *heuristic_score,
-                *child_visits,
+                child_visits,
                 visits_sqrt,
                 dynamic_cpuct,
             )
@@ -152,14 +294,17 @@ impl<const S: usize> TreeBridge<S> {
         best_child_node_index as usize
     }
 
+    /// Select and search one of the children.
+    /// Returns the result, and the node's proven result if the selected child's result is enough to prove it.
     pub fn select(
-        &mut self,
+        &self,
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors<S>,
         arena: &Arena,
+        transposition_table: Option<&TranspositionTable<S>>,
         our_visits: u32,
-    ) -> Result<f32, Error> {
+    ) -> Result<(f32, Option<ProvenResult>), Error> {
         assert_ne!(
             arena.get_slice(&self.children).len(),
             0,
@@ -169,46 +314,188 @@ impl<const S: usize> TreeBridge<S> {
 
         let best_child_node_index = self.best_child(settings, temp_vectors, arena, our_visits);
 
-        let child_edge = arena
-            .get_slice_mut(&mut self.children)
-            .get_mut(best_child_node_index)
-            .unwrap();
-        let child_visits = *arena
-            .get_slice_mut(&mut self.visitss)
-            .get_mut(best_child_node_index)
-            .unwrap();
-        let child_move = arena
-            .get_slice_mut(&mut self.moves)
-            .get_mut(best_child_node_index)
-            .unwrap()
-            .unwrap_or_else(|| {
-                panic!(
-                    "Move has {} visits from {} parent vists",
-                    child_visits, our_visits
-                )
-            });
+        let child_edge = &arena.get_slice(&self.children)[best_child_node_index];
+        let visits = &arena.get_slice(&self.visitss)[best_child_node_index];
+        let mean_action_value = &arena.get_slice(&self.mean_action_values)[best_child_node_index];
+        let child_move = arena.get_slice(&self.moves)[best_child_node_index].unwrap_or_else(|| {
+            panic!(
+                "Move has {} visits from {} parent vists",
+                visits.load(Ordering::Relaxed),
+                our_visits
+            )
+        });
+
+        // Count the visit before searching the child, so that other threads see it immediately
+        let child_visits = visits.fetch_add(1, Ordering::Relaxed);
+
+        if transposition_table.is_some() {
+            // If the child has already been visited more through other parents than through this edge,
+            // back up its current value instead of searching it again
+            if let Some(child) = child_edge.child.get().map(|index| arena.get(&index)) {
+                if child.visits.load(Ordering::Relaxed) > child_visits {
+                    let child_value = child.mean_action_value();
+                    mean_action_value.store(child_value);
+                    return Ok((
+                        1.0 - child_value,
+                        self.proven_result_from_child(child, arena),
+                    ));
+                }
+            }
+        }
+
+        let virtual_loss_child = if settings.threads > 1 {
+            child_edge.child.get().map(|index| arena.get(&index))
+        } else {
+            None
+        };
+        if let Some(child) = virtual_loss_child {
+            child.total_action_value.fetch_add(VIRTUAL_LOSS);
+            mean_action_value
+                .store(child.total_action_value.load() as f32 / (child_visits + 1) as f32);
+        }
 
         position.do_move(child_move);
 
-        let result =
-            1.0 - child_edge.select(position, settings, temp_vectors, arena, child_visits)?;
-
-        *arena
-            .get_slice_mut(&mut self.visitss)
-            .get_mut(best_child_node_index)
-            .unwrap() += 1;
-
-        *arena
-            .get_slice_mut(&mut self.mean_action_values)
-            .get_mut(best_child_node_index)
-            .unwrap() = arena
-            .get(child_edge.child.as_ref().unwrap())
-            .total_action_value as f32
-            / *arena
-                .get_slice_mut(&mut self.visitss)
-                .get_mut(best_child_node_index)
-                .unwrap() as f32;
-        Ok(result)
+        let result = match child_edge.select(
+            position,
+            settings,
+            temp_vectors,
+            arena,
+            transposition_table,
+            child_visits,
+        ) {
+            Ok(result) => 1.0 - result,
+            Err(err) => {
+                visits.fetch_sub(1, Ordering::Relaxed);
+                if let Some(child) = virtual_loss_child {
+                    child.total_action_value.fetch_add(-VIRTUAL_LOSS);
+                }
+                return Err(err);
+            }
+        };
+
+        let child = arena.get(&child_edge.child.get().unwrap());
+        if virtual_loss_child.is_some() {
+            child.total_action_value.fetch_add(-VIRTUAL_LOSS);
+        }
+
+        if transposition_table.is_some() {
+            // The child may also have been visited through other parents
+            mean_action_value.store(child.mean_action_value());
+        } else {
+            mean_action_value.store(
+                child.total_action_value.load() as f32 / visits.load(Ordering::Relaxed) as f32,
+            );
+        }
+        Ok((result, self.proven_result_from_child(child, arena)))
+    }
+
+    /// If `child` has a proven result, check whether the node's own result is now proven
+    #[inline(always)]
+    fn proven_result_from_child(&self, child: &Tree<S>, arena: &Arena) -> Option<ProvenResult> {
+        child.proven_result.load()?;
+        self.proven_result(arena)
+    }
+
+    /// The node's result, if it can be proven from the results of its children.
+    /// The node is won if any child is lost for the opponent, taking the fastest win.
+    /// If every child is proven, the node is drawn if any child is drawn, and otherwise lost, taking the slowest loss.
+    pub fn proven_result(&self, arena: &Arena) -> Option<ProvenResult> {
+        let mut fastest_win: Option<ProvenResult> = None;
+        let mut best_other_result: Option<ProvenResult> = None;
+        let mut all_children_proven = true;
+
+        for (mv, edge) in arena
+            .get_slice(&self.moves)
+            .iter()
+            .zip(arena.get_slice(&self.children))
+        {
+            if mv.is_none() {
+                continue;
+            }
+            let Some(child_result) = edge
+                .child
+                .get()
+                .and_then(|child| arena.get(&child).proven_result.load())
+            else {
+                all_children_proven = false;
+                continue;
+            };
+            let result = child_result.parent_result();
+            match result.result {
+                GameResultForUs::Win => {
+                    if fastest_win.is_none_or(|win| result.plies < win.plies) {
+                        fastest_win = Some(result);
+                    }
+                }
+                GameResultForUs::Draw => {
+                    if best_other_result.is_none_or(|best| {
+                        best.result == GameResultForUs::Loss || result.plies < best.plies
+                    }) {
+                        best_other_result = Some(result);
+                    }
+                }
+                GameResultForUs::Loss => {
+                    if best_other_result.is_none_or(|best| {
+                        best.result == GameResultForUs::Loss && result.plies > best.plies
+                    }) {
+                        best_other_result = Some(result);
+                    }
+                }
+            }
+        }
+        fastest_win.or(best_other_result.filter(|_| all_children_proven))
+    }
+
+    /// Copy the bridge, and all its child nodes, from `arena` into `new_arena`.
+    /// See `Tree::copy_to` for `copied_nodes`.
+    pub fn copy_to(
+        &self,
+        arena: &Arena,
+        new_arena: &Arena,
+        copied_nodes: &mut Option<HashMap<NonZeroU32, Index<Tree<S>>>>,
+    ) -> Result<TreeBridge<S>, Error> {
+        let children = arena
+            .get_slice(&self.children)
+            .iter()
+            .map(|edge| match edge.child.get() {
+                Some(child_index) => Ok(TreeEdge::new(Tree::copy_to(
+                    &child_index,
+                    arena,
+                    new_arena,
+                    copied_nodes,
+                )?)),
+                None => Ok(TreeEdge::empty()),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(TreeBridge {
+            children: new_arena
+                .add_slice(children.into_iter())
+                .ok_or(Error::OOM)?,
+            moves: new_arena
+                .add_slice(arena.get_slice(&self.moves).iter().copied())
+                .ok_or(Error::OOM)?,
+            mean_action_values: new_arena
+                .add_slice(
+                    arena
+                        .get_slice(&self.mean_action_values)
+                        .iter()
+                        .map(|value| AtomicF32::new(value.load())),
+                )
+                .ok_or(Error::OOM)?,
+            visitss: new_arena
+                .add_slice(
+                    arena
+                        .get_slice(&self.visitss)
+                        .iter()
+                        .map(|visits| AtomicU32::new(visits.load(Ordering::Relaxed))),
+                )
+                .ok_or(Error::OOM)?,
+            heuristic_scores: new_arena
+                .add_slice(arena.get_slice(&self.heuristic_scores).iter().copied())
+                .ok_or(Error::OOM)?,
+        })
     }
 
     /// Apply Dirichlet noise to the heuristic scores of the child node
@@ -233,36 +520,67 @@ impl<const S: usize> TreeBridge<S> {
 
 impl<const S: usize> TreeEdge<S> {
     pub fn select(
-        &mut self,
+        &self,
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors<S>,
         arena: &Arena,
+        transposition_table: Option<&TranspositionTable<S>>,
         parent_visits: u32,
     ) -> Result<f32, Error> {
-        if let Some(child) = self.child.as_mut() {
-            return arena.get_mut(child).select(
-                position,
-                settings,
-                temp_vectors,
-                arena,
-                parent_visits,
-            );
-        }
+        loop {
+            if let Some(child) = self.child.get() {
+                // A shared node may have been created through a different path, where the position was not repeated.
+                // This also ensures that selection cannot loop forever if the graph has a cycle
+                if transposition_table.is_some() && position.repetitions() >= 2 {
+                    return Ok(GameResultForUs::Draw.score());
+                }
+                return arena.get(&child).select(
+                    position,
+                    settings,
+                    temp_vectors,
+                    arena,
+                    transposition_table,
+                    parent_visits,
+                );
+            }
 
-        let (result, game_result) =
-            rollout(position, settings, settings.rollout_depth, temp_vectors);
-        self.child = Some(
-            arena
-                .add(Tree {
-                    total_action_value: result as f64,
-                    game_result,
-                    children: None,
-                })
-                .ok_or(Error::OOM)?,
-        );
+            if self.child.try_lock() {
+                // Repeated positions are never shared, because their game result depends on the path to them
+                let transposition_table =
+                    transposition_table.filter(|_| position.repetitions() == 0);
+                let hash = position.zobrist_hash();
+
+                if let Some(child) = transposition_table.and_then(|table| table.get(hash)) {
+                    let child_value = arena.get(&child).mean_action_value();
+                    self.child.set(child);
+                    return Ok(child_value);
+                }
+
+                let (result, game_result) =
+                    rollout(position, settings, settings.rollout_depth, temp_vectors);
+                let Some(child) = arena.add(Tree {
+                    total_action_value: AtomicF64::new(result as f64),
+                    visits: AtomicU32::new(1),
+                    proven_result: AtomicProvenResult::new(
+                        game_result.map(|result| ProvenResult { result, plies: 0 }),
+                    ),
+                    children: AtomicIndex::empty(),
+                }) else {
+                    self.child.unlock();
+                    return Err(Error::OOM);
+                };
+                if let Some(table) = transposition_table {
+                    table.insert(hash, &child);
+                }
+                self.child.set(child);
+
+                return Ok(result);
+            }
 
-        Ok(result)
+            // Another thread is creating the child node, wait for it to finish
+            hint::spin_loop();
+        }
     }
 }
 
@@ -273,100 +591,204 @@ impl<const S: usize> Tree<S> {
     ///
     /// Moves done on the board are not reversed.
     pub fn select(
-        &mut self,
+        &self,
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors<S>,
         arena: &Arena,
+        transposition_table: Option<&TranspositionTable<S>>,
         parent_visits: u32,
     ) -> Result<f32, Error> {
+        self.visits.fetch_add(1, Ordering::Relaxed);
         // TODO: Assume node has already had 1 visit before?
-        if let Some(game_result) = self.game_result {
-            let result = game_result.score();
-            self.total_action_value += result as f64;
+        if let Some(proven_result) = self.proven_result.load() {
+            let result = proven_result.result.score();
+            self.total_action_value.fetch_add(result as f64);
             return Ok(result);
         }
-        let Some(children) = self.children.as_mut() else {
-            let result = self.expand_child(position, settings, temp_vectors, arena)?;
-            self.total_action_value += result as f64;
-            return Ok(result);
-        };
+        loop {
+            if let Some(children) = self.children.get() {
+                let (result, proven_result) = match arena.get(&children).select(
+                    position,
+                    settings,
+                    temp_vectors,
+                    arena,
+                    transposition_table,
+                    parent_visits,
+                ) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        self.visits.fetch_sub(1, Ordering::Relaxed);
+                        return Err(err);
+                    }
+                };
+                self.total_action_value.fetch_add(result as f64);
+                if let Some(proven_result) = proven_result {
+                    self.proven_result.store(proven_result);
+                }
+                return Ok(result);
+            }
 
-        let result = arena.get_mut(children).select(
-            position,
-            settings,
-            temp_vectors,
-            arena,
-            parent_visits,
-        )?;
-        self.total_action_value += result as f64;
-        Ok(result)
+            if self.children.try_lock() {
+                let (result, proven_result) = match self.expand_child(
+                    position,
+                    settings,
+                    temp_vectors,
+                    arena,
+                    transposition_table,
+                ) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        self.visits.fetch_sub(1, Ordering::Relaxed);
+                        self.children.unlock();
+                        return Err(err);
+                    }
+                };
+                self.total_action_value.fetch_add(result as f64);
+                if let Some(proven_result) = proven_result {
+                    self.proven_result.store(proven_result);
+                }
+                return Ok(result);
+            }
+
+            // Another thread is expanding the node, wait for it to finish
+            hint::spin_loop();
+        }
+    }
+
+    /// The average result of all visits to this node for the side to move, or the exact result if it is proven
+    pub fn mean_action_value(&self) -> f32 {
+        match self.proven_result.load() {
+            Some(proven_result) => proven_result.result.score(),
+            None => {
+                self.total_action_value.load() as f32 / self.visits.load(Ordering::Relaxed) as f32
+            }
+        }
+    }
+
+    /// Copy the node at `index`, and all its children, from `arena` into `new_arena`, returning the index of the copy.
+    ///
+    /// If the tree has shared nodes, `copied_nodes` must be given, mapping the raw indexes of the nodes that are already copied to their copies.
+    /// Each shared node is then only copied once.
+    /// The tree must not be searched while it is being copied.
+    pub fn copy_to(
+        index: &Index<Tree<S>>,
+        arena: &Arena,
+        new_arena: &Arena,
+        copied_nodes: &mut Option<HashMap<NonZeroU32, Index<Tree<S>>>>,
+    ) -> Result<Index<Tree<S>>, Error> {
+        if let Some(copy) = copied_nodes
+            .as_ref()
+            .and_then(|copied_nodes| copied_nodes.get(&index.to_raw()))
+        {
+            return Ok(Index::from_raw(copy.to_raw()));
+        }
+        let node = arena.get(index);
+
+        // Add the node before its children, so that a cycle back to the node finds the copy
+        let copy_index = new_arena
+            .add(Tree {
+                total_action_value: AtomicF64::new(node.total_action_value.load()),
+                visits: AtomicU32::new(node.visits.load(Ordering::Relaxed)),
+                proven_result: AtomicProvenResult::new(node.proven_result.load()),
+                children: AtomicIndex::empty(),
+            })
+            .ok_or(Error::OOM)?;
+        if let Some(copied_nodes) = copied_nodes {
+            copied_nodes.insert(index.to_raw(), Index::from_raw(copy_index.to_raw()));
+        }
+
+        if let Some(bridge_index) = node.children.get() {
+            let bridge = arena
+                .get(&bridge_index)
+                .copy_to(arena, new_arena, copied_nodes)?;
+            let copy = new_arena.get(&copy_index);
+            assert!(copy.children.try_lock());
+            copy.children.set(new_arena.add(bridge).ok_or(Error::OOM)?);
+        }
+        Ok(copy_index)
     }
 
     /// Do not initialize children in the expansion phase, for better performance
     /// Never inline, for profiling purposes
     #[inline(never)]
     fn expand_child(
-        &mut self,
+        &self,
         position: &mut Position<S>,
         settings: &MctsSetting<S>,
         temp_vectors: &mut TempVectors<S>,
         arena: &Arena,
-    ) -> Result<f32, Error> {
-        assert!(self.children.is_none());
+        transposition_table: Option<&TranspositionTable<S>>,
+    ) -> Result<(f32, Option<ProvenResult>), Error> {
+        assert!(self.children.is_locked());
         let group_data = position.group_data();
         assert!(temp_vectors.simple_moves.is_empty());
         assert!(temp_vectors.moves.is_empty());
         assert!(temp_vectors.fcd_per_move.is_empty());
-        position.generate_moves_with_params(
-            match settings.policy_params.as_ref() {
-                Some(params) => params,
-                None => <Position<S>>::policy_params(position.komi()),
-            },
-            &group_data,
-            &mut temp_vectors.simple_moves,
-            &mut temp_vectors.moves,
-            &mut temp_vectors.fcd_per_move,
-            &mut temp_vectors.policy_feature_sets,
-        );
+        if let Some(policy_network) = settings.policy_network {
+            position.generate_moves_with_params(
+                policy_network,
+                &group_data,
+                &mut temp_vectors.simple_moves,
+                &mut temp_vectors.moves,
+                &mut temp_vectors.fcd_per_move,
+                &mut temp_vectors.network_policy_feature_sets,
+            );
+        } else {
+            position.generate_moves_with_params(
+                match settings.policy_params.as_ref() {
+                    Some(params) => params,
+                    None => <Position<S>>::policy_params(position.komi()),
+                },
+                &group_data,
+                &mut temp_vectors.simple_moves,
+                &mut temp_vectors.moves,
+                &mut temp_vectors.fcd_per_move,
+                &mut temp_vectors.policy_feature_sets,
+            );
+        }
 
         let num_children = temp_vectors.moves.len();
         let padding = (SIMD_WIDTH - (num_children % SIMD_WIDTH)) % SIMD_WIDTH;
 
-        let mut tree_edge = TreeBridge {
-            children: arena
-                .add_slice((0..(num_children + padding)).map(|_| TreeEdge { child: None }))
-                .ok_or(Error::OOM)?,
-            moves: arena
-                .add_slice(
+        let tree_edge = (|| {
+            Some(TreeBridge {
+                children: arena
+                    .add_slice((0..(num_children + padding)).map(|_| TreeEdge::empty()))?,
+                moves: arena.add_slice(
                     (0..(num_children + padding))
                         .map(|i| temp_vectors.moves.get(i).map(|(mv, _)| *mv)),
-                )
-                .ok_or(Error::OOM)?,
-            mean_action_values: arena
-                .add_slice(
-                    (0..(num_children + padding)).map(|_| settings.initial_mean_action_value()),
-                )
-                .ok_or(Error::OOM)?,
-            visitss: arena
-                .add_slice((0..(num_children + padding)).map(|_| 0))
-                .ok_or(Error::OOM)?,
-            heuristic_scores: arena
-                .add_slice((0..(num_children + padding)).map(|i| {
+                )?,
+                mean_action_values: arena.add_slice(
+                    (0..(num_children + padding))
+                        .map(|_| AtomicF32::new(settings.initial_mean_action_value())),
+                )?,
+                visitss: arena
+                    .add_slice((0..(num_children + padding)).map(|_| AtomicU32::new(0)))?,
+                heuristic_scores: arena.add_slice((0..(num_children + padding)).map(|i| {
                     temp_vectors
                         .moves
                         .get(i)
                         .map(|(_, score)| *score)
                         .unwrap_or(f16::NEG_INFINITY) // Ensure that this move never actually gets selected
-                }))
-                .ok_or(Error::OOM)?,
-        };
+                }))?,
+            })
+        })();
+        // Also cleared when running out of memory, because the tree may be searched again after re-rooting
         temp_vectors.moves.clear();
+        let tree_edge = tree_edge.ok_or(Error::OOM)?;
 
         // Select child edge before writing the child node into the tree, in case we OOM inside this call
-        let result = tree_edge.select(position, settings, temp_vectors, arena, 1)?;
+        let result = tree_edge.select(
+            position,
+            settings,
+            temp_vectors,
+            arena,
+            transposition_table,
+            1,
+        )?;
 
-        self.children = Some(arena.add(tree_edge).ok_or(Error::OOM)?);
+        self.children.set(arena.add(tree_edge).ok_or(Error::OOM)?);
 
         Ok(result)
     }
@@ -375,11 +797,17 @@ impl<const S: usize> Tree<S> {
 pub struct Pv<'a, const S: usize> {
     arena: &'a Arena,
     edge: &'a TreeEdge<S>,
+    // Nodes already in the pv. With a transposition table, the pv could otherwise loop forever
+    visited_nodes: Vec<NonZeroU32>,
 }
 
 impl<'a, const S: usize> Pv<'a, S> {
     pub fn new(edge: &'a TreeEdge<S>, arena: &'a Arena) -> Pv<'a, S> {
-        Pv { edge, arena }
+        Pv {
+            edge,
+            arena,
+            visited_nodes: vec![],
+        }
     }
 }
 
@@ -389,13 +817,15 @@ impl<const S: usize> Iterator for Pv<'_, S> {
     fn next(&mut self) -> Option<Self::Item> {
         self.edge
             .child
-            .as_ref()
+            .get()
+            .filter(|child_index| !self.visited_nodes.contains(&child_index.to_raw()))
             .and_then(|child_index| {
-                let child = self.arena.get(child_index);
-                child.children.as_ref()
+                self.visited_nodes.push(child_index.to_raw());
+                let child = self.arena.get(&child_index);
+                child.children.get()
             })
             .and_then(|index| {
-                let bridge = self.arena.get(index);
+                let bridge = self.arena.get(&index);
                 let (_, (mv, child)) = self
                     .arena
                     .get_slice(&bridge.visitss)
@@ -407,7 +837,7 @@ impl<const S: usize> Iterator for Pv<'_, S> {
                             .zip(self.arena.get_slice(&bridge.children)),
                     )
                     .filter(|(_, (mv, _))| mv.is_some())
-                    .max_by_key(|(visits, _)| **visits)?;
+                    .max_by_key(|(visits, _)| visits.load(Ordering::Relaxed))?;
                 self.edge = child;
                 *mv
             })
@@ -438,13 +868,21 @@ pub fn rollout<const S: usize>(
 
         (game_result_for_us.score(), Some(game_result_for_us))
     } else if depth == 0 {
-        let centipawn_score = position.static_eval_with_params_and_data(
-            &group_data,
-            match settings.value_params.as_ref() {
-                Some(params) => params,
-                None => <Position<S>>::value_params(position.komi()),
-            },
-        );
+        let centipawn_score = if let Some(value_network) = settings.value_network {
+            position.static_eval_with_network_and_data(
+                &group_data,
+                value_network,
+                &mut temp_vectors.network_value,
+            )
+        } else {
+            position.static_eval_with_params_and_data(
+                &group_data,
+                match settings.value_params.as_ref() {
+                    Some(params) => params,
+                    None => <Position<S>>::value_params(position.komi()),
+                },
+            )
+        };
         let static_eval = if let Some(static_eval_variance) = settings.static_eval_variance {
             let mut rng = rand::thread_rng();
             cp_to_win_percentage(
@@ -458,17 +896,28 @@ pub fn rollout<const S: usize>(
             Color::Black => (1.0 - static_eval, None),
         }
     } else {
-        position.generate_moves_with_probabilities(
-            &group_data,
-            &mut temp_vectors.simple_moves,
-            &mut temp_vectors.moves,
-            &mut temp_vectors.fcd_per_move,
-            match settings.policy_params.as_ref() {
-                Some(params) => params,
-                None => <Position<S>>::policy_params(position.komi()),
-            },
-            &mut temp_vectors.policy_feature_sets,
-        );
+        if let Some(policy_network) = settings.policy_network {
+            position.generate_moves_with_probabilities(
+                &group_data,
+                &mut temp_vectors.simple_moves,
+                &mut temp_vectors.moves,
+                &mut temp_vectors.fcd_per_move,
+                policy_network,
+                &mut temp_vectors.network_policy_feature_sets,
+            );
+        } else {
+            position.generate_moves_with_probabilities(
+                &group_data,
+                &mut temp_vectors.simple_moves,
+                &mut temp_vectors.moves,
+                &mut temp_vectors.fcd_per_move,
+                match settings.policy_params.as_ref() {
+                    Some(params) => params,
+                    None => <Position<S>>::policy_params(position.komi()),
+                },
+                &mut temp_vectors.policy_feature_sets,
+            );
+        }
 
         let mut rng = rand::thread_rng();
         let best_move = best_move(&mut rng, settings.rollout_temperature, &temp_vectors.moves);
@@ -502,7 +951,7 @@ impl ops::Not for GameResultForUs {
 }
 
 impl GameResultForUs {
-    fn score(self) -> f32 {
+    pub fn score(self) -> f32 {
         match self {
             GameResultForUs::Win => 1.0,
             GameResultForUs::Loss => 0.0,
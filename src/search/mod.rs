@@ -2,46 +2,54 @@
 //!
 //! This implementation does not use full Monte Carlo rollouts, relying on a heuristic evaluation when expanding new nodes instead.
 
+use board_game_traits::Position as PositionTrait;
 use half::f16;
-#[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::fmt::Display;
-use std::{mem, time};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::{iter, mem, thread};
 use std::{process, sync};
 
+use crate::evaluation::network::Network;
+use crate::evaluation::parameters::{num_policy_features, num_value_features};
 use crate::position::Move;
 use crate::position::Position;
 pub use crate::search::mcts_core::best_move;
+pub use crate::search::mcts_core::{GameResultForUs, ProvenResult};
 use crate::search::mcts_core::{TempVectors, Tree, TreeEdge};
 
 use self::arena::ArenaError;
 use self::mcts_core::Pv;
+use self::transposition_table::TranspositionTable;
 
-mod arena;
+pub(crate) mod arena;
 /// This module contains the public-facing convenience API for the search.
 /// The implementation itself in in mcts_core.
 mod mcts_core;
+pub mod time;
+pub(crate) mod transposition_table;
+mod tree_file;
 pub use arena::Arena;
-
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Clone)]
-pub enum TimeControl {
-    FixedNodes(u64),
-    Time(time::Duration, time::Duration), // Total time left, increment
-}
+use time::TimeBudget;
+pub use time::TimeControl;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct MctsSetting<const S: usize> {
     arena_size: u32,
     value_params: Option<&'static [f32]>,
     policy_params: Option<&'static [f32]>,
+    value_network: Option<&'static [f32]>,
+    policy_network: Option<&'static [f32]>,
     search_params: Box<[f32]>,
     dirichlet: Option<f32>,
     excluded_moves: Vec<Move<S>>,
     static_eval_variance: Option<f32>,
     rollout_depth: u16,
     rollout_temperature: Option<f64>,
+    threads: usize,
+    transposition_table: bool,
 }
 
 impl<const S: usize> Default for MctsSetting<S> {
@@ -50,12 +58,16 @@ impl<const S: usize> Default for MctsSetting<S> {
             arena_size: 3 * 2_u32.pow(30), // Default to 48GB max
             value_params: None,
             policy_params: None,
+            value_network: None,
+            policy_network: None,
             search_params: vec![1.50, 2200.0, 0.61].into_boxed_slice(),
             dirichlet: None,
             excluded_moves: vec![],
             static_eval_variance: None,
             rollout_depth: 0,
             rollout_temperature: None,
+            threads: 1,
+            transposition_table: false,
         }
     }
 }
@@ -97,6 +109,29 @@ impl<const S: usize> MctsSetting<S> {
         self
     }
 
+    /// Evaluate positions with a value network, instead of the linear value parameters.
+    /// See `evaluation::network` for the parameter layout
+    pub fn add_value_network(mut self, value_network: &'static [f32]) -> Self {
+        assert!(
+            Network::new(value_network, num_value_features::<S>()).is_some(),
+            "Value network does not fit {}s",
+            S
+        );
+        self.value_network = Some(value_network);
+        self
+    }
+
+    /// Evaluate moves with a policy network, instead of the linear policy parameters
+    pub fn add_policy_network(mut self, policy_network: &'static [f32]) -> Self {
+        assert!(
+            Network::new(policy_network, num_policy_features::<S>()).is_some(),
+            "Policy network does not fit {}s",
+            S
+        );
+        self.policy_network = Some(policy_network);
+        self
+    }
+
     pub fn add_search_params(mut self, search_params: Box<[f32]>) -> Self {
         self.search_params = search_params;
         self
@@ -130,6 +165,31 @@ impl<const S: usize> MctsSetting<S> {
         self
     }
 
+    /// Number of threads to search with. Defaults to 1.
+    /// With more than one thread, the threads search the same tree, using virtual loss to spread out
+    pub fn threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// Use one search thread for every logical core on the system
+    pub fn all_threads(self) -> Self {
+        self.threads(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
+
+    /// Share nodes between transpositions, turning the search tree into a graph. Defaults to false.
+    /// The transposition table uses about 2% as much memory as the arena, in addition to the arena itself
+    pub fn transposition_table(mut self, enabled: bool) -> Self {
+        self.transposition_table = enabled;
+        self
+    }
+
+    fn transposition_table_size(&self) -> usize {
+        // Most nodes use several arena slots, so one entry per 64 slots is plenty
+        self.arena_size as usize / 64
+    }
+
     pub fn c_puct_init(&self) -> f32 {
         self.search_params[0]
     }
@@ -174,7 +234,59 @@ pub struct MonteCarloTree<const S: usize> {
     temp_position: Position<S>,
     settings: MctsSetting<S>,
     temp_vectors: TempVectors<S>,
+    // Temporary vectors for the additional search threads, if any
+    thread_temp_vectors: Vec<TempVectors<S>>,
     arena: Arena,
+    transposition_table: Option<TranspositionTable<S>>,
+    root_priors: RootPriors<S>,
+}
+
+/// Why a tree could not be re-rooted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RerootError {
+    NotReusable,
+    /// Allocating the new arena failed
+    AllocationFailed,
+}
+
+/// The moves and policy scores of the root's children, before dirichlet noise and excluded moves were applied.
+/// Kept so that re-rooting the tree at the same position applies the new settings to the original priors
+#[derive(Clone, Debug, PartialEq)]
+struct RootPriors<const S: usize> {
+    moves: Vec<Option<Move<S>>>,
+    heuristic_scores: Vec<f16>,
+}
+
+impl<const S: usize> RootPriors<S> {
+    /// The root node must already be expanded
+    fn read(tree: &TreeEdge<S>, arena: &Arena) -> Self {
+        let bridge = arena.get(
+            &arena
+                .get(&tree.child.get().unwrap())
+                .children
+                .get()
+                .unwrap(),
+        );
+        RootPriors {
+            moves: arena.get_slice(&bridge.moves).to_vec(),
+            heuristic_scores: arena.get_slice(&bridge.heuristic_scores).to_vec(),
+        }
+    }
+
+    fn restore(&self, tree: &TreeEdge<S>, arena: &Arena) {
+        let mut bridge_index = arena
+            .get(&tree.child.get().unwrap())
+            .children
+            .get()
+            .unwrap();
+        let bridge = arena.get_mut(&mut bridge_index);
+        arena
+            .get_slice_mut(&mut bridge.moves)
+            .copy_from_slice(&self.moves);
+        arena
+            .get_slice_mut(&mut bridge.heuristic_scores)
+            .copy_from_slice(&self.heuristic_scores);
+    }
 }
 
 impl<const S: usize> MonteCarloTree<S> {
@@ -222,8 +334,9 @@ impl<const S: usize> MonteCarloTree<S> {
             Err(err) => panic!("{}", err),
         };
 
-        let mut tree = TreeEdge { child: None };
+        let tree = TreeEdge::empty();
         let mut temp_vectors = TempVectors::default();
+        let transposition_table = Self::new_transposition_table(&settings);
 
         // Applying dirichlet noise or excluding moves can only be done once the child edges of the root are initialized,
         // which is done on the 2nd select
@@ -232,6 +345,7 @@ impl<const S: usize> MonteCarloTree<S> {
             &settings,
             &mut temp_vectors,
             &arena,
+            transposition_table.as_ref(),
             0,
         )
         .unwrap();
@@ -240,36 +354,60 @@ impl<const S: usize> MonteCarloTree<S> {
             &settings,
             &mut temp_vectors,
             &arena,
+            transposition_table.as_ref(),
             1,
         )
         .unwrap();
 
+        let root_priors = RootPriors::read(&tree, &arena);
+        Self::apply_root_settings(&tree, &settings, &arena);
+
+        MonteCarloTree {
+            tree,
+            visits: 0,
+            position: position.clone(),
+            temp_position: position,
+            settings,
+            temp_vectors,
+            thread_temp_vectors: vec![],
+            arena,
+            transposition_table,
+            root_priors,
+        }
+    }
+
+    fn new_transposition_table(settings: &MctsSetting<S>) -> Option<TranspositionTable<S>> {
+        settings
+            .transposition_table
+            .then(|| TranspositionTable::new(settings.transposition_table_size()))
+    }
+
+    /// Apply dirichlet noise and excluded moves to the children of the root node.
+    /// The root node must already be expanded, and have its original priors.
+    fn apply_root_settings(tree: &TreeEdge<S>, settings: &MctsSetting<S>, arena: &Arena) {
+        let mut root_bridge_index = arena
+            .get(&tree.child.get().unwrap())
+            .children
+            .get()
+            .unwrap();
+
         if let Some(alpha) = settings.dirichlet {
             arena
-                .get_mut(
-                    (arena.get_mut(tree.child.as_mut().unwrap()))
-                        .children
-                        .as_mut()
-                        .unwrap(),
-                )
-                .apply_dirichlet(&arena, 0.25, alpha);
+                .get_mut(&mut root_bridge_index)
+                .apply_dirichlet(arena, 0.25, alpha);
         }
 
         if !settings.excluded_moves.is_empty() {
-            let bridge = arena.get_mut(
-                (arena.get_mut(tree.child.as_mut().unwrap()))
-                    .children
-                    .as_mut()
-                    .unwrap(),
-            );
+            let bridge = arena.get_mut(&mut root_bridge_index);
             for excluded_move in settings.excluded_moves.iter() {
-                let index = arena
+                // The move may not be legal
+                let Some(index) = arena
                     .get_slice(&bridge.moves)
                     .iter()
-                    .enumerate()
-                    .find(|(_, mv)| **mv == Some(*excluded_move))
-                    .unwrap()
-                    .0;
+                    .position(|mv| *mv == Some(*excluded_move))
+                else {
+                    continue;
+                };
                 let moves = arena.get_slice_mut(&mut bridge.moves);
                 let heuristic_scores = arena.get_slice_mut(&mut bridge.heuristic_scores);
 
@@ -277,40 +415,170 @@ impl<const S: usize> MonteCarloTree<S> {
                 heuristic_scores[index] = f16::NEG_INFINITY; // TODO: Also set infinite visitss?
             }
         }
+    }
 
-        MonteCarloTree {
-            tree,
-            visits: 0,
-            position: position.clone(),
-            temp_position: position,
-            settings,
-            temp_vectors,
-            arena,
+    /// Move the root of the tree to `position`, which must be the current root, or be reached by playing one or more moves from it.
+    /// The subtree that was already searched for `position` is kept, and copied into a new arena with `settings`.
+    /// The rest of the tree is thrown away, freeing its memory once the copy is done.
+    ///
+    /// Returns false if the subtree cannot be reused, in which case the tree is unchanged.
+    /// This happens if `position` is not a continuation of the current root, if the subtree is too small,
+    /// if the new arena cannot be allocated or is too small to hold it, or if `settings` turns the transposition table on or off.
+    pub fn reroot(&mut self, position: &Position<S>, settings: MctsSetting<S>) -> bool {
+        self.try_reroot(position, settings).is_ok()
+    }
+
+    fn try_reroot(
+        &mut self,
+        position: &Position<S>,
+        settings: MctsSetting<S>,
+    ) -> Result<(), RerootError> {
+        if settings.transposition_table != self.settings.transposition_table {
+            return Err(RerootError::NotReusable);
+        }
+        // A tree loaded from disk has no move history, but can still be used for the same position
+        let new_moves = if *position == self.position {
+            &[]
+        } else if let Some(new_moves) = position.moves().strip_prefix(&self.position.moves()[..]) {
+            new_moves
+        } else {
+            return Err(RerootError::NotReusable);
+        };
+        let mut new_root_position = self.position.clone();
+        for mv in new_moves {
+            new_root_position.do_move(*mv);
+        }
+        if new_root_position != *position {
+            return Err(RerootError::NotReusable);
+        }
+
+        // Find the edge to the new root, and its number of visits
+        let mut edge = &self.tree;
+        let mut visits = self.visits;
+        for mv in new_moves {
+            let Some(bridge_index) = edge
+                .child
+                .get()
+                .and_then(|child| self.arena.get(&child).children.get())
+            else {
+                return Err(RerootError::NotReusable);
+            };
+            let bridge = self.arena.get(&bridge_index);
+            let Some(i) = self
+                .arena
+                .get_slice(&bridge.moves)
+                .iter()
+                .position(|child_move| *child_move == Some(*mv))
+            else {
+                return Err(RerootError::NotReusable);
+            };
+            edge = &self.arena.get_slice(&bridge.children)[i];
+            visits = self.arena.get_slice(&bridge.visitss)[i].load(Ordering::Relaxed);
+        }
+
+        // The new root must already have its children initialized
+        let Some(root_index) = edge.child.get() else {
+            return Err(RerootError::NotReusable);
+        };
+        if self.arena.get(&root_index).children.get().is_none() {
+            return Err(RerootError::NotReusable);
+        }
+
+        let Ok(arena) = Arena::new(settings.arena_size) else {
+            return Err(RerootError::AllocationFailed);
+        };
+        // Shared nodes must only be copied once
+        let mut copied_nodes = self.transposition_table.as_ref().map(|_| HashMap::new());
+        let Ok(tree) =
+            Tree::copy_to(&root_index, &self.arena, &arena, &mut copied_nodes).map(TreeEdge::new)
+        else {
+            return Err(RerootError::NotReusable);
+        };
+        let transposition_table =
+            self.transposition_table
+                .as_ref()
+                .zip(copied_nodes)
+                .map(|(table, copied_nodes)| {
+                    table.copy_to(settings.transposition_table_size(), &copied_nodes)
+                });
+
+        let root_priors = if new_moves.is_empty() {
+            // Undo the noise and excluded moves from the previous settings
+            self.root_priors.restore(&tree, &arena);
+            self.root_priors.clone()
+        } else {
+            RootPriors::read(&tree, &arena)
+        };
+        Self::apply_root_settings(&tree, &settings, &arena);
+
+        self.tree = tree;
+        self.root_priors = root_priors;
+        self.transposition_table = transposition_table;
+        self.visits = visits;
+        self.position = position.clone();
+        self.temp_position = position.clone();
+        self.settings = settings;
+        self.arena = arena;
+        Ok(())
+    }
+
+    /// Create a tree for `position`, re-using the subtree of `previous_tree` if possible.
+    /// A re-used subtree is copied into a new arena while the previous tree is still allocated.
+    /// If there is not enough memory for both, the subtree is first moved into an arena that just fits the previous tree, freeing its full-size arena.
+    /// If the subtree cannot be re-used, the previous tree is freed before the new tree is allocated.
+    pub fn from_previous(
+        previous_tree: Option<MonteCarloTree<S>>,
+        position: Position<S>,
+        settings: MctsSetting<S>,
+    ) -> MonteCarloTree<S> {
+        if let Some(mut tree) = previous_tree {
+            match tree.try_reroot(&position, settings.clone()) {
+                Ok(()) => return tree,
+                Err(RerootError::AllocationFailed) => {
+                    let compact_settings = settings.clone().arena_size(tree.arena.slots_used());
+                    if tree.try_reroot(&position, compact_settings).is_ok()
+                        && tree.try_reroot(&position, settings.clone()).is_ok()
+                    {
+                        return tree;
+                    }
+                    eprintln!(
+                        "Warning: Not enough memory to re-use the previous search tree, starting a new one"
+                    );
+                }
+                Err(RerootError::NotReusable) => (),
+            }
         }
+        MonteCarloTree::new(position, settings)
     }
 
-    pub fn search_for_time<F>(&mut self, max_time: time::Duration, callback: F)
-    where
-        F: Fn(&Self),
+    /// Search until the time budget runs out, or `should_stop` is set.
+    /// The soft limit is extended while the search is unsure about the best move.
+    /// `callback` is called after every batch of nodes, and when the search stops
+    pub fn search_for_time<F>(
+        &mut self,
+        budget: TimeBudget,
+        should_stop: Option<&AtomicBool>,
+        mut callback: F,
+    ) where
+        F: FnMut(&Self),
     {
-        let start_time = time::Instant::now();
+        let start_time = Instant::now();
+        let start_visits = self.visits();
+        let mut nodes = 50;
 
-        for i in 0.. {
-            let nodes = (50.0 * 2.0_f32.powf(0.125).powi(i)) as u64;
-            for _ in 0..nodes {
-                if let Err(err) = self.select() {
-                    eprintln!("Warning: {err}");
-                    callback(self);
-                    return;
-                };
-            }
+        loop {
+            if let Err(err) = self.search_nodes(nodes, should_stop) {
+                eprintln!("Warning: {err}");
+                callback(self);
+                return;
+            };
 
             let mut shallow_edges = self.shallow_edges().unwrap();
+            let elapsed = start_time.elapsed();
 
-            // Always return when we have less than 10ms left
-            if max_time < (time::Duration::from_millis(10))
-                || start_time.elapsed() > max_time - (time::Duration::from_millis(10))
-                || shallow_edges.len() == 1
+            if shallow_edges.len() == 1
+                || self.proven_result().is_some()
+                || should_stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
             {
                 callback(self);
                 return;
@@ -319,38 +587,48 @@ impl<const S: usize> MonteCarloTree<S> {
             shallow_edges.sort_by_key(|edge| edge.visits);
             shallow_edges.reverse();
 
-            let node_ratio =
-                (1 + shallow_edges[1].visits) as f32 / (1 + shallow_edges[0].visits) as f32;
-            let time_ratio = start_time.elapsed().as_secs_f32() / max_time.as_secs_f32();
-
             let visits_sqrt = (self.visits() as f32).sqrt();
             let dynamic_cpuct = self.settings.c_puct_init()
                 + f32::ln(
                     (1.0 + self.visits() as f32 + self.settings.c_puct_base())
                         / self.settings.c_puct_base(),
                 );
-
-            let best_edge = shallow_edges.iter().max_by_key(|edge| edge.visits).unwrap();
-
-            let best_exploration_value = best_edge.exploration_value(visits_sqrt, dynamic_cpuct);
-
-            if time_ratio.powf(2.0) > node_ratio / 2.0 {
-                callback(self);
-                // Do not stop if any other child nodes have better exploration value
-                if shallow_edges.iter().any(|edge| {
-                    edge.mv != best_edge.mv
-                        && edge.exploration_value(visits_sqrt, dynamic_cpuct)
-                            > best_exploration_value + 0.01
-                }) {
-                    continue;
-                }
+            let best_exploration_value =
+                shallow_edges[0].exploration_value(visits_sqrt, dynamic_cpuct);
+
+            // Keep searching at full extension if another move is about to overtake the best move
+            let instability = if shallow_edges[1..].iter().any(|edge| {
+                edge.exploration_value(visits_sqrt, dynamic_cpuct) > best_exploration_value + 0.01
+            }) {
+                1.0
+            } else {
+                (1 + shallow_edges[1].visits) as f32 / (1 + shallow_edges[0].visits) as f32
+            };
+
+            callback(self);
+            if budget.should_stop(elapsed, instability) {
                 return;
-            } else if i % 2 == 0 {
-                callback(self);
             }
+
+            // Size the next batch to take a fraction of the time searched so far, without overshooting the hard limit
+            let nodes_per_second =
+                (self.visits() - start_visits) as f32 / elapsed.as_secs_f32().max(0.001);
+            let batch_time = (elapsed / 4)
+                .min(budget.hard_limit.saturating_sub(elapsed) / 2)
+                .max(Duration::from_millis(1));
+            nodes = ((nodes_per_second * batch_time.as_secs_f32()) as u64).max(1);
         }
     }
 
+    /// The root position of the tree
+    pub fn position(&self) -> &Position<S> {
+        &self.position
+    }
+
+    pub fn settings(&self) -> &MctsSetting<S> {
+        &self.settings
+    }
+
     // TODO: Count up to u64 on root?
     pub fn visits(&self) -> u32 {
         self.visits
@@ -358,21 +636,41 @@ impl<const S: usize> MonteCarloTree<S> {
 
     pub fn mem_usage(&self) -> usize {
         self.arena.slots_used() as usize * ARENA_ELEMENT_SIZE
+            + self
+                .transposition_table
+                .as_ref()
+                .map_or(0, TranspositionTable::mem_usage)
     }
 
+    /// The result of the root position for the side to move, if the search has proven it
+    pub fn proven_result(&self) -> Option<ProvenResult> {
+        self.tree
+            .child
+            .get()
+            .and_then(|index| self.arena.get(&index).proven_result.load())
+    }
+
+    /// The average result of the search for the side to move, or the exact result if it is proven
     pub fn mean_action_value(&self) -> f32 {
+        if let Some(proven_result) = self.proven_result() {
+            return proven_result.result.score();
+        }
         self.tree
             .child
-            .as_ref()
-            .map(|index| self.arena.get(index).total_action_value as f32 / self.visits as f32)
+            .get()
+            .map(|index| {
+                self.arena.get(&index).total_action_value.load() as f32 / self.visits as f32
+            })
             .unwrap_or(self.settings.initial_mean_action_value())
     }
 
+    /// The most visited move, unless the search has proven some moves to win or lose.
+    /// Proven wins are preferred over any other move, and proven losses are only played if every move loses.
     pub fn best_move(&self) -> Option<(Move<S>, f32)> {
         let best_edge = self
             .shallow_edges()?
             .into_iter()
-            .max_by_key(|edge| edge.visits)?;
+            .max_by_key(ShallowEdge::rank)?;
         Some((best_edge.mv, 1.0 - best_edge.mean_action_value))
     }
 
@@ -380,6 +678,28 @@ impl<const S: usize> MonteCarloTree<S> {
         Pv::new(&self.tree, &self.arena)
     }
 
+    /// The `k` best moves from the root, in the same order as `best_move`.
+    /// Moves that have not been visited are not included, so fewer than `k` lines may be returned.
+    pub fn multi_pv(&self, k: usize) -> Vec<PvLine<S>> {
+        let mut edges = self.shallow_edges().unwrap_or_default();
+        edges.retain(|edge| edge.visits > 0);
+        edges.sort_by_key(ShallowEdge::rank);
+        edges.reverse();
+        edges
+            .iter()
+            .take(k)
+            .map(|edge| PvLine {
+                mv: edge.mv,
+                visits: edge.visits,
+                win_probability: 1.0 - edge.mean_action_value,
+                proven_result: edge.proven_result.map(ProvenResult::parent_result),
+                pv: iter::once(edge.mv)
+                    .chain(Pv::new(edge.child, &self.arena))
+                    .collect(),
+            })
+            .collect()
+    }
+
     /// Print human-readable information of the search's progress.
     pub fn print_info(&self) {
         let mut best_children: Vec<ShallowEdge<S>> = self.shallow_edges().unwrap_or_default();
@@ -428,20 +748,102 @@ impl<const S: usize> MonteCarloTree<S> {
             &self.settings,
             &mut self.temp_vectors,
             &self.arena,
+            self.transposition_table.as_ref(),
             self.visits,
         )?;
         self.visits += 1;
         Ok(result)
     }
 
-    pub fn shallow_edges(&self) -> Option<Vec<ShallowEdge<'_, S>>> {
-        let child = self.arena.get(
-            self.arena
-                .get(self.tree.child.as_ref()?)
-                .children
-                .as_ref()?,
+    /// Search `nodes` more nodes, spread across the number of threads in the search settings.
+    /// Returns early if `should_stop` is set.
+    pub fn search_nodes(
+        &mut self,
+        nodes: u64,
+        should_stop: Option<&AtomicBool>,
+    ) -> Result<(), Error> {
+        let is_stopped = || should_stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+
+        if self.settings.threads == 1 {
+            for _ in 0..nodes {
+                if is_stopped() {
+                    break;
+                }
+                self.select()?;
+            }
+            return Ok(());
+        }
+
+        self.thread_temp_vectors
+            .resize_with(self.settings.threads - 1, TempVectors::default);
+
+        let visits = &AtomicU32::new(self.visits);
+        let nodes_left = &AtomicU64::new(nodes);
+        let aborted = &AtomicBool::new(false);
+        let (tree, position, settings, arena, transposition_table) = (
+            &self.tree,
+            &self.position,
+            &self.settings,
+            &self.arena,
+            self.transposition_table.as_ref(),
         );
 
+        let result = thread::scope(|scope| {
+            let handles: Vec<_> = iter::once(&mut self.temp_vectors)
+                .chain(self.thread_temp_vectors.iter_mut())
+                .map(|temp_vectors| {
+                    scope.spawn(move || {
+                        let mut temp_position = position.clone();
+                        while !aborted.load(Ordering::Relaxed) && !is_stopped() {
+                            if nodes_left
+                                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |nodes| {
+                                    nodes.checked_sub(1)
+                                })
+                                .is_err()
+                            {
+                                break;
+                            }
+                            let Ok(parent_visits) = visits.fetch_update(
+                                Ordering::Relaxed,
+                                Ordering::Relaxed,
+                                |visits| visits.checked_add(1),
+                            ) else {
+                                aborted.store(true, Ordering::Relaxed);
+                                return Err(Error::MaxVisits);
+                            };
+                            temp_position.clone_from(position);
+                            if let Err(err) = tree.select(
+                                &mut temp_position,
+                                settings,
+                                temp_vectors,
+                                arena,
+                                transposition_table,
+                                parent_visits,
+                            ) {
+                                visits.fetch_sub(1, Ordering::Relaxed);
+                                aborted.store(true, Ordering::Relaxed);
+                                return Err(err);
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().unwrap())
+        });
+
+        self.visits = visits.load(Ordering::SeqCst);
+        result
+    }
+
+    pub fn shallow_edges(&self) -> Option<Vec<ShallowEdge<'_, S>>> {
+        let child = self
+            .arena
+            .get(&self.arena.get(&self.tree.child.get()?).children.get()?);
+
         Some(
             self.arena
                 .get_slice(&child.visitss)
@@ -458,17 +860,34 @@ impl<const S: usize> MonteCarloTree<S> {
                 )
                 .filter_map(|(visits, (mv, (score, (child, policy))))| {
                     Some(ShallowEdge {
-                        visits: *visits,
+                        visits: visits.load(Ordering::Relaxed),
                         mv: (*mv)?,
-                        mean_action_value: *score,
+                        mean_action_value: score.load(),
                         child,
                         policy: *policy,
+                        proven_result: child
+                            .child
+                            .get()
+                            .and_then(|index| self.arena.get(&index).proven_result.load()),
                     })
                 })
                 .collect(),
         )
     }
 }
+/// One of the candidate moves returned by `MonteCarloTree::multi_pv`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PvLine<const S: usize> {
+    pub mv: Move<S>,
+    pub visits: u32,
+    /// Estimated winning probability for the side to move at the root
+    pub win_probability: f32,
+    /// The proven result of the move for the side to move at the root, counting plies from the root
+    pub proven_result: Option<ProvenResult>,
+    /// The principal variation, starting with `mv`
+    pub pv: Vec<Move<S>>,
+}
+
 // More convenient edge representation, allowing them to be stored as array-of-structs rather than struct-of-arrays
 pub struct ShallowEdge<'a, const S: usize> {
     visits: u32,
@@ -476,9 +895,36 @@ pub struct ShallowEdge<'a, const S: usize> {
     mean_action_value: f32,
     child: &'a TreeEdge<S>,
     policy: f16,
+    /// The proven result of the child, for the side to move in the child
+    proven_result: Option<ProvenResult>,
 }
 
 impl<const S: usize> ShallowEdge<'_, S> {
+    pub fn mv(&self) -> Move<S> {
+        self.mv
+    }
+
+    /// The policy score of the move, including any dirichlet noise
+    pub fn policy(&self) -> f16 {
+        self.policy
+    }
+
+    /// Sort key for picking the best move. Proven wins rank highest, the fastest first.
+    /// Then unproven and drawn moves by number of visits, and finally proven losses, the slowest first
+    fn rank(&self) -> (u8, u32) {
+        match self.proven_result {
+            Some(ProvenResult {
+                result: GameResultForUs::Loss,
+                plies,
+            }) => (2, u32::MAX - plies as u32),
+            Some(ProvenResult {
+                result: GameResultForUs::Win,
+                plies,
+            }) => (0, plies as u32),
+            _ => (1, self.visits),
+        }
+    }
+
     pub fn exploration_value(&self, parent_visits_sqrt: f32, dynamic_cpuct: f32) -> f32 {
         mcts_core::exploration_value(
             self.mean_action_value,
@@ -504,14 +950,18 @@ pub fn mcts<const S: usize>(position: Position<S>, nodes: u64) -> (Move<S>, f32)
 
 /// Play a move, calculating for a maximum duration.
 /// It will usually spend much less time, especially if the move is obvious.
-/// On average, it will spend around 20% of `max_time`, and rarely more than 50%.
+/// It aims for 20% of `max_time`, and only spends more if the best move is unclear.
 pub fn play_move_time<const S: usize>(
     board: Position<S>,
-    max_time: time::Duration,
+    max_time: Duration,
     settings: MctsSetting<S>,
 ) -> (Move<S>, f32) {
     let mut tree = MonteCarloTree::new(board.clone(), settings);
-    tree.search_for_time(max_time, |_| {});
+    tree.search_for_time(
+        TimeBudget::fixed(max_time).limit(max_time / 5, max_time),
+        None,
+        |_| {},
+    );
     tree.best_move().unwrap()
 }
 
@@ -533,9 +983,13 @@ pub fn mcts_training<const S: usize>(
                 }
             }
         }
-        TimeControl::Time(time, increment) => {
-            let max_time = *time / 5 + *increment / 2;
-            tree.search_for_time(max_time, |_| {});
+        TimeControl::Time(..) | TimeControl::MovesToGo(..) => {
+            let max_time = time_control.clock().unwrap().training_max_time();
+            tree.search_for_time(
+                TimeBudget::fixed(max_time).limit(max_time / 5, max_time),
+                None,
+                |_| {},
+            );
         }
     }
     let shallow_edges = tree.shallow_edges().unwrap();
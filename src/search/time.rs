@@ -0,0 +1,187 @@
+//! Time management, shared by all the front-ends.
+//!
+//! A `TimeBudget` for a single move is computed from the player's `Clock`. The search stops at the budget's soft limit,
+//! which is extended if the best move is unstable, and never continues past the hard limit.
+
+use std::time::Duration;
+
+use board_game_traits::{Color, Position as PositionTrait};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::position::Position;
+
+/// Time reserved for sending the move, and for the search overshooting its limit
+const MOVE_OVERHEAD: Duration = Duration::from_millis(20);
+
+/// Games rarely last until the reserves run out, and many moves are spreads, so assume that the game ends after this fraction of the remaining reserves has been placed
+const RESERVES_PLACED_PER_MOVE: f32 = 0.6;
+
+/// The game may always last a few more moves
+const MIN_MOVES_LEFT: f32 = 8.0;
+
+/// Most of the increment can be spent right away, because it is added back after the move
+const INCREMENT_FRACTION: f32 = 0.75;
+
+/// The hard limit is at most this many times the soft limit
+const HARD_LIMIT_FACTOR: f32 = 4.0;
+
+/// Never use more than this fraction of the remaining time on one move, except on the last move before the time is replenished
+const MAX_TIME_FRACTION: f32 = 0.4;
+
+/// The soft limit is extended by up to this many times itself, for a completely unstable best move
+const MAX_INSTABILITY_EXTENSION: f32 = 2.0;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub enum TimeControl {
+    FixedNodes(u64),
+    Time(Duration, Duration), // Total time left, increment
+    MovesToGo(Duration, u32), // Time left, moves until the starting time is added again
+}
+
+impl TimeControl {
+    /// The clock of a player with this time control, or `None` for `FixedNodes`
+    pub fn clock(&self) -> Option<Clock> {
+        match *self {
+            TimeControl::FixedNodes(_) => None,
+            TimeControl::Time(time_left, increment) => Some(Clock {
+                time_left,
+                increment,
+                moves_to_go: None,
+            }),
+            TimeControl::MovesToGo(time_left, moves_to_go) => Some(Clock {
+                time_left,
+                increment: Duration::ZERO,
+                moves_to_go: Some(moves_to_go),
+            }),
+        }
+    }
+
+    /// The player's time left, or `None` for `FixedNodes`
+    pub fn time_left(&self) -> Option<Duration> {
+        self.clock().map(|clock| clock.time_left)
+    }
+
+    /// The time control after a move that took `time_taken`. Running out of time leaves zero time.
+    /// `start` is the time control at the start of the game, which is repeated by `MovesToGo` time controls
+    pub fn after_move(&self, time_taken: Duration, start: &TimeControl) -> TimeControl {
+        match (self, start) {
+            (TimeControl::FixedNodes(nodes), _) => TimeControl::FixedNodes(*nodes),
+            (TimeControl::Time(time_left, increment), _) => TimeControl::Time(
+                time_left.saturating_sub(time_taken) + *increment,
+                *increment,
+            ),
+            (TimeControl::MovesToGo(time_left, 1), TimeControl::MovesToGo(time, moves)) => {
+                TimeControl::MovesToGo(time_left.saturating_sub(time_taken) + *time, *moves)
+            }
+            (TimeControl::MovesToGo(time_left, moves_to_go), _) => TimeControl::MovesToGo(
+                time_left.saturating_sub(time_taken),
+                moves_to_go.saturating_sub(1).max(1),
+            ),
+        }
+    }
+}
+
+/// The clock of the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub time_left: Duration,
+    pub increment: Duration,
+    /// Number of moves until more time is added, if the time control has such periods
+    pub moves_to_go: Option<u32>,
+}
+
+impl Clock {
+    /// The most time to spend on a move in training games: A fifth of the time left, plus half the increment.
+    /// Training games use this instead of `TimeBudget::for_clock`, so that their time use does not change with the engine's time management
+    pub fn training_max_time(&self) -> Duration {
+        self.time_left / 5 + self.increment / 2
+    }
+}
+
+/// How long to search a single move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    /// Stop the search after this, unless the best move is unstable
+    pub soft_limit: Duration,
+    /// Never search for longer than this
+    pub hard_limit: Duration,
+}
+
+impl TimeBudget {
+    /// Search for `movetime`, minus the time reserved for sending the move, unless the search finishes early
+    pub fn fixed(movetime: Duration) -> Self {
+        let usable_time = movetime.saturating_sub(MOVE_OVERHEAD);
+        TimeBudget {
+            soft_limit: usable_time,
+            hard_limit: usable_time,
+        }
+    }
+
+    /// Budget time from the clock, spreading the time left over the expected number of moves left in the game
+    pub fn for_clock<const S: usize>(position: &Position<S>, clock: Clock) -> Self {
+        let usable_time = clock.time_left.saturating_sub(MOVE_OVERHEAD);
+        let moves_left = match clock.moves_to_go {
+            Some(moves_to_go) => (moves_to_go.max(1) as f32).min(expected_moves_left(position)),
+            None => expected_moves_left(position),
+        };
+        let max_time = if clock.moves_to_go == Some(1) {
+            usable_time
+        } else {
+            usable_time.mul_f32(MAX_TIME_FRACTION)
+        };
+
+        let soft_limit = (usable_time.div_f32(moves_left)
+            + clock.increment.mul_f32(INCREMENT_FRACTION))
+        .mul_f32(opening_factor(position))
+        .min(max_time);
+        TimeBudget {
+            soft_limit,
+            hard_limit: soft_limit.mul_f32(HARD_LIMIT_FACTOR).min(max_time),
+        }
+    }
+
+    /// Lower the limits to at most `soft_limit` and `hard_limit`
+    pub fn limit(self, soft_limit: Duration, hard_limit: Duration) -> Self {
+        let hard_limit = self.hard_limit.min(hard_limit);
+        TimeBudget {
+            soft_limit: self.soft_limit.min(soft_limit).min(hard_limit),
+            hard_limit,
+        }
+    }
+
+    /// The budget left after `elapsed` has already been spent, for example on re-using the previous search tree
+    pub fn saturating_sub(self, elapsed: Duration) -> Self {
+        TimeBudget {
+            soft_limit: self.soft_limit.saturating_sub(elapsed),
+            hard_limit: self.hard_limit.saturating_sub(elapsed),
+        }
+    }
+
+    /// The soft limit, extended for an `instability` between 0.0 for a settled search, and 1.0 if the search has not settled on a move
+    pub fn extended_soft_limit(&self, instability: f32) -> Duration {
+        let extension = 1.0 + MAX_INSTABILITY_EXTENSION * instability.clamp(0.0, 1.0);
+        Duration::try_from_secs_f32(self.soft_limit.as_secs_f32() * extension)
+            .map_or(self.hard_limit, |limit| limit.min(self.hard_limit))
+    }
+
+    /// Whether a search that has run for `elapsed` should stop
+    pub fn should_stop(&self, elapsed: Duration, instability: f32) -> bool {
+        elapsed >= self.hard_limit || elapsed >= self.extended_soft_limit(instability)
+    }
+}
+
+/// Estimated number of moves left for the side to move, from their remaining reserves
+pub fn expected_moves_left<const S: usize>(position: &Position<S>) -> f32 {
+    let reserves = match position.side_to_move() {
+        Color::White => position.white_reserves_left() + position.white_caps_left(),
+        Color::Black => position.black_reserves_left() + position.black_caps_left(),
+    };
+    (reserves as f32 * RESERVES_PLACED_PER_MOVE).max(MIN_MOVES_LEFT)
+}
+
+/// The first few plies have few reasonable moves, and are often played from a book, so they get less time
+fn opening_factor<const S: usize>(position: &Position<S>) -> f32 {
+    ((position.half_moves_played() + 2) as f32 / (S + 2) as f32).min(1.0)
+}
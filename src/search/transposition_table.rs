@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::arena::Index;
+use super::mcts_core::Tree;
+
+/// A lock-free hash table from Zobrist hashes to nodes in the search tree, letting transpositions share a single node.
+///
+/// Each entry stores the node's arena index, and the full hash xored with the index, in two `AtomicU64`s,
+/// so that entries can be read and written by several search threads without locking.
+/// If another thread writes the entry while it is being read, the xor no longer matches, and the entry is ignored.
+/// The lower bits of the hash select the entry. If two positions map to the same entry, the newest one replaces the other.
+#[derive(Debug)]
+pub struct TranspositionTable<const S: usize> {
+    entries: Box<[Entry]>,
+    phantom: PhantomData<Tree<S>>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl<const S: usize> TranspositionTable<S> {
+    /// Create a table with at most `max_entries` entries. The actual size is rounded down to a power of two
+    pub fn new(max_entries: usize) -> Self {
+        let num_entries = if max_entries <= 1 {
+            1
+        } else {
+            1 << max_entries.ilog2()
+        };
+        // Zeroed memory is an empty table. Allocating it zeroed lets the OS map in the pages only once they are used,
+        // instead of writing the whole table up front
+        let entries = Box::<[Entry]>::new_zeroed_slice(num_entries);
+        TranspositionTable {
+            // Safety: An all-zero `Entry` is two `AtomicU64`s with the value 0
+            entries: unsafe { entries.assume_init() },
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.entries.len() * size_of::<Entry>()
+    }
+
+    /// Number of entries in the table, including empty ones
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// All non-empty entries, with their hashes and nodes
+    pub fn entries(&self) -> impl Iterator<Item = (u64, Index<Tree<S>>)> + '_ {
+        self.entries.iter().filter_map(Self::read_entry)
+    }
+
+    fn read_entry(entry: &Entry) -> Option<(u64, Index<Tree<S>>)> {
+        let data = entry.data.load(Ordering::Acquire);
+        let key = entry.key.load(Ordering::Acquire);
+        NonZeroU32::new(data as u32).map(|index| (key ^ data, Index::from_raw(index)))
+    }
+
+    fn entry(&self, hash: u64) -> &Entry {
+        &self.entries[hash as usize & (self.entries.len() - 1)]
+    }
+
+    pub fn get(&self, hash: u64) -> Option<Index<Tree<S>>> {
+        Self::read_entry(self.entry(hash))
+            .filter(|(entry_hash, _)| *entry_hash == hash)
+            .map(|(_, index)| index)
+    }
+
+    pub fn insert(&self, hash: u64, index: &Index<Tree<S>>) {
+        let data = index.to_raw().get() as u64;
+        let entry = self.entry(hash);
+        entry.data.store(data, Ordering::Release);
+        entry.key.store(hash ^ data, Ordering::Release);
+    }
+
+    /// Build a table for a copy of the tree in a new arena, where `copied_nodes` maps the raw indexes of the old nodes to the new ones.
+    /// Entries for nodes that were not copied are dropped.
+    pub fn copy_to(
+        &self,
+        max_entries: usize,
+        copied_nodes: &HashMap<NonZeroU32, Index<Tree<S>>>,
+    ) -> Self {
+        let table = Self::new(max_entries);
+        for (hash, index) in self.entries() {
+            if let Some(new_index) = copied_nodes.get(&index.to_raw()) {
+                table.insert(hash, new_index);
+            }
+        }
+        table
+    }
+}
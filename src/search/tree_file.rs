@@ -0,0 +1,677 @@
+//! Saving search trees to disk, and loading them back to continue the search.
+//!
+//! The file stores the root position as TPS, the search settings and every node of the tree, in a little-endian binary format.
+//! The root's move history is not stored, so a loaded tree cannot detect repetitions of positions from before the root.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use board_game_traits::Position as PositionTrait;
+use half::f16;
+use pgn_traits::PgnPosition;
+
+use super::arena::{AtomicIndex, Index};
+use super::mcts_core::{
+    AtomicF32, AtomicF64, AtomicProvenResult, GameResultForUs, ProvenResult, TempVectors, Tree,
+    TreeBridge, TreeEdge,
+};
+use super::transposition_table::TranspositionTable;
+use super::{Arena, MctsSetting, MonteCarloTree, RootPriors};
+use crate::evaluation::network::Network;
+use crate::evaluation::parameters::{num_policy_features, num_value_features};
+use crate::position::{Komi, Move, Position};
+
+const MAGIC: &[u8; 8] = b"TILTAKMC";
+const VERSION: u32 = 2;
+
+// Tags for the edges of the tree
+const EMPTY_EDGE: u8 = 0;
+const NEW_NODE: u8 = 1;
+// A node that has already been written, which is only possible with a transposition table
+const SHARED_NODE: u8 = 2;
+
+/// Upper limit for the length of parameter vectors, to reject corrupt files before allocating
+const MAX_PARAMS: u32 = 1 << 24;
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn out_of_memory() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::OutOfMemory,
+        "Search tree does not fit in the arena",
+    )
+}
+
+impl<const S: usize> MonteCarloTree<S> {
+    /// Write the tree, its root position and its settings to `writer`.
+    /// The tree must not be searched while it is being saved.
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = TreeWriter {
+            writer: BufWriter::new(writer),
+            arena: &self.arena,
+            node_ids: self.transposition_table.as_ref().map(|_| HashMap::new()),
+            num_nodes: 0,
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_u32(VERSION)?;
+        writer.write_u8(S as u8)?;
+        writer.write_u8(self.position.komi().half_komi() as u8)?;
+        writer.write_bytes(self.position.to_fen().as_bytes())?;
+        writer.write_settings(&self.settings)?;
+        writer.write_u32(self.visits)?;
+        writer.write_u32(self.arena.slots_used())?;
+        writer.write_edge(&self.tree)?;
+        writer.write_u32(self.root_priors.moves.len() as u32)?;
+        for (mv, heuristic_score) in self
+            .root_priors
+            .moves
+            .iter()
+            .zip(self.root_priors.heuristic_scores.iter())
+        {
+            writer.write_option(*mv, |writer, mv| writer.write_u16(mv.to_u16()))?;
+            writer.write_u16(heuristic_score.to_bits())?;
+        }
+
+        if let Some(table) = self.transposition_table.as_ref() {
+            let node_ids = writer.node_ids.take().unwrap();
+            // Entries for nodes that are not in the tree are dropped
+            let entries: Vec<(u64, u32)> = table
+                .entries()
+                .filter_map(|(hash, index)| node_ids.get(&index.to_raw()).map(|id| (hash, *id)))
+                .collect();
+            writer.write_u64(table.len() as u64)?;
+            writer.write_u64(entries.len() as u64)?;
+            for (hash, id) in entries {
+                writer.write_u64(hash)?;
+                writer.write_u32(id)?;
+            }
+        }
+        writer.writer.flush()
+    }
+
+    /// Read a tree that was written by `save`, so that the search can continue where it left off.
+    /// The tree gets the same settings as when it was saved, including the arena size. Use `reroot` to change them.
+    ///
+    /// Returns an error if the data is not a valid tree for this board size.
+    pub fn load<R: Read>(reader: R) -> io::Result<Self> {
+        Self::load_inner(reader, None)
+    }
+
+    /// Like `load`, but allocate an arena of `mem_usage` bytes instead of the saved arena size,
+    /// which may be too large for this machine.
+    ///
+    /// Returns an error if the saved tree does not fit.
+    pub fn load_with_mem_usage<R: Read>(reader: R, mem_usage: usize) -> io::Result<Self> {
+        Self::load_inner(reader, Some(mem_usage))
+    }
+
+    fn load_inner<R: Read>(reader: R, mem_usage: Option<usize>) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a search tree file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported search tree file version {}",
+                version
+            )));
+        }
+        let size = read_u8(&mut reader)?;
+        if size as usize != S {
+            return Err(invalid_data(format!(
+                "Search tree is for {}s, expected {}s",
+                size, S
+            )));
+        }
+        let komi = Komi::from_half_komi(read_u8(&mut reader)? as i8)
+            .ok_or_else(|| invalid_data("Invalid komi"))?;
+        let tps = String::from_utf8(read_bytes(&mut reader, MAX_PARAMS)?).map_err(invalid_data)?;
+        let position = Position::from_fen_with_komi(&tps, komi).map_err(invalid_data)?;
+        let (mut settings, params) = read_settings(&mut reader)?;
+        if let Some(mem_usage) = mem_usage {
+            settings = settings.mem_usage(mem_usage);
+        }
+        let visits = read_u32(&mut reader)?;
+        let slots_used = read_u32(&mut reader)?;
+        // Check before allocating, so that a too small arena fails without reading the whole tree
+        if slots_used > settings.arena_size {
+            return Err(out_of_memory());
+        }
+
+        let arena = Arena::new(settings.arena_size)
+            .map_err(|err| io::Error::new(io::ErrorKind::OutOfMemory, err.to_string()))?;
+        let mut tree_reader = TreeReader {
+            reader,
+            arena: &arena,
+            node_indexes: settings.transposition_table.then(Vec::new),
+            legal_moves: vec![],
+        };
+        let tree = tree_reader.read_edge(&mut position.clone())?;
+        // The search assumes that the root node is always expanded
+        if tree
+            .child
+            .get()
+            .and_then(|index| arena.get(&index).children.get())
+            .is_none()
+        {
+            return Err(invalid_data("Root node of search tree is not expanded"));
+        }
+        let root_priors = read_root_priors(&mut tree_reader.reader, &position, &tree, &arena)?;
+
+        let transposition_table = match tree_reader.node_indexes.take() {
+            Some(node_indexes) => {
+                let reader = &mut tree_reader.reader;
+                let saved_len = read_u64(reader)?;
+                let num_entries = read_u64(reader)?;
+                let table = TranspositionTable::new(
+                    settings.transposition_table_size().min(saved_len as usize),
+                );
+                for _ in 0..num_entries {
+                    let hash = read_u64(reader)?;
+                    let id = read_u32(reader)?;
+                    let index = node_indexes.get(id as usize).ok_or_else(|| {
+                        invalid_data("Transposition table entry for unknown node")
+                    })?;
+                    table.insert(hash, index);
+                }
+                Some(table)
+            }
+            None => None,
+        };
+
+        Ok(MonteCarloTree {
+            tree,
+            visits,
+            position: position.clone(),
+            temp_position: position,
+            settings: params.add_to(settings),
+            temp_vectors: TempVectors::default(),
+            thread_temp_vectors: vec![],
+            arena,
+            transposition_table,
+            root_priors,
+        })
+    }
+}
+
+struct TreeWriter<'a, W> {
+    writer: W,
+    arena: &'a Arena,
+    // Ids of the nodes that have been written, if nodes can be shared
+    node_ids: Option<HashMap<NonZeroU32, u32>>,
+    num_nodes: u32,
+}
+
+impl<W: Write> TreeWriter<'_, W> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+
+    fn write_u8(&mut self, value: u8) -> io::Result<()> {
+        self.write_all(&[value])
+    }
+
+    fn write_u16(&mut self, value: u16) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, value: u64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_f32(&mut self, value: f32) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_f64(&mut self, value: f64) -> io::Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_u32(bytes.len() as u32)?;
+        self.write_all(bytes)
+    }
+
+    fn write_f32s(&mut self, values: &[f32]) -> io::Result<()> {
+        self.write_u32(values.len() as u32)?;
+        values.iter().try_for_each(|value| self.write_f32(*value))
+    }
+
+    fn write_option<T>(
+        &mut self,
+        value: Option<T>,
+        write: impl FnOnce(&mut Self, T) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match value {
+            Some(value) => {
+                self.write_u8(1)?;
+                write(self, value)
+            }
+            None => self.write_u8(0),
+        }
+    }
+
+    fn write_settings<const S: usize>(&mut self, settings: &MctsSetting<S>) -> io::Result<()> {
+        self.write_u32(settings.arena_size)?;
+        self.write_option(settings.value_params, Self::write_f32s)?;
+        self.write_option(settings.policy_params, Self::write_f32s)?;
+        self.write_option(settings.value_network, Self::write_f32s)?;
+        self.write_option(settings.policy_network, Self::write_f32s)?;
+        self.write_f32s(&settings.search_params)?;
+        self.write_option(settings.dirichlet, Self::write_f32)?;
+        self.write_u32(settings.excluded_moves.len() as u32)?;
+        for mv in settings.excluded_moves.iter() {
+            self.write_u16(mv.to_u16())?;
+        }
+        self.write_option(settings.static_eval_variance, Self::write_f32)?;
+        self.write_u16(settings.rollout_depth)?;
+        self.write_option(settings.rollout_temperature, Self::write_f64)?;
+        self.write_u32(settings.threads as u32)?;
+        self.write_u8(settings.transposition_table as u8)
+    }
+
+    fn write_proven_result(&mut self, proven_result: Option<ProvenResult>) -> io::Result<()> {
+        let (result, plies) = match proven_result {
+            None => (0, 0),
+            Some(ProvenResult { result, plies }) => match result {
+                GameResultForUs::Win => (1, plies),
+                GameResultForUs::Loss => (2, plies),
+                GameResultForUs::Draw => (3, plies),
+            },
+        };
+        self.write_u8(result)?;
+        self.write_u16(plies)
+    }
+
+    fn write_edge<const S: usize>(&mut self, edge: &TreeEdge<S>) -> io::Result<()> {
+        let Some(index) = edge.child.get() else {
+            return self.write_u8(EMPTY_EDGE);
+        };
+        if let Some(node_ids) = self.node_ids.as_mut() {
+            if let Some(id) = node_ids.get(&index.to_raw()) {
+                let id = *id;
+                self.write_u8(SHARED_NODE)?;
+                return self.write_u32(id);
+            }
+            // Assign the id before writing the children, in case they lead back to this node
+            node_ids.insert(index.to_raw(), self.num_nodes);
+        }
+        self.num_nodes += 1;
+        self.write_u8(NEW_NODE)?;
+
+        let node = self.arena.get(&index);
+        self.write_f64(node.total_action_value.load())?;
+        self.write_u32(node.visits.load(Ordering::Relaxed))?;
+        self.write_proven_result(node.proven_result.load())?;
+        match node.children.get() {
+            Some(bridge_index) => {
+                self.write_u8(1)?;
+                self.write_bridge(self.arena.get(&bridge_index))
+            }
+            None => self.write_u8(0),
+        }
+    }
+
+    fn write_bridge<const S: usize>(&mut self, bridge: &TreeBridge<S>) -> io::Result<()> {
+        let arena = self.arena;
+        let children = arena.get_slice(&bridge.children);
+        self.write_u32(children.len() as u32)?;
+        for (i, child) in children.iter().enumerate() {
+            self.write_option(arena.get_slice(&bridge.moves)[i], |writer, mv| {
+                writer.write_u16(mv.to_u16())
+            })?;
+            self.write_f32(arena.get_slice(&bridge.mean_action_values)[i].load())?;
+            self.write_u32(arena.get_slice(&bridge.visitss)[i].load(Ordering::Relaxed))?;
+            self.write_u16(arena.get_slice(&bridge.heuristic_scores)[i].to_bits())?;
+            self.write_edge(child)?;
+        }
+        Ok(())
+    }
+}
+
+struct TreeReader<'a, R, const S: usize> {
+    reader: R,
+    arena: &'a Arena,
+    // Indexes of the nodes that have been read, if nodes can be shared
+    node_indexes: Option<Vec<Index<Tree<S>>>>,
+    legal_moves: Vec<Move<S>>,
+}
+
+impl<R: Read, const S: usize> TreeReader<'_, R, S> {
+    fn read_proven_result(&mut self) -> io::Result<Option<ProvenResult>> {
+        let result = match read_u8(&mut self.reader)? {
+            0 => None,
+            1 => Some(GameResultForUs::Win),
+            2 => Some(GameResultForUs::Loss),
+            3 => Some(GameResultForUs::Draw),
+            _ => return Err(invalid_data("Invalid proven result")),
+        };
+        let plies = read_u16(&mut self.reader)?;
+        Ok(result.map(|result| ProvenResult { result, plies }))
+    }
+
+    /// Read an edge, whose node is for `position`
+    fn read_edge(&mut self, position: &mut Position<S>) -> io::Result<TreeEdge<S>> {
+        match read_u8(&mut self.reader)? {
+            EMPTY_EDGE => return Ok(TreeEdge::empty()),
+            NEW_NODE => (),
+            SHARED_NODE => {
+                let id = read_u32(&mut self.reader)?;
+                return self
+                    .node_indexes
+                    .as_ref()
+                    .and_then(|node_indexes| node_indexes.get(id as usize))
+                    .map(|index| TreeEdge::new(Index::from_raw(index.to_raw())))
+                    .ok_or_else(|| invalid_data("Reference to unknown node"));
+            }
+            tag => return Err(invalid_data(format!("Invalid edge tag {}", tag))),
+        }
+
+        let node = Tree {
+            total_action_value: AtomicF64::new(read_f64(&mut self.reader)?),
+            visits: AtomicU32::new(read_u32(&mut self.reader)?),
+            proven_result: AtomicProvenResult::new(self.read_proven_result()?),
+            children: AtomicIndex::empty(),
+        };
+        let index = self.arena.add(node).ok_or_else(out_of_memory)?;
+        if let Some(node_indexes) = self.node_indexes.as_mut() {
+            node_indexes.push(Index::from_raw(index.to_raw()));
+        }
+
+        match read_u8(&mut self.reader)? {
+            0 => (),
+            1 => {
+                let bridge = self.read_bridge(position)?;
+                let node = self.arena.get(&index);
+                assert!(node.children.try_lock());
+                node.children
+                    .set(self.arena.add(bridge).ok_or_else(out_of_memory)?);
+            }
+            _ => return Err(invalid_data("Invalid node")),
+        }
+        Ok(TreeEdge::new(index))
+    }
+
+    fn read_bridge(&mut self, position: &mut Position<S>) -> io::Result<TreeBridge<S>> {
+        let num_children = read_u32(&mut self.reader)?;
+
+        // Every move must be legal, or the search would panic later
+        self.legal_moves.clear();
+        position.generate_moves(&mut self.legal_moves);
+        let mut legal_moves: Vec<u16> = self.legal_moves.iter().map(|mv| mv.to_u16()).collect();
+        legal_moves.sort_unstable();
+
+        let mut children = Vec::with_capacity(num_children.min(1024) as usize);
+        let mut moves = Vec::with_capacity(children.capacity());
+        let mut mean_action_values = Vec::with_capacity(children.capacity());
+        let mut visitss = Vec::with_capacity(children.capacity());
+        let mut heuristic_scores = Vec::with_capacity(children.capacity());
+
+        for _ in 0..num_children {
+            let mv = match read_u8(&mut self.reader)? {
+                0 => None,
+                1 => {
+                    let data = read_u16(&mut self.reader)?;
+                    if legal_moves.binary_search(&data).is_err() {
+                        return Err(invalid_data("Illegal move in search tree"));
+                    }
+                    Some(Move::from_u16(data).unwrap())
+                }
+                _ => return Err(invalid_data("Invalid move")),
+            };
+            mean_action_values.push(AtomicF32::new(read_f32(&mut self.reader)?));
+            visitss.push(AtomicU32::new(read_u32(&mut self.reader)?));
+            heuristic_scores.push(f16::from_bits(read_u16(&mut self.reader)?));
+
+            let child = match mv {
+                Some(mv) => {
+                    let reverse_move = position.do_move(mv);
+                    let child = self.read_edge(position);
+                    position.reverse_move(reverse_move);
+                    child?
+                }
+                None => self.read_edge(position)?,
+            };
+            if mv.is_none() && child.child.get().is_some() {
+                return Err(invalid_data("Search tree node without a move"));
+            }
+            children.push(child);
+            moves.push(mv);
+        }
+
+        Ok(TreeBridge {
+            children: self
+                .arena
+                .add_slice(children.into_iter())
+                .ok_or_else(out_of_memory)?,
+            moves: self
+                .arena
+                .add_slice(moves.into_iter())
+                .ok_or_else(out_of_memory)?,
+            mean_action_values: self
+                .arena
+                .add_slice(mean_action_values.into_iter())
+                .ok_or_else(out_of_memory)?,
+            visitss: self
+                .arena
+                .add_slice(visitss.into_iter())
+                .ok_or_else(out_of_memory)?,
+            heuristic_scores: self
+                .arena
+                .add_slice(heuristic_scores.into_iter())
+                .ok_or_else(out_of_memory)?,
+        })
+    }
+}
+
+/// Evaluation parameters from a file. The settings need them to live forever, so they are leaked, but only once the whole file has been read
+struct SavedParams {
+    value_params: Option<Box<[f32]>>,
+    policy_params: Option<Box<[f32]>>,
+    value_network: Option<Box<[f32]>>,
+    policy_network: Option<Box<[f32]>>,
+}
+
+impl SavedParams {
+    fn add_to<const S: usize>(self, settings: MctsSetting<S>) -> MctsSetting<S> {
+        let leak = |params: Option<Box<[f32]>>| params.map(|params| &*Box::leak(params));
+        MctsSetting {
+            value_params: leak(self.value_params),
+            policy_params: leak(self.policy_params),
+            value_network: leak(self.value_network),
+            policy_network: leak(self.policy_network),
+            ..settings
+        }
+    }
+}
+
+/// Read the settings, without the evaluation parameters, which are returned separately
+fn read_settings<R: Read, const S: usize>(
+    reader: &mut R,
+) -> io::Result<(MctsSetting<S>, SavedParams)> {
+    let arena_size = read_u32(reader)?;
+    if arena_size == 0 || arena_size >= u32::MAX - 1 {
+        return Err(invalid_data(format!("Invalid arena size {}", arena_size)));
+    }
+    let value_params = read_option(reader, read_f32s)?;
+    if value_params
+        .as_ref()
+        .is_some_and(|params| params.len() != num_value_features::<S>())
+    {
+        return Err(invalid_data("Wrong number of value parameters"));
+    }
+    let policy_params = read_option(reader, read_f32s)?;
+    if policy_params
+        .as_ref()
+        .is_some_and(|params| params.len() != num_policy_features::<S>())
+    {
+        return Err(invalid_data("Wrong number of policy parameters"));
+    }
+    let value_network = read_option(reader, read_f32s)?;
+    if value_network
+        .as_ref()
+        .is_some_and(|params| Network::new(params, num_value_features::<S>()).is_none())
+    {
+        return Err(invalid_data("Invalid value network"));
+    }
+    let policy_network = read_option(reader, read_f32s)?;
+    if policy_network
+        .as_ref()
+        .is_some_and(|params| Network::new(params, num_policy_features::<S>()).is_none())
+    {
+        return Err(invalid_data("Invalid policy network"));
+    }
+    let search_params = read_f32s(reader)?;
+    if search_params.len() < 3 {
+        return Err(invalid_data("Too few search parameters"));
+    }
+    let dirichlet = read_option(reader, read_f32)?;
+    let num_excluded_moves = read_u32(reader)?;
+    let excluded_moves = (0..num_excluded_moves)
+        .map(|_| {
+            Move::from_u16(read_u16(reader)?).ok_or_else(|| invalid_data("Invalid excluded move"))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let static_eval_variance = read_option(reader, read_f32)?;
+    let rollout_depth = read_u16(reader)?;
+    let rollout_temperature = read_option(reader, read_f64)?;
+    let threads = read_u32(reader)?;
+    if threads == 0 {
+        return Err(invalid_data("Invalid number of threads"));
+    }
+    let transposition_table = match read_u8(reader)? {
+        0 => false,
+        1 => true,
+        _ => return Err(invalid_data("Invalid transposition table setting")),
+    };
+    let settings = MctsSetting {
+        arena_size,
+        value_params: None,
+        policy_params: None,
+        value_network: None,
+        policy_network: None,
+        search_params,
+        dirichlet,
+        excluded_moves,
+        static_eval_variance,
+        rollout_depth,
+        rollout_temperature,
+        threads: threads as usize,
+        transposition_table,
+    };
+    let params = SavedParams {
+        value_params,
+        policy_params,
+        value_network,
+        policy_network,
+    };
+    Ok((settings, params))
+}
+
+/// Read the root's priors, which must match the root's children, except for excluded moves
+fn read_root_priors<R: Read, const S: usize>(
+    reader: &mut R,
+    position: &Position<S>,
+    tree: &TreeEdge<S>,
+    arena: &Arena,
+) -> io::Result<RootPriors<S>> {
+    let root = RootPriors::read(tree, arena);
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    if read_u32(reader)? as usize != root.moves.len() {
+        return Err(invalid_data("Wrong number of root priors"));
+    }
+    let mut root_priors = RootPriors {
+        moves: Vec::with_capacity(root.moves.len()),
+        heuristic_scores: Vec::with_capacity(root.moves.len()),
+    };
+    for root_move in root.moves {
+        let mv = read_option(reader, read_u16)?
+            .map(|data| {
+                Move::from_u16(data)
+                    .filter(|mv| legal_moves.contains(mv))
+                    .ok_or_else(|| invalid_data("Illegal move in root priors"))
+            })
+            .transpose()?;
+        if root_move.is_some() && root_move != mv {
+            return Err(invalid_data("Root priors do not match the root node"));
+        }
+        root_priors.moves.push(mv);
+        root_priors
+            .heuristic_scores
+            .push(f16::from_bits(read_u16(reader)?));
+    }
+    Ok(root_priors)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    read_u64(reader).map(f64::from_bits)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, max_len: u32) -> io::Result<Vec<u8>> {
+    let len = read_u32(reader)?;
+    if len > max_len {
+        return Err(invalid_data("Invalid length"));
+    }
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_f32s<R: Read>(reader: &mut R) -> io::Result<Box<[f32]>> {
+    let len = read_u32(reader)?;
+    if len > MAX_PARAMS {
+        return Err(invalid_data("Invalid length"));
+    }
+    (0..len).map(|_| read_f32(reader)).collect()
+}
+
+fn read_option<R: Read, T>(
+    reader: &mut R,
+    read: impl FnOnce(&mut R) -> io::Result<T>,
+) -> io::Result<Option<T>> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        1 => read(reader).map(Some),
+        _ => Err(invalid_data("Invalid optional value")),
+    }
+}
@@ -0,0 +1,149 @@
+//! A solver for forced road wins, also known as tinue.
+//!
+//! The solver looks for sequences where every move by the attacker creates a road threat, until the road cannot be stopped.
+//! The defender's replies are all checked, so a road win that is found is always forced.
+//! Wins that require quiet moves without a threat are not found, but these are rare in practice.
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+
+use crate::position::{Move, Position, Role};
+
+/// A forced road win for the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoadWin<const S: usize> {
+    /// The first move of the winning sequence
+    pub mv: Move<S>,
+    /// Number of moves by the winning side until the road is completed, including `mv`
+    pub moves: u32,
+}
+
+/// Find the fastest forced road win for the side to move, in at most `max_moves` of its own moves.
+/// Returns `None` if there is no such win, or if the game is already over.
+pub fn road_win<const S: usize>(position: &Position<S>, max_moves: u32) -> Option<RoadWin<S>> {
+    let mut position = position.clone();
+    if position.game_result().is_some() {
+        return None;
+    }
+    // Iterative deepening, so that the fastest win is found
+    (1..=max_moves)
+        .find_map(|moves| win_in(&mut position, moves, &mut vec![]).map(|mv| RoadWin { mv, moves }))
+}
+
+/// A move that wins by road on the spot, if there is one
+pub fn immediate_road_win<const S: usize>(position: &mut Position<S>) -> Option<Move<S>> {
+    // The first two moves place the opponent's stones
+    if position.half_moves_played() < 2 {
+        return None;
+    }
+    let us = position.side_to_move();
+    let (stones_left, caps_left) = match us {
+        Color::White => (position.white_reserves_left(), position.white_caps_left()),
+        Color::Black => (position.black_reserves_left(), position.black_caps_left()),
+    };
+    let role = if stones_left > 0 {
+        Some(Role::Flat)
+    } else if caps_left > 0 {
+        Some(Role::Cap)
+    } else {
+        None
+    };
+
+    // Placing on an empty critical square completes the road
+    if let Some(role) = role {
+        if let Some(square) = position
+            .group_data()
+            .critical_squares(us)
+            .find(|square| position.top_stones()[*square].is_none())
+        {
+            return Some(Move::placement(role, square));
+        }
+    }
+
+    // Otherwise, only a spread can complete a road
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    moves
+        .into_iter()
+        .filter(|mv| !mv.is_placement())
+        .find(|mv| {
+            let reverse_move = position.do_move(*mv);
+            let is_win = position.game_result() == Some(GameResult::win_by(us));
+            position.reverse_move(reverse_move);
+            is_win
+        })
+}
+
+/// Whether the side that just moved threatens to win by road on its next move
+fn has_road_threat<const S: usize>(position: &mut Position<S>) -> bool {
+    let them = position.side_to_move();
+    position.null_move();
+    let threat = immediate_road_win(position).is_some();
+    position.null_move();
+    debug_assert_eq!(position.side_to_move(), them);
+    threat
+}
+
+/// A move for the side to move that forces a road win in at most `moves` moves.
+/// `moves_buffer` is re-used between calls, to avoid allocations.
+fn win_in<const S: usize>(
+    position: &mut Position<S>,
+    moves: u32,
+    moves_buffer: &mut Vec<Vec<Move<S>>>,
+) -> Option<Move<S>> {
+    if moves <= 1 {
+        return immediate_road_win(position);
+    }
+    let us = position.side_to_move();
+
+    let mut our_moves = moves_buffer.pop().unwrap_or_default();
+    our_moves.clear();
+    position.generate_moves(&mut our_moves);
+
+    let mut winning_move = None;
+    for mv in our_moves.iter() {
+        let reverse_move = position.do_move(*mv);
+        let is_win = match position.game_result() {
+            Some(game_result) => game_result == GameResult::win_by(us),
+            // Every move in the sequence must threaten a road
+            None => {
+                has_road_threat(position) && all_replies_lose(position, moves - 1, us, moves_buffer)
+            }
+        };
+        position.reverse_move(reverse_move);
+        if is_win {
+            winning_move = Some(*mv);
+            break;
+        }
+    }
+    moves_buffer.push(our_moves);
+    winning_move
+}
+
+/// Whether every reply by the side to move allows `winner` to force a road win in at most `moves` moves.
+/// The search stops at the first reply that escapes, like a beta cutoff.
+fn all_replies_lose<const S: usize>(
+    position: &mut Position<S>,
+    moves: u32,
+    winner: Color,
+    moves_buffer: &mut Vec<Vec<Move<S>>>,
+) -> bool {
+    let mut replies = moves_buffer.pop().unwrap_or_default();
+    replies.clear();
+    position.generate_moves(&mut replies);
+
+    let mut all_lose = true;
+    for reply in replies.iter() {
+        let reverse_move = position.do_move(*reply);
+        let loses = match position.game_result() {
+            Some(game_result) => game_result == GameResult::win_by(winner),
+            None => win_in(position, moves, moves_buffer).is_some(),
+        };
+        position.reverse_move(reverse_move);
+        if !loses {
+            all_lose = false;
+            break;
+        }
+    }
+    moves_buffer.push(replies);
+    all_lose
+}
@@ -0,0 +1,398 @@
+//! Parser for the Tak Engine Interface (TEI), a text protocol for Tak engines based on UCI.
+//!
+//! The parser only checks the syntax of each command. Whether the command is valid in the engine's current state, for example `go` before any `position`, is checked by the engine.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::iter;
+use std::time::Duration;
+
+use board_game_traits::{Color, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+
+use crate::position::{Komi, Position};
+use crate::search::time::Clock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeiError {
+    EmptyCommand,
+    UnknownCommand(String),
+    InvalidSize(String),
+    UnsupportedSize(usize),
+    /// `position` or `go` was received before `teinewgame`
+    NoGame,
+    /// `go` was received before `position`
+    NoPosition,
+    /// `go` was received for a position where the game is over
+    GameOver,
+    InvalidPositionCommand(String),
+    InvalidTps {
+        tps: String,
+        error: String,
+    },
+    IllegalMove {
+        mv: String,
+        error: String,
+    },
+    InvalidGoCommand(String),
+    InvalidPerftCommand(String),
+    InvalidSetOptionCommand(String),
+    UnknownOption(String),
+    InvalidOptionValue {
+        name: String,
+        value: String,
+    },
+    /// The command cannot be run while the engine is searching
+    Searching,
+    /// The search thread panicked, so its search tree is lost
+    SearchFailed,
+    /// There is no search tree to save
+    NoSearchTree,
+    SearchTreeFile {
+        path: String,
+        error: String,
+    },
+    BookFile {
+        path: String,
+        error: String,
+    },
+    NetworkFile {
+        path: String,
+        error: String,
+    },
+    /// The network set with `ValueNetwork` or `PolicyNetwork` is for a different board size
+    WrongNetworkSize {
+        network_size: usize,
+        size: usize,
+    },
+    ParameterFile {
+        path: String,
+        error: String,
+    },
+    /// The parameters set with `ValueParams` or `PolicyParams` are for a different board size or komi
+    WrongParameterFile {
+        file_size: usize,
+        file_komi: Komi,
+        size: usize,
+        komi: Komi,
+    },
+}
+
+impl Display for TeiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeiError::EmptyCommand => write!(f, "Empty command"),
+            TeiError::UnknownCommand(command) => write!(f, "Unknown command \"{}\"", command),
+            TeiError::InvalidSize(size) => write!(f, "Invalid size \"{}\"", size),
+            TeiError::UnsupportedSize(size) => write!(f, "Unsupported size {}", size),
+            TeiError::NoGame => write!(f, "Expected teinewgame first"),
+            TeiError::NoPosition => write!(f, "Expected position first"),
+            TeiError::GameOver => write!(f, "The game is already over"),
+            TeiError::InvalidPositionCommand(line) => {
+                write!(f, "Invalid position command \"{}\"", line)
+            }
+            TeiError::InvalidTps { tps, error } => write!(f, "Invalid tps \"{}\": {}", tps, error),
+            TeiError::IllegalMove { mv, error } => write!(f, "Illegal move \"{}\": {}", mv, error),
+            TeiError::InvalidGoCommand(line) => write!(f, "Invalid go command \"{}\"", line),
+            TeiError::InvalidPerftCommand(line) => {
+                write!(f, "Invalid perft command \"{}\"", line)
+            }
+            TeiError::InvalidSetOptionCommand(line) => {
+                write!(f, "Invalid setoption command \"{}\"", line)
+            }
+            TeiError::UnknownOption(name) => write!(f, "Unknown option \"{}\"", name),
+            TeiError::InvalidOptionValue { name, value } => {
+                write!(f, "Invalid value \"{}\" for option {}", value, name)
+            }
+            TeiError::Searching => write!(f, "Cannot do that while searching"),
+            TeiError::SearchFailed => write!(f, "The search failed"),
+            TeiError::NoSearchTree => write!(f, "No search tree to save"),
+            TeiError::SearchTreeFile { path, error } => {
+                write!(f, "Search tree file \"{}\": {}", path, error)
+            }
+            TeiError::BookFile { path, error } => {
+                write!(f, "Opening book file \"{}\": {}", path, error)
+            }
+            TeiError::NetworkFile { path, error } => {
+                write!(f, "Network file \"{}\": {}", path, error)
+            }
+            TeiError::WrongNetworkSize { network_size, size } => write!(
+                f,
+                "Network is for size {}, but the game is size {}",
+                network_size, size
+            ),
+            TeiError::ParameterFile { path, error } => {
+                write!(f, "Parameter file \"{}\": {}", path, error)
+            }
+            TeiError::WrongParameterFile {
+                file_size,
+                file_komi,
+                size,
+                komi,
+            } => write!(
+                f,
+                "Parameters are for size {} with komi {}, but the game is size {} with komi {}",
+                file_size, file_komi, size, komi
+            ),
+        }
+    }
+}
+
+impl Error for TeiError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeiCommand {
+    Tei,
+    IsReady,
+    Quit,
+    Stop,
+    PonderHit,
+    SetOption {
+        name: String,
+        value: String,
+    },
+    TeiNewGame {
+        size: usize,
+    },
+    Position(PositionCommand),
+    Go(GoCommand),
+    /// Count the leaf nodes below each legal move in the current position, to the given depth
+    Perft {
+        depth: u16,
+    },
+}
+
+impl TeiCommand {
+    pub fn parse(line: &str) -> Result<Self, TeiError> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => Err(TeiError::EmptyCommand),
+            Some("tei") => Ok(TeiCommand::Tei),
+            Some("isready") => Ok(TeiCommand::IsReady),
+            Some("quit") => Ok(TeiCommand::Quit),
+            Some("stop") => Ok(TeiCommand::Stop),
+            Some("ponderhit") => Ok(TeiCommand::PonderHit),
+            Some("setoption") => parse_setoption(line),
+            Some("teinewgame") => {
+                let size_string = words.next().unwrap_or_default();
+                let size = size_string
+                    .parse()
+                    .map_err(|_| TeiError::InvalidSize(size_string.to_string()))?;
+                Ok(TeiCommand::TeiNewGame { size })
+            }
+            Some("position") => PositionCommand::parse(line).map(TeiCommand::Position),
+            Some("go") => GoCommand::parse(line).map(TeiCommand::Go),
+            Some("perft") => match (words.next().map(str::parse), words.next()) {
+                (Some(Ok(depth)), None) => Ok(TeiCommand::Perft { depth }),
+                _ => Err(TeiError::InvalidPerftCommand(line.to_string())),
+            },
+            Some(command) => Err(TeiError::UnknownCommand(command.to_string())),
+        }
+    }
+}
+
+/// Parse a `setoption name <name> value <value>` string. Both the name and the value may contain spaces
+fn parse_setoption(line: &str) -> Result<TeiCommand, TeiError> {
+    let mut words = line.split_whitespace();
+    words.next(); // setoption
+    if words.next() != Some("name") {
+        return Err(TeiError::InvalidSetOptionCommand(line.to_string()));
+    }
+    let name: Vec<&str> = words.by_ref().take_while(|word| *word != "value").collect();
+    let value: Vec<&str> = words.collect();
+    if name.is_empty() {
+        return Err(TeiError::InvalidSetOptionCommand(line.to_string()));
+    }
+    Ok(TeiCommand::SetOption {
+        name: name.join(" "),
+        value: value.join(" "),
+    })
+}
+
+/// A `position` command. The board size is not known when parsing, so the position is only constructed by `to_position`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionCommand {
+    /// The starting position's tps, or `None` for the start position
+    pub tps: Option<String>,
+    pub moves: Vec<String>,
+}
+
+impl PositionCommand {
+    pub fn parse(line: &str) -> Result<Self, TeiError> {
+        let invalid = || TeiError::InvalidPositionCommand(line.to_string());
+        let mut words = line.split_whitespace().peekable();
+        words.next(); // position
+
+        let tps = match words.next() {
+            Some("startpos") => None,
+            Some("tps") => {
+                // The tps continues until `moves`, or until the end of the line
+                let tps_words: Vec<&str> =
+                    iter::from_fn(|| words.next_if(|word| *word != "moves")).collect();
+                if tps_words.is_empty() {
+                    return Err(invalid());
+                }
+                Some(tps_words.join(" "))
+            }
+            _ => return Err(invalid()),
+        };
+
+        let moves = match words.next() {
+            Some("moves") => words.map(ToString::to_string).collect(),
+            Some(_) => return Err(invalid()),
+            None => vec![],
+        };
+        Ok(PositionCommand { tps, moves })
+    }
+
+    /// Set up the position, and check that all the moves are legal
+    pub fn to_position<const S: usize>(&self, komi: Komi) -> Result<Position<S>, TeiError> {
+        let mut position = match &self.tps {
+            None => Position::start_position_with_komi(komi),
+            Some(tps) => {
+                Position::from_fen_with_komi(tps, komi).map_err(|err| TeiError::InvalidTps {
+                    tps: tps.clone(),
+                    error: err.to_string(),
+                })?
+            }
+        };
+
+        for move_string in self.moves.iter() {
+            let illegal_move = |error: &str| TeiError::IllegalMove {
+                mv: move_string.clone(),
+                error: error.to_string(),
+            };
+            let mv = position
+                .move_from_san(move_string)
+                .map_err(|err| illegal_move(&err.to_string()))?;
+            if position.game_result().is_some() {
+                return Err(illegal_move("the game is already over"));
+            }
+            let mut legal_moves = vec![];
+            position.generate_moves(&mut legal_moves);
+            if !legal_moves.contains(&mv) {
+                return Err(illegal_move("not a legal move in this position"));
+            }
+            position.do_move(mv);
+        }
+        Ok(position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GoTimeControl {
+    MoveTime(Duration),
+    Infinite,
+    Clock {
+        white_time: Duration,
+        white_inc: Duration,
+        black_time: Duration,
+        black_inc: Duration,
+        /// Number of moves until the next time control, if any
+        moves_to_go: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoCommand {
+    pub ponder: bool,
+    pub time_control: GoTimeControl,
+}
+
+impl GoCommand {
+    pub fn parse(line: &str) -> Result<Self, TeiError> {
+        let invalid = || TeiError::InvalidGoCommand(line.to_string());
+        let mut words = line.split_whitespace();
+        words.next(); // go
+
+        let mut ponder = false;
+        let mut time_control = None;
+        let mut clock: [Option<Duration>; 4] = [None; 4];
+        let mut moves_to_go = None;
+
+        while let Some(word) = words.next() {
+            if word == "movestogo" {
+                moves_to_go = Some(
+                    words
+                        .next()
+                        .and_then(|moves| moves.parse().ok())
+                        .filter(|moves: &u32| *moves > 0)
+                        .ok_or_else(invalid)?,
+                );
+                continue;
+            }
+            let mut parse_time = || -> Result<Duration, TeiError> {
+                words
+                    .next()
+                    .and_then(|time| time.parse().ok())
+                    .map(Duration::from_millis)
+                    .ok_or_else(invalid)
+            };
+            let clock_index = match word {
+                "ponder" => {
+                    ponder = true;
+                    continue;
+                }
+                "movetime" | "infinite" if time_control.is_some() => return Err(invalid()),
+                "movetime" => {
+                    time_control = Some(GoTimeControl::MoveTime(parse_time()?));
+                    continue;
+                }
+                "infinite" => {
+                    time_control = Some(GoTimeControl::Infinite);
+                    continue;
+                }
+                "wtime" => 0,
+                "winc" => 1,
+                "btime" => 2,
+                "binc" => 3,
+                _ => return Err(invalid()),
+            };
+            clock[clock_index] = Some(parse_time()?);
+        }
+
+        if clock.iter().any(Option::is_some) {
+            if time_control.is_some() {
+                return Err(invalid());
+            }
+            time_control = Some(GoTimeControl::Clock {
+                white_time: clock[0].unwrap_or_default(),
+                white_inc: clock[1].unwrap_or_default(),
+                black_time: clock[2].unwrap_or_default(),
+                black_inc: clock[3].unwrap_or_default(),
+                moves_to_go,
+            });
+        } else if moves_to_go.is_some() {
+            return Err(invalid());
+        }
+
+        Ok(GoCommand {
+            ponder,
+            time_control: time_control.ok_or_else(invalid)?,
+        })
+    }
+
+    /// The clock of the side to move, if the command has one
+    pub fn clock(&self, side_to_move: Color) -> Option<Clock> {
+        match self.time_control {
+            GoTimeControl::Clock {
+                white_time,
+                white_inc,
+                black_time,
+                black_inc,
+                moves_to_go,
+            } => {
+                let (time_left, increment) = match side_to_move {
+                    Color::White => (white_time, white_inc),
+                    Color::Black => (black_time, black_inc),
+                };
+                Some(Clock {
+                    time_left,
+                    increment,
+                    moves_to_go,
+                })
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::alpha_beta::{AlphaBeta, AlphaBetaSetting};
+use crate::position::Position;
+use crate::search::time::TimeBudget;
+use crate::tests::TestPosition;
+
+fn settings<const S: usize>() -> AlphaBetaSetting<S> {
+    AlphaBetaSetting::default().mem_usage(16 * 1024 * 1024)
+}
+
+#[test]
+fn win_in_one_test() {
+    let position: Position<5> =
+        TestPosition::from_move_strings(&["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"])
+            .position();
+    let mut engine = AlphaBeta::new(settings());
+    let search_info = engine.search_depth(&position, 3).unwrap();
+
+    assert!(["a2", "Ca2"].contains(&position.move_to_san(&search_info.best_move()).as_str()));
+    assert_eq!(search_info.proven_plies(), Some(1));
+    assert_eq!(search_info.win_probability(), 1.0);
+    // The search stops as soon as the win is proven
+    assert_eq!(search_info.depth, 1);
+}
+
+#[test]
+fn win_in_two_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "a5", "e5", "e4", "Cc3", "e3", "e2", "Cd3", "d2", "e1", "c4", "1e1+1", "e1", "1d3-1", "Sd1",
+    ])
+    .position();
+    let mut engine = AlphaBeta::new(settings());
+    let search_info = engine.search_depth(&position, 3).unwrap();
+
+    assert_eq!(position.move_to_san(&search_info.best_move()), "d2>");
+    assert_eq!(search_info.proven_plies(), Some(3));
+}
+
+#[test]
+fn avoid_loss_in_one_test() {
+    let position: Position<5> =
+        TestPosition::from_move_strings(&["b4", "c2", "d2", "c4", "b2", "d4", "e2"]).position();
+    let mut engine = AlphaBeta::new(settings());
+    let search_info = engine.search_depth(&position, 2).unwrap();
+
+    assert!(["a2", "Ca2", "Sa2"].contains(&position.move_to_san(&search_info.best_move()).as_str()));
+    assert_eq!(search_info.proven_plies(), None);
+}
+
+#[test]
+fn pv_is_legal_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "b4", "c2", "d2", "d4", "b2", "c4", "e2", "a2", "c3", "b3", "b2+", "c4-", "c2+", "b2",
+    ])
+    .position();
+    let mut engine = AlphaBeta::new(settings());
+    let search_info = engine.search_depth(&position, 4).unwrap();
+    assert_eq!(search_info.depth, 4);
+
+    let mut pv_position = position.clone();
+    let mut legal_moves = vec![];
+    for mv in search_info.pv {
+        legal_moves.clear();
+        pv_position.generate_moves(&mut legal_moves);
+        assert!(legal_moves.contains(&mv));
+        pv_position.do_move(mv);
+    }
+}
+
+#[test]
+fn transposition_table_is_reused_test() {
+    let position = <Position<5>>::start_position();
+    let mut engine = AlphaBeta::new(settings());
+    let first_search = engine.search_depth(&position, 3).unwrap();
+    let second_search = engine.search_depth(&position, 3).unwrap();
+
+    assert_eq!(first_search.score, second_search.score);
+    assert!(second_search.nodes < first_search.nodes);
+}
+
+#[test]
+fn search_nodes_test() {
+    let position = <Position<6>>::start_position();
+    let mut engine = AlphaBeta::new(settings());
+    let search_info = engine.search_nodes(&position, 20_000).unwrap();
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&search_info.best_move()));
+    assert!(search_info.nodes <= 20_000);
+}
+
+#[test]
+fn search_for_time_test() {
+    let position = <Position<6>>::start_position();
+    let mut engine = AlphaBeta::new(settings());
+    let start_time = Instant::now();
+    let mut depths = vec![];
+    let search_info = engine
+        .search_for_time(
+            &position,
+            TimeBudget {
+                soft_limit: Duration::from_millis(100),
+                hard_limit: Duration::from_millis(200),
+            },
+            None,
+            |info| depths.push(info.depth),
+        )
+        .unwrap();
+
+    assert!(start_time.elapsed() < Duration::from_secs(1));
+    assert_eq!(depths, (1..=search_info.depth).collect::<Vec<_>>());
+}
+
+#[test]
+fn game_over_test() {
+    let position: Position<5> =
+        TestPosition::from_move_strings(&["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3", "a2"])
+            .position();
+    let mut engine = AlphaBeta::new(settings());
+    assert_eq!(engine.search_depth(&position, 2), None);
+}
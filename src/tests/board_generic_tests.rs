@@ -475,7 +475,7 @@ fn bitboard_full_board_file_rank_prop<const S: usize>() {
         &mut position,
         &(move_strings.iter().map(AsRef::as_ref).collect::<Vec<_>>()),
     );
-    if S % 2 == 0 {
+    if S.is_multiple_of(2) {
         assert_eq!(position.game_result(), Some(BlackWin));
     } else {
         assert_eq!(position.game_result(), Some(WhiteWin));
@@ -429,6 +429,25 @@ fn repetitions_are_draws_test() {
     assert_eq!(position.game_result(), None);
 }
 
+#[test]
+fn repetitions_are_kept_after_reversing_a_placement_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a1", "e5"]);
+    let cycle_move_strings = ["e5-", "a1+", "e4+", "a2-"];
+    do_moves_and_check_validity(&mut position, &cycle_move_strings);
+    do_moves_and_check_validity(&mut position, &cycle_move_strings[..3]);
+
+    let mv = position.move_from_san("c3").unwrap();
+    let reverse_move = position.do_move(mv);
+    assert_eq!(position.repetitions(), 0);
+    position.reverse_move(reverse_move);
+
+    // The repetition draw is still found after undoing the placement
+    do_moves_and_check_validity(&mut position, &["a2-"]);
+    assert_eq!(position.repetitions(), 2);
+    assert_eq!(position.game_result(), Some(GameResult::Draw));
+}
+
 #[test]
 fn fake_repetitions_are_not_draws_test() {
     let mut position = <Position<6>>::start_position();
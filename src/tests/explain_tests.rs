@@ -0,0 +1,67 @@
+use board_game_traits::{EvalPosition, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+
+use crate::evaluation::parameters::{policy_indexes, value_indexes};
+use crate::position::{Komi, Position};
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() <= 0.001 * a.abs().max(1.0), "{} != {}", a, b);
+}
+
+#[test]
+fn explain_static_eval_sums_to_static_eval_test() {
+    let mut position = <Position<6>>::start_position_with_komi(Komi::from_half_komi(4).unwrap());
+    for move_string in ["a1", "f6", "c3", "d4", "c4", "d3", "c5", "Cd5", "Cc2", "d2"] {
+        let explanation = position.explain_static_eval();
+        assert_eq!(
+            explanation.groups.len(),
+            value_indexes::<6>().named_groups().len()
+        );
+        assert_close(explanation.eval(), position.static_eval());
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+}
+
+#[test]
+fn explain_static_eval_ranked_test() {
+    let position = <Position<5>>::from_fen("x5/x5/x,1,2,x2/x,1,x3/x5 1 3").unwrap();
+    let explanation = position.explain_static_eval();
+    let ranked = explanation.ranked();
+    assert!(!ranked.is_empty());
+    assert!(ranked
+        .windows(2)
+        .all(|pair| pair[0].total().abs() >= pair[1].total().abs()));
+    assert!(ranked.iter().all(|group| group.name != "padding"));
+}
+
+#[test]
+fn explain_policy_test() {
+    let position = <Position<5>>::from_fen("x5/x5/x,1,2,x2/x,1,x3/x5 1 3").unwrap();
+    let explanations = position.explain_policy();
+
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    assert_eq!(explanations.len(), moves.len());
+
+    for explanation in explanations.iter() {
+        assert_eq!(
+            explanation.groups.len(),
+            policy_indexes::<5>().named_groups().len()
+        );
+        assert!(explanation.score() > 0.0 && explanation.score() < 1.0);
+        let ranked = explanation.ranked();
+        assert!(ranked
+            .windows(2)
+            .all(|pair| pair[0].1.abs() >= pair[1].1.abs()));
+    }
+
+    // Placing a flat on a1 gets its psqt bonus
+    let a1 = explanations
+        .iter()
+        .find(|explanation| explanation.mv == position.move_from_san("a1").unwrap())
+        .unwrap();
+    assert!(a1
+        .ranked()
+        .iter()
+        .any(|(name, _)| *name == "flat_psqt_white"));
+}
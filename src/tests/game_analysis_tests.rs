@@ -0,0 +1,45 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::game_analysis::annotate_game;
+use crate::position::Position;
+use crate::ptn::{ptn_parser, Game};
+use crate::search::MctsSetting;
+
+#[test]
+fn annotate_blunder_test() {
+    // Black can win with e3, but plays c5 and lets white win with e1
+    let ptn = r#"[Size "5"]
+[TPS "x5/x5/2,2,2,2,x/x5/1,1,1,1,x 2 5"]
+
+5... c5 6. e1 R-0
+"#;
+    let games: Vec<Game<Position<5>>> = ptn_parser::parse_ptn(ptn).unwrap();
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let mut moves_seen = 0;
+    let annotated_game = annotate_game(&games[0], settings, 5_000, |_, _| moves_seen += 1);
+    assert_eq!(moves_seen, 2);
+
+    let blunder = &annotated_game.moves[0];
+    assert_eq!(blunder.annotations, vec!["?", "?"]);
+    assert!(blunder.comment.starts_with("0.0%"), "{}", blunder.comment);
+    assert_eq!(blunder.variations.len(), 1);
+    let position = &games[0].start_position;
+    assert_eq!(
+        blunder.variations[0][0].mv,
+        position.move_from_san("e3").unwrap()
+    );
+
+    let winning_move = &annotated_game.moves[1];
+    assert!(!winning_move.annotations.contains(&"?"));
+    assert_eq!(winning_move.comment, "100.0%");
+    assert!(winning_move.variations.is_empty());
+
+    // The annotated game can be written and read back
+    let mut ptn = vec![];
+    annotated_game.game_to_ptn(&mut ptn).unwrap();
+    let ptn = String::from_utf8(ptn).unwrap();
+    let parsed_games: Vec<Game<Position<5>>> = ptn_parser::parse_ptn(&ptn).unwrap();
+    assert_eq!(parsed_games[0].moves, annotated_game.moves, "{}", ptn);
+    assert!(parsed_games[0].start_position.game_result().is_none());
+}
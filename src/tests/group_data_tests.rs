@@ -0,0 +1,91 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::position::Position;
+
+fn assert_group_data_matches_from_scratch<const S: usize>(position: &Position<S>) {
+    assert_eq!(
+        position.group_data(),
+        position.group_data_from_scratch(),
+        "Wrong group data for {}",
+        position.to_fen()
+    );
+}
+
+/// Play random games, checking the group data after every `do_move` and `reverse_move`
+fn random_games_prop<const S: usize>(num_games: usize) {
+    let mut rng = StdRng::seed_from_u64(S as u64);
+    let mut moves = vec![];
+    for _ in 0..num_games {
+        let mut position = <Position<S>>::start_position();
+        let mut reverse_moves = vec![];
+        while position.game_result().is_none() && position.half_moves_played() < 200 {
+            assert_group_data_matches_from_scratch(&position);
+            moves.clear();
+            position.generate_moves(&mut moves);
+            let mv = moves[rng.gen_range(0..moves.len())];
+            reverse_moves.push((position.do_move(mv), position.clone()));
+        }
+        assert_group_data_matches_from_scratch(&position);
+        for symmetry in position.symmetries_with_swapped_colors() {
+            assert_group_data_matches_from_scratch(&symmetry);
+        }
+        assert_group_data_matches_from_scratch(
+            &<Position<S>>::from_fen(&position.to_fen()).unwrap(),
+        );
+
+        while let Some((reverse_move, position_after_move)) = reverse_moves.pop() {
+            assert_eq!(position.group_data(), position_after_move.group_data());
+            position.reverse_move(reverse_move);
+            assert_group_data_matches_from_scratch(&position);
+        }
+    }
+}
+
+#[test]
+fn group_data_random_games_3s_test() {
+    random_games_prop::<3>(200)
+}
+
+#[test]
+fn group_data_random_games_4s_test() {
+    random_games_prop::<4>(100)
+}
+
+#[test]
+fn group_data_random_games_5s_test() {
+    random_games_prop::<5>(50)
+}
+
+#[test]
+fn group_data_random_games_6s_test() {
+    random_games_prop::<6>(50)
+}
+
+#[test]
+fn group_data_random_games_7s_test() {
+    random_games_prop::<7>(20)
+}
+
+#[test]
+fn group_data_random_games_8s_test() {
+    random_games_prop::<8>(20)
+}
+
+#[test]
+fn critical_squares_test() {
+    // White has groups from a2 to a5 and from c2 to c5, which are both one square away from a road
+    let position =
+        <Position<5>>::from_fen("1,x,1,x2/1,x,1,x2/1,x,1,x2/1,2,1,x2/x,2,2,x,2 2 6").unwrap();
+    let group_data = position.group_data();
+    assert_eq!(group_data, position.group_data_from_scratch());
+
+    let white_critical_squares: Vec<String> = group_data
+        .critical_squares(board_game_traits::Color::White)
+        .map(|square| square.to_string())
+        .collect();
+    // Black's stone on c1 is critical as well, since a white stack could move onto it
+    assert_eq!(white_critical_squares, vec!["a1", "c1"]);
+}
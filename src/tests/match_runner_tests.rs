@@ -0,0 +1,142 @@
+use crate::alpha_beta::AlphaBetaSetting;
+use crate::position::Komi;
+use crate::search::{MctsSetting, TimeControl};
+use crate::tune::match_runner::{run_match, MatchEngine, MatchEngineKind, MatchSettings};
+use crate::tune::play_match::Player;
+use crate::tune::sprt::{elo_to_score, score_to_elo, MatchScore, Sprt, SprtResult};
+
+#[test]
+fn elo_from_score_test() {
+    let score = MatchScore {
+        wins: 60,
+        losses: 20,
+        draws: 20,
+    };
+    assert_eq!(score.games(), 100);
+    assert_eq!(score.score(), 0.7);
+    assert!((score.elo() - 147.2).abs() < 0.1, "{}", score.elo());
+    assert!((score_to_elo(elo_to_score(35.0)) - 35.0).abs() < 1e-9);
+    assert_eq!(score.to_string(), "+60-20=20");
+
+    let even_score = MatchScore {
+        wins: 30,
+        losses: 30,
+        draws: 40,
+    };
+    assert_eq!(even_score.elo(), 0.0);
+    assert!((even_score.los() - 0.5).abs() < 1e-6);
+    // More games give smaller error bars
+    let more_games = MatchScore {
+        wins: 300,
+        losses: 300,
+        draws: 400,
+    };
+    assert!(more_games.elo_error() < even_score.elo_error());
+
+    let one_sided = MatchScore {
+        wins: 10,
+        losses: 0,
+        draws: 0,
+    };
+    assert_eq!(one_sided.elo(), f64::INFINITY);
+    assert!(one_sided.los() > 0.999);
+}
+
+#[test]
+fn sprt_test() {
+    let sprt = Sprt {
+        elo0: 0.0,
+        elo1: 10.0,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+    let (lower_bound, upper_bound) = sprt.bounds();
+    assert!((lower_bound + 2.944).abs() < 0.001);
+    assert!((upper_bound - 2.944).abs() < 0.001);
+
+    assert_eq!(sprt.result(&MatchScore::default()), SprtResult::Continue);
+    let few_games = MatchScore {
+        wins: 6,
+        losses: 4,
+        draws: 2,
+    };
+    assert_eq!(sprt.result(&few_games), SprtResult::Continue);
+    let strong = MatchScore {
+        wins: 600,
+        losses: 400,
+        draws: 200,
+    };
+    assert_eq!(sprt.result(&strong), SprtResult::AcceptH1);
+    let weak = MatchScore {
+        wins: 400,
+        losses: 600,
+        draws: 200,
+    };
+    assert_eq!(sprt.result(&weak), SprtResult::AcceptH0);
+}
+
+#[test]
+fn in_process_match_test() {
+    let mcts = MatchEngine::<4> {
+        name: "mcts".to_string(),
+        kind: MatchEngineKind::InProcess(Player::Mcts(
+            MctsSetting::default().arena_size_for_nodes(200),
+        )),
+    };
+    let alpha_beta = MatchEngine::<4> {
+        name: "alphabeta".to_string(),
+        kind: MatchEngineKind::InProcess(Player::AlphaBeta(AlphaBetaSetting::default())),
+    };
+    let settings = MatchSettings {
+        komi: Komi::default(),
+        time_control: TimeControl::FixedNodes(200),
+        openings: vec![],
+        pairs: 2,
+        concurrency: 2,
+        sprt: None,
+    };
+
+    let mut pair_numbers = vec![];
+    let result = run_match(&mcts, &alpha_beta, &settings, |pair_result, _| {
+        pair_numbers.push(pair_result.pair_number);
+        let [game1, game2] = &pair_result.games;
+        // Both games start from the same opening, with colors swapped
+        assert_eq!(game1.moves[0].mv, game2.moves[0].mv);
+        assert_eq!(game1.moves[1].mv, game2.moves[1].mv);
+        assert!(game1
+            .tags
+            .contains(&("Player1".to_string(), "mcts".to_string())));
+        assert!(game2
+            .tags
+            .contains(&("Player1".to_string(), "alphabeta".to_string())));
+        assert!(game1.game_result_str.is_some() && game2.game_result_str.is_some());
+        assert_eq!(pair_result.score.games(), 2);
+    })
+    .unwrap();
+
+    pair_numbers.sort();
+    assert_eq!(pair_numbers, vec![0, 1]);
+    assert_eq!(result.score.games(), 4);
+    assert_eq!(result.sprt_result, None);
+}
+
+#[test]
+fn tei_engine_needs_time_control_test() {
+    let tei_engine = MatchEngine::<4> {
+        name: "tei".to_string(),
+        kind: MatchEngineKind::Tei {
+            program: "tei".to_string(),
+            args: vec![],
+            options: vec![],
+        },
+    };
+    let settings = MatchSettings {
+        komi: Komi::default(),
+        time_control: TimeControl::FixedNodes(200),
+        openings: vec![],
+        pairs: 1,
+        concurrency: 1,
+        sprt: None,
+    };
+    assert!(run_match(&tei_engine, &tei_engine, &settings, |_, _| ()).is_err());
+}
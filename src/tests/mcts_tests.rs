@@ -1,6 +1,6 @@
 use crate::position::{Move, Position};
-use crate::search::MctsSetting;
 use crate::search::{self, MonteCarloTree};
+use crate::search::{GameResultForUs, MctsSetting, ProvenResult};
 use crate::tests::TestPosition;
 use board_game_traits::Position as PositionTrait;
 use half::f16;
@@ -29,6 +29,293 @@ fn exclude_moves_test() {
     );
 }
 
+#[test]
+fn multithreaded_search_test() {
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(10_000)
+        .threads(4);
+    let mut tree = MonteCarloTree::new(<Position<5>>::start_position(), settings);
+
+    tree.search_nodes(10_000, None).unwrap();
+    assert_eq!(tree.visits(), 10_000);
+    assert!(tree.pv().count() > 1);
+    assert!(tree.best_move().is_some());
+}
+
+#[test]
+fn multithreaded_win_in_one_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(20_000)
+        .threads(4);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(20_000, None).unwrap();
+
+    let (best_move, score) = tree.best_move().unwrap();
+    assert!(
+        ["a2", "Ca2"].contains(&position.move_to_san(&best_move).as_str()),
+        "Played {} with score {}",
+        best_move,
+        score
+    );
+}
+
+#[test]
+fn reroot_keeps_subtree_test() {
+    let mut position = <Position<5>>::start_position();
+    let settings = MctsSetting::default().arena_size_for_nodes(20_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+    tree.search_nodes(10_000, None).unwrap();
+
+    let pv: Vec<Move<5>> = tree.pv().take(2).collect();
+    assert_eq!(pv.len(), 2);
+    for mv in pv.iter() {
+        position.do_move(*mv);
+    }
+
+    assert!(tree.reroot(&position, settings));
+    assert!(tree.visits() > 0);
+    let visits = tree.visits();
+
+    tree.search_nodes(1000, None).unwrap();
+    assert_eq!(tree.visits(), visits + 1000);
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&tree.best_move().unwrap().0));
+}
+
+#[test]
+fn reroot_after_out_of_memory_test() {
+    let position = <Position<5>>::start_position();
+    let mut tree = MonteCarloTree::new(
+        position.clone(),
+        MctsSetting::default().arena_size_for_nodes(100),
+    );
+    assert!(tree.search_nodes(100_000, None).is_err());
+    let visits = tree.visits();
+
+    assert!(tree.reroot(
+        &position,
+        MctsSetting::default().arena_size_for_nodes(10_000)
+    ));
+    tree.search_nodes(1000, None).unwrap();
+    assert_eq!(tree.visits(), visits + 1000);
+}
+
+fn root_policies<const S: usize>(tree: &MonteCarloTree<S>) -> Vec<(Move<S>, f16)> {
+    tree.shallow_edges()
+        .unwrap()
+        .iter()
+        .map(|edge| (edge.mv(), edge.policy()))
+        .collect()
+}
+
+#[test]
+fn reroot_same_position_test() {
+    let position = <Position<5>>::start_position();
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+    tree.search_nodes(1000, None).unwrap();
+    let priors = root_policies(&tree);
+
+    // Noise is applied to the original priors every time, instead of adding up
+    let noisy_settings = settings.clone().add_dirichlet(0.2);
+    assert!(tree.reroot(&position, noisy_settings.clone()));
+    assert_ne!(root_policies(&tree), priors);
+    assert!(tree.reroot(&position, noisy_settings));
+    assert!(tree.reroot(&position, settings.clone()));
+    assert_eq!(root_policies(&tree), priors);
+
+    // A move that is no longer excluded can be searched again
+    let excluded_move = priors[0].0;
+    assert!(tree.reroot(
+        &position,
+        settings.clone().exclude_moves(vec![excluded_move])
+    ));
+    assert!(root_policies(&tree)
+        .iter()
+        .all(|(mv, _)| *mv != excluded_move));
+    assert!(tree.reroot(&position, settings));
+    assert_eq!(root_policies(&tree), priors);
+    tree.search_nodes(1000, None).unwrap();
+}
+
+#[test]
+fn reroot_into_just_fitting_arena_test() {
+    let position = <Position<5>>::start_position();
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+    tree.search_nodes(1000, None).unwrap();
+
+    // What `from_previous` does when there is not enough memory for two full-size arenas
+    assert!(tree.reroot(&position, settings.clone().mem_usage(tree.mem_usage())));
+    assert_eq!(tree.visits(), 1000);
+    assert!(tree.reroot(&position, settings));
+    tree.search_nodes(1000, None).unwrap();
+    assert_eq!(tree.visits(), 2000);
+}
+
+#[test]
+fn reroot_unrelated_position_test() {
+    let position = <Position<5>>::start_position();
+    let settings = MctsSetting::default().arena_size_for_nodes(1000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+    tree.search_nodes(1000, None).unwrap();
+
+    let other_position = <Position<5>>::from_fen("x5/x5/x5/x5/x4,1 2 1").unwrap();
+    assert!(!tree.reroot(&other_position, settings.clone()));
+    assert_eq!(tree.visits(), 1000);
+
+    let tree = MonteCarloTree::from_previous(Some(tree), other_position, settings);
+    assert_eq!(tree.visits(), 0);
+}
+
+#[test]
+fn multi_pv_test() {
+    let position = <Position<5>>::start_position();
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(10_000, None).unwrap();
+
+    let lines = tree.multi_pv(3);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0].mv, tree.best_move().unwrap().0);
+    assert_eq!(lines[0].pv, tree.pv().collect::<Vec<_>>());
+    for line in lines.iter() {
+        assert_eq!(line.pv[0], line.mv);
+        assert!((0.0..=1.0).contains(&line.win_probability));
+    }
+    assert!(lines
+        .windows(2)
+        .all(|pair| pair[0].visits >= pair[1].visits));
+    assert_ne!(lines[0].mv, lines[1].mv);
+}
+
+#[test]
+fn proven_win_in_one_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(10_000, None).unwrap();
+
+    let win = ProvenResult {
+        result: GameResultForUs::Win,
+        plies: 1,
+    };
+    assert_eq!(tree.proven_result(), Some(win));
+    let lines = tree.multi_pv(2);
+    assert_eq!(lines[0].proven_result, Some(win));
+    assert!(["a2", "Ca2"].contains(&position.move_to_san(&lines[0].mv).as_str()));
+    assert_eq!(lines[0].mv, tree.best_move().unwrap().0);
+}
+
+#[test]
+fn proven_win_in_two_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "a5", "e5", "e4", "Cc3", "e3", "e2", "Cd3", "d2", "e1", "c4", "1e1+1", "e1", "1d3-1", "Sd1",
+    ])
+    .position();
+    let settings = MctsSetting::default().arena_size_for_nodes(200_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(200_000, None).unwrap();
+
+    assert_eq!(
+        tree.proven_result(),
+        Some(ProvenResult {
+            result: GameResultForUs::Win,
+            plies: 3
+        })
+    );
+    assert_eq!(position.move_to_san(&tree.best_move().unwrap().0), "d2>");
+}
+
+#[test]
+fn transposition_win_in_one_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    for threads in [1, 4] {
+        let settings = MctsSetting::default()
+            .arena_size_for_nodes(20_000)
+            .threads(threads)
+            .transposition_table(true);
+        let mut tree = MonteCarloTree::new(position.clone(), settings);
+        tree.search_nodes(20_000, None).unwrap();
+
+        let (best_move, score) = tree.best_move().unwrap();
+        assert!(
+            ["a2", "Ca2"].contains(&position.move_to_san(&best_move).as_str()),
+            "Played {} with score {} on {} threads",
+            best_move,
+            score,
+            threads
+        );
+    }
+}
+
+#[test]
+fn transposition_search_with_repetitions_test() {
+    // Position with several stacks that can move back and forth, so that the search graph may have cycles
+    let position = <Position<5>>::from_fen(
+        "2,x4/2,1,x,1,2S/1112,1,x,1S,x/2,121121C,x,1,x/1,x,112C,x,111212S 2 24",
+    )
+    .unwrap();
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(50_000)
+        .transposition_table(true);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(50_000, None).unwrap();
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&tree.best_move().unwrap().0));
+    assert!((0.0..=1.0).contains(&tree.mean_action_value()));
+    for line in tree.multi_pv(5) {
+        let mut pv_position = position.clone();
+        for mv in line.pv {
+            assert!(pv_position.game_result().is_none());
+            legal_moves.clear();
+            pv_position.generate_moves(&mut legal_moves);
+            assert!(legal_moves.contains(&mv));
+            pv_position.do_move(mv);
+        }
+    }
+}
+
+#[test]
+fn transposition_reroot_test() {
+    let mut position = <Position<5>>::start_position();
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(20_000)
+        .transposition_table(true);
+    let mut tree = MonteCarloTree::new(position.clone(), settings.clone());
+    tree.search_nodes(10_000, None).unwrap();
+
+    for mv in tree.pv().take(2).collect::<Vec<_>>() {
+        position.do_move(mv);
+    }
+    // The tree cannot be re-used without the transposition table
+    assert!(!tree.reroot(&position, settings.clone().transposition_table(false)));
+
+    assert!(tree.reroot(&position, settings));
+    let visits = tree.visits();
+    assert!(visits > 0);
+    tree.search_nodes(1000, None).unwrap();
+    assert_eq!(tree.visits(), visits + 1000);
+
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&tree.best_move().unwrap().0));
+}
+
 #[test]
 fn play_on_low_time() {
     let time = Duration::from_millis(5);
@@ -0,0 +1,86 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use board_game_traits::Position as PositionTrait;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::mock_playtak::{ClientMessage, GameStart, MockPlaytakServer};
+use crate::position::{Move, Position};
+
+/// A minimal client, which seeks a 3s game, and plays the first legal move every turn
+fn run_simple_client(port: u16) -> Vec<String> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut lines = vec![];
+    let mut position = <Position<3>>::start_position();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap() == 0 {
+            return lines;
+        }
+        let line = line.trim().to_string();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["Login", ..] => writeln!(writer, "Login Guest").unwrap(),
+            ["Welcome", name] if name.starts_with("Guest") => {
+                writeln!(writer, "PING").unwrap();
+                writeln!(writer, "Seek 3 10 0 W 0 10 0 0 0 ").unwrap();
+            }
+            ["Game#1", "P" | "M", ..] => {
+                position.do_move(Move::from_string_playtak(&words[1..].join(" ")));
+            }
+            ["Game#1", "Over", ..] => {
+                lines.push(line);
+                return lines;
+            }
+            _ => (),
+        }
+        if words[0].starts_with("Game")
+            && position.side_to_move() == board_game_traits::Color::White
+            && position.game_result().is_none()
+        {
+            let mut moves = vec![];
+            position.generate_moves(&mut moves);
+            writeln!(writer, "Game#1 {}", moves[0].to_string_playtak()).unwrap();
+            position.do_move(moves[0]);
+        }
+        lines.push(line);
+    }
+}
+
+#[test]
+fn play_random_game_test() {
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let port = server.local_addr().unwrap().port();
+    let client_thread = thread::spawn(move || run_simple_client(port));
+
+    let mut client = server.accept().unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    assert_eq!(client.name(), "Guest1");
+    let seek = match client.read_message().unwrap() {
+        ClientMessage::Seek(seek) => seek,
+        message => panic!("Expected seek, got {:?}", message),
+    };
+    assert_eq!(seek.size, 3);
+    assert_eq!(seek.time, Duration::from_secs(10));
+
+    let game = GameStart::from_seek(1, &seek, client.name(), "RandomBot");
+    assert_eq!(game.white_player, "Guest1");
+    let result = client
+        .play_random_game::<3, _>(&game, &mut StdRng::seed_from_u64(0))
+        .unwrap();
+
+    let lines = client_thread.join().unwrap();
+    assert!(lines.contains(&"OK".to_string()), "{:?}", lines);
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("Game Start 1 3 Guest1 vs RandomBot white 10 0")));
+    assert!(lines.iter().any(|line| line.starts_with("Game#1 Time ")));
+    assert_eq!(lines.last().unwrap(), &format!("Game#1 Over {}", result));
+}
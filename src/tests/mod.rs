@@ -1,16 +1,37 @@
+mod alpha_beta_tests;
 mod arena_tests;
 mod blunder_tests;
 mod board_generic_tests;
 mod board_tests;
+mod explain_tests;
+mod game_analysis_tests;
+mod group_data_tests;
 mod komi_policy_tests;
+#[cfg(feature = "constant-tuning")]
+mod match_runner_tests;
 mod mcts_tests;
+mod mock_playtak_tests;
 mod move_gen_5s_tests;
 mod move_gen_6s_tests;
 mod move_gen_generic_tests;
+mod network_tests;
+mod opening_book_tests;
+mod parameter_file_tests;
+mod parameter_generation_tests;
+mod perft_tests;
 mod policy_tests;
+mod position_encoding_tests;
 mod ptn_tests;
+#[cfg(feature = "serde")]
+mod serde_tests;
+mod solver_tests;
 mod tactics_tests_5s;
 mod tactics_tests_6s;
+mod time_tests;
+#[cfg(feature = "constant-tuning")]
+mod training_data_tests;
+mod transposition_table_tests;
+mod tree_file_tests;
 
 use crate::evaluation::parameters::{IncrementalPolicy, PolicyApplier};
 use crate::position::{Komi, Move, Position};
@@ -1,4 +1,4 @@
-use crate::position::Position;
+use crate::position::{Move, Position};
 use board_game_traits::Position as PositionTrait;
 
 #[test]
@@ -38,3 +38,34 @@ fn start_position_move_gen_prop<const S: usize>() {
         position.reverse_move(reverse_move);
     }
 }
+
+#[test]
+fn move_u16_roundtrip_test() {
+    move_u16_roundtrip_prop::<3>();
+    move_u16_roundtrip_prop::<4>();
+    move_u16_roundtrip_prop::<5>();
+    move_u16_roundtrip_prop::<6>();
+    move_u16_roundtrip_prop::<7>();
+    move_u16_roundtrip_prop::<8>();
+}
+
+fn move_u16_roundtrip_prop<const S: usize>() {
+    let mut position = <Position<S>>::default();
+    let mut moves = vec![];
+    for _ in 0..4 {
+        position.generate_moves(&mut moves);
+        for mv in moves.iter() {
+            assert_eq!(Move::from_u16(mv.to_u16()), Some(*mv));
+        }
+        position.do_move(moves[moves.len() / 2]);
+        moves.clear();
+    }
+    // Any data that is accepted must be a move on the board
+    for data in 0..=u16::MAX {
+        if let Some(mv) = <Move<S>>::from_u16(data) {
+            assert_eq!(mv.to_u16(), data);
+            assert_eq!(Move::compress(mv.expand()), mv);
+            mv.destination_square();
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use rand::SeedableRng;
+
+use crate::evaluation::network::{
+    num_network_params, random_network_params, Network, NetworkFile, NetworkKind, NetworkValue,
+};
+use crate::evaluation::parameters::{num_policy_features, num_value_features};
+use crate::position::Position;
+use crate::search::{MctsSetting, MonteCarloTree};
+
+#[test]
+fn forward_test() {
+    #[rustfmt::skip]
+    let params = [
+        1.0, 2.0, -1.0, 0.5, // Hidden weights
+        0.0, 0.5, // Hidden biases
+        2.0, -3.0, // Output weights
+        0.25, // Output bias
+    ];
+    let network = Network::new(&params, 2).unwrap();
+    assert_eq!(network.num_hidden(), 2);
+    assert_eq!(network.forward(&[1.0, 1.0]), 6.25);
+    assert_eq!(network.forward(&[1.0, -2.0]), 0.25);
+    assert_eq!(network.forward(&[-1.0, 2.0]), 0.25 + 2.0 * 3.0 - 3.0 * 2.5);
+
+    assert!(Network::new(&params[1..], 2).is_none());
+    assert!(Network::new(&[], 2).is_none());
+}
+
+#[test]
+fn network_file_round_trip_test() {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let network_file = NetworkFile {
+        kind: NetworkKind::Policy,
+        size: 5,
+        params: random_network_params(num_policy_features::<5>(), 4, &mut rng),
+    };
+    let mut data = vec![];
+    network_file.write(&mut data).unwrap();
+    let read_network_file = NetworkFile::read(data.as_slice()).unwrap();
+    assert_eq!(read_network_file, network_file);
+    assert_eq!(read_network_file.network().num_hidden(), 4);
+
+    // The header says 5 hidden neurons, but there are parameters for 4
+    let text = String::from_utf8(data).unwrap();
+    let wrong_text = text.replacen("policy 5 4", "policy 5 5", 1);
+    assert!(NetworkFile::read(wrong_text.as_bytes()).is_err());
+    // Value networks for 5s have a different number of inputs
+    let wrong_text = text.replacen("policy 5 4", "value 5 4", 1);
+    assert!(NetworkFile::read(wrong_text.as_bytes()).is_err());
+    assert!(NetworkFile::read("tiltak-network policy 9 4\n".as_bytes()).is_err());
+}
+
+#[test]
+fn search_with_networks_test() {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let value_network: &'static [f32] =
+        random_network_params(num_value_features::<5>(), 8, &mut rng).leak();
+    let policy_network: &'static [f32] =
+        random_network_params(num_policy_features::<5>(), 8, &mut rng).leak();
+    assert_eq!(
+        value_network.len(),
+        num_network_params(num_value_features::<5>(), 8)
+    );
+
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(5000)
+        .add_value_network(value_network)
+        .add_policy_network(policy_network);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(5000, None).unwrap();
+
+    let (best_move, _score) = tree.best_move().unwrap();
+    let mut legal_moves = vec![];
+    position.generate_moves(&mut legal_moves);
+    assert!(legal_moves.contains(&best_move));
+    assert!(position.static_eval_with_network(value_network).is_finite());
+}
+
+#[test]
+fn reused_value_network_buffers_test() {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let value_network: &'static [f32] =
+        random_network_params(num_value_features::<5>(), 8, &mut rng).leak();
+
+    let mut network_value = NetworkValue::default();
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+        let eval = position.static_eval_with_network_and_data(
+            &position.group_data(),
+            value_network,
+            &mut network_value,
+        );
+        assert_eq!(eval, position.static_eval_with_network(value_network));
+    }
+}
+
+#[test]
+#[should_panic]
+fn network_for_wrong_size_test() {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let value_network: &'static [f32] =
+        random_network_params(num_value_features::<5>(), 8, &mut rng).leak();
+    let _settings = MctsSetting::<6>::default().add_value_network(value_network);
+}
+
+#[cfg(feature = "constant-tuning")]
+#[test]
+fn train_network_test() {
+    use half::f16;
+
+    use crate::evaluation::value_eval;
+    use crate::tune::gradient_descent::TrainingSample;
+    use crate::tune::network_training;
+
+    // XOR of the first two features, which no linear evaluation can learn
+    let samples: Vec<TrainingSample<8>> = [
+        (0.0, 0.0, 0.0),
+        (0.0, 1.0, 1.0),
+        (1.0, 0.0, 1.0),
+        (1.0, 1.0, 0.0),
+    ]
+    .iter()
+    .map(|&(a, b, result)| {
+        let mut features = [f16::ZERO; 8];
+        features[0] = f16::from_f32(a);
+        features[1] = f16::from_f32(b);
+        features[2] = f16::ONE;
+        TrainingSample {
+            features,
+            offset: 0.0,
+            result: f16::from_f32(result),
+        }
+    })
+    .collect();
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let initial_params = random_network_params(8, 8, &mut rng);
+    let initial_error =
+        network_training::average_error(&samples, &initial_params, &value_eval::sigmoid);
+    let params = network_training::train_network(
+        &samples,
+        &initial_params,
+        1.0,
+        &mut rng,
+        &value_eval::sigmoid,
+        &value_eval::sigmoid_derived,
+    );
+    let error = network_training::average_error(&samples, &params, &value_eval::sigmoid);
+    assert!(
+        error < initial_error / 2.0,
+        "Error {} after training, initial error {}",
+        error,
+        initial_error
+    );
+}
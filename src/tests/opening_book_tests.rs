@@ -0,0 +1,207 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::opening_book::{canonical_hash, OpeningBook, OpeningBookBuilder};
+use crate::position::{Komi, Move, Position};
+use crate::ptn::ptn_parser;
+use crate::search::{MctsSetting, MonteCarloTree};
+
+const GAMES: &str = r#"[Size "5"]
+[Komi "2"]
+[Result "R-0"]
+
+1. a1 e5 2. c3 d4 3. c4 d3 4. c2 d2 5. c1 R-0
+
+[Size "5"]
+[Komi "2"]
+[Result "0-R"]
+
+1. a1 e5 2. b3 c3 3. b4 b2 4. b5 d3 5. e4 d2 6. d4 d1 0-R
+
+[Size "5"]
+[Komi "0"]
+[Result "R-0"]
+
+1. e5 a1 2. b2 c2 3. b3 c3 4. b4 c4 5. b5 R-0
+"#;
+
+fn moves_from_san<const S: usize>(position: &Position<S>, move_strings: &[&str]) -> Vec<Move<S>> {
+    let mut position = position.clone();
+    move_strings
+        .iter()
+        .map(|move_string| {
+            let mv = position.move_from_san(move_string).unwrap();
+            position.do_move(mv);
+            mv
+        })
+        .collect()
+}
+
+#[test]
+fn canonical_hash_test() {
+    let start_position = <Position<5>>::start_position();
+    let hashes: Vec<u64> = [["a1", "e5"], ["e5", "a1"], ["a5", "e1"], ["e1", "a5"]]
+        .iter()
+        .map(|line| {
+            let mut position = start_position.clone();
+            for mv in moves_from_san(&start_position, line) {
+                position.do_move(mv);
+            }
+            canonical_hash(&position)
+        })
+        .collect();
+    assert!(hashes.iter().all(|hash| *hash == hashes[0]));
+
+    let mut position = start_position.clone();
+    for mv in moves_from_san(&start_position, &["a1", "c3"]) {
+        position.do_move(mv);
+    }
+    assert_ne!(canonical_hash(&position), hashes[0]);
+}
+
+#[test]
+fn book_from_games_test() {
+    let komi = Komi::from_half_komi(4).unwrap();
+    let games = ptn_parser::parse_ptn::<Position<5>>(GAMES).unwrap();
+    let mut builder = OpeningBookBuilder::<5>::new(komi).max_plies(4);
+    assert!(builder.add_game(&games[0]));
+    assert!(builder.add_game(&games[1]));
+    // Wrong komi
+    assert!(!builder.add_game(&games[2]));
+    let book = builder.build();
+
+    let start_position = <Position<5>>::start_position_with_komi(komi);
+    // Both winners opened in a corner, and all corners are the same move
+    let book_moves = book.moves(&start_position);
+    assert_eq!(book_moves.len(), 1);
+    assert_eq!(book_moves[0].weight, 2);
+    assert_eq!(
+        canonical_hash(&{
+            let mut position = start_position.clone();
+            position.do_move(book_moves[0].mv);
+            position
+        }),
+        canonical_hash(&{
+            let mut position = start_position.clone();
+            position.do_move(position.move_from_san("a1").unwrap());
+            position
+        })
+    );
+
+    // After 1. a1 e5, white's winning move c3 is in the book, but black's winning game has its own moves
+    let mut position = start_position.clone();
+    for mv in moves_from_san(&start_position, &["a1", "e5"]) {
+        position.do_move(mv);
+    }
+    let book_moves = book.moves(&position);
+    assert_eq!(book_moves.len(), 1);
+    assert_eq!(position.move_to_san(&book_moves[0].mv), "c3");
+
+    // Only the winner's moves are added
+    position.do_move(book_moves[0].mv);
+    assert!(book.moves(&position).is_empty());
+
+    // Moves past the ply limit are not added
+    let mut position = start_position.clone();
+    for mv in moves_from_san(&start_position, &["a1", "e5", "c3", "d4"]) {
+        position.do_move(mv);
+    }
+    assert!(book.moves(&position).is_empty());
+
+    // The book is only for its own komi
+    assert!(book.moves(&<Position<5>>::start_position()).is_empty());
+}
+
+#[test]
+fn book_probe_test() {
+    let start_position = <Position<6>>::start_position();
+    let mut builder = OpeningBookBuilder::<6>::new(Komi::default());
+    builder.add_line(
+        &start_position,
+        &moves_from_san(&start_position, &["a1", "f6", "c3"]),
+        3,
+    );
+    builder.add_line(
+        &start_position,
+        &moves_from_san(&start_position, &["a1", "f6", "d4"]),
+        1,
+    );
+    let book = builder.build();
+
+    let mut position = start_position.clone();
+    for mv in moves_from_san(&start_position, &["a1", "f6"]) {
+        position.do_move(mv);
+    }
+    let book_moves = book.moves(&position);
+    assert_eq!(book_moves.len(), 2);
+    assert_eq!(position.move_to_san(&book_moves[0].mv), "c3");
+    assert_eq!(book_moves[0].weight, 3);
+    assert_eq!(position.move_to_san(&book_moves[1].mv), "d4");
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let probes: Vec<Move<6>> = (0..1000)
+        .map(|_| book.probe(&position, &mut rng).unwrap())
+        .collect();
+    let c3_probes = probes
+        .iter()
+        .filter(|mv| position.move_to_san(mv) == "c3")
+        .count();
+    assert!((650..850).contains(&c3_probes), "{}", c3_probes);
+
+    // Positions outside the book, and books for other sizes, give no moves
+    position.do_move(book_moves[0].mv);
+    assert_eq!(book.probe(&position, &mut rng), None);
+    assert!(book.moves(&<Position<5>>::start_position()).is_empty());
+}
+
+#[test]
+fn book_from_tree_test() {
+    let mut position = <Position<5>>::start_position();
+    for mv in moves_from_san(&position.clone(), &["a1", "e5"]) {
+        position.do_move(mv);
+    }
+    let settings = MctsSetting::default().arena_size_for_nodes(5_000);
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(5_000, None).unwrap();
+
+    let mut builder = OpeningBookBuilder::<5>::new(Komi::default()).min_weight(100);
+    builder.add_tree(&tree);
+    let book = builder.build();
+
+    let book_moves = book.moves(&position);
+    assert!(!book_moves.is_empty());
+    assert!(book_moves.iter().all(|book_move| book_move.weight >= 100));
+    // The position is symmetrical, so the book move may be a mirror image of the best move
+    let child_hash = |mv| {
+        let mut child = position.clone();
+        child.do_move(mv);
+        canonical_hash(&child)
+    };
+    assert_eq!(
+        child_hash(book_moves[0].mv),
+        child_hash(tree.best_move().unwrap().0)
+    );
+}
+
+#[test]
+fn write_and_read_book_test() {
+    let start_position = <Position<5>>::start_position_with_komi(Komi::from_half_komi(4).unwrap());
+    let mut builder = OpeningBookBuilder::<5>::new(start_position.komi());
+    builder.add_line(
+        &start_position,
+        &moves_from_san(&start_position, &["a1", "e5", "c3", "c2"]),
+        5,
+    );
+    let book = builder.build();
+    assert_eq!(book.len(), 4);
+
+    let mut data = vec![];
+    book.write(&mut data).unwrap();
+    let read_book = OpeningBook::read(data.as_slice()).unwrap();
+    assert_eq!(read_book, book);
+
+    data[0] = b'X';
+    assert!(OpeningBook::read(data.as_slice()).is_err());
+}
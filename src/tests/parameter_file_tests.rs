@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+
+use board_game_traits::{EvalPosition, Position as PositionTrait};
+
+use crate::evaluation::parameter_file::{ParameterFile, ParameterKind, VERSION};
+use crate::evaluation::parameters::num_policy_features;
+use crate::position::{Komi, Position};
+use crate::search::{MctsSetting, MonteCarloTree};
+
+fn built_in_parameter_file(kind: ParameterKind, komi: Komi) -> ParameterFile {
+    let params = match kind {
+        ParameterKind::Value => <Position<5>>::value_params(komi),
+        ParameterKind::Policy => <Position<5>>::policy_params(komi),
+    };
+    ParameterFile {
+        kind,
+        size: 5,
+        komi,
+        params: params.to_vec(),
+    }
+}
+
+#[test]
+fn parameter_file_round_trip_test() {
+    let komi = Komi::from_half_komi(4).unwrap();
+    for kind in [ParameterKind::Value, ParameterKind::Policy] {
+        let parameter_file = built_in_parameter_file(kind, komi);
+        let mut data = vec![];
+        parameter_file.write(&mut data).unwrap();
+        let read_parameter_file = ParameterFile::read(data.as_slice()).unwrap();
+        assert_eq!(read_parameter_file, parameter_file);
+
+        assert_eq!(
+            read_parameter_file.params_for::<5>(komi),
+            Some(parameter_file.params.as_slice())
+        );
+        assert_eq!(read_parameter_file.params_for::<5>(Komi::default()), None);
+        assert_eq!(read_parameter_file.params_for::<6>(komi), None);
+    }
+}
+
+#[test]
+fn invalid_parameter_file_test() {
+    let parameter_file = built_in_parameter_file(ParameterKind::Policy, Komi::default());
+    let mut data = vec![];
+    parameter_file.write(&mut data).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    let header = format!(
+        "tiltak-params {} policy 5 0 {}",
+        VERSION,
+        num_policy_features::<5>()
+    );
+    assert!(text.starts_with(&header));
+
+    // One parameter too few
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.pop();
+    assert!(ParameterFile::read(lines.join("\n").as_bytes()).is_err());
+
+    for wrong_header in [
+        format!(
+            "tiltak-params {} policy 5 0 {}",
+            VERSION + 1,
+            num_policy_features::<5>()
+        ),
+        format!(
+            "tiltak-params {} value 5 0 {}",
+            VERSION,
+            num_policy_features::<5>()
+        ),
+        format!(
+            "tiltak-params {} policy 6 0 {}",
+            VERSION,
+            num_policy_features::<5>()
+        ),
+        format!(
+            "tiltak-params {} policy 5 11 {}",
+            VERSION,
+            num_policy_features::<5>()
+        ),
+        format!("tiltak-params {} policy 5 0", VERSION),
+    ] {
+        let wrong_text = text.replacen(&header, &wrong_header, 1);
+        assert!(
+            ParameterFile::read(wrong_text.as_bytes()).is_err(),
+            "Read parameter file with header {}",
+            wrong_header
+        );
+    }
+}
+
+#[test]
+fn load_parameter_file_test() -> io::Result<()> {
+    let parameter_file = built_in_parameter_file(ParameterKind::Value, Komi::default());
+    let path = std::env::temp_dir().join(format!(
+        "tiltak_load_parameter_file_test_{}.txt",
+        std::process::id()
+    ));
+    let mut file = std::fs::File::create(&path)?;
+    parameter_file.write(&mut file)?;
+    file.flush()?;
+
+    assert_eq!(
+        ParameterFile::load(&path, ParameterKind::Value)?,
+        parameter_file
+    );
+    assert!(ParameterFile::load(&path, ParameterKind::Policy).is_err());
+    std::fs::remove_file(&path)
+}
+
+#[test]
+fn search_with_loaded_params_test() {
+    let mut parameter_file = built_in_parameter_file(ParameterKind::Value, Komi::default());
+    // Only value the first move, so that every other position is evaluated as equal
+    for param in parameter_file.params.iter_mut().skip(1) {
+        *param = 0.0;
+    }
+    let parameter_file: &'static ParameterFile = Box::leak(Box::new(parameter_file));
+    let value_params = parameter_file.params_for::<5>(Komi::default()).unwrap();
+
+    let mut position = <Position<5>>::start_position();
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    position.do_move(moves[0]);
+    position.generate_moves(&mut moves);
+    position.do_move(moves[1]);
+    assert_eq!(position.static_eval_with_params(value_params), 0.0);
+    assert_ne!(position.static_eval(), 0.0);
+
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(1000)
+        .add_value_params(value_params);
+    let mut tree = MonteCarloTree::new(position, settings);
+    tree.search_nodes(1000, None).unwrap();
+    assert!(tree.best_move().is_some());
+}
@@ -0,0 +1,78 @@
+use board_game_traits::Position as PositionTrait;
+
+use crate::evaluation::parameters::{
+    self, generate_policy_params, generate_value_params, policy_indexes, value_indexes,
+    NUM_POLICY_FEATURES_4S, NUM_POLICY_FEATURES_5S, NUM_POLICY_FEATURES_6S, NUM_VALUE_FEATURES_4S,
+    NUM_VALUE_FEATURES_5S, NUM_VALUE_FEATURES_6S,
+};
+use crate::position::{Komi, Position};
+use crate::search;
+
+#[test]
+fn generate_params_from_same_size_test() {
+    let komi = Komi::from_half_komi(4).unwrap();
+    generate_params_from_same_size_prop::<4, NUM_VALUE_FEATURES_4S, NUM_POLICY_FEATURES_4S>(
+        parameters::value_features_4s(komi),
+        parameters::policy_features_4s(komi),
+    );
+    generate_params_from_same_size_prop::<5, NUM_VALUE_FEATURES_5S, NUM_POLICY_FEATURES_5S>(
+        parameters::value_features_5s(komi),
+        parameters::policy_features_5s(komi),
+    );
+    generate_params_from_same_size_prop::<6, NUM_VALUE_FEATURES_6S, NUM_POLICY_FEATURES_6S>(
+        parameters::value_features_6s(komi),
+        parameters::policy_features_6s(komi),
+    );
+}
+
+/// Generating parameters from the same size should reproduce them exactly, except for the padding
+fn generate_params_from_same_size_prop<const S: usize, const V: usize, const P: usize>(
+    value_params: &[f32; V],
+    policy_params: &[f32; P],
+) {
+    let mut expected_value_params = *value_params;
+    let padding = value_indexes::<S>().padding;
+    padding.as_mut_slice(&mut expected_value_params).fill(0.0);
+    padding
+        .as_mut_slice(&mut expected_value_params[V / 2..])
+        .fill(0.0);
+    assert_eq!(
+        generate_value_params::<S, S, V>(value_params),
+        expected_value_params
+    );
+
+    let mut expected_policy_params = *policy_params;
+    policy_indexes::<S>()
+        .padding
+        .as_mut_slice(&mut expected_policy_params)
+        .fill(0.0);
+    assert_eq!(
+        generate_policy_params::<S, S, P>(policy_params),
+        expected_policy_params
+    );
+}
+
+#[test]
+fn search_generated_sizes_test() {
+    search_generated_size_prop::<3>();
+    search_generated_size_prop::<7>();
+    search_generated_size_prop::<8>();
+}
+
+fn search_generated_size_prop<const S: usize>() {
+    for half_komi in [0, 4] {
+        let mut position: Position<S> =
+            Position::start_position_with_komi(Komi::from_half_komi(half_komi).unwrap());
+        for _ in 0..6 {
+            if position.game_result().is_some() {
+                break;
+            }
+            let (best_move, score) = search::mcts(position.clone(), 500);
+            let mut legal_moves = vec![];
+            position.generate_moves(&mut legal_moves);
+            assert!(legal_moves.contains(&best_move));
+            assert!((0.0..=1.0).contains(&score), "Bad score {}", score);
+            position.do_move(best_move);
+        }
+    }
+}
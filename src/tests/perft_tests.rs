@@ -0,0 +1,107 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::perft::{reference_suite, Perft, PerftSetting};
+use crate::position::Position;
+
+#[test]
+fn reference_suite_test() {
+    let suite = reference_suite();
+    for size in 3..=8 {
+        assert!(suite.iter().any(|reference| reference.size == size));
+    }
+    let perft = Perft::new(PerftSetting::default().threads(4));
+    for reference in suite {
+        let mismatches = reference.check(&perft, 1_000_000);
+        assert!(
+            mismatches.is_empty(),
+            "Wrong perft results for {}: {:?}",
+            reference.tps,
+            mismatches
+        );
+    }
+}
+
+/// Check perft against `Position::bulk_perft`, with and without threads and the hash table
+fn perft_matches_bulk_perft_prop<const S: usize>(mut position: Position<S>, depth: u16) {
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    let expected_divide: Vec<_> = moves
+        .into_iter()
+        .map(|mv| {
+            let reverse_move = position.do_move(mv);
+            let nodes = position.bulk_perft(depth - 1);
+            position.reverse_move(reverse_move);
+            (mv, nodes)
+        })
+        .collect();
+
+    for settings in [
+        PerftSetting::default(),
+        PerftSetting::default().threads(3),
+        PerftSetting::default().threads(3).mem_usage(0),
+        // A tiny hash table, where most entries are overwritten
+        PerftSetting::default().threads(3).mem_usage(256),
+    ] {
+        let perft = Perft::new(settings);
+        assert_eq!(perft.divide(&position, depth), expected_divide);
+        assert_eq!(perft.perft(&position, depth), position.bulk_perft(depth));
+        // Again, with the hash table already filled
+        assert_eq!(perft.perft(&position, depth), position.bulk_perft(depth));
+    }
+}
+
+#[test]
+fn perft_matches_bulk_perft_test() {
+    perft_matches_bulk_perft_prop(<Position<4>>::start_position(), 4);
+    perft_matches_bulk_perft_prop(
+        <Position<5>>::from_fen("x5/x5/x2,121212C,x2/1,x4/1,x4 2 7").unwrap(),
+        3,
+    );
+    perft_matches_bulk_perft_prop(
+        <Position<6>>::from_fen(
+            "x,2,2,1S,1,1S/2,x3,2S,2S/2,x2,1C,2S,11S/12C,x2,2S,x2/x2,2S,2,x,1S/x4,1,1S 1 16",
+        )
+        .unwrap(),
+        3,
+    );
+}
+
+#[test]
+fn perft_with_repetitions_test() {
+    let mut position = <Position<3>>::from_fen("2,x,1/x3/1,x,2 1 3").unwrap();
+    // Repeat the start position, so that repeating it once more is a draw
+    for move_string in ["a1+", "c1+", "a2-", "c2-"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    assert_eq!(position.repetitions(), 1);
+    assert!(!position.after_irreversible_move());
+    // Without the history, there are no repetition draws
+    let position_without_history = <Position<3>>::from_fen(&position.to_fen()).unwrap();
+    assert_eq!(position_without_history, position);
+    assert_ne!(
+        position_without_history.clone().bulk_perft(5),
+        position.bulk_perft(5)
+    );
+
+    let perft = Perft::new(PerftSetting::default().threads(2));
+    for depth in 0..=5 {
+        assert_eq!(
+            perft.perft(&position, depth),
+            position.bulk_perft(depth),
+            "Wrong perft result at depth {}",
+            depth
+        );
+    }
+    perft_matches_bulk_perft_prop(position, 5);
+}
+
+#[test]
+fn divide_game_over_test() {
+    let position = <Position<4>>::from_fen("1,1,1,1/2,2,2,x/x4/x4 2 4").unwrap();
+    let perft = Perft::new(PerftSetting::default());
+    assert!(position.game_result().is_some());
+    assert!(perft.divide(&position, 2).is_empty());
+    assert_eq!(perft.perft(&position, 0), 1);
+    assert_eq!(perft.perft(&position, 2), 0);
+}
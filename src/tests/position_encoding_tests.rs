@@ -0,0 +1,168 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::position::{Komi, Move, Position};
+use crate::tests::do_moves_and_check_validity;
+
+fn assert_encoding_round_trips<const S: usize>(position: &Position<S>) {
+    let bytes = position.encode();
+    assert_eq!(bytes.len(), <Position<S>>::ENCODED_SIZE);
+    let decoded = <Position<S>>::decode(&bytes).unwrap();
+    assert_eq!(
+        decoded,
+        *position,
+        "Wrong position for {}",
+        position.to_fen()
+    );
+    assert!(decoded.moves().is_empty());
+}
+
+fn assert_history_round_trips<const S: usize>(position: &Position<S>) {
+    assert_encoding_round_trips(position);
+    let decoded = <Position<S>>::decode_with_history(&position.encode_with_history()).unwrap();
+    assert_eq!(
+        decoded,
+        *position,
+        "Wrong position for {}",
+        position.to_fen()
+    );
+    assert_eq!(decoded.moves(), position.moves());
+    assert_eq!(decoded.zobrist_hash(), position.zobrist_hash());
+    assert_eq!(decoded.repetitions(), position.repetitions());
+}
+
+fn random_games_prop<const S: usize>(num_games: usize) {
+    let mut rng = StdRng::seed_from_u64(S as u64);
+    let mut moves = vec![];
+    for i in 0..num_games {
+        let komi = Komi::from_half_komi(i as i8 % 5).unwrap();
+        let mut position = <Position<S>>::start_position_with_komi(komi);
+        while position.game_result().is_none() && position.half_moves_played() < 200 {
+            assert_history_round_trips(&position);
+            moves.clear();
+            position.generate_moves(&mut moves);
+            position.do_move(moves[rng.gen_range(0..moves.len())]);
+        }
+        assert_history_round_trips(&position);
+        // The symmetries keep the original move history, so only the positions themselves are encoded
+        for symmetry in position.symmetries_with_swapped_colors() {
+            assert_encoding_round_trips(&symmetry);
+        }
+    }
+}
+
+#[test]
+fn encoding_random_games_3s_test() {
+    random_games_prop::<3>(50)
+}
+
+#[test]
+fn encoding_random_games_4s_test() {
+    random_games_prop::<4>(30)
+}
+
+#[test]
+fn encoding_random_games_5s_test() {
+    random_games_prop::<5>(20)
+}
+
+#[test]
+fn encoding_random_games_6s_test() {
+    random_games_prop::<6>(10)
+}
+
+#[test]
+fn encoding_random_games_7s_test() {
+    random_games_prop::<7>(10)
+}
+
+#[test]
+fn encoding_random_games_8s_test() {
+    random_games_prop::<8>(10)
+}
+
+#[test]
+fn encoded_size_test() {
+    assert_eq!(<Position<3>>::ENCODED_SIZE, 21);
+    assert_eq!(<Position<6>>::ENCODED_SIZE, 59);
+    assert_eq!(<Position<8>>::ENCODED_SIZE, 98);
+}
+
+#[test]
+fn encode_history_with_flattened_wall_test() {
+    let start_position = <Position<5>>::from_fen("x5/x2,2S,x2/x2,1C,x2/x5/2,x3,1 1 3").unwrap();
+    let mut position = start_position.clone();
+    do_moves_and_check_validity(&mut position, &["c3+", "a1>"]);
+    assert_history_round_trips(&position);
+
+    // The history starts with the wall still standing
+    let bytes = position.encode_with_history();
+    assert_eq!(
+        <Position<5>>::decode(&bytes[..<Position<5>>::ENCODED_SIZE]).unwrap(),
+        start_position
+    );
+}
+
+#[test]
+fn encode_history_with_repetitions_test() {
+    let mut position = <Position<3>>::from_fen("2,x,1/x3/1,x,2 1 3").unwrap();
+    do_moves_and_check_validity(&mut position, &["a1+", "c1+", "a2-", "c2-"]);
+    assert_eq!(position.repetitions(), 1);
+    assert_history_round_trips(&position);
+}
+
+#[test]
+fn decode_invalid_position_test() {
+    let position = <Position<5>>::from_fen("2,x4/x,1S,x3/x,1C,2,x2/x5/x5 2 3").unwrap();
+    let bytes = position.encode();
+    assert_eq!(<Position<5>>::decode(&bytes).unwrap(), position);
+    assert!(<Position<4>>::decode(&bytes).is_err());
+    assert!(<Position<5>>::decode(&bytes[1..]).is_err());
+
+    // Bytes 5..30 are the stack heights, followed by 4 bytes each for the walls and capstones, and 6 bytes of colors
+    let mut invalid_bytes = vec![];
+    for (i, byte) in [
+        (0, 6),     // Wrong size
+        (1, 2),     // Invalid side to move
+        (2, 11),    // Too much komi
+        (5, 65),    // Stack too high
+        (30, 2),    // Wall on an empty square
+        (30, 0xc0), // Wall and capstone on the same square
+        (33, 0x80), // Padding bit set
+        (43, 0x80), // Unused color bit set
+    ] {
+        let mut bytes = bytes.clone();
+        bytes[i] = byte;
+        invalid_bytes.push(bytes);
+    }
+    let mut too_many_pieces = bytes.clone();
+    too_many_pieces[5] = 30;
+    invalid_bytes.push(too_many_pieces);
+
+    for bytes in invalid_bytes {
+        assert!(
+            <Position<5>>::decode(&bytes).is_err(),
+            "Decoded invalid position {:?}",
+            bytes
+        );
+    }
+}
+
+#[test]
+fn decode_illegal_history_test() {
+    let mut position = <Position<5>>::start_position();
+    do_moves_and_check_validity(&mut position, &["a5", "e1", "c3"]);
+    let bytes = position.encode_with_history();
+    assert_eq!(bytes.len(), <Position<5>>::ENCODED_SIZE + 2 + 3 * 2);
+
+    // Replace the last move with a placement on an occupied square
+    let mut illegal_bytes = bytes.clone();
+    let mv = <Move<5>>::from_string("e1").unwrap().to_u16().to_le_bytes();
+    illegal_bytes[bytes.len() - 2..].copy_from_slice(&mv);
+    assert!(<Position<5>>::decode_with_history(&illegal_bytes).is_err());
+
+    assert!(<Position<5>>::decode_with_history(&bytes[..bytes.len() - 1]).is_err());
+    assert!(<Position<5>>::decode_with_history(&position.encode()).is_err());
+}
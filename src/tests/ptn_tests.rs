@@ -19,6 +19,7 @@ pub fn write_and_read_ptn_test() {
             mv: *mv,
             annotations: vec![],
             comment: "".to_string(),
+            variations: vec![],
         })
         .collect();
 
@@ -34,6 +35,7 @@ pub fn write_and_read_ptn_test() {
             ("Result".to_string(), "1-0".to_string()),
             ("Round".to_string(), "1".to_string()),
         ],
+        comment: String::new(),
     };
 
     let mut ptn_writer = Cursor::new(vec![]);
@@ -58,6 +60,7 @@ pub fn custom_start_position_test() {
         mv: position.move_from_san("e6").unwrap(),
         annotations: vec![],
         comment: String::new(),
+        variations: vec![],
     }];
 
     let mut game: Game<Position<6>> = Game {
@@ -72,6 +75,7 @@ pub fn custom_start_position_test() {
             ("Result".to_string(), "1-0".to_string()),
             ("Round".to_string(), "1".to_string()),
         ],
+        comment: String::new(),
     };
 
     let mut ptn_writer = Cursor::new(vec![]);
@@ -108,6 +112,86 @@ fn parse_ptn_without_result2() {
     assert_eq!(games[1].game_result(), None)
 }
 
+const PTN_WITH_VARIATIONS: &str = r#"[Player1 "tiltak"]
+[Player2 "tiltak"]
+[Date "2021.03.06"]
+[Size "6"]
+[Result "R-0"]
+
+{Opening comment} 1. a1 f6 {Common} {reply} (1... a6 2. c3 (2. f1) 2... c4) 2. c3 d4'
+(2... d3 {Also good}) 3. c4 R-0
+"#;
+
+#[test]
+fn parse_variations_test() {
+    let games: Vec<Game<Position<6>>> = ptn_parser::parse_ptn(PTN_WITH_VARIATIONS).unwrap();
+    assert_eq!(games.len(), 1);
+    let game = &games[0];
+    assert_eq!(game.comment, "Opening comment");
+    assert_eq!(game.moves.len(), 5);
+    assert_eq!(game.game_result_str, Some("R-0"));
+
+    let position = <Position<6>>::start_position();
+    let f6 = &game.moves[1];
+    assert_eq!(f6.comment, "Common reply");
+    assert_eq!(f6.variations.len(), 1);
+    let variation = &f6.variations[0];
+    assert_eq!(variation.len(), 3);
+    assert_eq!(variation[0].mv, position.move_from_san("a6").unwrap());
+    assert_eq!(variation[1].variations.len(), 1);
+    assert_eq!(
+        variation[1].variations[0][0].mv,
+        position.move_from_san("f1").unwrap()
+    );
+
+    let d4 = &game.moves[3];
+    assert_eq!(d4.annotations, vec!["'"]);
+    assert_eq!(d4.variations[0][0].comment, "Also good");
+}
+
+#[test]
+fn write_and_read_variations_test() {
+    let games: Vec<Game<Position<6>>> = ptn_parser::parse_ptn(PTN_WITH_VARIATIONS).unwrap();
+
+    let mut ptn_writer = Cursor::new(vec![]);
+    games[0].game_to_ptn(&mut ptn_writer).unwrap();
+    let ptn = String::from_utf8(ptn_writer.into_inner()).unwrap();
+
+    let parsed_games: Vec<Game<Position<6>>> = ptn_parser::parse_ptn(&ptn).unwrap();
+    assert_eq!(parsed_games, games, "ptn:\n{}", ptn);
+}
+
+#[test]
+fn parse_error_location_test() {
+    let ptn = "[Size \"6\"]\n\n1. a1 f6\n2. c3 (2. b3 x4) 3. c4 R-0";
+    let error = ptn_parser::parse_ptn::<Position<6>>(ptn).unwrap_err();
+    assert_eq!((error.game, error.line, error.column), (0, 4, 14));
+
+    let ptn = "1. a1 f6 (2. c3";
+    let error = ptn_parser::parse_ptn::<Position<6>>(ptn).unwrap_err();
+    assert_eq!((error.line, error.column), (1, 10));
+
+    let ptn = "[Size \"6\"]\n[Player1 tiltak]\n\n1. a1 f6";
+    let error = ptn_parser::parse_ptn::<Position<6>>(ptn).unwrap_err();
+    assert_eq!((error.line, error.column), (2, 10));
+}
+
+#[test]
+fn parse_ptn_lenient_test() {
+    let ptn = "[Size \"6\"]\n\n1. a1 f6 2. c3 R-0\n\n\
+        [Size \"6\"]\n[Player1 tiltak]\n\n1. a1 f6 2. c3 R-0\n\n\
+        [Size \"6\"]\n\n1. a1 a1 2. c3 R-0\n\n\
+        [Size \"6\"]\n\n1. f6 a1 0-R\n";
+
+    assert!(ptn_parser::parse_ptn::<Position<6>>(ptn).is_err());
+
+    let (games, errors) = ptn_parser::parse_ptn_lenient::<Position<6>>(ptn);
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[1].game_result_str, Some("0-R"));
+    assert_eq!(errors.len(), 2);
+    assert_eq!((errors[1].game, errors[1].line), (2, 12));
+}
+
 #[test]
 fn parse_bad_direction_test() {
     assert!(<Move<6>>::from_string("a1d").is_err())
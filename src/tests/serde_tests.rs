@@ -0,0 +1,99 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::{Komi, Move, Position, Square};
+use crate::tests::do_moves_and_check_validity;
+
+#[test]
+fn serialize_square_test() {
+    let square = <Square<5>>::parse_square("c4").unwrap();
+    assert_eq!(serde_json::to_string(&square).unwrap(), "\"c4\"");
+    assert_eq!(serde_json::from_str::<Square<5>>("\"c4\"").unwrap(), square);
+    assert!(serde_json::from_str::<Square<5>>("\"f1\"").is_err());
+    // The format of older versions
+    let legacy_json = format!("{{\"inner\":{}}}", square.into_inner());
+    assert_eq!(
+        serde_json::from_str::<Square<5>>(&legacy_json).unwrap(),
+        square
+    );
+    assert!(serde_json::from_str::<Square<5>>("{\"inner\":25}").is_err());
+
+    let bytes = bincode::serialize(&square).unwrap();
+    assert_eq!(bytes, [square.into_inner()]);
+    assert_eq!(bincode::deserialize::<Square<5>>(&bytes).unwrap(), square);
+    assert!(bincode::deserialize::<Square<5>>(&[25]).is_err());
+}
+
+#[test]
+fn serialize_move_test() {
+    for move_string in ["a1", "Sc3", "Ce5", "b2<", "3c3+12", "5a5>221*"] {
+        let mv = <Move<6>>::from_string(move_string).unwrap();
+        let json = serde_json::to_string(&mv).unwrap();
+        assert_eq!(json, format!("\"{}\"", mv));
+        assert_eq!(serde_json::from_str::<Move<6>>(&json).unwrap(), mv);
+        let legacy_json = format!("{{\"inner\":{}}}", mv.to_u16());
+        assert_eq!(serde_json::from_str::<Move<6>>(&legacy_json).unwrap(), mv);
+
+        let bytes = bincode::serialize(&mv).unwrap();
+        assert_eq!(bytes, mv.to_u16().to_le_bytes());
+        assert_eq!(bincode::deserialize::<Move<6>>(&bytes).unwrap(), mv);
+    }
+    // Parses as a move, but goes off the board
+    assert!(serde_json::from_str::<Move<5>>("\"3a1<\"").is_err());
+    assert!(serde_json::from_str::<Move<5>>("\"f1\"").is_err());
+    assert!(bincode::deserialize::<Move<5>>(&u16::MAX.to_le_bytes()).is_err());
+}
+
+#[test]
+fn serialize_komi_test() {
+    let komi = Komi::from_half_komi(5).unwrap();
+    assert_eq!(serde_json::to_string(&komi).unwrap(), "2.5");
+    assert_eq!(serde_json::from_str::<Komi>("2.5").unwrap(), komi);
+    assert_eq!(serde_json::from_str::<Komi>("-2").unwrap().half_komi(), -4);
+    assert!(serde_json::from_str::<Komi>("2.25").is_err());
+    assert!(serde_json::from_str::<Komi>("6").is_err());
+}
+
+#[test]
+fn serialize_position_test() {
+    let komi = Komi::from_half_komi(4).unwrap();
+    let start_position =
+        <Position<5>>::from_fen_with_komi("x5/x2,2S,x2/x2,1C,x2/x5/2,x3,1 1 3", komi).unwrap();
+    let mut position = start_position.clone();
+    do_moves_and_check_validity(&mut position, &["c3+", "a1>", "e1<"]);
+
+    let json = serde_json::to_string(&position).unwrap();
+    assert_eq!(
+        json,
+        format!(
+            "{{\"tps\":\"{}\",\"komi\":2.0,\"moves\":[\"c3+\",\"a1>\",\"e1<\"]}}",
+            start_position.to_fen()
+        )
+    );
+    let deserialized: Position<5> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, position);
+    assert_eq!(deserialized.moves(), position.moves());
+
+    let bytes = bincode::serialize(&position).unwrap();
+    // Bincode prefixes the bytes with their length
+    assert_eq!(bytes[8..], position.encode_with_history());
+    let deserialized: Position<5> = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(deserialized, position);
+    assert_eq!(deserialized.moves(), position.moves());
+}
+
+#[test]
+fn deserialize_illegal_position_test() {
+    let json = "{\"tps\":\"x5/x5/x5/x5/x5 1 1\",\"komi\":0,\"moves\":[\"a1\",\"a1\"]}";
+    assert!(serde_json::from_str::<Position<5>>(json).is_err());
+    let json = "{\"tps\":\"x5/x5/x5/x5/x5 1 1\",\"komi\":0,\"moves\":[\"a1\",\"b1\"]}";
+    let position: Position<5> = serde_json::from_str(json).unwrap();
+    assert_eq!(position.moves().len(), 2);
+    assert!(serde_json::from_str::<Position<6>>(json).is_err());
+
+    let mut position = <Position<5>>::start_position();
+    position.do_move(position.move_from_san("a1").unwrap());
+    let mut bytes = bincode::serialize(&position).unwrap();
+    bytes[8] = 6;
+    assert!(bincode::deserialize::<Position<5>>(&bytes).is_err());
+}
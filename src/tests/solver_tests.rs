@@ -0,0 +1,79 @@
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::Position;
+use crate::solver;
+use crate::tests::TestPosition;
+
+#[test]
+fn immediate_road_win_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let mv = solver::immediate_road_win(&mut position).unwrap();
+    assert_eq!(position.move_to_san(&mv), "a2");
+
+    let road_win = solver::road_win(&position, 3).unwrap();
+    assert_eq!(road_win.moves, 1);
+}
+
+#[test]
+fn no_road_win_in_start_position_test() {
+    let mut position = <Position<5>>::start_position();
+    assert_eq!(solver::immediate_road_win(&mut position), None);
+    assert_eq!(solver::road_win(&position, 3), None);
+}
+
+#[test]
+fn no_road_win_after_game_over_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["b4", "c2", "d2", "c4", "b2", "d4", "e2", "c3", "a2"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    assert!(position.game_result().is_some());
+    assert_eq!(solver::road_win(&position, 2), None);
+}
+
+#[test]
+fn find_win_in_two_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "a5", "e4", "Cc3", "c4", "b3", "Cd3", "b4", "b5", "d4", "d5", "a4", "c4>", "e4<", "d3+",
+        "e3", "d3", "d2", "4d4<22", "a3", "3b4-", "c5", "2c4+", "a4+", "b2", "b4", "c4", "b1",
+        "c2", "c1", "d1", "d4", "a2", "a4", "e2", "d2<", "c4<", "a4>", "d2", "c4", "b2>", "c1+",
+        "b5-", "4c2>22", "4b4>22", "c3+", "d3-", "3c4>", "3d2>", "4d4-22", "5e2+122", "d4>",
+        "2e3+", "d4>", "2e5-", "d4", "3e4<", "e3", "c2", "a4", "e1", "e3+", "4d4>", "a1", "a2+",
+        "a2",
+    ])
+    .position();
+
+    assert_eq!(solver::road_win(&position, 1), None);
+    let road_win = solver::road_win(&position, 2).unwrap();
+    assert_eq!(road_win.moves, 2);
+    assert_eq!(position.move_to_san(&road_win.mv), "5e4+");
+}
+
+#[test]
+fn find_win_in_two2_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "a5", "e5", "e4", "Cc3", "e3", "e2", "Cd3", "d2", "e1", "c4", "1e1+1", "e1", "1d3-1", "Sd1",
+    ])
+    .position();
+
+    let road_win = solver::road_win(&position, 2).unwrap();
+    assert_eq!(road_win.moves, 2);
+    assert_eq!(position.move_to_san(&road_win.mv), "d2>");
+}
+
+#[test]
+fn find_win_in_three_test() {
+    let position: Position<5> = TestPosition::from_move_strings(&[
+        "e1", "e5", "Cc3", "c1", "d1", "d2", "a3", "b1", "b3", "d2-", "a1", "a2", "a1>", "Cb2",
+        "Sc2", "a1", "2b1>", "b2+", "b5", "b1", "c4", "d2", "c5",
+    ])
+    .position();
+
+    let road_win = solver::road_win(&position, 3).unwrap();
+    assert_eq!(road_win.moves, 3);
+    assert_eq!(position.move_to_san(&road_win.mv), "2b3-11");
+}
@@ -0,0 +1,200 @@
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::Position;
+use crate::search::time::{Clock, TimeBudget, TimeControl};
+use crate::search::{MctsSetting, MonteCarloTree};
+
+fn clock(secs: u64, increment_millis: u64) -> Clock {
+    Clock {
+        time_left: Duration::from_secs(secs),
+        increment: Duration::from_millis(increment_millis),
+        moves_to_go: None,
+    }
+}
+
+#[test]
+fn time_after_move_test() {
+    let start = TimeControl::Time(Duration::from_secs(10), Duration::from_secs(1));
+    assert_eq!(
+        start.after_move(Duration::from_secs(3), &start),
+        TimeControl::Time(Duration::from_secs(8), Duration::from_secs(1))
+    );
+    // Running out of time leaves only the increment
+    assert_eq!(
+        start.after_move(Duration::from_secs(30), &start),
+        TimeControl::Time(Duration::from_secs(1), Duration::from_secs(1))
+    );
+    assert_eq!(
+        start
+            .after_move(Duration::from_secs(30), &start)
+            .time_left(),
+        Some(Duration::from_secs(1))
+    );
+
+    let nodes = TimeControl::FixedNodes(1000);
+    assert_eq!(nodes.after_move(Duration::from_secs(1), &nodes), nodes);
+    assert_eq!(nodes.clock(), None);
+}
+
+#[test]
+fn moves_to_go_after_move_test() {
+    let start = TimeControl::MovesToGo(Duration::from_secs(60), 2);
+    let after_one_move = start.after_move(Duration::from_secs(20), &start);
+    assert_eq!(
+        after_one_move,
+        TimeControl::MovesToGo(Duration::from_secs(40), 1)
+    );
+    // The period's time is added back after its last move
+    assert_eq!(
+        after_one_move.after_move(Duration::from_secs(30), &start),
+        TimeControl::MovesToGo(Duration::from_secs(70), 2)
+    );
+    assert_eq!(
+        after_one_move.clock(),
+        Some(Clock {
+            time_left: Duration::from_secs(40),
+            increment: Duration::ZERO,
+            moves_to_go: Some(1),
+        })
+    );
+}
+
+#[test]
+fn budget_within_time_left_test() {
+    let position =
+        <Position<6>>::from_fen("2,x5/x,1,x4/x2,1,2C,x2/x2,12,x3/x3,1S,x2/x6 2 6").unwrap();
+    for clock in [clock(0, 0), clock(1, 0), clock(60, 0), clock(60, 5000)] {
+        let budget = TimeBudget::for_clock(&position, clock);
+        assert!(budget.soft_limit <= budget.hard_limit, "{:?}", budget);
+        assert!(budget.hard_limit < clock.time_left.max(Duration::from_millis(1)) / 2);
+    }
+    let budget = TimeBudget::for_clock(&position, clock(60, 0));
+    assert!(budget.hard_limit > budget.soft_limit);
+    assert!(budget.soft_limit > Duration::from_secs(1));
+}
+
+#[test]
+fn budget_uses_increment_test() {
+    let position = <Position<5>>::start_position();
+    let without_increment = TimeBudget::for_clock(&position, clock(60, 0));
+    let with_increment = TimeBudget::for_clock(&position, clock(60, 2000));
+    assert!(with_increment.soft_limit > without_increment.soft_limit);
+}
+
+#[test]
+fn budget_for_game_phase_test() {
+    let opening = <Position<6>>::start_position();
+    let middlegame =
+        <Position<6>>::from_fen("2,x5/x,1,x4/x2,1,2C,x2/x2,12,x3/x3,1S,x2/x6 2 6").unwrap();
+    let endgame = <Position<6>>::from_fen(
+        "2,2,2,2,2,1/1,1,1,1,1,2/2,2,2,2,2,1/1,1,1,1,1,2/2,2,2,2,2,1/x,1,1,1,1C,2C 2 15",
+    )
+    .unwrap();
+
+    let opening_budget = TimeBudget::for_clock(&opening, clock(300, 0));
+    let middlegame_budget = TimeBudget::for_clock(&middlegame, clock(300, 0));
+    let endgame_budget = TimeBudget::for_clock(&endgame, clock(300, 0));
+    assert!(opening_budget.soft_limit < middlegame_budget.soft_limit);
+    // With few reserves left, the game is expected to end soon
+    assert!(middlegame_budget.soft_limit < endgame_budget.soft_limit);
+}
+
+#[test]
+fn budget_with_moves_to_go_test() {
+    let position =
+        <Position<6>>::from_fen("2,x5/x,1,x4/x2,1,2C,x2/x2,12,x3/x3,1S,x2/x6 2 6").unwrap();
+    let clock_with_moves_to_go = |moves_to_go| Clock {
+        moves_to_go: Some(moves_to_go),
+        ..clock(60, 0)
+    };
+    let many_moves = TimeBudget::for_clock(&position, clock_with_moves_to_go(40));
+    let few_moves = TimeBudget::for_clock(&position, clock_with_moves_to_go(4));
+    let last_move = TimeBudget::for_clock(&position, clock_with_moves_to_go(1));
+
+    assert!(many_moves.soft_limit < few_moves.soft_limit);
+    assert!(few_moves.hard_limit < last_move.hard_limit);
+    // The last move before the time is added back may use almost all of it
+    assert!(last_move.hard_limit > Duration::from_secs(50));
+    assert!(last_move.hard_limit < Duration::from_secs(60));
+}
+
+#[test]
+fn fixed_budget_reserves_move_overhead_test() {
+    let movetime = Duration::from_secs(1);
+    let budget = TimeBudget::fixed(movetime);
+    assert_eq!(budget.soft_limit, budget.hard_limit);
+    assert!(budget.hard_limit < movetime);
+    assert!(budget.hard_limit > Duration::from_millis(900));
+
+    assert_eq!(TimeBudget::fixed(Duration::ZERO).hard_limit, Duration::ZERO);
+}
+
+#[test]
+fn training_max_time_test() {
+    assert_eq!(
+        clock(60, 1000).training_max_time(),
+        Duration::from_millis(12_500)
+    );
+}
+
+#[test]
+fn instability_extends_soft_limit_test() {
+    let budget = TimeBudget {
+        soft_limit: Duration::from_secs(1),
+        hard_limit: Duration::from_secs(4),
+    };
+    let elapsed = Duration::from_secs(2);
+    assert!(budget.should_stop(elapsed, 0.0));
+    assert!(!budget.should_stop(elapsed, 1.0));
+    assert!(budget.should_stop(Duration::from_secs(4), 1.0));
+    assert_eq!(budget.extended_soft_limit(1.0), Duration::from_secs(3));
+
+    let unlimited = TimeBudget::fixed(Duration::MAX);
+    assert!(!unlimited.should_stop(Duration::from_secs(1_000_000), 1.0));
+    assert_eq!(
+        unlimited.limit(Duration::from_secs(1), Duration::from_secs(2)),
+        budget.limit(Duration::MAX, Duration::from_secs(2))
+    );
+}
+
+#[test]
+fn mcts_search_for_time_test() {
+    let position = <Position<6>>::start_position();
+    let mut tree = MonteCarloTree::new(
+        position,
+        MctsSetting::default().arena_size_for_nodes(1_000_000),
+    );
+    let budget = TimeBudget {
+        soft_limit: Duration::from_millis(50),
+        hard_limit: Duration::from_millis(200),
+    };
+    let start_time = Instant::now();
+    let mut callbacks = 0;
+    tree.search_for_time(budget, None, |_| callbacks += 1);
+
+    assert!(start_time.elapsed() >= budget.soft_limit);
+    assert!(start_time.elapsed() < Duration::from_secs(1));
+    assert!(callbacks > 1);
+    assert!(tree.visits() > 50);
+}
+
+#[test]
+fn mcts_search_for_time_stopped_test() {
+    let position = <Position<6>>::start_position();
+    let mut tree = MonteCarloTree::new(
+        position,
+        MctsSetting::default().arena_size_for_nodes(1_000_000),
+    );
+    let start_time = Instant::now();
+    tree.search_for_time(
+        TimeBudget::fixed(Duration::MAX),
+        Some(&AtomicBool::new(true)),
+        |_| {},
+    );
+    assert!(start_time.elapsed() < Duration::from_secs(1));
+    assert!(tree.best_move().is_some());
+}
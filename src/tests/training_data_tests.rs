@@ -0,0 +1,128 @@
+use board_game_traits::{GameResult, Position as PositionTrait};
+use half::f16;
+use pgn_traits::PgnPosition;
+
+use crate::position::{Komi, Move, Position};
+use crate::ptn::ptn_parser;
+use crate::tune::training_data::{samples_from_game, PositionSample, SampleReader, SampleWriter};
+
+fn round_trip<const S: usize>(samples: &[PositionSample<S>]) -> Vec<PositionSample<S>> {
+    let mut writer = SampleWriter::new(vec![]).unwrap();
+    for sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    SampleReader::<_, S>::new(bytes.as_slice())
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+fn assert_samples_eq<const S: usize>(samples: &[PositionSample<S>], read: &[PositionSample<S>]) {
+    assert_eq!(samples.len(), read.len());
+    for (sample, read_sample) in samples.iter().zip(read) {
+        assert_eq!(sample.position.to_fen(), read_sample.position.to_fen());
+        assert_eq!(sample.position.komi(), read_sample.position.komi());
+        assert_eq!(
+            sample.position.half_moves_played(),
+            read_sample.position.half_moves_played()
+        );
+        assert_eq!(
+            sample.position.zobrist_hash(),
+            read_sample.position.zobrist_hash()
+        );
+        assert_eq!(sample.result, read_sample.result);
+        assert_eq!(sample.move_scores, read_sample.move_scores);
+    }
+}
+
+#[test]
+fn tall_stacks_round_trip_test() {
+    let komi = Komi::from_half_komi(4).unwrap();
+    let position = <Position<6>>::from_fen_with_komi(
+        "2,212221C,2,2,2C,1/1,2,1,1,2,1/12,x,1S,2S,2,1/2,2,x2,1,1/x4,2,2/x,2,2,1,1,1 1 24",
+        komi,
+    )
+    .unwrap();
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    let move_scores: Vec<(Move<6>, f16)> = moves
+        .iter()
+        .map(|mv| (*mv, f16::from_f32(1.0 / moves.len() as f32)))
+        .collect();
+
+    let samples = vec![
+        PositionSample {
+            position: position.clone(),
+            result: GameResult::BlackWin,
+            move_scores,
+        },
+        PositionSample {
+            position: Position::start_position(),
+            result: GameResult::Draw,
+            move_scores: vec![],
+        },
+    ];
+    assert_samples_eq(&samples, &round_trip(&samples));
+}
+
+#[test]
+fn game_samples_round_trip_test() {
+    let ptn = "[Size \"5\"]\n[Komi \"2\"]\n\n1. a1 e5 2. Cc3 c4 3. Sd3 b3 4. c3< d4 5. d3+ 1-0\n";
+    let games = ptn_parser::parse_ptn::<Position<5>>(ptn).unwrap();
+    let game = &games[0];
+    let move_scores: Vec<Vec<(Move<5>, f16)>> = game
+        .moves
+        .iter()
+        .map(|ptn_move| vec![(ptn_move.mv, f16::ONE)])
+        .collect();
+
+    let samples = samples_from_game(game, &move_scores).unwrap();
+    assert_eq!(samples.len(), game.moves.len());
+    assert_eq!(samples[0].position.komi(), Komi::from_half_komi(4).unwrap());
+    assert!(samples
+        .iter()
+        .all(|sample| sample.result == GameResult::WhiteWin));
+    assert_samples_eq(&samples, &round_trip(&samples));
+}
+
+#[test]
+fn reject_wrong_size_test() {
+    let writer = SampleWriter::<_, 5>::new(vec![]).unwrap();
+    let bytes = writer.finish().unwrap();
+    assert!(SampleReader::<_, 6>::new(bytes.as_slice()).is_err());
+    assert!(SampleReader::<_, 5>::new(&b"TILTAKxx\x01\x05"[..]).is_err());
+}
+
+#[test]
+fn truncated_sample_is_error_test() {
+    let sample = PositionSample {
+        position: <Position<4>>::start_position(),
+        result: GameResult::WhiteWin,
+        move_scores: vec![],
+    };
+    let mut writer = SampleWriter::new(vec![]).unwrap();
+    writer.write_sample(&sample).unwrap();
+    let mut bytes = writer.finish().unwrap();
+    bytes.pop();
+    let mut reader = SampleReader::<_, 4>::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_err());
+}
+
+#[test]
+fn too_many_pieces_is_error_test() {
+    let mut bytes = SampleWriter::<_, 3>::new(vec![]).unwrap().finish().unwrap();
+    // A stack of 12 white flats on a1, more than the 10 stones in the reserves
+    bytes.extend_from_slice(&[12, 0, 0, 0]);
+    bytes.extend_from_slice(&[0; 8]);
+    // Side to move, plies played, half komi, game result and number of move scores
+    bytes.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0]);
+
+    let mut reader = SampleReader::<_, 3>::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_err());
+
+    // The same stack with 10 stones is valid
+    bytes[10] = 10;
+    let mut reader = SampleReader::<_, 3>::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+}
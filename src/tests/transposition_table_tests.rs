@@ -0,0 +1,36 @@
+use std::num::NonZeroU32;
+
+use crate::search::arena::Index;
+use crate::search::transposition_table::TranspositionTable;
+
+#[test]
+fn colliding_hashes_test() {
+    let table = <TranspositionTable<5>>::new(1024);
+    let index = Index::from_raw(NonZeroU32::new(42).unwrap());
+    let hash = 0x1234_5678_0000_0001;
+    table.insert(hash, &index);
+    assert_eq!(table.get(hash).map(|index| index.to_raw().get()), Some(42));
+
+    // Same entry, and same upper 32 bits, but a different position
+    assert!(table.get(hash | 1 << 20).is_none());
+    // Same entry, but different upper bits
+    assert!(table.get(hash ^ 1 << 63).is_none());
+
+    let entries: Vec<_> = table.entries().collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].0, hash);
+}
+
+#[test]
+fn newest_entry_replaces_test() {
+    let table = <TranspositionTable<5>>::new(1000);
+    let first = Index::from_raw(NonZeroU32::new(1).unwrap());
+    let second = Index::from_raw(NonZeroU32::new(2).unwrap());
+    table.insert(3, &first);
+    table.insert(512 + 3, &second);
+    assert!(table.get(3).is_none());
+    assert_eq!(
+        table.get(512 + 3).map(|index| index.to_raw().get()),
+        Some(2)
+    );
+}
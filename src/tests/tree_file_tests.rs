@@ -0,0 +1,188 @@
+use std::io;
+
+use board_game_traits::Position as PositionTrait;
+use pgn_traits::PgnPosition;
+
+use crate::position::Position;
+use crate::search::{MctsSetting, MonteCarloTree};
+
+fn save_to_vec<const S: usize>(tree: &MonteCarloTree<S>) -> Vec<u8> {
+    let mut data = vec![];
+    tree.save(&mut data).unwrap();
+    data
+}
+
+fn searched_tree<const S: usize>(
+    position: &Position<S>,
+    settings: MctsSetting<S>,
+    nodes: u32,
+) -> MonteCarloTree<S> {
+    let mut tree = MonteCarloTree::new(position.clone(), settings);
+    tree.search_nodes(nodes as u64, None).unwrap();
+    tree
+}
+
+fn assert_same_tree<const S: usize>(tree: &MonteCarloTree<S>, loaded_tree: &MonteCarloTree<S>) {
+    assert_eq!(loaded_tree.position(), tree.position());
+    assert_eq!(loaded_tree.settings(), tree.settings());
+    assert_eq!(loaded_tree.visits(), tree.visits());
+    assert_eq!(loaded_tree.mean_action_value(), tree.mean_action_value());
+    assert_eq!(loaded_tree.best_move(), tree.best_move());
+    assert_eq!(
+        loaded_tree.pv().collect::<Vec<_>>(),
+        tree.pv().collect::<Vec<_>>()
+    );
+    assert_eq!(loaded_tree.multi_pv(5), tree.multi_pv(5));
+}
+
+#[test]
+fn save_and_load_test() {
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["a1", "e5", "c3", "d3"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let settings = MctsSetting::default().arena_size_for_nodes(20_000);
+    let tree = searched_tree(&position, settings, 10_000);
+
+    let data = save_to_vec(&tree);
+    let mut loaded_tree = <MonteCarloTree<5>>::load(data.as_slice()).unwrap();
+    assert_same_tree(&tree, &loaded_tree);
+    assert_eq!(save_to_vec(&loaded_tree), data);
+
+    // The search continues where it left off
+    loaded_tree.search_nodes(1000, None).unwrap();
+    assert_eq!(loaded_tree.visits(), tree.visits() + 1000);
+}
+
+#[test]
+fn save_and_load_with_transposition_table_test() {
+    let position = <Position<5>>::from_fen(
+        "2,x4/2,1,x,1,2S/1112,1,x,1S,x/2,121121C,x,1,x/1,x,112C,x,111212S 2 24",
+    )
+    .unwrap();
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(20_000)
+        .transposition_table(true);
+    let tree = searched_tree(&position, settings, 10_000);
+
+    let data = save_to_vec(&tree);
+    let mut loaded_tree = <MonteCarloTree<5>>::load(data.as_slice()).unwrap();
+    assert_same_tree(&tree, &loaded_tree);
+    assert_eq!(save_to_vec(&loaded_tree), data);
+
+    loaded_tree.search_nodes(1000, None).unwrap();
+    assert_eq!(loaded_tree.visits(), tree.visits() + 1000);
+}
+
+#[test]
+fn reroot_loaded_tree_test() {
+    let mut position = <Position<6>>::start_position();
+    for move_string in ["a1", "f6", "c3", "d4"] {
+        position.do_move(position.move_from_san(move_string).unwrap());
+    }
+    let settings = MctsSetting::default().arena_size_for_nodes(10_000);
+    let tree = searched_tree(&position, settings.clone(), 5_000);
+    let mut loaded_tree = <MonteCarloTree<6>>::load(save_to_vec(&tree).as_slice()).unwrap();
+    assert!(loaded_tree.position().moves().is_empty());
+
+    // The tree can be used for the same position with its move history, and for positions after it
+    assert!(loaded_tree.reroot(&position, settings.clone()));
+    assert_eq!(loaded_tree.position().moves(), position.moves());
+    assert_eq!(loaded_tree.visits(), tree.visits());
+
+    let best_move = loaded_tree.best_move().unwrap().0;
+    position.do_move(best_move);
+    assert!(loaded_tree.reroot(&position, settings));
+    assert!(loaded_tree.visits() > 0);
+}
+
+#[test]
+fn load_with_mem_usage_test() {
+    let settings = MctsSetting::default().arena_size_for_nodes(2_000);
+    let tree = searched_tree(&<Position<5>>::start_position(), settings.clone(), 1_000);
+    let data = save_to_vec(&tree);
+
+    // Enough for the saved tree, but smaller than the arena it was saved from
+    let mem_usage = tree.mem_usage() * 2;
+    let loaded_tree = <MonteCarloTree<5>>::load_with_mem_usage(data.as_slice(), mem_usage).unwrap();
+    assert_eq!(loaded_tree.settings(), &settings.mem_usage(mem_usage));
+    assert_eq!(loaded_tree.visits(), tree.visits());
+    assert_eq!(loaded_tree.best_move(), tree.best_move());
+
+    let error = <MonteCarloTree<5>>::load_with_mem_usage(data.as_slice(), 1024)
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::OutOfMemory);
+}
+
+#[test]
+fn reroot_loaded_tree_with_excluded_moves_test() {
+    let position = <Position<5>>::start_position();
+    let excluded_move = position.move_from_san("a1").unwrap();
+    let settings = MctsSetting::default().arena_size_for_nodes(2_000);
+    let tree = searched_tree(
+        &position,
+        settings.clone().exclude_moves(vec![excluded_move]),
+        1_000,
+    );
+
+    let mut loaded_tree = <MonteCarloTree<5>>::load(save_to_vec(&tree).as_slice()).unwrap();
+    assert!(loaded_tree.reroot(&position, settings));
+    assert!(loaded_tree
+        .shallow_edges()
+        .unwrap()
+        .iter()
+        .any(|edge| edge.mv() == excluded_move));
+}
+
+#[test]
+fn load_wrong_number_of_value_params_test() {
+    let value_params = <Position<5>>::value_params_0komi();
+    let settings = MctsSetting::default()
+        .arena_size_for_nodes(1_000)
+        .add_value_params(value_params);
+    let tree = searched_tree(&<Position<5>>::start_position(), settings, 100);
+    let data = save_to_vec(&tree);
+
+    // Drop the last parameter, and adjust the length to match
+    let mut header = vec![1];
+    header.extend((value_params.len() as u32).to_le_bytes());
+    header.extend(value_params[0].to_le_bytes());
+    let start = data
+        .windows(header.len())
+        .position(|window| window == header)
+        .unwrap();
+    let end = start + 5 + 4 * value_params.len();
+    let mut corrupted_data = data[..start + 1].to_vec();
+    corrupted_data.extend((value_params.len() as u32 - 1).to_le_bytes());
+    corrupted_data.extend_from_slice(&data[start + 5..end - 4]);
+    corrupted_data.extend_from_slice(&data[end..]);
+
+    let error = <MonteCarloTree<5>>::load(corrupted_data.as_slice())
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(<MonteCarloTree<5>>::load(data.as_slice()).is_ok());
+}
+
+#[test]
+fn load_invalid_tree_test() {
+    let settings = MctsSetting::default().arena_size_for_nodes(2_000);
+    let tree = searched_tree(&<Position<5>>::start_position(), settings, 1_000);
+    let data = save_to_vec(&tree);
+
+    let error = <MonteCarloTree<6>>::load(data.as_slice()).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+    let error = <MonteCarloTree<5>>::load(&data[..data.len() / 2])
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut corrupted_data = data.clone();
+    corrupted_data[0] = b'X';
+    let error = <MonteCarloTree<5>>::load(corrupted_data.as_slice())
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,399 @@
+//! Engine-vs-engine matches, played as pairs of games from the same opening with colors swapped.
+//!
+//! Each engine is either a set of in-process settings, or an external TEI engine.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+use chrono::Datelike;
+use pgn_traits::PgnPosition;
+use rand::seq::SliceRandom;
+
+use crate::position::{Komi, Move, Position};
+use crate::ptn::{Game, PtnMove};
+use crate::search;
+use crate::search::TimeControl;
+use crate::tune::play_match::Player;
+use crate::tune::sprt::{MatchScore, Sprt, SprtResult};
+use crate::tune::tei_engine::TeiEngine;
+
+/// Games that reach this many plies are adjudicated as draws
+const MAX_PLIES: usize = 400;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MatchEngine<const S: usize> {
+    /// Name used in the games' `Player1` and `Player2` tags
+    pub name: String,
+    pub kind: MatchEngineKind<S>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MatchEngineKind<const S: usize> {
+    InProcess(Player<S>),
+    /// An external engine, started with the given program and arguments, and configured with `setoption`
+    Tei {
+        program: String,
+        args: Vec<String>,
+        options: Vec<(String, String)>,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MatchSettings<const S: usize> {
+    pub komi: Komi,
+    /// TEI engines only support `TimeControl::Time`
+    pub time_control: TimeControl,
+    /// Each pair of games starts from the next opening. If empty, each pair starts with two random placements
+    pub openings: Vec<Vec<Move<S>>>,
+    pub pairs: usize,
+    /// Number of game pairs that are played in parallel
+    pub concurrency: usize,
+    /// Stop the match early when the test is decided
+    pub sprt: Option<Sprt>,
+}
+
+/// A finished pair of games. The first engine is white in the first game
+pub struct PairResult<const S: usize> {
+    pub pair_number: usize,
+    pub games: [Game<Position<S>>; 2],
+    /// The first engine's score from the two games
+    pub score: MatchScore,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchResult {
+    pub score: MatchScore,
+    /// The final state of the SPRT, if there was one
+    pub sprt_result: Option<SprtResult>,
+}
+
+/// Play the match, calling `on_pair` with each finished pair, and the total score so far.
+/// Pairs that finish after the SPRT is decided are not counted
+pub fn run_match<const S: usize>(
+    engine1: &MatchEngine<S>,
+    engine2: &MatchEngine<S>,
+    settings: &MatchSettings<S>,
+    mut on_pair: impl FnMut(&PairResult<S>, &MatchScore),
+) -> io::Result<MatchResult> {
+    if matches!(settings.time_control, TimeControl::FixedNodes(_))
+        && [engine1, engine2]
+            .iter()
+            .any(|engine| matches!(engine.kind, MatchEngineKind::Tei { .. }))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "TEI engines cannot play with a fixed number of nodes",
+        ));
+    }
+
+    let next_pair = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            let sender = sender.clone();
+            let (next_pair, stop) = (&next_pair, &stop);
+            scope.spawn(move || {
+                let result = play_pairs(engine1, engine2, settings, next_pair, stop, &sender);
+                if let Err(err) = result {
+                    let _ = sender.send(Err(err));
+                }
+            });
+        }
+        drop(sender);
+
+        let mut score = MatchScore::default();
+        let mut sprt_result = settings.sprt.map(|_| SprtResult::Continue);
+        let mut error = None;
+        for message in receiver {
+            match message {
+                Ok(_) if stop.load(Ordering::Relaxed) => (),
+                Ok(pair_result) => {
+                    score.wins += pair_result.score.wins;
+                    score.losses += pair_result.score.losses;
+                    score.draws += pair_result.score.draws;
+                    on_pair(&pair_result, &score);
+                    if let Some(sprt) = settings.sprt {
+                        let result = sprt.result(&score);
+                        sprt_result = Some(result);
+                        if result != SprtResult::Continue {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                Err(err) => {
+                    stop.store(true, Ordering::Relaxed);
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(MatchResult { score, sprt_result }),
+        }
+    })
+}
+
+/// Worker thread that plays pairs until the match is over. Each worker starts its own engine instances
+fn play_pairs<const S: usize>(
+    engine1: &MatchEngine<S>,
+    engine2: &MatchEngine<S>,
+    settings: &MatchSettings<S>,
+    next_pair: &AtomicUsize,
+    stop: &AtomicBool,
+    sender: &mpsc::Sender<io::Result<PairResult<S>>>,
+) -> io::Result<()> {
+    let mut instance1 = EngineInstance::start(&engine1.kind)?;
+    let mut instance2 = EngineInstance::start(&engine2.kind)?;
+    while !stop.load(Ordering::Relaxed) {
+        let pair_number = next_pair.fetch_add(1, Ordering::Relaxed);
+        if pair_number >= settings.pairs {
+            break;
+        }
+        let opening = if settings.openings.is_empty() {
+            random_opening()
+        } else {
+            settings.openings[pair_number % settings.openings.len()].clone()
+        };
+
+        let (game1, result1) = play_match_game(
+            &mut instance1,
+            &mut instance2,
+            engine1,
+            engine2,
+            settings,
+            &opening,
+        )?;
+        let (game2, result2) = play_match_game(
+            &mut instance2,
+            &mut instance1,
+            engine2,
+            engine1,
+            settings,
+            &opening,
+        )?;
+
+        let mut score = MatchScore::default();
+        for (result, engine1_color) in [(result1, Color::White), (result2, Color::Black)] {
+            match (result, engine1_color) {
+                (GameResult::Draw, _) => score.draws += 1,
+                (GameResult::WhiteWin, Color::White) | (GameResult::BlackWin, Color::Black) => {
+                    score.wins += 1
+                }
+                (GameResult::WhiteWin, Color::Black) | (GameResult::BlackWin, Color::White) => {
+                    score.losses += 1
+                }
+            }
+        }
+
+        let pair_result = PairResult {
+            pair_number,
+            games: [game1, game2],
+            score,
+        };
+        if sender.send(Ok(pair_result)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn random_opening<const S: usize>() -> Vec<Move<S>> {
+    let mut rng = rand::thread_rng();
+    let mut position = <Position<S>>::start_position();
+    let mut opening = vec![];
+    for _ in 0..2 {
+        let mut legal_moves = vec![];
+        position.generate_moves(&mut legal_moves);
+        let mv = *legal_moves.choose(&mut rng).unwrap();
+        position.do_move(mv);
+        opening.push(mv);
+    }
+    opening
+}
+
+/// A started engine, ready to play games
+enum EngineInstance<const S: usize> {
+    InProcess(Player<S>),
+    Tei(TeiEngine),
+}
+
+impl<const S: usize> EngineInstance<S> {
+    fn start(kind: &MatchEngineKind<S>) -> io::Result<Self> {
+        match kind {
+            MatchEngineKind::InProcess(player) => Ok(EngineInstance::InProcess(player.clone())),
+            MatchEngineKind::Tei {
+                program,
+                args,
+                options,
+            } => {
+                let mut engine = TeiEngine::start(program, args)?;
+                for (name, value) in options {
+                    engine.set_option(name, value)?;
+                }
+                Ok(EngineInstance::Tei(engine))
+            }
+        }
+    }
+
+    fn new_game(&mut self, komi: Komi) -> io::Result<()> {
+        match self {
+            EngineInstance::InProcess(_) => Ok(()),
+            EngineInstance::Tei(engine) => engine.new_game(S, komi),
+        }
+    }
+
+    /// Returns `None` if the engine played an illegal move
+    fn best_move(
+        &mut self,
+        position: &Position<S>,
+        moves: &[Move<S>],
+        white_clock: &TimeControl,
+        black_clock: &TimeControl,
+    ) -> io::Result<Option<Move<S>>> {
+        match self {
+            EngineInstance::InProcess(player) => {
+                let clock = match position.side_to_move() {
+                    Color::White => white_clock,
+                    Color::Black => black_clock,
+                };
+                let moves_scores = player.search(position, clock);
+                Ok(Some(search::best_move(
+                    &mut rand::thread_rng(),
+                    None,
+                    &moves_scores,
+                )))
+            }
+            EngineInstance::Tei(engine) => {
+                // TEI engines are never used with `TimeControl::FixedNodes`
+                let move_string = engine.go(
+                    moves,
+                    white_clock.clock().unwrap(),
+                    black_clock.clock().unwrap(),
+                )?;
+                let mut legal_moves = vec![];
+                position.generate_moves(&mut legal_moves);
+                Ok(position
+                    .move_from_san(&move_string)
+                    .ok()
+                    .filter(|mv| legal_moves.contains(mv)))
+            }
+        }
+    }
+}
+
+/// Play a single game. Engines that run out of time, or play an illegal move, lose the game
+fn play_match_game<const S: usize>(
+    white: &mut EngineInstance<S>,
+    black: &mut EngineInstance<S>,
+    white_engine: &MatchEngine<S>,
+    black_engine: &MatchEngine<S>,
+    settings: &MatchSettings<S>,
+    opening: &[Move<S>],
+) -> io::Result<(Game<Position<S>>, GameResult)> {
+    white.new_game(settings.komi)?;
+    black.new_game(settings.komi)?;
+
+    let mut position = <Position<S>>::start_position_with_komi(settings.komi);
+    let mut moves = opening.to_vec();
+    for mv in opening {
+        position.do_move(*mv);
+    }
+
+    let mut white_clock = settings.time_control.clone();
+    let mut black_clock = settings.time_control.clone();
+
+    // How the game ended, if it did not end normally
+    let mut termination = None;
+    let game_result = loop {
+        if let Some(game_result) = position.game_result() {
+            break game_result;
+        }
+        if moves.len() >= MAX_PLIES {
+            termination = Some("move limit");
+            break GameResult::Draw;
+        }
+        let side_to_move = position.side_to_move();
+        let loss = match side_to_move {
+            Color::White => GameResult::BlackWin,
+            Color::Black => GameResult::WhiteWin,
+        };
+
+        let start_time = Instant::now();
+        let engine = match side_to_move {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+        let best_move = engine.best_move(&position, &moves, &white_clock, &black_clock)?;
+
+        let clock = match side_to_move {
+            Color::White => &mut white_clock,
+            Color::Black => &mut black_clock,
+        };
+        let elapsed = start_time.elapsed();
+        if clock
+            .time_left()
+            .is_some_and(|time_left| elapsed > time_left)
+        {
+            termination = Some("time forfeit");
+            break loss;
+        }
+        *clock = clock.after_move(elapsed, &settings.time_control);
+
+        match best_move {
+            Some(mv) => {
+                position.do_move(mv);
+                moves.push(mv);
+            }
+            None => {
+                termination = Some("illegal move");
+                break loss;
+            }
+        }
+    };
+
+    let date = chrono::Local::now();
+    let mut tags = vec![
+        ("Event".to_string(), "Tiltak match".to_string()),
+        ("Site".to_string(), "Tiltak".to_string()),
+        ("Player1".to_string(), white_engine.name.clone()),
+        ("Player2".to_string(), black_engine.name.clone()),
+        ("Size".to_string(), S.to_string()),
+        (
+            "Date".to_string(),
+            format!("{}.{:0>2}.{:0>2}", date.year(), date.month(), date.day()),
+        ),
+        ("Komi".to_string(), settings.komi.to_string()),
+    ];
+    if let Some(termination) = termination {
+        tags.push(("Termination".to_string(), termination.to_string()));
+    }
+
+    let game_result_str = position.pgn_game_result().or(match game_result {
+        GameResult::WhiteWin => Some("1-0"),
+        GameResult::BlackWin => Some("0-1"),
+        GameResult::Draw => Some("1/2-1/2"),
+    });
+
+    let game = Game {
+        start_position: <Position<S>>::start_position_with_komi(settings.komi),
+        moves: moves
+            .into_iter()
+            .map(|mv| PtnMove {
+                mv,
+                annotations: vec![],
+                comment: String::new(),
+                variations: vec![],
+            })
+            .collect(),
+        game_result_str,
+        tags,
+        comment: String::new(),
+    };
+    Ok((game, game_result))
+}
@@ -1,5 +1,10 @@
 pub mod gradient_descent;
-mod openings;
+pub mod match_runner;
+pub mod network_training;
+pub mod openings;
 pub mod play_match;
+pub mod sprt;
 pub mod spsa;
+pub mod tei_engine;
 pub mod training;
+pub mod training_data;
@@ -0,0 +1,208 @@
+//! Training for the networks in `evaluation::network`, using minibatch gradient descent with backpropagation.
+//! Uses the same training samples as the linear parameters, see `gradient_descent`.
+
+use std::time::Instant;
+
+use half::f16;
+use log::trace;
+use rayon::prelude::*;
+
+use crate::evaluation::network::Network;
+use crate::tune::gradient_descent::TrainingSample;
+
+pub fn train_network<R: rand::Rng, F, FDx, const N: usize>(
+    samples: &[TrainingSample<N>],
+    initial_params: &[f32],
+    initial_learning_rate: f32,
+    rng: &mut R,
+    sigmoid: &F,
+    sigmoid_derived: &FDx,
+) -> Vec<f32>
+where
+    F: Fn(f32) -> f32 + Sync,
+    FDx: Fn(f32) -> f32 + Sync,
+{
+    assert!(
+        Network::new(initial_params, N).is_some(),
+        "Got {} network parameters for {} inputs",
+        initial_params.len(),
+        N
+    );
+    let start_time = Instant::now();
+    let beta = 0.9;
+
+    // If error is not reduced this number of times, reduce eta
+    const MAX_TRIES: usize = 10;
+    const MINIBATCH_SIZE: usize = 1000;
+
+    let initial_error = average_error(samples, initial_params, sigmoid);
+    let num_minibatches = samples.len().div_ceil(MINIBATCH_SIZE);
+    println!(
+        "Training network with {} hidden neurons on {} positions",
+        Network::new(initial_params, N).unwrap().num_hidden(),
+        samples.len()
+    );
+    println!("Initial error: {}", initial_error);
+
+    let mut lowest_error = initial_error;
+    let mut best_parameter_set = initial_params.to_vec();
+    let mut i = 0;
+
+    for eta in [
+        initial_learning_rate,
+        initial_learning_rate / 10.0,
+        initial_learning_rate / 100.0,
+    ] {
+        trace!("\nTraining with eta = {}\n", eta);
+        let mut parameter_set = best_parameter_set.clone();
+        let mut gradients = vec![0.0; parameter_set.len()];
+
+        let mut iterations_since_improvement = 0;
+        loop {
+            for _ in 0..num_minibatches {
+                let minibatch_samples = if samples.len() <= MINIBATCH_SIZE {
+                    samples
+                } else {
+                    let start_index = rng.gen_range(0..(samples.len() - MINIBATCH_SIZE));
+                    &samples[start_index..(start_index + MINIBATCH_SIZE)]
+                };
+                let slopes =
+                    calc_slope(minibatch_samples, &parameter_set, sigmoid, sigmoid_derived);
+                gradients
+                    .iter_mut()
+                    .zip(slopes.iter())
+                    .for_each(|(gradient, slope)| {
+                        *gradient = beta * *gradient + (1.0 - beta) * slope
+                    });
+                parameter_set
+                    .iter_mut()
+                    .zip(gradients.iter())
+                    .for_each(|(param, gradient)| *param -= gradient * eta);
+            }
+
+            let error = average_error(samples, &parameter_set, sigmoid);
+            println!(
+                "{:04} iterations in {:.1}s: Error {:.8}, eta={:.4}, {} iterations since improvement",
+                i,
+                start_time.elapsed().as_secs_f32(),
+                error,
+                eta,
+                iterations_since_improvement,
+            );
+            i += 1;
+
+            if error < lowest_error {
+                iterations_since_improvement = 0;
+                lowest_error = error;
+                best_parameter_set.clone_from(&parameter_set);
+            } else {
+                iterations_since_improvement += 1;
+                if iterations_since_improvement >= MAX_TRIES {
+                    break;
+                }
+            }
+        }
+    }
+
+    println!(
+        "Finished training in {:.1}s, error is {:.8}",
+        start_time.elapsed().as_secs_f64(),
+        lowest_error,
+    );
+    best_parameter_set
+}
+
+/// Convert features to the network's input format
+fn inputs<const N: usize>(features: &[f16; N]) -> Vec<f32> {
+    features.iter().map(|feature| feature.to_f32()).collect()
+}
+
+/// The gradient of the average error, for each parameter
+fn calc_slope<F, FDx, const N: usize>(
+    samples: &[TrainingSample<N>],
+    params: &[f32],
+    sigmoid: &F,
+    sigmoid_derived: &FDx,
+) -> Vec<f32>
+where
+    F: Fn(f32) -> f32 + Sync,
+    FDx: Fn(f32) -> f32 + Sync,
+{
+    let network = Network::new(params, N).unwrap();
+    let num_hidden = network.num_hidden();
+    let hidden_biases_start = num_hidden * N;
+    let output_weights_start = hidden_biases_start + num_hidden;
+
+    let mut slopes = samples
+        .par_iter()
+        .fold(
+            || vec![0.0; params.len()],
+            |mut slopes,
+             TrainingSample {
+                 features,
+                 offset,
+                 result,
+             }| {
+                let inputs = inputs(features);
+                let mut activations = vec![0.0; num_hidden];
+                let output = network
+                    .forward_with_hidden(&inputs, |i, activation| activations[i] = activation)
+                    + offset;
+                let output_slope = (sigmoid(output) - result.to_f32()) * sigmoid_derived(output);
+
+                for (i, activation) in activations.into_iter().enumerate() {
+                    slopes[output_weights_start + i] += output_slope * activation;
+                    // The derivative of ReLU is zero for inactive neurons
+                    if activation > 0.0 {
+                        let hidden_slope = output_slope * network.output_weights()[i];
+                        slopes[hidden_biases_start + i] += hidden_slope;
+                        for (slope, input) in slopes[i * N..(i + 1) * N].iter_mut().zip(&inputs) {
+                            *slope += hidden_slope * input;
+                        }
+                    }
+                }
+                *slopes.last_mut().unwrap() += output_slope;
+                slopes
+            },
+        )
+        .reduce(
+            || vec![0.0; params.len()],
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            },
+        );
+
+    for slope in slopes.iter_mut() {
+        *slope /= samples.len() as f32;
+    }
+    slopes
+}
+
+/// Mean squared error of the network, measured against given results and positions
+pub fn average_error<F, const N: usize>(
+    samples: &[TrainingSample<N>],
+    params: &[f32],
+    sigmoid: &F,
+) -> f64
+where
+    F: Fn(f32) -> f32 + Sync,
+{
+    let network = Network::new(params, N).unwrap();
+    samples
+        .par_iter()
+        .map(
+            |TrainingSample {
+                 features,
+                 result,
+                 offset,
+             }| {
+                (sigmoid(network.forward(&inputs(features)) + offset) - result.to_f32()).powi(2)
+            },
+        )
+        .map(|f| f as f64)
+        .sum::<f64>()
+        / (samples.len() as f64)
+}
@@ -1,4 +1,3 @@
-use std::time::Duration;
 use std::time::Instant;
 
 use board_game_traits::{Color, Position as PositionTrait};
@@ -7,6 +6,7 @@ use half::f16;
 use pgn_traits::PgnPosition;
 use rand::seq::SliceRandom;
 
+use crate::alpha_beta::{AlphaBeta, AlphaBetaSetting};
 use crate::position::ExpMove;
 use crate::position::Komi;
 use crate::position::Move;
@@ -14,13 +14,66 @@ use crate::position::Position;
 use crate::position::Role;
 use crate::ptn::{Game, PtnMove};
 use crate::search;
+use crate::search::time::TimeBudget;
 use crate::search::MctsSetting;
 use crate::search::TimeControl;
 
-/// Play a single training game between two parameter sets
+/// The engine playing one side of a game
+#[derive(Clone, PartialEq, Debug)]
+pub enum Player<const S: usize> {
+    Mcts(MctsSetting<S>),
+    AlphaBeta(AlphaBetaSetting<S>),
+}
+
+impl<const S: usize> From<MctsSetting<S>> for Player<S> {
+    fn from(settings: MctsSetting<S>) -> Self {
+        Player::Mcts(settings)
+    }
+}
+
+impl<const S: usize> From<AlphaBetaSetting<S>> for Player<S> {
+    fn from(settings: AlphaBetaSetting<S>) -> Self {
+        Player::AlphaBeta(settings)
+    }
+}
+
+impl<const S: usize> Player<S> {
+    /// Search the position, returning the moves with their scores.
+    /// `clock` is the player's current time control state, with its time left.
+    /// The alpha-beta engine only returns its best move, with a score of 1
+    pub(crate) fn search(
+        &self,
+        position: &Position<S>,
+        clock: &TimeControl,
+    ) -> Vec<(Move<S>, f16)> {
+        match self {
+            Player::Mcts(settings) => {
+                search::mcts_training::<S>(position.clone(), clock, settings.clone())
+            }
+            Player::AlphaBeta(settings) => {
+                // Allocating the transposition table takes time, which counts against the time budget
+                let setup_start_time = Instant::now();
+                let mut engine = AlphaBeta::new(settings.clone());
+                let search_info = match clock {
+                    TimeControl::FixedNodes(nodes) => engine.search_nodes(position, *nodes),
+                    TimeControl::Time(..) | TimeControl::MovesToGo(..) => {
+                        let max_time = clock.clock().unwrap().training_max_time();
+                        let budget = TimeBudget::fixed(max_time)
+                            .limit(max_time / 2, max_time)
+                            .saturating_sub(setup_start_time.elapsed());
+                        engine.search_for_time(position, budget, None, |_| {})
+                    }
+                };
+                vec![(search_info.unwrap().best_move(), f16::ONE)]
+            }
+        }
+    }
+}
+
+/// Play a single game between two engines, or two parameter sets
 pub fn play_game<const S: usize>(
-    white_settings: &MctsSetting<S>,
-    black_settings: &MctsSetting<S>,
+    white: &Player<S>,
+    black: &Player<S>,
     komi: Komi,
     opening: &[Move<S>],
     temperature: f64,
@@ -34,10 +87,8 @@ pub fn play_game<const S: usize>(
     }
     let mut rng = rand::thread_rng();
 
-    let (mut white_time_left, mut black_time_left, increment) = match time_control {
-        TimeControl::FixedNodes(_) => (Duration::MAX, Duration::MAX, Duration::ZERO),
-        TimeControl::Time(time, increment) => (*time, *time, *increment),
-    };
+    let mut white_clock = time_control.clone();
+    let mut black_clock = time_control.clone();
 
     while position.game_result().is_none() {
         let num_plies = game_moves.len();
@@ -47,35 +98,12 @@ pub fn play_game<const S: usize>(
 
         let start_time = Instant::now();
 
-        let moves_scores = match (time_control, position.side_to_move()) {
-            (TimeControl::FixedNodes(_), Color::White) => {
-                search::mcts_training::<S>(position.clone(), time_control, white_settings.clone())
-            }
-            (TimeControl::FixedNodes(_), Color::Black) => {
-                search::mcts_training::<S>(position.clone(), time_control, black_settings.clone())
-            }
-            (TimeControl::Time(_, _), Color::White) => search::mcts_training::<S>(
-                position.clone(),
-                &TimeControl::Time(white_time_left, increment),
-                white_settings.clone(),
-            ),
-            (TimeControl::Time(_, _), Color::Black) => search::mcts_training::<S>(
-                position.clone(),
-                &TimeControl::Time(black_time_left, increment),
-                white_settings.clone(),
-            ),
+        let (player, clock) = match position.side_to_move() {
+            Color::White => (white, &mut white_clock),
+            Color::Black => (black, &mut black_clock),
         };
-
-        match position.side_to_move() {
-            Color::White => {
-                white_time_left -= start_time.elapsed();
-                white_time_left += increment;
-            }
-            Color::Black => {
-                black_time_left -= start_time.elapsed();
-                black_time_left += increment;
-            }
-        }
+        let moves_scores = player.search(&position, clock);
+        *clock = clock.after_move(start_time.elapsed(), time_control);
 
         // For white's first and second move, choose a random flatstone move
         // This reduces white's first move advantage, and prevents white from "cheesing"
@@ -83,12 +111,13 @@ pub fn play_game<const S: usize>(
         let best_move = if komi.half_komi() < 4
             && (position.half_moves_played() == 0 || position.half_moves_played() == 2)
         {
-            let flat_moves = moves_scores
-                .iter()
-                .map(|(mv, _)| mv)
+            let mut legal_moves = vec![];
+            position.generate_moves(&mut legal_moves);
+            let flat_moves = legal_moves
+                .into_iter()
                 .filter(|mv| matches!(mv.expand(), ExpMove::Place(Role::Flat, _)))
                 .collect::<Vec<_>>();
-            **flat_moves.choose(&mut rng).unwrap()
+            *flat_moves.choose(&mut rng).unwrap()
         } else {
             // Turn off temperature after the opening (after `2 * (S - 1)` ply), when all games are expected to be unique
             let temperature = (position.half_moves_played() < 2 * (S - 1)).then_some(temperature);
@@ -123,10 +152,12 @@ pub fn play_game<const S: usize>(
                     mv,
                     annotations: vec![],
                     comment: String::new(),
+                    variations: vec![],
                 })
                 .collect::<Vec<_>>(),
             game_result_str: position.pgn_game_result(),
             tags,
+            comment: String::new(),
         },
         move_scores,
     )
@@ -0,0 +1,147 @@
+//! Match statistics: Elo difference with error bars, likelihood of superiority, and the sequential probability ratio test (SPRT)
+//!
+//! The SPRT uses the normal approximation of the trinomial (win/draw/loss) model, like cutechess-cli.
+
+use std::fmt;
+
+/// Results of a match, from the first engine's perspective
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u64,
+    pub losses: u64,
+    pub draws: u64,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u64 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Average score per game, between 0 and 1
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// Variance of the score of a single game
+    fn variance(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        let score = self.score();
+        (self.wins as f64 * (1.0 - score).powi(2)
+            + self.losses as f64 * score.powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2))
+            / self.games() as f64
+    }
+
+    /// Estimated Elo difference. Infinite if one engine won every game
+    pub fn elo(&self) -> f64 {
+        score_to_elo(self.score())
+    }
+
+    /// Half the width of the 95% confidence interval of the Elo difference. Infinite if the interval includes a score of 0 or 1
+    pub fn elo_error(&self) -> f64 {
+        if self.games() == 0 {
+            return f64::INFINITY;
+        }
+        let score_error = 1.959964 * (self.variance() / self.games() as f64).sqrt();
+        let score = self.score();
+        let upper_elo = score_to_elo((score + score_error).min(1.0));
+        let lower_elo = score_to_elo((score - score_error).max(0.0));
+        (upper_elo - lower_elo) / 2.0
+    }
+
+    /// Likelihood of superiority, the probability that the first engine is stronger
+    pub fn los(&self) -> f64 {
+        if self.wins + self.losses == 0 {
+            return 0.5;
+        }
+        let decisive_games = (self.wins + self.losses) as f64;
+        0.5 * (1.0 + erf((self.wins as f64 - self.losses as f64) / (2.0 * decisive_games).sqrt()))
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{}-{}={}", self.wins, self.losses, self.draws)
+    }
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10.0_f64.powf(-elo / 400.0))
+}
+
+/// Abramowitz and Stegun's approximation of the error function, accurate to about 1e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+    if x >= 0.0 {
+        y
+    } else {
+        -y
+    }
+}
+
+/// Test whether the Elo difference is `elo0` (the null hypothesis) or `elo1`,
+/// with false positive rate `alpha` and false negative rate `beta`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtResult {
+    /// Not enough games to decide yet
+    Continue,
+    /// The Elo difference is likely `elo0` or less
+    AcceptH0,
+    /// The Elo difference is likely `elo1` or more
+    AcceptH1,
+}
+
+impl Sprt {
+    /// The log-likelihood ratio bounds. The test stops when the ratio falls outside them
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// Log-likelihood ratio of H1 versus H0
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        let variance = score.variance();
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let score0 = elo_to_score(self.elo0);
+        let score1 = elo_to_score(self.elo1);
+        score.games() as f64 * (score1 - score0) * (2.0 * score.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+
+    pub fn result(&self, score: &MatchScore) -> SprtResult {
+        let llr = self.llr(score);
+        let (lower_bound, upper_bound) = self.bounds();
+        if llr <= lower_bound {
+            SprtResult::AcceptH0
+        } else if llr >= upper_bound {
+            SprtResult::AcceptH1
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
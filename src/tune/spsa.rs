@@ -102,8 +102,8 @@ fn tuning_iteration<R: rand::Rng, const S: usize>(
         .add_search_params(player2_variables.iter().map(|(_, a)| *a).collect());
 
     let (game, _) = play_game::<S>(
-        &player1_settings,
-        &player2_settings,
+        &player1_settings.into(),
+        &player2_settings.into(),
         komi,
         opening,
         0.2,
@@ -0,0 +1,139 @@
+//! Client for external engines that speak the Tak Engine Interface (TEI) over stdin/stdout
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::position::{Komi, Move};
+use crate::search::time::Clock;
+
+/// A running external engine process
+pub struct TeiEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// The name the engine reported with `id name`, if any
+    pub name: Option<String>,
+}
+
+impl TeiEngine {
+    /// Start the engine, and wait for it to finish the `tei` handshake
+    pub fn start(program: &str, args: &[String]) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut engine = TeiEngine {
+            child,
+            stdin,
+            stdout,
+            name: None,
+        };
+
+        engine.send_line("tei")?;
+        loop {
+            let line = engine.read_line()?;
+            if line.trim() == "teiok" {
+                break;
+            }
+            if let Some(name) = line.trim().strip_prefix("id name ") {
+                engine.name = Some(name.to_string());
+            }
+        }
+        Ok(engine)
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()
+    }
+
+    /// Read the next line from the engine. Returns an error if the engine has exited
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The engine exited",
+            ));
+        }
+        Ok(line)
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        self.send_line(&format!("setoption name {} value {}", name, value))
+    }
+
+    /// Start a new game, and wait until the engine is ready
+    pub fn new_game(&mut self, size: usize, komi: Komi) -> io::Result<()> {
+        self.set_option("HalfKomi", &komi.half_komi().to_string())?;
+        self.send_line(&format!("teinewgame {}", size))?;
+        self.send_line("isready")?;
+        while self.read_line()?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    /// Send the game so far, and search it with the given clocks.
+    /// Returns the move string from the engine's `bestmove`, which is not checked for legality
+    pub fn go<const S: usize>(
+        &mut self,
+        moves: &[Move<S>],
+        white_clock: Clock,
+        black_clock: Clock,
+    ) -> io::Result<String> {
+        let mut position_command = "position startpos".to_string();
+        if !moves.is_empty() {
+            position_command.push_str(" moves");
+            for mv in moves {
+                position_command.push(' ');
+                position_command.push_str(&mv.to_string());
+            }
+        }
+        self.send_line(&position_command)?;
+        let mut go_command = format!(
+            "go wtime {} btime {} winc {} binc {}",
+            white_clock.time_left.as_millis(),
+            black_clock.time_left.as_millis(),
+            white_clock.increment.as_millis(),
+            black_clock.increment.as_millis()
+        );
+        // The game always starts from the start position, so the number of moves gives the side to move
+        let side_to_move_clock = if moves.len().is_multiple_of(2) {
+            white_clock
+        } else {
+            black_clock
+        };
+        if let Some(moves_to_go) = side_to_move_clock.moves_to_go {
+            go_command.push_str(&format!(" movestogo {}", moves_to_go));
+        }
+        self.send_line(&go_command)?;
+        loop {
+            let line = self.read_line()?;
+            let mut words = line.split_whitespace();
+            if words.next() == Some("bestmove") {
+                return words.next().map(ToString::to_string).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Empty bestmove from engine")
+                });
+            }
+        }
+    }
+}
+
+impl Drop for TeiEngine {
+    fn drop(&mut self) {
+        let _ = self.send_line("quit");
+        // Give the engine a second to exit by itself
+        for _ in 0..20 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
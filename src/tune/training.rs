@@ -7,6 +7,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::time;
 use std::{error, fs, io};
 
+use crate::evaluation::network::{self, NetworkFile, NetworkKind};
 use crate::evaluation::parameters::Policy;
 use crate::evaluation::parameters::PolicyApplier;
 use crate::evaluation::parameters::Value;
@@ -29,7 +30,11 @@ use crate::ptn::{ptn_parser, PtnMove};
 use crate::search::MctsSetting;
 use crate::tune::gradient_descent;
 use crate::tune::gradient_descent::TrainingSample;
+use crate::tune::network_training;
 use crate::tune::play_match::play_game;
+use crate::tune::training_data::{
+    read_samples_from_file, samples_from_game, PositionSample, SampleWriter,
+};
 
 // The score, or probability of being played, for a given move
 type MoveScore<const S: usize> = (Move<S>, f16);
@@ -89,11 +94,49 @@ pub fn continue_training<const S: usize, const N: usize, const M: usize>(
                 }
             }
         }
-        let mut move_scores_batch = read_move_scores_from_file::<S>(&format!(
-            "move_scores{}_{}s_batch{}.ptn",
+        let samples_file_name = format!(
+            "samples{}_{}s_batch{}.bin",
             options.training_id, S, batch_id
-        ))?;
-        move_scores.append(&mut move_scores_batch);
+        );
+        match read_samples_from_file::<S>(&samples_file_name) {
+            Ok(samples) => {
+                let mut samples = samples.into_iter();
+                for game in &games[move_scores.len()..] {
+                    let game_move_scores: MoveScoresForGame<S> = samples
+                        .by_ref()
+                        .take(game.moves.len())
+                        .map(|sample| sample.move_scores)
+                        .collect();
+                    if game_move_scores.len() != game.moves.len() {
+                        return Err(format!(
+                            "{} has {} samples for a game with {} moves",
+                            samples_file_name,
+                            game_move_scores.len(),
+                            game.moves.len()
+                        )
+                        .into());
+                    }
+                    move_scores.push(game_move_scores);
+                }
+                if samples.len() != 0 {
+                    return Err(format!(
+                        "{} has {} samples left over after the last game",
+                        samples_file_name,
+                        samples.len()
+                    )
+                    .into());
+                }
+            }
+            // Batches from older versions only have the move scores as text
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                let mut move_scores_batch = read_move_scores_from_file::<S>(&format!(
+                    "move_scores{}_{}s_batch{}.ptn",
+                    options.training_id, S, batch_id
+                ))?;
+                move_scores.append(&mut move_scores_batch);
+            }
+            Err(error) => return Err(error.into()),
+        }
         batch_id += 1;
     }
 
@@ -177,34 +220,18 @@ pub fn train_perpetually<const S: usize, const N: usize, const M: usize>(
             game.game_to_ptn(&mut writer)?;
         }
 
-        let games_and_move_scores_outfile = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(format!(
-                "move_scores{}_{}s_batch{}.ptn",
-                options.training_id, S, batch_id
-            ))
-            .unwrap();
         writer.flush()?;
 
-        let mut writer = io::BufWriter::new(games_and_move_scores_outfile);
-
+        let mut sample_writer = SampleWriter::new(io::BufWriter::new(fs::File::create(format!(
+            "samples{}_{}s_batch{}.bin",
+            options.training_id, S, batch_id
+        ))?))?;
         for (game, move_scores) in games.iter().zip(move_scores) {
-            for (mv, move_scores) in game
-                .moves
-                .iter()
-                .map(|PtnMove { mv, .. }| mv)
-                .zip(move_scores)
-            {
-                write!(writer, "{}: ", mv)?;
-                for (mv, score) in move_scores {
-                    write!(writer, "{} {}, ", mv, score)?;
-                }
-                writeln!(writer)?;
+            for sample in samples_from_game(game, &move_scores)? {
+                sample_writer.write_sample(&sample)?;
             }
-            writeln!(writer)?;
         }
-        writer.flush()?;
+        sample_writer.finish()?;
 
         let game_stats = GameStats::from_games(&games);
 
@@ -280,10 +307,10 @@ fn play_game_pair<const S: usize>(
         .add_value_params(last_value_params)
         .add_policy_params(last_policy_params)
         .add_dirichlet(0.2);
-    if i % 2 == 0 {
+    if i.is_multiple_of(2) {
         let game = play_game::<S>(
-            &settings,
-            &last_settings,
+            &settings.into(),
+            &last_settings.into(),
             komi,
             &[],
             1.0,
@@ -301,8 +328,8 @@ fn play_game_pair<const S: usize>(
         game
     } else {
         let game = play_game::<S>(
-            &last_settings,
-            &settings,
+            &last_settings.into(),
+            &settings.into(),
             komi,
             &[],
             1.0,
@@ -352,7 +379,10 @@ pub fn read_games_from_file<const S: usize>(
     let mut file = fs::File::open(file_name)?;
     let mut input = String::new();
     file.read_to_string(&mut input)?;
-    let mut games = ptn_parser::parse_ptn::<Position<S>>(&input)?;
+    let (mut games, errors) = ptn_parser::parse_ptn_lenient::<Position<S>>(&input);
+    for error in errors.iter() {
+        eprintln!("Skipped game in {}: {}", file_name, error);
+    }
     for game in games.iter_mut() {
         if let Some((_, komi_str)) = game
             .tags
@@ -449,42 +479,123 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
     komi: Komi,
     initial_value_params: &[f32; N],
     initial_policy_params: &[f32; M],
+) -> Result<([f32; N], [f32; M]), DynError> {
+    let samples: Vec<PositionSample<S>> = games
+        .par_iter()
+        .zip(move_scoress)
+        .map(|(game, move_scores)| samples_from_game(game, move_scores))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    assert!(samples.iter().all(|sample| sample.position.komi() == komi));
+
+    tune_value_and_policy_from_samples(&samples, initial_value_params, initial_policy_params)
+}
+
+pub fn tune_value_and_policy_from_samples<const S: usize, const N: usize, const M: usize>(
+    samples: &[PositionSample<S>],
+    initial_value_params: &[f32; N],
+    initial_policy_params: &[f32; M],
 ) -> Result<([f32; N], [f32; M]), DynError> {
     let mut rng = rand::rngs::StdRng::from_seed(Default::default());
 
-    let (positions, results) = positions_and_results_from_games(games, komi);
+    let value_training_samples = value_training_samples::<S, N>(samples, &mut rng);
+    let policy_training_samples = policy_training_samples::<S, M>(samples, &mut rng);
 
-    let start_time = time::Instant::now();
-    let mut value_training_samples = positions
-        .par_iter()
-        .zip(results)
-        .map(|(position, game_result)| {
-            let mut white_features: Value<S> = Value::new(&[]);
-            let mut black_features: Value<S> = Value::new(&[]);
-            position.static_eval_features(&mut white_features, &mut black_features);
+    let tuned_value_parameters = gradient_descent::gradient_descent(
+        &value_training_samples,
+        initial_value_params,
+        50.0,
+        &mut rng,
+        &value_eval::sigmoid,
+        &value_eval::sigmoid_derived,
+    );
 
-            let features = white_features
-                .features
-                .into_iter()
-                .chain(black_features.features)
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap();
+    let tuned_policy_parameters = gradient_descent::gradient_descent(
+        &policy_training_samples,
+        initial_policy_params,
+        500.0,
+        &mut rng,
+        &policy_eval::sigmoid,
+        &policy_eval::sigmoid_derived,
+    );
 
-            let result = match game_result {
-                GameResult::WhiteWin => f16::ONE,
-                GameResult::Draw => f16::ONE / (f16::ONE + f16::ONE),
-                GameResult::BlackWin => f16::ZERO,
-            };
-            TrainingSample {
-                features,
-                offset: 0.0,
-                result,
-            }
-        })
+    Ok((tuned_value_parameters, tuned_policy_parameters))
+}
+
+/// Train value and policy networks with `num_hidden` hidden neurons, from randomly initialized weights
+pub fn train_networks_from_samples<const S: usize, const N: usize, const M: usize>(
+    samples: &[PositionSample<S>],
+    num_hidden: usize,
+) -> (Vec<f32>, Vec<f32>) {
+    let mut rng = rand::rngs::StdRng::from_seed(Default::default());
+
+    let value_training_samples = value_training_samples::<S, N>(samples, &mut rng);
+    let policy_training_samples = policy_training_samples::<S, M>(samples, &mut rng);
+
+    let value_network = network_training::train_network(
+        &value_training_samples,
+        &network::random_network_params(N, num_hidden, &mut rng),
+        1.0,
+        &mut rng,
+        &value_eval::sigmoid,
+        &value_eval::sigmoid_derived,
+    );
+
+    let policy_network = network_training::train_network(
+        &policy_training_samples,
+        &network::random_network_params(M, num_hidden, &mut rng),
+        10.0,
+        &mut rng,
+        &policy_eval::sigmoid,
+        &policy_eval::sigmoid_derived,
+    );
+
+    (value_network, policy_network)
+}
+
+/// Value features for every position, with the game result as the target
+fn value_training_samples<const S: usize, const N: usize>(
+    samples: &[PositionSample<S>],
+    rng: &mut StdRng,
+) -> Vec<TrainingSample<N>> {
+    let start_time = time::Instant::now();
+    let mut value_training_samples = samples
+        .par_iter()
+        .map(
+            |PositionSample {
+                 position,
+                 result: game_result,
+                 ..
+             }| {
+                let mut white_features: Value<S> = Value::new(&[]);
+                let mut black_features: Value<S> = Value::new(&[]);
+                position.static_eval_features(&mut white_features, &mut black_features);
+
+                let features = white_features
+                    .features
+                    .into_iter()
+                    .chain(black_features.features)
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap();
+
+                let result = match game_result {
+                    GameResult::WhiteWin => f16::ONE,
+                    GameResult::Draw => f16::ONE / (f16::ONE + f16::ONE),
+                    GameResult::BlackWin => f16::ZERO,
+                };
+                TrainingSample {
+                    features,
+                    offset: 0.0,
+                    result,
+                }
+            },
+        )
         .collect::<Vec<_>>();
 
-    value_training_samples.shuffle(&mut rng);
+    value_training_samples.shuffle(rng);
 
     println!(
         "Generated {} value training samples in {:.1}s, {:.2}GiB total",
@@ -493,59 +604,60 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
         (value_training_samples.len() * mem::size_of::<TrainingSample<N>>()) as f32
             / f32::powf(2.0, 30.0),
     );
+    value_training_samples
+}
 
-    let number_of_feature_sets = move_scoress.iter().flatten().flatten().count();
+/// Policy features for every move with a score, with the score as the target
+fn policy_training_samples<const S: usize, const M: usize>(
+    samples: &[PositionSample<S>],
+    rng: &mut StdRng,
+) -> Vec<TrainingSample<M>> {
+    let number_of_feature_sets = samples.iter().map(|sample| sample.move_scores.len()).sum();
 
     let start_time: time::Instant = time::Instant::now();
 
     let mut policy_training_samples: Vec<TrainingSample<M>> =
         Vec::with_capacity(number_of_feature_sets);
 
-    policy_training_samples.extend(games.iter().zip(move_scoress.iter()).flat_map(
-        |(game, move_scores)| {
-            let mut position = game.start_position.clone();
+    policy_training_samples.extend(samples.iter().flat_map(
+        |PositionSample {
+             position,
+             move_scores,
+             ..
+         }| {
+            let group_data = position.group_data();
+
+            let mut policies: Vec<Policy<S>> = vec![Policy::new(&[]); move_scores.len()];
+            let moves: Vec<Move<S>> = move_scores.iter().map(|(mv, _score)| *mv).collect();
+
+            position.features_for_moves(
+                &mut policies,
+                &moves,
+                &mut Vec::with_capacity(moves.len()),
+                &group_data,
+            );
 
-            game.moves
+            move_scores
                 .iter()
-                .map(|PtnMove { mv, .. }| mv)
-                .zip(move_scores.iter())
-                .flat_map(move |(mv, move_scores)| {
-                    let group_data = position.group_data();
-
-                    let mut policies: Vec<Policy<S>> = vec![Policy::new(&[]); move_scores.len()];
-                    let moves: Vec<Move<S>> = move_scores.iter().map(|(mv, _score)| *mv).collect();
-
-                    position.features_for_moves(
-                        &mut policies,
-                        &moves,
-                        &mut Vec::with_capacity(moves.len()),
-                        &group_data,
-                    );
-
-                    position.do_move(*mv);
-
-                    move_scores
-                        .iter()
-                        .zip(
-                            policies
-                                .into_iter()
-                                .map(|pol| pol.features.try_into().unwrap()),
-                        )
-                        .map(|((_, result), features)| {
-                            let offset = policy_offset(move_scores.len());
-                            {
-                                TrainingSample {
-                                    features,
-                                    offset,
-                                    result: *result,
-                                }
-                            }
-                        })
+                .zip(
+                    policies
+                        .into_iter()
+                        .map(|pol| pol.features.try_into().unwrap()),
+                )
+                .map(|((_, result), features)| {
+                    let offset = policy_offset(move_scores.len());
+                    {
+                        TrainingSample {
+                            features,
+                            offset,
+                            result: *result,
+                        }
+                    }
                 })
         },
     ));
 
-    policy_training_samples.shuffle(&mut rng);
+    policy_training_samples.shuffle(rng);
     println!(
         "Generated {} policy training samples in {:.1}s, {:.2}GiB total",
         policy_training_samples.len(),
@@ -553,26 +665,7 @@ pub fn tune_value_and_policy<const S: usize, const N: usize, const M: usize>(
         (policy_training_samples.len() * mem::size_of::<TrainingSample<M>>()) as f32
             / f32::powf(2.0, 30.0),
     );
-
-    let tuned_value_parameters = gradient_descent::gradient_descent(
-        &value_training_samples,
-        initial_value_params,
-        50.0,
-        &mut rng,
-        &value_eval::sigmoid,
-        &value_eval::sigmoid_derived,
-    );
-
-    let tuned_policy_parameters = gradient_descent::gradient_descent(
-        &policy_training_samples,
-        initial_policy_params,
-        500.0,
-        &mut rng,
-        &policy_eval::sigmoid,
-        &policy_eval::sigmoid_derived,
-    );
-
-    Ok((tuned_value_parameters, tuned_policy_parameters))
+    policy_training_samples
 }
 
 pub fn tune_value_and_policy_from_file<const S: usize, const N: usize, const M: usize>(
@@ -597,6 +690,77 @@ pub fn tune_value_and_policy_from_file<const S: usize, const N: usize, const M:
     )
 }
 
+pub fn tune_value_and_policy_from_samples_file<const S: usize, const N: usize, const M: usize>(
+    file_name: &str,
+) -> Result<([f32; N], [f32; M]), DynError> {
+    let start_time = time::Instant::now();
+    let samples = read_samples_from_file::<S>(file_name)?;
+    println!(
+        "Read {} samples in {:.1}s",
+        samples.len(),
+        start_time.elapsed().as_secs_f32()
+    );
+    let mut rng = rand::rngs::StdRng::from_seed(Default::default());
+
+    let initial_value_params: [f32; N] = array_from_fn(|| rng.gen_range(-0.01..0.01));
+
+    let initial_policy_params: [f32; M] = array_from_fn(|| rng.gen_range(-0.01..0.01));
+
+    tune_value_and_policy_from_samples(&samples, &initial_value_params, &initial_policy_params)
+}
+
+/// Train value and policy networks from a binary samples file, and write them to network files
+pub fn train_networks_from_samples_file<const S: usize, const N: usize, const M: usize>(
+    file_name: &str,
+    num_hidden: usize,
+    value_output_file_name: &str,
+    policy_output_file_name: &str,
+) -> Result<(), DynError> {
+    let samples = read_samples_from_file::<S>(file_name)?;
+    println!("Read {} samples", samples.len());
+
+    let (value_network, policy_network) =
+        train_networks_from_samples::<S, N, M>(&samples, num_hidden);
+
+    for (kind, params, output_file_name) in [
+        (NetworkKind::Value, value_network, value_output_file_name),
+        (NetworkKind::Policy, policy_network, policy_output_file_name),
+    ] {
+        let network_file = NetworkFile {
+            kind,
+            size: S,
+            params,
+        };
+        network_file.write(io::BufWriter::new(fs::File::create(output_file_name)?))?;
+        println!("Wrote {} network to {}", kind, output_file_name);
+    }
+    Ok(())
+}
+
+/// Convert a ptn file of games and a text file of their move scores into a binary samples file
+pub fn convert_to_samples_file<const S: usize>(
+    games_file_name: &str,
+    move_scores_file_name: &str,
+    output_file_name: &str,
+    komi: Komi,
+) -> Result<(), DynError> {
+    let games = read_games_from_file::<S>(games_file_name, komi)?;
+    let move_scoress = read_move_scores_from_file::<S>(move_scores_file_name)?;
+    assert_eq!(games.len(), move_scoress.len());
+
+    let mut writer = SampleWriter::new(io::BufWriter::new(fs::File::create(output_file_name)?))?;
+    let mut num_samples = 0;
+    for (game, move_scores) in games.iter().zip(&move_scoress) {
+        for sample in samples_from_game(game, move_scores)? {
+            writer.write_sample(&sample)?;
+            num_samples += 1;
+        }
+    }
+    writer.finish()?;
+    println!("Wrote {} samples to {}", num_samples, output_file_name);
+    Ok(())
+}
+
 type DynError = Box<dyn error::Error + Send + Sync>;
 
 pub fn games_and_move_scoress_from_file<const S: usize>(
@@ -0,0 +1,282 @@
+//! A compact binary format for training samples, with a streaming writer and reader.
+//!
+//! A file starts with the magic bytes `TILTAKTD`, a format version and the board size. It is followed by the samples, each of which is:
+//! * For each square, its stack height as a `u8`. Non-empty stacks are followed by the top stone (role in bits 0-1, color in bit 2),
+//!   and the colors of the stones below it as a little-endian bitset, one bit per stone
+//! * Side to move (`0` for white, `1` for black), and the number of plies played as a `u16`
+//! * Half komi as an `i8`
+//! * Game result (`0` for a white win, `1` for a black win, `2` for a draw)
+//! * Number of move scores as a `u16`, followed by each move as `Move::to_u16`, and its score as an `f16`
+//!
+//! All integers are little-endian.
+
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::str::FromStr;
+
+use board_game_traits::{Color, GameResult, Position as PositionTrait};
+use half::f16;
+
+use crate::position::{squares_iterator, AbstractBoard, Komi, Move, Piece, Position, Role, Stack};
+use crate::ptn::{Game, PtnMove};
+
+const MAGIC: &[u8; 8] = b"TILTAKTD";
+const VERSION: u8 = 1;
+
+/// A position from a game, with the search's move scores
+#[derive(Clone, Debug)]
+pub struct PositionSample<const S: usize> {
+    /// The position, including side to move and komi. Move history is not stored
+    pub position: Position<S>,
+    /// The result of the game the position is from
+    pub result: GameResult,
+    /// The search's visit distribution over the moves. Empty if the position was not searched
+    pub move_scores: Vec<(Move<S>, f16)>,
+}
+
+/// Get one sample for each move of the game, with the position before the move.
+/// Returns an error if the game's komi tag is invalid
+pub fn samples_from_game<const S: usize>(
+    game: &Game<Position<S>>,
+    move_scores: &[Vec<(Move<S>, f16)>],
+) -> io::Result<Vec<PositionSample<S>>> {
+    let result = game.game_result().unwrap_or(GameResult::Draw);
+    let mut position = game.start_position.clone();
+    if let Some((_, komi_str)) = game
+        .tags
+        .iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case("Komi"))
+    {
+        let komi = Komi::from_str(komi_str)
+            .map_err(|err| invalid_data(format!("Invalid komi \"{}\": {}", komi_str, err)))?;
+        position.set_komi(komi);
+    }
+    let mut samples = Vec::with_capacity(game.moves.len());
+    for (PtnMove { mv, .. }, move_scores) in game.moves.iter().zip(move_scores) {
+        if position.game_result().is_some() {
+            break;
+        }
+        samples.push(PositionSample {
+            position: position.clone(),
+            result,
+            move_scores: move_scores.clone(),
+        });
+        position.do_move(*mv);
+    }
+    Ok(samples)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub struct SampleWriter<W: Write, const S: usize> {
+    writer: W,
+}
+
+impl<W: Write, const S: usize> SampleWriter<W, S> {
+    /// Start a new file, by writing the header
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, S as u8])?;
+        Ok(SampleWriter { writer })
+    }
+
+    pub fn write_sample(&mut self, sample: &PositionSample<S>) -> io::Result<()> {
+        let position = &sample.position;
+        for square in squares_iterator::<S>() {
+            let stack = position.get_stack(square);
+            let height = stack.len();
+            self.writer.write_all(&[height])?;
+            if let Some(top_stone) = stack.top_stone() {
+                let color_bit = (top_stone.color() == Color::Black) as u8;
+                self.writer
+                    .write_all(&[top_stone.role() as u8 | color_bit << 2])?;
+                let mut colors = vec![0; height as usize / 8 + 1];
+                for i in 0..height - 1 {
+                    if stack.get(i).unwrap().color() == Color::Black {
+                        colors[i as usize / 8] |= 1 << (i % 8);
+                    }
+                }
+                self.writer
+                    .write_all(&colors[..(height as usize - 1).div_ceil(8)])?;
+            }
+        }
+
+        let side_to_move = (position.side_to_move() == Color::Black) as u8;
+        self.writer.write_all(&[side_to_move])?;
+        self.writer
+            .write_all(&(position.half_moves_played() as u16).to_le_bytes())?;
+        self.writer
+            .write_all(&position.komi().half_komi().to_le_bytes())?;
+        let result = match sample.result {
+            GameResult::WhiteWin => 0,
+            GameResult::BlackWin => 1,
+            GameResult::Draw => 2,
+        };
+        self.writer.write_all(&[result])?;
+
+        self.writer
+            .write_all(&(sample.move_scores.len() as u16).to_le_bytes())?;
+        for (mv, score) in sample.move_scores.iter() {
+            self.writer.write_all(&mv.to_u16().to_le_bytes())?;
+            self.writer.write_all(&score.to_bits().to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Flush the writer, and return it
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads samples one at a time. Any reader works, including a byte slice of a memory-mapped file
+pub struct SampleReader<R: Read, const S: usize> {
+    reader: R,
+}
+
+impl<R: Read, const S: usize> SampleReader<R, S> {
+    /// Check the header, and return an error if the file is not a sample file for size `S`
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 10];
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(invalid_data("Not a training sample file".to_string()));
+        }
+        if header[8] != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported training sample version {}",
+                header[8]
+            )));
+        }
+        if header[9] as usize != S {
+            return Err(invalid_data(format!(
+                "Expected samples for size {}, got size {}",
+                S, header[9]
+            )));
+        }
+        Ok(SampleReader { reader })
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buffer = [0; 1];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buffer = [0; 2];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    fn read_stack(&mut self, height: u8) -> io::Result<Stack> {
+        let mut stack = Stack::default();
+        if height == 0 {
+            return Ok(stack);
+        }
+        if height > 64 {
+            return Err(invalid_data(format!("Invalid stack height {}", height)));
+        }
+        let top_stone = self.read_u8()?;
+        if top_stone & 3 > 2 || top_stone > 7 {
+            return Err(invalid_data(format!("Invalid top stone {}", top_stone)));
+        }
+        let mut colors = vec![0; (height as usize - 1).div_ceil(8)];
+        self.reader.read_exact(&mut colors)?;
+        for i in 0..height as usize - 1 {
+            let color = if colors[i / 8] & (1 << (i % 8)) == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+            stack.push(Piece::from_role_color(Role::Flat, color));
+        }
+        let top_color = if top_stone & 4 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        stack.push(Piece::from_role_color(
+            Role::from_disc(top_stone & 3),
+            top_color,
+        ));
+        Ok(stack)
+    }
+
+    /// Read the next sample, or `None` at the end of the input
+    pub fn read_sample(&mut self) -> io::Result<Option<PositionSample<S>>> {
+        let mut stacks: AbstractBoard<Stack, S> = Default::default();
+        for (i, square) in squares_iterator::<S>().enumerate() {
+            let mut height = [0; 1];
+            if i == 0 && self.reader.read(&mut height)? == 0 {
+                return Ok(None);
+            } else if i > 0 {
+                self.reader.read_exact(&mut height)?;
+            }
+            stacks[square] = self.read_stack(height[0])?;
+        }
+
+        let side_to_move = match self.read_u8()? {
+            0 => Color::White,
+            1 => Color::Black,
+            n => return Err(invalid_data(format!("Invalid side to move {}", n))),
+        };
+        let half_moves_played = self.read_u16()? as usize;
+        let half_komi = self.read_u8()? as i8;
+        let komi = Komi::from_half_komi(half_komi)
+            .ok_or_else(|| invalid_data(format!("Invalid half komi {}", half_komi)))?;
+        let result = match self.read_u8()? {
+            0 => GameResult::WhiteWin,
+            1 => GameResult::BlackWin,
+            2 => GameResult::Draw,
+            n => return Err(invalid_data(format!("Invalid game result {}", n))),
+        };
+
+        let num_move_scores = self.read_u16()?;
+        let mut move_scores = Vec::with_capacity(num_move_scores as usize);
+        for _ in 0..num_move_scores {
+            let move_data = self.read_u16()?;
+            let mv = Move::from_u16(move_data)
+                .ok_or_else(|| invalid_data(format!("Invalid move {}", move_data)))?;
+            let score = f16::from_bits(self.read_u16()?);
+            move_scores.push((mv, score));
+        }
+
+        Position::check_reserves(&stacks).map_err(invalid_data)?;
+
+        Ok(Some(PositionSample {
+            position: Position::from_stacks(&stacks, side_to_move, half_moves_played, komi),
+            result,
+            move_scores,
+        }))
+    }
+}
+
+impl<R: Read, const S: usize> Iterator for SampleReader<R, S> {
+    type Item = io::Result<PositionSample<S>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_sample().transpose()
+    }
+}
+
+pub fn write_samples_to_file<const S: usize>(
+    file_name: &str,
+    samples: &[PositionSample<S>],
+) -> io::Result<()> {
+    let mut writer = SampleWriter::new(BufWriter::new(fs::File::create(file_name)?))?;
+    for sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn read_samples_from_file<const S: usize>(
+    file_name: &str,
+) -> io::Result<Vec<PositionSample<S>>> {
+    SampleReader::new(BufReader::new(fs::File::open(file_name)?))?.collect()
+}
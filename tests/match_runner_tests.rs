@@ -0,0 +1,61 @@
+//! Matches against the `tei` binary, as an external engine
+
+use std::time::Duration;
+
+use tiltak::alpha_beta::AlphaBetaSetting;
+use tiltak::position::Komi;
+use tiltak::search::TimeControl;
+use tiltak::tune::match_runner::{run_match, MatchEngine, MatchEngineKind, MatchSettings};
+use tiltak::tune::play_match::Player;
+use tiltak::tune::sprt::{Sprt, SprtResult};
+
+#[test]
+fn tei_engine_match_test() {
+    let tei_engine = MatchEngine::<4> {
+        name: "tei".to_string(),
+        kind: MatchEngineKind::Tei {
+            program: env!("CARGO_BIN_EXE_tei").to_string(),
+            args: vec![],
+            options: vec![("Hash".to_string(), "64".to_string())],
+        },
+    };
+    let alpha_beta = MatchEngine::<4> {
+        name: "alphabeta".to_string(),
+        kind: MatchEngineKind::InProcess(Player::AlphaBeta(
+            AlphaBetaSetting::default().mem_usage(16 * 1024 * 1024),
+        )),
+    };
+    let settings = MatchSettings {
+        komi: Komi::from_half_komi(4).unwrap(),
+        time_control: TimeControl::Time(Duration::from_secs(2), Duration::from_millis(50)),
+        openings: vec![],
+        pairs: 1,
+        concurrency: 1,
+        sprt: Some(Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }),
+    };
+
+    let mut pairs = 0;
+    let result = run_match(&tei_engine, &alpha_beta, &settings, |pair_result, _| {
+        pairs += 1;
+        for game in pair_result.games.iter() {
+            assert!(game.game_result_str.is_some());
+            // Nobody forfeits on time or by illegal moves
+            assert!(
+                !game.tags.iter().any(|(tag, _)| tag == "Termination"),
+                "{:?}",
+                game.tags
+            );
+            assert!(game.tags.contains(&("Komi".to_string(), "2".to_string())));
+        }
+    })
+    .unwrap();
+
+    assert_eq!(pairs, 1);
+    assert_eq!(result.score.games(), 2);
+    assert_eq!(result.sprt_result, Some(SprtResult::Continue));
+}
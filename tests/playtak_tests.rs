@@ -0,0 +1,396 @@
+//! Tests of the `playtak` bot against the mock Playtak server
+
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use board_game_traits::{Color, Position as PositionTrait};
+use pgn_traits::PgnPosition;
+use tiltak::mock_playtak::{ClientMessage, GameStart, MockClient, MockPlaytakServer, Seek};
+use tiltak::position::{Komi, Position};
+use tiltak::ptn::Game;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The bot process, which is killed if the test ends before it exits
+struct Bot(Child);
+
+impl Bot {
+    fn spawn(server: &MockPlaytakServer, args: &[&str]) -> Self {
+        let port = server.local_addr().unwrap().port().to_string();
+        let child = Command::new(env!("CARGO_BIN_EXE_playtak"))
+            .args(["--playtak-base-url", "127.0.0.1", "--playtak-port", &port])
+            .args(["--target-move-time", "1", "--threads", "1"])
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Bot(child)
+    }
+
+    fn wait_for_exit(&mut self) -> bool {
+        let start_time = Instant::now();
+        while start_time.elapsed() < TIMEOUT {
+            if let Some(status) = self.0.try_wait().unwrap() {
+                return status.success();
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("Bot did not exit");
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn accept(server: &mut MockPlaytakServer) -> MockClient {
+    let client = server.accept().unwrap();
+    client.set_read_timeout(Some(TIMEOUT)).unwrap();
+    client
+}
+
+fn game_start(game_no: u64, client: &MockClient, client_color: Color) -> GameStart {
+    let (white_player, black_player) = match client_color {
+        Color::White => (client.name(), "Opponent"),
+        Color::Black => ("Opponent", client.name()),
+    };
+    GameStart {
+        game_no,
+        size: 5,
+        white_player: white_player.to_string(),
+        black_player: black_player.to_string(),
+        client_color,
+        time: Duration::from_secs(60),
+        increment: Duration::from_secs(1),
+        komi: Komi::default(),
+    }
+}
+
+/// Play the first legal move for the opponent
+fn play_opponent_move(
+    client: &mut MockClient,
+    game_no: u64,
+    position: &mut Position<5>,
+) -> io::Result<()> {
+    let mut moves = vec![];
+    position.generate_moves(&mut moves);
+    client.send_move(game_no, moves[0])?;
+    position.do_move(moves[0]);
+    Ok(())
+}
+
+#[test]
+fn seek_and_play_game_test() {
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let _bot = Bot::spawn(
+        &server,
+        &[
+            "--size",
+            "5",
+            "--tc",
+            "60+1",
+            "--komi",
+            "2",
+            "--seek-color",
+            "white",
+        ],
+    );
+    let mut client = accept(&mut server);
+    assert!(client.name().starts_with("Guest"));
+    client.send_online(2).unwrap();
+
+    let seek = client.expect_seek().unwrap();
+    assert_eq!(
+        seek,
+        Seek {
+            size: 5,
+            time: Duration::from_secs(60),
+            increment: Duration::from_secs(1),
+            color: Some(Color::White),
+            komi: Komi::from_half_komi(4).unwrap(),
+            flat_stones: 21,
+            capstones: 1,
+            unrated: false,
+            tournament: false,
+            extra_time: None,
+        }
+    );
+
+    let game = GameStart::from_seek(1, &seek, client.name(), "Opponent");
+    client.start_game(&game).unwrap();
+    let mut position = <Position<5>>::start_position_with_komi(seek.komi);
+    for _ in 0..2 {
+        let mv = client.expect_move(1, &position).unwrap();
+        position.do_move(mv);
+        play_opponent_move(&mut client, 1, &mut position).unwrap();
+    }
+    let mv = client.expect_move(1, &position).unwrap();
+    position.do_move(mv);
+
+    // The bot seeks again after the game
+    client.send_game_over(1, "0-R").unwrap();
+    assert_eq!(client.expect_seek().unwrap(), seek);
+}
+
+#[test]
+fn accept_seek_test() {
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let mut bot = Bot::spawn(&server, &["--size", "5", "--play-bot", "OpponentBot"]);
+    let mut client = accept(&mut server);
+
+    let seek = Seek {
+        size: 5,
+        time: Duration::from_secs(60),
+        increment: Duration::from_secs(1),
+        color: Some(Color::White),
+        komi: Komi::default(),
+        flat_stones: 21,
+        capstones: 1,
+        unrated: false,
+        tournament: false,
+        extra_time: None,
+    };
+    client.send_seek(1, "SomeoneElse", &seek).unwrap();
+    client.send_seek(2, "OpponentBot", &seek).unwrap();
+    assert_eq!(client.read_message().unwrap(), ClientMessage::Accept(2));
+
+    let game = game_start(2, &client, Color::Black);
+    client.start_game(&game).unwrap();
+    let mut position = <Position<5>>::start_position();
+    play_opponent_move(&mut client, 2, &mut position).unwrap();
+    client.expect_move(2, &position).unwrap();
+
+    // The bot exits after playing the game
+    client.send_abandoned(2, "OpponentBot").unwrap();
+    assert!(bot.wait_for_exit());
+}
+
+#[test]
+fn chat_commands_test() {
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let _bot = Bot::spawn(
+        &server,
+        &[
+            "--size",
+            "5",
+            "--tc",
+            "60+1",
+            "--allow-choosing-size",
+            "--username",
+            "TestBot",
+            "--password",
+            "secret",
+        ],
+    );
+    let mut client = accept(&mut server);
+    assert_eq!(client.name(), "TestBot");
+    client.send_online(2).unwrap();
+    assert_eq!(client.expect_seek().unwrap().size, 5);
+
+    client.tell("alice", "TestBot: size 6").unwrap();
+    assert_eq!(
+        client.expect_tell().unwrap(),
+        (
+            "alice".to_string(),
+            "Seeking next game with size 6".to_string()
+        )
+    );
+    assert_eq!(client.expect_seek().unwrap().size, 6);
+
+    client.shout("bob", "TestBot: color white").unwrap();
+    assert_eq!(
+        client.read_message().unwrap(),
+        ClientMessage::Shout("bob Cannot choose color for this bot".to_string())
+    );
+
+    // Messages for other players are ignored
+    client.tell("alice", "OtherBot: size 4").unwrap();
+    client.tell("alice", "TestBot: dance").unwrap();
+    assert_eq!(
+        client.expect_tell().unwrap(),
+        ("alice".to_string(), "Unknown command".to_string())
+    );
+}
+
+#[test]
+fn session_restore_test() {
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let _bot = Bot::spawn(&server, &["--size", "5", "--tc", "60+1"]);
+    let mut client = accept(&mut server);
+
+    // The server resumes an unfinished game by sending the game start and all moves so far
+    let game = game_start(3, &client, Color::White);
+    client.start_game(&game).unwrap();
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["a1", "e5", "c3", "c2"] {
+        let mv = position.move_from_san(move_string).unwrap();
+        client.send_move(3, mv).unwrap();
+        position.do_move(mv);
+    }
+    client.send_message("Your game is resumed").unwrap();
+
+    // The bot continues the game, and seeks after it is over
+    let mv = client.expect_move(3, &position).unwrap();
+    position.do_move(mv);
+    client.send_game_over(3, "R-0").unwrap();
+    assert_eq!(client.expect_seek().unwrap().size, 5);
+}
+
+#[test]
+fn archive_game_test() {
+    let archive_dir = std::env::temp_dir().join(format!("tiltak_archive_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&archive_dir);
+
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let _bot = Bot::spawn(
+        &server,
+        &[
+            "--size",
+            "5",
+            "--tc",
+            "60+1",
+            "--seek-color",
+            "white",
+            "--archive-dir",
+            archive_dir.to_str().unwrap(),
+        ],
+    );
+    let mut client = accept(&mut server);
+    client.send_online(2).unwrap();
+    let seek = client.expect_seek().unwrap();
+
+    let game = GameStart::from_seek(4, &seek, client.name(), "Opponent");
+    client.start_game(&game).unwrap();
+    // Clocks sent before the first move do not belong to any move
+    client
+        .send_time(4, Duration::from_secs(60), Duration::from_secs(60))
+        .unwrap();
+    let mut position = <Position<5>>::start_position();
+    for (white_time, black_time) in [(59, 60), (58, 61)] {
+        let mv = client.expect_move(4, &position).unwrap();
+        position.do_move(mv);
+        client
+            .send_time(
+                4,
+                Duration::from_secs(white_time),
+                Duration::from_secs(black_time - 1),
+            )
+            .unwrap();
+        play_opponent_move(&mut client, 4, &mut position).unwrap();
+        client
+            .send_time(
+                4,
+                Duration::from_secs(white_time),
+                Duration::from_secs(black_time),
+            )
+            .unwrap();
+    }
+    client.expect_move(4, &position).unwrap();
+    client
+        .send_time(4, Duration::from_secs(57), Duration::from_secs(61))
+        .unwrap();
+    client.send_game_over(4, "0-1").unwrap();
+
+    // The game is archived before the next seek
+    client.expect_seek().unwrap();
+    let archived_game = &read_archived_game(&archive_dir);
+    let tag = |name: &str| {
+        archived_game
+            .tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(tag("Player1"), Some(client.name()));
+    assert_eq!(tag("Player2"), Some("Opponent"));
+    assert_eq!(tag("Size"), Some("5"));
+    assert_eq!(tag("Komi"), Some("0"));
+    assert_eq!(tag("Clock"), Some("1:00 +1"));
+    assert!(tag("Date").is_some());
+    assert_eq!(archived_game.game_result_str, Some("0-1"));
+
+    assert_eq!(archived_game.moves.len(), 5);
+    // The first move is placed instantly, without a search
+    assert_eq!(archived_game.moves[0].comment, "[%clk 0:00:59]");
+    assert_eq!(archived_game.moves[1].comment, "[%clk 0:01:00]");
+    let searched_move = &archived_game.moves[2].comment;
+    assert!(
+        searched_move.starts_with("[%clk 0:00:58] ") && searched_move.contains("%, pv "),
+        "{}",
+        searched_move
+    );
+    assert_eq!(archived_game.moves[3].comment, "[%clk 0:01:01]");
+    assert!(archived_game.moves[4]
+        .comment
+        .starts_with("[%clk 0:00:57] "));
+}
+
+#[test]
+fn archive_game_lost_on_opponent_move_test() {
+    let archive_dir =
+        std::env::temp_dir().join(format!("tiltak_archive_lost_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&archive_dir);
+
+    let mut server = MockPlaytakServer::bind("127.0.0.1:0").unwrap();
+    let _bot = Bot::spawn(
+        &server,
+        &[
+            "--size",
+            "5",
+            "--tc",
+            "60+1",
+            "--archive-dir",
+            archive_dir.to_str().unwrap(),
+        ],
+    );
+    let mut client = accept(&mut server);
+
+    // Resume a game where black can complete a road on a1
+    let game = game_start(5, &client, Color::White);
+    client.start_game(&game).unwrap();
+    let mut position = <Position<5>>::start_position();
+    for move_string in ["a5", "e1", "b1", "a4", "c1", "a3", "d1", "a2", "e2", "a1"] {
+        if move_string == "a1" {
+            client.send_message("Your game is resumed").unwrap();
+        }
+        let mv = position.move_from_san(move_string).unwrap();
+        client.send_move(5, mv).unwrap();
+        position.do_move(mv);
+    }
+    // The clocks after the winning move are still recorded
+    client
+        .send_time(5, Duration::from_secs(50), Duration::from_secs(40))
+        .unwrap();
+    client.send_game_over(5, "0-R").unwrap();
+
+    client.expect_seek().unwrap();
+    let archived_game = read_archived_game(&archive_dir);
+    assert_eq!(archived_game.game_result_str, Some("0-R"));
+    assert_eq!(archived_game.moves.len(), 10);
+    assert_eq!(archived_game.moves[9].comment, "[%clk 0:00:40]");
+    assert!(archived_game.moves[..9]
+        .iter()
+        .all(|ptn_move| ptn_move.comment.is_empty()));
+}
+
+/// Read the only game in the archive directory, and delete the directory
+fn read_archived_game(archive_dir: &Path) -> Game<Position<5>> {
+    let files: Vec<_> = std::fs::read_dir(archive_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    let ptn = std::fs::read_to_string(&files[0]).unwrap();
+    std::fs::remove_dir_all(archive_dir).unwrap();
+
+    let mut games = tiltak::ptn::ptn_parser::parse_ptn::<Position<5>>(&ptn).unwrap();
+    assert_eq!(games.len(), 1);
+    games.pop().unwrap()
+}
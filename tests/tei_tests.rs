@@ -0,0 +1,103 @@
+//! Sessions with the `tei` binary, checking that it answers the way the protocol requires
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Tei {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Tei {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tei"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                if sender.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut tei = Tei {
+            child,
+            stdin,
+            lines,
+        };
+        tei.send("tei");
+        tei.wait_for("teiok", Duration::from_secs(10)).unwrap();
+        tei.send("setoption name Hash value 64");
+        tei
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.stdin, "{}", command).unwrap();
+    }
+
+    /// Wait for a line that starts with `prefix`, and return it
+    fn wait_for(&mut self, prefix: &str, timeout: Duration) -> Result<String, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))?;
+            if line.starts_with(prefix) {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+impl Drop for Tei {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn go_during_infinite_search_test() {
+    let mut tei = Tei::start();
+    tei.send("teinewgame 4");
+    tei.send("position startpos moves a1 d4");
+    tei.send("go infinite");
+    thread::sleep(Duration::from_millis(200));
+
+    // The infinite search is stopped, instead of blocking the engine
+    tei.send("go movetime 200");
+    tei.wait_for("bestmove", Duration::from_secs(10)).unwrap();
+    tei.wait_for("bestmove", Duration::from_secs(10)).unwrap();
+    tei.send("isready");
+    tei.wait_for("readyok", Duration::from_secs(10)).unwrap();
+}
+
+#[test]
+fn go_infinite_proven_position_test() {
+    let mut tei = Tei::start();
+    tei.send("teinewgame 4");
+    // White wins by placing on d4
+    tei.send("position tps 1,1,1,x/x4/x4/2,2,2,x 1 4");
+    tei.send("go infinite");
+
+    thread::sleep(Duration::from_millis(300));
+    while tei.lines.try_recv().is_ok() {}
+
+    // Once the root is proven, the engine stops searching, but does not send `bestmove` before `stop`
+    assert_eq!(
+        tei.wait_for("", Duration::from_millis(300)),
+        Err(RecvTimeoutError::Timeout)
+    );
+    tei.send("stop");
+    let best_move = tei.wait_for("bestmove", Duration::from_secs(10)).unwrap();
+    assert_eq!(best_move, "bestmove d4");
+}